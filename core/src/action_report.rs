@@ -3,17 +3,18 @@ use ergo_lib::ergo_chain_types::EcPoint;
 
 use crate::oracle_types::Rate;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RefreshActionReport {
     pub oracle_boxes_collected: Vec<EcPoint>,
+    pub rate: Rate,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PublishDatapointActionReport {
     pub posted_datapoint: Rate,
 }
 
-#[derive(Debug, From)]
+#[derive(Debug, Clone, From)]
 pub enum PoolActionReport {
     Refresh(RefreshActionReport),
     PublishDatapoint(PublishDatapointActionReport),
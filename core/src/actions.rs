@@ -7,10 +7,14 @@ use derive_more::From;
 use ergo_node_interface::node_interface::NodeError;
 use thiserror::Error;
 
+use crate::context::OracleContext;
 use crate::explorer_api::ergo_explorer_transaction_link;
+use crate::metrics::inc_tx_input_box_not_found;
 use crate::node_interface::node_api::NodeApi;
 use crate::node_interface::node_api::NodeApiError;
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::node_interface::VerifyTransaction;
+use crate::pool_config::PoolConfig;
+use crate::wallet::OracleWallet;
 
 mod action_result;
 
@@ -21,6 +25,15 @@ pub enum PoolAction {
     PublishDatapoint(PublishDataPointAction),
 }
 
+impl PoolAction {
+    pub fn tx(&self) -> &UnsignedTransaction {
+        match self {
+            PoolAction::Refresh(action) => &action.tx,
+            PoolAction::PublishDatapoint(action) => &action.tx,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RefreshAction {
     pub tx: UnsignedTransaction,
@@ -35,12 +48,25 @@ pub struct PublishDataPointAction {
 pub enum ActionExecError {
     #[error("node error: {0}")]
     NodeError(#[from] NodeApiError),
+    #[error("tx verification failed: {0}")]
+    TxVerification(NodeApiError),
 }
 
-pub fn execute_action(action: PoolAction, node_api: &NodeApi) -> Result<(), anyhow::Error> {
+pub fn execute_action(
+    ctx: &OracleContext,
+    pool_name: &str,
+    action: PoolAction,
+    node_api: &NodeApi,
+    wallet: &OracleWallet,
+    pool_config: &PoolConfig,
+) -> Result<(), anyhow::Error> {
     let exec_res = match action {
-        PoolAction::Refresh(action) => execute_refresh_action(action, node_api),
-        PoolAction::PublishDatapoint(action) => execute_publish_datapoint_action(action, node_api),
+        PoolAction::Refresh(action) => {
+            execute_refresh_action(ctx, action, node_api, wallet, pool_config)
+        }
+        PoolAction::PublishDatapoint(action) => {
+            execute_publish_datapoint_action(ctx, action, node_api, wallet, pool_config)
+        }
     };
     match exec_res {
         Ok(_) => Ok(()),
@@ -55,32 +81,49 @@ pub fn execute_action(action: PoolAction, node_api: &NodeApi) -> Result<(), anyh
             log::debug!("Node rejected tx with error: {msg}");
             Ok(())
         }
+        // input box is spent by our tx from the last main loop iteration, same as the "Not enough boxes to spend" case above,
+        // or by someone else's tx. Counted in case it keeps happening.
+        Err(ActionExecError::TxVerification(NodeApiError::BoxNotFound(box_id))) => {
+            log::warn!("Skipping tx, input box {box_id} is not found on the node (already spent?)");
+            inc_tx_input_box_not_found(pool_name);
+            Ok(())
+        }
         Err(e) => Err(e.into()),
     }
 }
 
 fn execute_refresh_action(
+    ctx: &OracleContext,
     action: RefreshAction,
     node_api: &NodeApi,
+    wallet: &OracleWallet,
+    pool_config: &PoolConfig,
 ) -> Result<(), ActionExecError> {
-    let tx_id = node_api.sign_and_submit_transaction(&action.tx)?;
-    let network_prefix = &ORACLE_CONFIG.oracle_address.network();
+    node_api
+        .verify_transaction(&action.tx, pool_config)
+        .map_err(ActionExecError::TxVerification)?;
+    let tx_id = wallet.sign_and_submit_transaction(&action.tx)?;
     log::info!(
         "Refresh tx published. Check status: {}",
-        ergo_explorer_transaction_link(tx_id, *network_prefix)
+        ergo_explorer_transaction_link(&ctx.oracle_config, tx_id, ctx.network_prefix())
     );
     Ok(())
 }
 
 fn execute_publish_datapoint_action(
+    ctx: &OracleContext,
     action: PublishDataPointAction,
     node_api: &NodeApi,
+    wallet: &OracleWallet,
+    pool_config: &PoolConfig,
 ) -> Result<(), ActionExecError> {
-    let tx_id = node_api.sign_and_submit_transaction(&action.tx)?;
-    let network_prefix = &ORACLE_CONFIG.oracle_address.network();
+    node_api
+        .verify_transaction(&action.tx, pool_config)
+        .map_err(ActionExecError::TxVerification)?;
+    let tx_id = wallet.sign_and_submit_transaction(&action.tx)?;
     log::info!(
         "Datapoint tx published. Check status: {}",
-        ergo_explorer_transaction_link(tx_id, *network_prefix)
+        ergo_explorer_transaction_link(&ctx.oracle_config, tx_id, ctx.network_prefix())
     );
     Ok(())
 }
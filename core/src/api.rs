@@ -1,21 +1,28 @@
 use std::convert::From;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::RwLock;
 
 use crate::box_kind::PoolBox;
+use crate::config_reload::ConfigReloadReport;
+use crate::context::OracleContext;
 use crate::monitor::{
     check_oracle_health, check_pool_health, HealthStatus, OracleHealth, PoolHealth,
 };
-use crate::node_interface::node_api::{NodeApi, NodeApiError};
-use crate::oracle_config::{ORACLE_CONFIG, ORACLE_SECRETS};
+use crate::node_interface::async_node::AsyncNodeClient;
+use crate::node_interface::node_api::NodeApiError;
 use crate::oracle_state::{DataSourceError, LocalDatapointState, OraclePool};
-use crate::pool_config::POOL_CONFIG;
+use crate::oracle_types::BlockHeight;
+use crate::running_pool::RunningPool;
+use crate::scheduler::ShutdownSignal;
+use crate::shadow_mode::ShadowReportStorage;
+use crate::wallet_funds::WalletFundsMonitor;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
 use crossbeam::channel::Receiver;
-use ergo_lib::ergotree_ir::chain::address::{Address, AddressEncoder};
+use ergo_lib::ergotree_ir::chain::address::{Address, AddressEncoder, NetworkPrefix};
 use ergo_node_interface::scanning::NodeError;
 use serde_json::json;
 use tokio::task;
@@ -28,29 +35,71 @@ async fn root() -> &'static str {
         /poolStatus - status of the oracle pool
         /oracleInfo - basic information about the oracle
         /oracleStatus - status of the oracle
-        /oracleHealth - returns OK if our collected datapoint box height is the same as the pool box height OR our posted datapoint box height is greater than the pool box height
+        /oracleHealth - returns OK if our collected datapoint box height is the same as the pool box height OR our posted datapoint box height is greater than the pool box height, and the wallet balance is above the low balance threshold (the wallet funds and the estimated runway in epochs are included)
         /poolHealth - returns OK if the pool box height is greater or equal to (current height - epoch length)
+        /shadowReport - transactions built in shadow mode and their on-chain outcome
+        /configReload - outcome of the last config reload (on SIGHUP or config file change)
+        /pools - names of the pools run by this oracle, the pool endpoints above are available for each pool under /pools/<name>/ (the top-level ones serve the first pool)
         "
 }
 
 /// Basic oracle information
-async fn oracle_info() -> impl IntoResponse {
-    let conf = &ORACLE_CONFIG;
+async fn oracle_info(ctx: Arc<OracleContext>) -> impl IntoResponse {
+    let conf = &ctx.oracle_config;
     Json(json! ( {
         "oracle_address": conf.oracle_address.to_base58(),
         "base_fee": conf.base_fee,
     } ))
 }
 
+/// Calls `read` with the pool boxes of the current block. The boxes are fetched through the async
+/// node client into the box cache, and `read` reads them from the cache. `read` runs on a blocking
+/// thread, since a lookup missing from the cache (e.g. if a new block was observed or the cache was
+/// invalidated meanwhile) goes to the node through the blocking client. Without the async client
+/// (the box source doesn't use the node) or if the prefetch fails, `read` looks the boxes up itself.
+async fn read_pool_boxes<T: Send + 'static>(
+    oracle_pool: Arc<OraclePool>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    read: impl FnOnce(Arc<OraclePool>, BlockHeight) -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    let prefetched_height = match node_client {
+        Some(node_client) => match oracle_pool.prefetch(&node_client).await {
+            Ok(current_height) => Some(current_height),
+            Err(e) => {
+                log::debug!("Failed to prefetch the pool boxes: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    task::spawn_blocking(move || {
+        let current_height = match prefetched_height {
+            Some(current_height) => current_height,
+            None => oracle_pool.current_height()?,
+        };
+        read(oracle_pool, current_height)
+    })
+    .await
+    .unwrap()
+}
+
 /// Status of the oracle
-async fn oracle_status(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::Value>, ApiError> {
-    let json = task::spawn_blocking(|| oracle_status_sync(oracle_pool))
-        .await
-        .unwrap()?;
-    Ok(json)
+async fn oracle_status(
+    oracle_pool: Arc<OraclePool>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    read_pool_boxes(oracle_pool, node_client, |oracle_pool, current_height| {
+        oracle_status_sync(oracle_pool, current_height, wallet_funds)
+    })
+    .await
 }
 
-fn oracle_status_sync(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::Value>, ApiError> {
+fn oracle_status_sync(
+    oracle_pool: Arc<OraclePool>,
+    current_height: BlockHeight,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
     let live_epoch = oracle_pool.get_live_epoch_state()?;
     if let Some(local_datapoint_box_state) = live_epoch.local_datapoint_box_state {
         let json = match local_datapoint_box_state {
@@ -64,7 +113,7 @@ fn oracle_status_sync(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::V
                 "height": height,
             }),
         };
-        let oracle_health = oracle_health_sync(oracle_pool)?;
+        let oracle_health = oracle_health_sync(oracle_pool, current_height, wallet_funds)?;
         Ok(Json(json!({
                 "local_datapoint_box_state": json,
                 "oracle_health": oracle_health,
@@ -77,10 +126,9 @@ fn oracle_status_sync(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::V
 }
 
 // Basic information about the oracle pool
-async fn pool_info() -> impl IntoResponse {
-    let conf = &POOL_CONFIG;
-    let network = &ORACLE_CONFIG.oracle_address.network();
-    let address_encoder = AddressEncoder::new(*network);
+async fn pool_info(ctx: Arc<OracleContext>, oracle_pool: Arc<OraclePool>) -> impl IntoResponse {
+    let conf = oracle_pool.pool_config();
+    let address_encoder = AddressEncoder::new(ctx.network_prefix());
     let pool_box_address = Address::P2S(
         conf.pool_box_wrapper_inputs
             .contract_inputs
@@ -120,35 +168,42 @@ async fn pool_info() -> impl IntoResponse {
 }
 
 /// Status of the oracle pool
-async fn pool_status(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::Value>, ApiError> {
-    let json = task::spawn_blocking(|| pool_status_sync(oracle_pool))
-        .await
-        .unwrap()?;
-    Ok(json)
+async fn pool_status(
+    oracle_pool: Arc<OraclePool>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    network_prefix: NetworkPrefix,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    read_pool_boxes(
+        oracle_pool,
+        node_client,
+        move |oracle_pool, current_height| {
+            pool_status_sync(oracle_pool, current_height, network_prefix)
+        },
+    )
+    .await
 }
 
-fn pool_status_sync(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::Value>, ApiError> {
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &ORACLE_CONFIG.node_url,
-    );
-    let current_height = node_api.node.current_block_height()? as u32;
+fn pool_status_sync(
+    oracle_pool: Arc<OraclePool>,
+    current_height: BlockHeight,
+    network_prefix: NetworkPrefix,
+) -> Result<Json<serde_json::Value>, ApiError> {
     let pool_box = oracle_pool.get_pool_box_source().get_pool_box()?;
-    let epoch_length = POOL_CONFIG
+    let epoch_length = oracle_pool
+        .pool_config()
         .refresh_box_wrapper_inputs
         .contract_inputs
         .contract_parameters()
         .epoch_length();
     let pool_box_height = pool_box.get_box().creation_height;
     let epoch_end_height = pool_box_height + epoch_length.0 as u32;
-    let pool_health = pool_health_sync(oracle_pool)?;
+    let pool_health = pool_health_sync(oracle_pool, current_height, network_prefix)?;
     let active_oracle_count = pool_health.details.active_oracle_boxes.len();
     let json = Json(json!({
         "latest_pool_datapoint": pool_box.rate(),
         "latest_pool_box_height": pool_box_height,
         "pool_box_epoch_id" : pool_box.epoch_counter(),
-        "current_block_height": current_height,
+        "current_block_height": current_height.0,
         "epoch_end_height": epoch_end_height,
         "reward_tokens_in_pool_box": pool_box.reward_token().amount.as_u64(),
         "number_of_oracles": active_oracle_count,
@@ -158,17 +213,16 @@ fn pool_status_sync(oracle_pool: Arc<OraclePool>) -> Result<Json<serde_json::Val
 }
 
 /// Block height of the Ergo blockchain
-async fn block_height() -> Result<impl IntoResponse, ApiError> {
-    let current_height = task::spawn_blocking(move || {
-        let node_api = NodeApi::new(
-            ORACLE_SECRETS.node_api_key.clone(),
-            ORACLE_SECRETS.wallet_password.clone(),
-            &ORACLE_CONFIG.node_url,
-        );
-        node_api.node.current_block_height()
-    })
-    .await
-    .unwrap()?;
+async fn block_height(
+    ctx: Arc<OracleContext>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let current_height = match node_client {
+        Some(node_client) => node_client.current_block_height().await?,
+        None => task::spawn_blocking(move || ctx.current_height())
+            .await
+            .unwrap()?,
+    };
     Ok(format!("{}", current_height))
 }
 
@@ -183,11 +237,15 @@ async fn require_datapoint_repost(repost_receiver: Receiver<bool>) -> impl IntoR
 
 /// Return true if the our collected datapoint box height is the same as the pool box height
 /// and our posted datapoint box height is greater than the pool box height
-async fn oracle_health(oracle_pool: Arc<OraclePool>) -> impl IntoResponse {
-    let pool_health = match task::spawn_blocking(|| oracle_health_sync(oracle_pool))
-        .await
-        .unwrap()
-    {
+async fn oracle_health(
+    oracle_pool: Arc<OraclePool>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+) -> impl IntoResponse {
+    let oracle_health = read_pool_boxes(oracle_pool, node_client, |oracle_pool, current_height| {
+        oracle_health_sync(oracle_pool, current_height, wallet_funds)
+    });
+    let pool_health = match oracle_health.await {
         Ok(v) => v,
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(e.0))),
     };
@@ -204,14 +262,13 @@ async fn oracle_health(oracle_pool: Arc<OraclePool>) -> impl IntoResponse {
     )
 }
 
-fn oracle_health_sync(oracle_pool: Arc<OraclePool>) -> Result<OracleHealth, ApiError> {
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &ORACLE_CONFIG.node_url,
-    );
-    let current_height = (node_api.node.current_block_height()? as u32).into();
-    let epoch_length = POOL_CONFIG
+fn oracle_health_sync(
+    oracle_pool: Arc<OraclePool>,
+    current_height: BlockHeight,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+) -> Result<OracleHealth, ApiError> {
+    let epoch_length = oracle_pool
+        .pool_config()
         .refresh_box_wrapper_inputs
         .contract_inputs
         .contract_parameters()
@@ -224,16 +281,30 @@ fn oracle_health_sync(oracle_pool: Arc<OraclePool>) -> Result<OracleHealth, ApiE
         .get_box()
         .creation_height
         .into();
-    let oracle_health =
-        check_oracle_health(oracle_pool, pool_box_height, current_height, epoch_length)?;
+    let wallet_funds = wallet_funds.read().unwrap().report().cloned();
+    let oracle_health = check_oracle_health(
+        oracle_pool,
+        pool_box_height,
+        current_height,
+        epoch_length,
+        wallet_funds.as_ref(),
+    )?;
     Ok(oracle_health)
 }
 
-async fn pool_health(oracle_pool: Arc<OraclePool>) -> impl IntoResponse {
-    let pool_health = match task::spawn_blocking(|| pool_health_sync(oracle_pool))
-        .await
-        .unwrap()
-    {
+async fn pool_health(
+    oracle_pool: Arc<OraclePool>,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    network_prefix: NetworkPrefix,
+) -> impl IntoResponse {
+    let pool_health = read_pool_boxes(
+        oracle_pool,
+        node_client,
+        move |oracle_pool, current_height| {
+            pool_health_sync(oracle_pool, current_height, network_prefix)
+        },
+    );
+    let pool_health = match pool_health.await {
         Ok(v) => v,
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!(e.0))),
     };
@@ -250,16 +321,13 @@ async fn pool_health(oracle_pool: Arc<OraclePool>) -> impl IntoResponse {
     )
 }
 
-fn pool_health_sync(oracle_pool: Arc<OraclePool>) -> Result<PoolHealth, ApiError> {
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &ORACLE_CONFIG.node_url,
-    );
-    let current_height = (node_api.node.current_block_height()? as u32).into();
+fn pool_health_sync(
+    oracle_pool: Arc<OraclePool>,
+    current_height: BlockHeight,
+    network_prefix: NetworkPrefix,
+) -> Result<PoolHealth, ApiError> {
     let pool_box = &oracle_pool.get_pool_box_source().get_pool_box()?;
     let pool_box_height = pool_box.get_box().creation_height.into();
-    let network_prefix = node_api.get_change_address()?.network();
     let pool_health = check_pool_health(
         current_height,
         pool_box_height,
@@ -270,37 +338,133 @@ fn pool_health_sync(oracle_pool: Arc<OraclePool>) -> Result<PoolHealth, ApiError
     Ok(pool_health)
 }
 
+/// Transactions built (but not submitted) in shadow mode compared with what happened on-chain
+async fn shadow_report(
+    shadow_report_storage: Option<Arc<RwLock<ShadowReportStorage>>>,
+) -> impl IntoResponse {
+    match shadow_report_storage {
+        Some(storage) => {
+            let entries = storage.read().unwrap().entries();
+            (StatusCode::OK, Json(json!(entries)))
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!("shadow mode is not enabled (run with --shadow)")),
+        ),
+    }
+}
+
+/// Names of the pools run by this oracle
+/// Outcome of the last config reload, null if the config was not reloaded
+async fn config_reload(
+    config_reload_report: Arc<RwLock<Option<ConfigReloadReport>>>,
+) -> impl IntoResponse {
+    let report = config_reload_report.read().unwrap().clone();
+    Json(json!(report))
+}
+
+async fn pools(pool_names: Vec<String>) -> impl IntoResponse {
+    Json(json!(pool_names))
+}
+
+/// Endpoints serving the state of a single pool
+fn pool_routes(
+    ctx: Arc<OracleContext>,
+    pool: RunningPool,
+    node_client: Option<Arc<AsyncNodeClient>>,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+) -> Router {
+    let op_clone = pool.oracle_pool.clone();
+    let op_clone2 = pool.oracle_pool.clone();
+    let op_clone3 = pool.oracle_pool.clone();
+    let op_clone4 = pool.oracle_pool.clone();
+    let wallet_funds_clone = wallet_funds.clone();
+    let network_prefix = ctx.network_prefix();
+    let node_client_clone = node_client.clone();
+    let node_client_clone2 = node_client.clone();
+    let node_client_clone3 = node_client.clone();
+    Router::new()
+        .route(
+            "/oracleStatus",
+            get(|| oracle_status(pool.oracle_pool, node_client, wallet_funds_clone)),
+        )
+        .route("/poolInfo", get(|| pool_info(ctx, op_clone4)))
+        .route(
+            "/poolStatus",
+            get(move || pool_status(op_clone, node_client_clone, network_prefix)),
+        )
+        .route(
+            "/oracleHealth",
+            get(|| oracle_health(op_clone2, node_client_clone2, wallet_funds)),
+        )
+        .route(
+            "/poolHealth",
+            get(move || pool_health(op_clone3, node_client_clone3, network_prefix)),
+        )
+        .route(
+            "/shadowReport",
+            get(|| shadow_report(pool.shadow_report_storage)),
+        )
+}
+
 pub async fn start_rest_server(
+    ctx: Arc<OracleContext>,
     repost_receiver: Receiver<bool>,
-    oracle_pool: Arc<OraclePool>,
-    api_port: u16,
+    pools_to_serve: Vec<RunningPool>,
+    config_reload_report: Arc<RwLock<Option<ConfigReloadReport>>>,
+    wallet_funds: Arc<RwLock<WalletFundsMonitor>>,
+    shutdown: ShutdownSignal,
 ) -> Result<(), anyhow::Error> {
-    let op_clone = oracle_pool.clone();
-    let op_clone2 = oracle_pool.clone();
-    let op_clone3 = oracle_pool.clone();
-    let app = Router::new()
+    let api_port = ctx.oracle_config.core_api_port;
+    let pool_names: Vec<String> = pools_to_serve.iter().map(|p| p.name.clone()).collect();
+    let ctx_clone = ctx.clone();
+    let ctx_clone2 = ctx.clone();
+    // shared by the handlers, the Explorer API box source doesn't use the node
+    let node_client = if ctx.oracle_config.box_source.requires_node() {
+        Some(Arc::new(AsyncNodeClient::new(ctx.nodes.clone())))
+    } else {
+        None
+    };
+    let node_client_clone = node_client.clone();
+    let mut app = Router::new()
         .route("/", get(root))
-        .route("/oracleInfo", get(oracle_info))
-        .route("/oracleStatus", get(|| oracle_status(oracle_pool)))
-        .route("/poolInfo", get(pool_info))
-        .route("/poolStatus", get(|| pool_status(op_clone)))
-        .route("/blockHeight", get(block_height))
-        .route("/oracleHealth", get(|| oracle_health(op_clone2)))
-        .route("/poolHealth", get(|| pool_health(op_clone3)))
+        .route("/oracleInfo", get(|| oracle_info(ctx_clone)))
+        .route(
+            "/blockHeight",
+            get(|| block_height(ctx_clone2, node_client_clone)),
+        )
         .route(
             "/requireDatapointRepost",
             get(|| require_datapoint_repost(repost_receiver)),
         )
-        .layer(
-            CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
-                .allow_methods([axum::http::Method::GET]),
+        .route("/configReload", get(|| config_reload(config_reload_report)))
+        .route("/pools", get(|| pools(pool_names)));
+    if let Some(first_pool) = pools_to_serve.first() {
+        app = app.merge(pool_routes(
+            ctx.clone(),
+            first_pool.clone(),
+            node_client.clone(),
+            wallet_funds.clone(),
+        ));
+    }
+    for pool in pools_to_serve {
+        app = app.nest(
+            &format!("/pools/{}", pool.name),
+            pool_routes(ctx.clone(), pool, node_client.clone(), wallet_funds.clone()),
         );
+    }
+    let app = app.layer(
+        CorsLayer::new()
+            .allow_origin(tower_http::cors::Any)
+            .allow_methods([axum::http::Method::GET]),
+    );
     let addr = SocketAddr::from(([0, 0, 0, 0], api_port));
     log::info!("Starting REST server on {}", addr);
     axum::Server::try_bind(&addr)?
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown.triggered())
         .await?;
+    log::info!("REST server stopped");
     Ok(())
 }
 
@@ -0,0 +1,237 @@
+//! Reference external signer for oracle-core (`signer: { type: external, url: ... }` in the oracle
+//! config). Holds the oracle key and signs the pool transactions allowed by the policy. Meant for
+//! tests and as a starting point for the key custody setups.
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use clap::Parser;
+use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+use ergo_lib::wallet::Wallet;
+use oracle_core::pool_config::PoolConfig;
+use oracle_core::secrets::Secret;
+use oracle_core::secrets::SecretSources;
+use oracle_core::wallet::external_signer::sign_request;
+use oracle_core::wallet::external_signer::SignRequest;
+use oracle_core::wallet::external_signer::SignResponse;
+use oracle_core::wallet::external_signer::SignerEndpoint;
+use oracle_core::wallet::local_wallet::secret_key_from_file;
+use oracle_core::wallet::local_wallet::secret_key_from_mnemonic;
+use oracle_core::wallet::sign_policy::AllowAll;
+use oracle_core::wallet::sign_policy::PoolTxKind;
+use oracle_core::wallet::sign_policy::PoolTxPolicy;
+use oracle_core::wallet::sign_policy::SignPolicy;
+
+#[derive(Debug, Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Where to listen, `http://<host>:<port>` or `unix:<socket path>`
+    #[clap(long)]
+    listen: String,
+    /// Encrypted secret file of the node wallet (the password is in
+    /// ORACLE_WALLET_SECRET_FILE_PASSWORD). The mnemonic in ORACLE_WALLET_MNEMONIC is used if not set.
+    #[clap(long)]
+    secret_file: Option<PathBuf>,
+    /// Secrets file with the mnemonic or the secret file password, see `secrets_file` in the
+    /// oracle config. The secrets are also taken from the systemd credentials and the environment.
+    #[clap(long)]
+    secrets_file: Option<PathBuf>,
+    /// Pool config of the pool whose transactions are signed
+    #[clap(long)]
+    pool_config_file: Option<PathBuf>,
+    /// Kinds of the pool transactions to sign
+    #[clap(long, value_enum, value_delimiter = ',', default_values_t = [PoolTxKind::Refresh, PoolTxKind::Datapoint])]
+    allow: Vec<PoolTxKind>,
+    /// The only key the oracle token may be transferred to (with `--allow transfer-oracle-token`)
+    #[clap(long, value_name = "ADDRESS")]
+    oracle_token_target: Option<String>,
+    /// The only address the reward tokens may be extracted to (with
+    /// `--allow extract-reward-tokens`)
+    #[clap(long, value_name = "ADDRESS")]
+    reward_tokens_target: Option<String>,
+    /// The largest miner fee (in nanoERG) the signed pool transactions may pay, set it to the
+    /// `base_fee` of the oracle config if that is higher
+    #[clap(long, value_name = "NANOERG", default_value_t = *SUGGESTED_TX_FEE().as_u64())]
+    max_fee: u64,
+    /// Sign any transaction (for the CLI commands like bootstrap)
+    #[clap(long)]
+    allow_all: bool,
+}
+
+struct Signer {
+    secret: SecretKey,
+    policy: Box<dyn SignPolicy + Send + Sync>,
+}
+
+impl Signer {
+    fn sign(&self, request: SignRequest) -> SignResponse {
+        let tx_id = String::from(request.tx.id());
+        let wallet = Wallet::from_secrets(vec![self.secret.clone()]);
+        let response = sign_request(&wallet, self.policy.as_ref(), request);
+        match &response {
+            SignResponse::Signed { .. } => println!("Signed tx {tx_id}"),
+            SignResponse::Rejected { reason } => println!("Rejected tx {tx_id}: {reason}"),
+        }
+        response
+    }
+}
+
+fn exit_with_error(message: String) -> ! {
+    eprintln!("{message}");
+    std::process::exit(exitcode::CONFIG);
+}
+
+fn load_secret(args: &Args) -> SecretKey {
+    let sources = SecretSources::load(args.secrets_file.as_deref())
+        .unwrap_or_else(|e| exit_with_error(format!("Failed to load the secrets: {e}")));
+    let secret = match &args.secret_file {
+        Some(path) => {
+            let password = sources
+                .get_required(Secret::WalletSecretFilePassword)
+                .unwrap_or_else(|e| exit_with_error(format!("{e}")));
+            secret_key_from_file(path, &password)
+        }
+        None => {
+            let mnemonic = sources
+                .get_required(Secret::WalletMnemonic)
+                .unwrap_or_else(|e| exit_with_error(format!("{e}")));
+            let mnemonic_password = sources
+                .get(Secret::WalletMnemonicPassword)
+                .unwrap_or_else(|e| exit_with_error(format!("{e}")))
+                .unwrap_or_default();
+            secret_key_from_mnemonic(&mnemonic, &mnemonic_password)
+        }
+    };
+    secret.unwrap_or_else(|e| exit_with_error(format!("Failed to load the key: {e}")))
+}
+
+fn load_policy(args: &Args, secret: &SecretKey) -> Box<dyn SignPolicy + Send + Sync> {
+    if args.allow_all {
+        return Box::new(AllowAll);
+    }
+    let pool_config_file = args.pool_config_file.as_ref().unwrap_or_else(|| {
+        exit_with_error("Either --pool-config-file or --allow-all is required".to_string())
+    });
+    let pool_config = PoolConfig::load_from_file(pool_config_file).unwrap_or_else(|e| {
+        exit_with_error(format!(
+            "Failed to load pool config from {}: {e:?}",
+            pool_config_file.display()
+        ))
+    });
+    let parse_address = |address: &String| {
+        AddressEncoder::unchecked_parse_network_address_from_str(address)
+            .map(|address| address.address())
+            .unwrap_or_else(|e| exit_with_error(format!("Invalid address {address}: {e}")))
+    };
+    let oracle_token_target = args.oracle_token_target.as_ref().map(parse_address);
+    let reward_tokens_target = args.reward_tokens_target.as_ref().map(parse_address);
+    let max_fee = BoxValue::try_from(args.max_fee)
+        .unwrap_or_else(|e| exit_with_error(format!("Invalid --max-fee: {e}")));
+    Box::new(
+        PoolTxPolicy::new(
+            &pool_config,
+            &secret.get_address_from_public_image(),
+            max_fee,
+            args.allow.clone(),
+            oracle_token_target.as_ref(),
+            reward_tokens_target.as_ref(),
+        )
+        .unwrap_or_else(|e| exit_with_error(format!("Failed to create the policy: {e}"))),
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let endpoint: SignerEndpoint = args
+        .listen
+        .parse()
+        .unwrap_or_else(|e| exit_with_error(format!("{e}")));
+    let secret = load_secret(&args);
+    let policy = load_policy(&args, &secret);
+    println!(
+        "Signing for {} (mainnet) / {} (testnet)",
+        NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &secret.get_address_from_public_image()
+        )
+        .to_base58(),
+        NetworkAddress::new(
+            NetworkPrefix::Testnet,
+            &secret.get_address_from_public_image()
+        )
+        .to_base58()
+    );
+    let signer = Arc::new(Signer { secret, policy });
+    let res = match endpoint {
+        SignerEndpoint::Http(url) => serve_http(signer, url).await,
+        SignerEndpoint::Unix(path) => serve_unix(signer, path).await,
+    };
+    if let Err(e) = res {
+        eprintln!("Signer error: {e:?}");
+        std::process::exit(exitcode::SOFTWARE);
+    }
+}
+
+async fn serve_http(signer: Arc<Signer>, url: reqwest::Url) -> Result<(), anyhow::Error> {
+    let addr: SocketAddr = url
+        .socket_addrs(|| None)?
+        .first()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("cannot resolve {url}"))?;
+    let app = Router::new().route(
+        "/sign",
+        post(move |Json(request): Json<SignRequest>| async move { Json(signer.sign(request)) }),
+    );
+    println!("Listening on {addr}");
+    axum::Server::try_bind(&addr)?
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn serve_unix(signer: Arc<Signer>, path: PathBuf) -> Result<(), anyhow::Error> {
+    use tokio::io::AsyncBufReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::io::BufReader;
+
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    println!("Listening on {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let signer = signer.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let response = match serde_json::from_str::<SignRequest>(&line) {
+                    Ok(request) => signer.sign(request),
+                    Err(e) => SignResponse::Rejected {
+                        reason: format!("invalid request: {e}"),
+                    },
+                };
+                let mut response_line = serde_json::to_string(&response).unwrap();
+                response_line.push('\n');
+                if writer.write_all(response_line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_signer: Arc<Signer>, _path: PathBuf) -> Result<(), anyhow::Error> {
+    Err(anyhow::anyhow!(
+        "unix sockets are not supported on this platform"
+    ))
+}
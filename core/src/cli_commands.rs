@@ -1,8 +1,11 @@
 pub mod bootstrap;
+pub mod consolidate_wallet;
 pub mod extract_reward_tokens;
 pub mod import_pool_update;
 pub mod prepare_update;
 pub mod print_reward_tokens;
+pub mod replay;
 pub mod transfer_oracle_token;
 pub mod update_pool;
+pub mod verify_pool;
 pub mod vote_update_pool;
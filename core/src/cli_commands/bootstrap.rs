@@ -30,6 +30,7 @@ use thiserror::Error;
 
 use crate::{
     box_kind::{make_pool_box_candidate, make_refresh_box_candidate},
+    context::OracleContext,
     contracts::{
         ballot::{BallotContractError, BallotContractParameters},
         oracle::OracleContractParameters,
@@ -43,10 +44,9 @@ use crate::{
     },
     explorer_api::wait_for_txs_confirmation,
     node_interface::{
-        node_api::{NodeApi, NodeApiError},
-        try_ensure_wallet_unlocked, SignTransactionWithInputs, SubmitTransaction,
+        node_api::NodeApiError, try_ensure_wallet_unlocked, SignTransactionWithInputs,
+        SubmitTransaction,
     },
-    oracle_config::{BASE_FEE, ORACLE_CONFIG, ORACLE_SECRETS},
     oracle_types::{BlockHeight, EpochCounter},
     pool_config::{
         PoolConfig, PoolConfigError, PredefinedDataPointSource, TokenIds,
@@ -57,47 +57,46 @@ use crate::{
         BallotTokenId, OracleTokenId, PoolTokenId, RefreshTokenId, RewardTokenId, SpecToken,
         TokenIdKind, UpdateTokenId,
     },
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{OracleWallet, WalletDataError, WalletDataSource},
 };
 
 /// Loads bootstrap configuration file and performs the chain-transactions for minting of tokens and
 /// box creations. An oracle configuration file is then created which contains the `TokenId`s of the
 /// minted tokens.
-pub fn bootstrap(config_file_name: String) -> Result<(), anyhow::Error> {
-    let oracle_config = &ORACLE_CONFIG;
+pub fn bootstrap(ctx: &OracleContext, config_file_name: String) -> Result<(), anyhow::Error> {
+    let oracle_config = &ctx.oracle_config;
     let s = std::fs::read_to_string(config_file_name)?;
     let config: BootstrapConfig = serde_yaml::from_str(&s)?;
 
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &oracle_config.node_url,
-    );
-    try_ensure_wallet_unlocked(&node_api);
-    let change_address = node_api.get_change_address()?;
+    let node_api = ctx.node_api();
+    let wallet = ctx.wallet()?;
+    if let OracleWallet::Node(node_api) = &wallet {
+        try_ensure_wallet_unlocked(node_api);
+    }
+    let change_address = wallet.get_change_address()?;
     debug!("Change address: {:?}", change_address);
     let erg_value_per_box = config.oracle_contract_parameters.min_storage_rent;
     let input = BootstrapInput {
         oracle_address: oracle_config.oracle_address.clone(),
         config,
-        wallet: &node_api as &dyn WalletDataSource,
-        tx_signer: &node_api.node as &dyn SignTransactionWithInputs,
-        submit_tx: &node_api.node as &dyn SubmitTransaction,
-        tx_fee: *BASE_FEE,
+        wallet: &wallet as &dyn WalletDataSource,
+        tx_signer: &wallet as &dyn SignTransactionWithInputs,
+        submit_tx: &wallet as &dyn SubmitTransaction,
+        tx_fee: ctx.base_fee(),
         erg_value_per_box,
         change_address: change_address.address(),
-        height: BlockHeight(node_api.node.current_block_height()? as u32),
+        height: BlockHeight(node_api.node().current_block_height()? as u32),
     };
-    let (oracle_config, submitted_tx_ids) = perform_bootstrap_chained_transaction(input)?;
+    let (pool_config, submitted_tx_ids) = perform_bootstrap_chained_transaction(input)?;
     info!("Bootstrap chain-transaction complete");
-    let s = serde_yaml::to_string(&oracle_config)?;
+    let s = serde_yaml::to_string(&pool_config)?;
     let mut file = std::fs::File::create(DEFAULT_POOL_CONFIG_FILE_NAME)?;
     file.write_all(s.as_bytes())?;
     info!(
         "Pool configuration file created: {}",
         DEFAULT_POOL_CONFIG_FILE_NAME
     );
-    wait_for_txs_confirmation(submitted_tx_ids);
+    wait_for_txs_confirmation(&ctx.oracle_config, submitted_tx_ids);
     Ok(())
 }
 
@@ -371,7 +370,7 @@ pub(crate) fn perform_bootstrap_chained_transaction(
     // Create pool box -----------------------------------------------------------------------------
     info!("Create and sign pool box tx");
 
-    // we don't have a working ORACLE_CONFIG during bootstrap so token ids are created without any checks
+    // we don't have a working pool config during bootstrap so token ids are created without any checks
     let token_ids = TokenIds {
         pool_nft_token_id: PoolTokenId::from_token_id_unchecked(pool_nft_token.token_id),
         refresh_nft_token_id: RefreshTokenId::from_token_id_unchecked(refresh_nft_token.token_id),
@@ -676,7 +675,7 @@ pub(crate) mod tests {
             address::{AddressEncoder, NetworkAddress, NetworkPrefix},
             ergo_box::{ErgoBox, NonMandatoryRegisters},
         },
-        wallet::Wallet,
+        wallet::{tx_builder::SUGGESTED_TX_FEE, Wallet},
     };
     use sigma_test_util::force_any_val;
 
@@ -710,7 +709,7 @@ pub(crate) mod tests {
         let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
         let ergo_tree = address.address().script().unwrap();
 
-        let value = BASE_FEE.checked_mul_u32(10000).unwrap();
+        let value = SUGGESTED_TX_FEE().checked_mul_u32(10000).unwrap();
         let unspent_boxes = vec![ErgoBox::new(
             value,
             ergo_tree.clone(),
@@ -742,8 +741,8 @@ pub(crate) mod tests {
                 wallet: &wallet,
             },
             submit_tx: &submit_tx,
-            tx_fee: *BASE_FEE,
-            erg_value_per_box: *BASE_FEE,
+            tx_fee: SUGGESTED_TX_FEE(),
+            erg_value_per_box: SUGGESTED_TX_FEE(),
             change_address: change_address.address(),
             height,
         })
@@ -0,0 +1,62 @@
+use crate::{
+    context::OracleContext,
+    explorer_api::ergo_explorer_transaction_link,
+    node_interface::{SignTransaction, SubmitTransaction},
+    oracle_types::BlockHeight,
+    wallet::WalletDataSource,
+    wallet_consolidation::{build_consolidation_tx, WalletConsolidationConfig},
+};
+
+pub fn consolidate_wallet(
+    ctx: &OracleContext,
+    wallet: &dyn WalletDataSource,
+    tx_signer: &dyn SignTransaction,
+    tx_submit: &dyn SubmitTransaction,
+    config: WalletConsolidationConfig,
+    height: BlockHeight,
+) -> Result<(), anyhow::Error> {
+    let (change_address, network_prefix) = {
+        let net_address = wallet.get_change_address()?;
+        (net_address.address(), net_address.network())
+    };
+    let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
+    let box_count = unspent_boxes.len();
+    let unsigned_tx = match build_consolidation_tx(
+        unspent_boxes,
+        &config,
+        ctx.base_fee(),
+        change_address,
+        height,
+    )? {
+        Some(unsigned_tx) => unsigned_tx,
+        None => {
+            println!(
+                "Nothing to consolidate: less than {} of the {} wallet boxes hold at most {} nanoERG and no tokens.",
+                config.min_boxes, box_count, config.max_box_value_nano_ergs
+            );
+            return Ok(());
+        }
+    };
+
+    println!(
+        "YOU WILL BE MERGING {} OF {} WALLET BOXES INTO ONE BOX OF {} NANOERG (FEE {} NANOERG). TYPE 'YES' TO INITIATE THE TRANSACTION.",
+        unsigned_tx.inputs.len(),
+        box_count,
+        unsigned_tx.output_candidates.first().value.as_u64(),
+        ctx.base_fee().as_u64()
+    );
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    if input.trim() == "YES" {
+        let signed_tx = tx_signer.sign_transaction(&unsigned_tx)?;
+        let tx_id = tx_submit.submit_transaction(&signed_tx)?;
+        crate::explorer_api::wait_for_tx_confirmation(&ctx.oracle_config, signed_tx.id());
+        println!(
+            "Transaction made. Check status here: {}",
+            ergo_explorer_transaction_link(&ctx.oracle_config, tx_id, network_prefix)
+        );
+    } else {
+        println!("Aborting the transaction.")
+    }
+    Ok(())
+}
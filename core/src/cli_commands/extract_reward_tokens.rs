@@ -9,6 +9,7 @@ use ergo_lib::{
     ergotree_ir::{
         chain::{
             address::{Address, AddressEncoder, AddressEncoderError},
+            ergo_box::box_value::BoxValue,
             token::Token,
         },
         serialization::SigmaParsingError,
@@ -25,9 +26,9 @@ use crate::{
     box_kind::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
+    context::OracleContext,
     explorer_api::ergo_explorer_transaction_link,
     node_interface::{SignTransaction, SubmitTransaction},
-    oracle_config::BASE_FEE,
     oracle_state::{DataSourceError, LocalDatapointBoxSource},
     oracle_types::BlockHeight,
     spec_token::SpecToken,
@@ -65,6 +66,7 @@ pub enum ExtractRewardTokensActionError {
 }
 
 pub fn extract_reward_tokens(
+    ctx: &OracleContext,
     wallet: &dyn WalletDataSource,
     tx_signer: &dyn SignTransaction,
     tx_submit: &dyn SubmitTransaction,
@@ -84,6 +86,7 @@ pub fn extract_reward_tokens(
         rewards_destination.address(),
         height,
         change_address.address(),
+        ctx.base_fee(),
     )?;
 
     println!(
@@ -95,10 +98,10 @@ pub fn extract_reward_tokens(
     if input.trim() == "YES" {
         let signed_tx = tx_signer.sign_transaction(&unsigned_tx)?;
         let tx_id = tx_submit.submit_transaction(&signed_tx)?;
-        crate::explorer_api::wait_for_tx_confirmation(signed_tx.id());
+        crate::explorer_api::wait_for_tx_confirmation(&ctx.oracle_config, signed_tx.id());
         println!(
             "Transaction made. Check status here: {}",
-            ergo_explorer_transaction_link(tx_id, network_prefix)
+            ergo_explorer_transaction_link(&ctx.oracle_config, tx_id, network_prefix)
         );
     } else {
         println!("Aborting the transaction.")
@@ -112,6 +115,7 @@ fn build_extract_reward_tokens_tx(
     rewards_destination: Address,
     height: BlockHeight,
     change_address: Address,
+    tx_fee: BoxValue,
 ) -> Result<(UnsignedTransaction, u64), ExtractRewardTokensActionError> {
     let in_oracle_box = local_datapoint_box_source
         .get_local_oracle_datapoint_box()?
@@ -154,7 +158,7 @@ fn build_extract_reward_tokens_tx(
 
         // Build box to hold extracted tokens
         let mut builder =
-            ErgoBoxCandidateBuilder::new(*BASE_FEE, rewards_destination.script()?, height.0);
+            ErgoBoxCandidateBuilder::new(tx_fee, rewards_destination.script()?, height.0);
 
         let extracted_reward_tokens = Token {
             token_id: in_oracle_box.reward_token().token_id(),
@@ -166,8 +170,8 @@ fn build_extract_reward_tokens_tx(
 
         let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
 
-        // `BASE_FEE` each for the fee and the box holding the extracted reward tokens.
-        let target_balance = BASE_FEE.checked_mul_u32(2).unwrap();
+        // `tx_fee` each for the fee and the box holding the extracted reward tokens.
+        let target_balance = tx_fee.checked_mul_u32(2).unwrap();
 
         let box_selector = SimpleBoxSelector::new();
         let selection = box_selector.select(unspent_boxes, target_balance, &[])?;
@@ -181,7 +185,7 @@ fn build_extract_reward_tokens_tx(
             box_selection,
             vec![oracle_box_candidate, reward_box_candidate],
             height.0,
-            *BASE_FEE,
+            tx_fee,
             change_address,
         );
         // The following context value ensures that `outIndex` in the oracle contract is properly set.
@@ -213,6 +217,7 @@ mod tests {
     use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
     use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
     use ergo_lib::wallet::signing::TransactionContext;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
     use ergo_lib::wallet::Wallet;
     use sigma_test_util::force_any_val;
 
@@ -236,7 +241,7 @@ mod tests {
                 200,
                 EpochCounter(1),
                 &token_ids,
-                BASE_FEE.checked_mul_u32(100).unwrap(),
+                SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
                 BlockHeight(height.0),
                 num_reward_tokens_in_box,
             ),
@@ -252,7 +257,7 @@ mod tests {
 
         let wallet_unspent_box = make_wallet_unspent_box(
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(10000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(10000).unwrap(),
             None,
         );
         let wallet_mock = WalletDataMock {
@@ -265,6 +270,7 @@ mod tests {
             change_address.address(),
             height,
             change_address.address(),
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
@@ -40,6 +40,7 @@ use crate::{
         make_refresh_box_candidate, BallotBoxWrapperInputs, PoolBox, PoolBoxWrapperInputs,
         RefreshBoxWrapperInputs, UpdateBoxWrapperInputs,
     },
+    context::OracleContext,
     contracts::{
         ballot::BallotContractError,
         pool::{PoolContractError, PoolContractParameters},
@@ -52,19 +53,16 @@ use crate::{
         },
     },
     explorer_api::wait_for_txs_confirmation,
-    node_interface::{
-        node_api::{NodeApi, NodeApiError},
-        SignTransactionWithInputs, SubmitTransaction,
-    },
-    oracle_config::{OracleConfig, BASE_FEE, ORACLE_CONFIG},
+    node_interface::{node_api::NodeApiError, SignTransactionWithInputs, SubmitTransaction},
+    oracle_config::OracleConfig,
     oracle_state::{DataSourceError, OraclePool},
     oracle_types::BlockHeight,
-    pool_config::{PoolConfig, POOL_CONFIG},
+    pool_config::PoolConfig,
     serde::{PoolConfigSerde, SerdeConversionError, UpdateBootstrapConfigSerde},
     spec_token::{
         BallotTokenId, OracleTokenId, RefreshTokenId, RewardTokenId, TokenIdKind, UpdateTokenId,
     },
-    wallet::{WalletDataError, WalletDataSource},
+    wallet::{token_guard::TokenGuardedWallet, OracleWallet, WalletDataError, WalletDataSource},
 };
 
 use super::bootstrap::{NftMintDetails, TokenMintDetails};
@@ -87,26 +85,30 @@ pub struct UpdateBootstrapConfig {
 }
 
 pub fn prepare_update(
+    ctx: &OracleContext,
+    op: &OraclePool,
     config_file_name: String,
-    node_api: &NodeApi,
+    wallet: &OracleWallet,
     height: BlockHeight,
 ) -> Result<(), anyhow::Error> {
     let s = std::fs::read_to_string(config_file_name)?;
     let config_serde: UpdateBootstrapConfigSerde = serde_yaml::from_str(&s)?;
 
-    let change_address = node_api.get_change_address()?.address();
+    let change_address = wallet.get_change_address()?.address();
     let config = UpdateBootstrapConfig::try_from(config_serde)?;
+    // only ERG is needed from the wallet, the tokens are minted
+    let wallet_boxes = TokenGuardedWallet::new(wallet).protect_pool(&op.pool_config().token_ids);
     let update_bootstrap_input = PrepareUpdateInput {
-        wallet: node_api,
-        tx_signer: &node_api.node,
-        submit_tx: &node_api.node,
-        tx_fee: *BASE_FEE,
-        erg_value_per_box: *BASE_FEE,
+        wallet: &wallet_boxes,
+        tx_signer: wallet,
+        submit_tx: wallet,
+        tx_fee: ctx.base_fee(),
+        erg_value_per_box: ctx.base_fee(),
         change_address,
         height,
     };
 
-    let prepare = PrepareUpdate::new(update_bootstrap_input, &POOL_CONFIG, &ORACLE_CONFIG)?;
+    let prepare = PrepareUpdate::new(update_bootstrap_input, op.pool_config(), &ctx.oracle_config)?;
     let (new_config, submitted_tx_ids) = prepare.execute(config)?;
     let blake2b_pool_ergo_tree: String = blake2b256_hash(
         new_config
@@ -129,19 +131,19 @@ pub fn prepare_update(
         "Base16-encoded blake2b hash of the serialized new pool box contract(ErgoTree): {}",
         blake2b_pool_ergo_tree
     );
-    print_hints_for_voting(height)?;
-    wait_for_txs_confirmation(submitted_tx_ids);
+    print_hints_for_voting(op, height)?;
+    wait_for_txs_confirmation(&ctx.oracle_config, submitted_tx_ids);
     Ok(())
 }
 
-fn print_hints_for_voting(height: BlockHeight) -> Result<(), PrepareUpdateError> {
-    let epoch_length = POOL_CONFIG
+fn print_hints_for_voting(op: &OraclePool, height: BlockHeight) -> Result<(), PrepareUpdateError> {
+    let epoch_length = op
+        .pool_config()
         .refresh_box_wrapper_inputs
         .contract_inputs
         .contract_parameters()
         .epoch_length()
         .0 as u32;
-    let op = OraclePool::load().unwrap();
     let oracle_boxes = op
         .get_posted_datapoint_boxes_source()
         .get_posted_datapoint_boxes()?;
@@ -365,6 +367,7 @@ impl<'a> PrepareUpdate<'a> {
             )?;
             new_pool_config.token_ids.oracle_token_id =
                 OracleTokenId::from_token_id_unchecked(token.token_id);
+            new_pool_config.oracle_tokens_emission = Some(token_mint_details.quantity);
         }
         if let Some(ref token_mint_details) = config.tokens_to_mint.ballot_tokens {
             info!("Minting ballot tokens");
@@ -376,6 +379,7 @@ impl<'a> PrepareUpdate<'a> {
             )?;
             new_pool_config.token_ids.ballot_token_id =
                 BallotTokenId::from_token_id_unchecked(token.token_id);
+            new_pool_config.ballot_tokens_emission = Some(token_mint_details.quantity);
         }
         if let Some(ref token_mint_details) = config.tokens_to_mint.reward_tokens {
             info!("Minting reward tokens");
@@ -566,7 +570,7 @@ mod test {
             address::{AddressEncoder, NetworkAddress, NetworkPrefix},
             ergo_box::{ErgoBox, NonMandatoryRegisters},
         },
-        wallet::Wallet,
+        wallet::{tx_builder::SUGGESTED_TX_FEE, Wallet},
     };
     use sigma_test_util::force_any_val;
 
@@ -652,7 +656,7 @@ data_point_source_custom_script: ~
         let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
         let ergo_tree = network_address.address().script().unwrap();
 
-        let value = BASE_FEE.checked_mul_u32(10000).unwrap();
+        let value = SUGGESTED_TX_FEE().checked_mul_u32(10000).unwrap();
         let unspent_boxes = vec![ErgoBox::new(
             value,
             ergo_tree.clone(),
@@ -711,8 +715,8 @@ data_point_source_custom_script: ~
                 wallet: &wallet,
             },
             submit_tx: &submit_tx,
-            tx_fee: *BASE_FEE,
-            erg_value_per_box: *BASE_FEE,
+            tx_fee: SUGGESTED_TX_FEE(),
+            erg_value_per_box: SUGGESTED_TX_FEE(),
             change_address: change_address.address(),
             height,
         };
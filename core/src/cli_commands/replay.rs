@@ -0,0 +1,209 @@
+//! Replay of a main loop recording (see [`crate::recording`]). Each recorded iteration is fed
+//! through [`crate::state::process`] and [`crate::pool_commands::build_action`] offline, with the
+//! recorded boxes, wallet and datapoint, and the decision is compared with the recorded one. The
+//! recording can be replayed by another oracle-core version to see how its decisions differ.
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ergo_lib::chain::transaction::TxId;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::context::OracleContext;
+use crate::oracle_config::OracleConfigFileError;
+use crate::oracle_state::OraclePool;
+use crate::oracle_types::BlockHeight;
+use crate::pool_commands::build_action;
+use crate::pool_commands::PoolCommand;
+use crate::pool_config::PoolConfig;
+use crate::recording::IterationRecord;
+use crate::recording::MainLoopRecording;
+use crate::recording::RecordedDataPointSource;
+use crate::recording::RecordedWallet;
+use crate::recording::ORACLE_CORE_VERSION;
+use crate::scans::PoolTokenBoxes;
+use crate::state::process;
+use crate::state::PoolState;
+use crate::wallet::WalletDataError;
+use crate::wallet::WalletDataSource;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("failed to read the recording {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("oracle config error: {0}")]
+    OracleConfig(#[from] OracleConfigFileError),
+    #[error("recorded wallet error: {0}")]
+    Wallet(#[from] WalletDataError),
+    #[error("oracle pool error: {0}")]
+    OraclePool(#[from] anyhow::Error),
+}
+
+/// The recorded and the replayed decision of a main loop iteration
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayedIteration {
+    pub height: BlockHeight,
+    /// oracle-core version of the recording oracle
+    pub recorded_by: String,
+    pub recorded_command: Option<PoolCommand>,
+    pub recorded_tx_id: Option<TxId>,
+    pub recorded_error: Option<String>,
+    pub command: Option<PoolCommand>,
+    pub tx_id: Option<TxId>,
+    pub error: Option<String>,
+}
+
+impl ReplayedIteration {
+    /// Same command and tx, or both failed to build (the error messages may differ between the
+    /// versions)
+    pub fn is_same_decision(&self) -> bool {
+        self.command == self.recorded_command
+            && self.tx_id == self.recorded_tx_id
+            && self.error.is_some() == self.recorded_error.is_some()
+    }
+}
+
+/// Replays the iterations of `pool_name` in the recording. The replayed decisions are printed and
+/// written to `output` as JSON lines if given.
+pub fn replay(
+    ctx: &OracleContext,
+    pool_config: &PoolConfig,
+    recording_path: &Path,
+    pool_name: &str,
+    output: Option<&Path>,
+) -> Result<(), ReplayError> {
+    let recording = MainLoopRecording::new(recording_path.to_path_buf());
+    let records = recording
+        .load()
+        .map_err(|e| ReplayError::Io(recording_path.to_path_buf(), e))?;
+    let records: Vec<IterationRecord> = records
+        .into_iter()
+        .filter(|record| record.pool == pool_name)
+        .collect();
+    if records.is_empty() {
+        println!(
+            "No iterations of pool {} in {}",
+            pool_name,
+            recording_path.display()
+        );
+        return Ok(());
+    }
+    let mut replayed = vec![];
+    for record in &records {
+        let iteration = replay_iteration(ctx, pool_config, record)?;
+        println!(
+            "{} height {}: recorded {} by v{}, replayed {}",
+            if iteration.is_same_decision() {
+                "SAME"
+            } else {
+                "DIFFERENT"
+            },
+            iteration.height.0,
+            describe_decision(
+                iteration.recorded_command,
+                iteration.recorded_tx_id.as_ref(),
+                iteration.recorded_error.as_deref()
+            ),
+            iteration.recorded_by,
+            describe_decision(
+                iteration.command,
+                iteration.tx_id.as_ref(),
+                iteration.error.as_deref()
+            ),
+        );
+        replayed.push(iteration);
+    }
+    let different = replayed
+        .iter()
+        .filter(|iteration| !iteration.is_same_decision())
+        .count();
+    println!(
+        "Replayed {} iterations with v{}, {} different decisions",
+        replayed.len(),
+        ORACLE_CORE_VERSION,
+        different
+    );
+    if let Some(output) = output {
+        let lines: Vec<String> = replayed
+            .iter()
+            .map(|iteration| serde_json::to_string(iteration).unwrap())
+            .collect();
+        std::fs::write(output, lines.join("\n") + "\n")
+            .map_err(|e| ReplayError::Io(output.to_path_buf(), e))?;
+    }
+    Ok(())
+}
+
+/// Runs the recorded iteration through the pool state machine and the tx builders
+pub fn replay_iteration(
+    ctx: &OracleContext,
+    pool_config: &PoolConfig,
+    record: &IterationRecord,
+) -> Result<ReplayedIteration, ReplayError> {
+    // the node is not contacted, the boxes come from the recording
+    let oracle_pool = OraclePool::new(
+        Arc::new(ctx.node_api()),
+        &PoolTokenBoxes::from_recording(pool_config, Arc::new(record.boxes.clone())),
+        pool_config,
+        ctx.oracle_config.oracle_address_p2pk()?,
+    )?;
+    let height = oracle_pool.current_height().map_err(anyhow::Error::from)?;
+    let pool_state = match oracle_pool.get_live_epoch_state() {
+        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
+        Err(_) => PoolState::NeedsBootstrap,
+    };
+    let epoch_length = pool_config
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters()
+        .epoch_length();
+    // the main loop doesn't build txs without a node, the box source of the recording oracle
+    // is not in the recordings of the older versions
+    let box_source = record.box_source.unwrap_or(ctx.oracle_config.box_source);
+    let command = if box_source.requires_node() {
+        process(pool_state, epoch_length, height)
+    } else {
+        None
+    };
+    let wallet = RecordedWallet::from_record(record)?;
+    let change_address = wallet.get_change_address()?.address();
+    let (tx_id, error) = match command {
+        Some(cmd) => match build_action(
+            ctx,
+            cmd,
+            &oracle_pool,
+            &wallet,
+            height,
+            change_address,
+            &RecordedDataPointSource(record.datapoint.clone()),
+        ) {
+            Ok((action, _)) => (Some(action.tx().id()), None),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        None => (None, None),
+    };
+    Ok(ReplayedIteration {
+        height: record.height(),
+        recorded_by: record.oracle_core_version.clone(),
+        recorded_command: record.command,
+        recorded_tx_id: record.tx.as_ref().map(|tx| tx.id()),
+        recorded_error: record.build_error.clone(),
+        command,
+        tx_id,
+        error,
+    })
+}
+
+fn describe_decision(
+    command: Option<PoolCommand>,
+    tx_id: Option<&TxId>,
+    error: Option<&str>,
+) -> String {
+    match (command, tx_id, error) {
+        (None, _, _) => "no action".to_string(),
+        (Some(cmd), Some(tx_id), _) => format!("{:?} (tx {})", cmd, tx_id),
+        (Some(cmd), None, Some(error)) => format!("{:?} (failed: {})", cmd, error),
+        (Some(cmd), None, None) => format!("{:?}", cmd),
+    }
+}
@@ -7,7 +7,10 @@ use ergo_lib::{
     },
     ergotree_interpreter::sigma_protocol::prover::ContextExtension,
     ergotree_ir::{
-        chain::address::{Address, AddressEncoder, AddressEncoderError},
+        chain::{
+            address::{Address, AddressEncoder, AddressEncoderError},
+            ergo_box::box_value::BoxValue,
+        },
         serialization::SigmaParsingError,
     },
     wallet::{
@@ -22,9 +25,9 @@ use crate::{
     box_kind::{
         make_collected_oracle_box_candidate, make_oracle_box_candidate, OracleBox, OracleBoxWrapper,
     },
+    context::OracleContext,
     explorer_api::ergo_explorer_transaction_link,
     node_interface::{SignTransaction, SubmitTransaction},
-    oracle_config::BASE_FEE,
     oracle_state::{DataSourceError, LocalDatapointBoxSource},
     oracle_types::BlockHeight,
     wallet::{WalletDataError, WalletDataSource},
@@ -64,6 +67,7 @@ pub enum TransferOracleTokenActionError {
 }
 
 pub fn transfer_oracle_token(
+    ctx: &OracleContext,
     wallet: &dyn WalletDataSource,
     tx_signer: &dyn SignTransaction,
     tx_submit: &dyn SubmitTransaction,
@@ -83,6 +87,7 @@ pub fn transfer_oracle_token(
         rewards_destination.address(),
         height,
         change_address,
+        ctx.base_fee(),
     )?;
 
     println!(
@@ -94,10 +99,10 @@ pub fn transfer_oracle_token(
     if input.trim() == "YES" {
         let signed_tx = tx_signer.sign_transaction(&unsigned_tx)?;
         let tx_id = tx_submit.submit_transaction(&signed_tx)?;
-        crate::explorer_api::wait_for_tx_confirmation(signed_tx.id());
+        crate::explorer_api::wait_for_tx_confirmation(&ctx.oracle_config, signed_tx.id());
         println!(
             "Transaction made. Check status here: {}",
-            ergo_explorer_transaction_link(tx_id, network_prefix)
+            ergo_explorer_transaction_link(&ctx.oracle_config, tx_id, network_prefix)
         );
     } else {
         println!("Aborting the transaction.")
@@ -110,6 +115,7 @@ fn build_transfer_oracle_token_tx(
     oracle_token_destination: Address,
     height: BlockHeight,
     change_address: Address,
+    tx_fee: BoxValue,
 ) -> Result<UnsignedTransaction, TransferOracleTokenActionError> {
     let in_oracle_box = local_datapoint_box_source
         .get_local_oracle_datapoint_box()?
@@ -148,7 +154,7 @@ fn build_transfer_oracle_token_tx(
 
         let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
 
-        let target_balance = *BASE_FEE;
+        let target_balance = tx_fee;
 
         let box_selector = SimpleBoxSelector::new();
         let selection = box_selector.select(unspent_boxes, target_balance, &[])?;
@@ -194,6 +200,7 @@ mod tests {
     use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
     use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
     use ergo_lib::wallet::signing::TransactionContext;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
     use ergo_lib::wallet::Wallet;
     use sigma_test_util::force_any_val;
 
@@ -215,7 +222,7 @@ mod tests {
                 200,
                 EpochCounter(1),
                 &token_ids,
-                BASE_FEE.checked_mul_u32(100).unwrap(),
+                SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
                 BlockHeight(height.0) - 9,
                 1,
             ),
@@ -231,7 +238,7 @@ mod tests {
 
         let wallet_unspent_box = make_wallet_unspent_box(
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(10000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(10000).unwrap(),
             None,
         );
         let wallet_mock = WalletDataMock {
@@ -244,6 +251,7 @@ mod tests {
             change_address.address(),
             height,
             change_address.address(),
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
@@ -8,7 +8,7 @@ use ergo_lib::{
     ergotree_interpreter::sigma_protocol::prover::ContextExtension,
     ergotree_ir::chain::{
         address::Address,
-        ergo_box::{ErgoBox, NonMandatoryRegisterId},
+        ergo_box::{box_value::BoxValue, ErgoBox, NonMandatoryRegisterId},
     },
     ergotree_ir::serialization::SigmaSerializable,
     wallet::{
@@ -26,15 +26,15 @@ use crate::{
         make_pool_box_candidate_unchecked, BallotBox, CastBallotBoxVoteParameters, PoolBox,
         PoolBoxWrapper, VoteBallotBoxWrapper,
     },
+    context::OracleContext,
     contracts::pool::PoolContract,
     explorer_api::ergo_explorer_transaction_link,
-    node_interface::{SignTransaction, SubmitTransaction},
-    oracle_config::BASE_FEE,
+    node_interface::{SignTransaction, SubmitTransaction, VerifyTransaction},
     oracle_state::{
         DataSourceError, OraclePool, PoolBoxSource, UpdateBoxSource, VoteBallotBoxesSource,
     },
     oracle_types::BlockHeight,
-    pool_config::{PoolConfig, POOL_CONFIG},
+    pool_config::PoolConfig,
     spec_token::{RewardTokenId, SpecToken, TokenIdKind},
     wallet::{WalletDataError, WalletDataSource},
 };
@@ -73,8 +73,10 @@ pub enum UpdatePoolError {
 }
 
 pub fn update_pool(
+    ctx: &OracleContext,
     op: &OraclePool,
     wallet: &dyn WalletDataSource,
+    tx_verifier: &dyn VerifyTransaction,
     tx_signer: &dyn SignTransaction,
     tx_submit: &dyn SubmitTransaction,
     new_reward_tokens: Option<SpecToken<RewardTokenId>>,
@@ -105,7 +107,7 @@ pub fn update_pool(
     );
 
     display_update_diff(
-        &POOL_CONFIG,
+        op.pool_config(),
         &new_pool_config,
         op.get_pool_box_source().get_pool_box()?,
         new_reward_tokens.clone(),
@@ -120,8 +122,10 @@ pub fn update_pool(
         height,
         change_address,
         new_pool_contract,
+        ctx.base_fee(),
     )?;
 
+    tx_verifier.verify_transaction(&tx.spending_tx, op.pool_config())?;
     log::debug!("Signing update pool box tx: {:#?}", tx);
     let signed_tx = tx_signer.sign_transaction(&tx.spending_tx)?;
 
@@ -143,14 +147,14 @@ pub fn update_pool(
     std::io::stdin().read_line(&mut input)?;
     if input.trim_end() == "YES" {
         let tx_id_str = tx_submit.submit_transaction(&signed_tx)?;
-        crate::explorer_api::wait_for_tx_confirmation(signed_tx.id());
+        crate::explorer_api::wait_for_tx_confirmation(&ctx.oracle_config, signed_tx.id());
         println!(
             "Update pool box transaction submitted: view here, {}",
-            ergo_explorer_transaction_link(tx_id_str, network_prefix)
+            ergo_explorer_transaction_link(&ctx.oracle_config, tx_id_str, network_prefix)
         );
         println!("Send the new pool_config_updated.yaml to the oracle operators.");
         println!("The operators should import it with `import-pool-update` command.");
-        remind_send_minted_tokens_to_oracles(&POOL_CONFIG, &new_pool_config);
+        remind_send_minted_tokens_to_oracles(op.pool_config(), &new_pool_config);
     } else {
         println!("Aborting the transaction.")
     }
@@ -273,6 +277,7 @@ fn build_update_pool_box_tx(
     height: BlockHeight,
     change_address: Address,
     new_pool_contract: PoolContract,
+    tx_fee: BoxValue,
 ) -> Result<TransactionContext<UnsignedTransaction>, UpdatePoolError> {
     let update_box = update_box.get_update_box()?;
     let min_votes = update_box.min_votes();
@@ -357,7 +362,7 @@ fn build_update_pool_box_tx(
         return Err(UpdatePoolError::NoUsableWalletBoxes);
     }
 
-    let target_balance = *BASE_FEE;
+    let target_balance = tx_fee;
     let target_tokens =
         if reward_tokens.token_id.token_id() != old_pool_box.reward_token().token_id() {
             vec![reward_tokens.clone().into()]
@@ -398,7 +403,7 @@ fn build_update_pool_box_tx(
         box_selection.clone(),
         outputs.clone(),
         height.0,
-        *BASE_FEE,
+        tx_fee,
         change_address,
     );
 
@@ -439,7 +444,7 @@ mod tests {
             },
             serialization::SigmaSerializable,
         },
-        wallet::Wallet,
+        wallet::{tx_builder::SUGGESTED_TX_FEE, Wallet},
     };
     use sigma_test_util::force_any_val;
     use std::convert::TryInto;
@@ -454,7 +459,6 @@ mod tests {
             pool::{PoolContract, PoolContractInputs},
             update::{UpdateContract, UpdateContractInputs, UpdateContractParameters},
         },
-        oracle_config::BASE_FEE,
         oracle_types::{BlockHeight, EpochCounter},
         pool_commands::test_utils::{
             generate_token_ids, make_wallet_unspent_box, BallotBoxesMock, PoolBoxMock,
@@ -509,7 +513,7 @@ mod tests {
         .unwrap();
         let update_contract = UpdateContract::checked_load(&update_contract_inputs).unwrap();
         let mut update_box_candidate =
-            ErgoBoxCandidateBuilder::new(*BASE_FEE, update_contract.ergo_tree(), height.0);
+            ErgoBoxCandidateBuilder::new(SUGGESTED_TX_FEE(), update_contract.ergo_tree(), height.0);
         update_box_candidate.add_token(Token {
             token_id: token_ids.update_nft_token_id.token_id(),
             amount: 1.try_into().unwrap(),
@@ -539,7 +543,7 @@ mod tests {
                 amount: 1.try_into().unwrap(),
             },
             reward_tokens.clone(),
-            *BASE_FEE,
+            SUGGESTED_TX_FEE(),
             height,
         )
         .unwrap();
@@ -605,7 +609,7 @@ mod tests {
         let wallet_unspent_box = make_wallet_unspent_box(
             // create a wallet box with new reward tokens
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(4_000_000_000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(4_000_000_000).unwrap(),
             Some(vec![new_reward_tokens.clone().into()].try_into().unwrap()),
         );
         let change_address = AddressEncoder::unchecked_parse_network_address_from_str(
@@ -648,6 +652,7 @@ mod tests {
             BlockHeight(height.0 + 1),
             change_address.address(),
             new_pool_contract,
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
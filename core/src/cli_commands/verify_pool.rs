@@ -0,0 +1,498 @@
+//! Checks that the live pool boxes match the pool config: the ErgoTrees of the pool, refresh,
+//! update, oracle and ballot boxes against the contract parameters, the tokens in the boxes
+//! against the token ids and the token id constants of the contracts (e.g. `refresh_nft_index`)
+//! against the tokens they must point at, and the total amounts of the oracle and ballot tokens
+//! against the amounts minted for the pool.
+use std::fmt;
+
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::ergotree_ir::mir::constant::TryExtractInto;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+
+use crate::oracle_state::DataSourceError;
+use crate::oracle_state::OraclePool;
+use crate::pool_config::PoolConfig;
+use crate::recording::BoxSnapshot;
+use crate::spec_token::TokenIdKind;
+
+/// A check of the report, failed if any mismatch is found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolCheck {
+    pub name: String,
+    pub mismatches: Vec<String>,
+}
+
+impl PoolCheck {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PoolVerificationReport {
+    pub checks: Vec<PoolCheck>,
+}
+
+impl PoolVerificationReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(PoolCheck::passed)
+    }
+
+    pub fn failed_checks(&self) -> impl Iterator<Item = &PoolCheck> {
+        self.checks.iter().filter(|check| !check.passed())
+    }
+
+    fn check(&mut self, name: String, mismatches: Vec<String>) {
+        self.checks.push(PoolCheck { name, mismatches });
+    }
+}
+
+impl fmt::Display for PoolVerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed() { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}", status, check.name)?;
+            for mismatch in &check.mismatches {
+                writeln!(f, "    - {}", mismatch)?;
+            }
+        }
+        let failed = self.failed_checks().count();
+        if failed == 0 {
+            writeln!(f, "All {} checks passed", self.checks.len())
+        } else {
+            writeln!(f, "{} of {} checks failed", failed, self.checks.len())
+        }
+    }
+}
+
+/// What the boxes of a kind must look like according to the pool config
+struct ExpectedBoxes {
+    kind: &'static str,
+    ergo_tree_bytes: Vec<u8>,
+    /// Name and ID of the tokens in the order they are in the box, with the amount if it is fixed
+    tokens: Vec<(&'static str, TokenId, Option<u64>)>,
+    /// Name and value of the constant index in the contract parameters, with the name and ID of
+    /// the token the constant must hold
+    token_constants: Vec<(&'static str, usize, &'static str, TokenId)>,
+}
+
+/// Verifies the boxes of the pool at the current height
+pub fn verify_pool(op: &OraclePool) -> Result<PoolVerificationReport, DataSourceError> {
+    let height = op.current_height()?;
+    Ok(verify_pool_boxes(
+        op.pool_config(),
+        &op.box_snapshot(height)?,
+    ))
+}
+
+/// Verifies the boxes in the snapshot against the pool config
+pub fn verify_pool_boxes(
+    pool_config: &PoolConfig,
+    snapshot: &BoxSnapshot,
+) -> PoolVerificationReport {
+    let token_ids = &pool_config.token_ids;
+    let pool_nft = token_ids.pool_nft_token_id.token_id();
+    let refresh_nft = token_ids.refresh_nft_token_id.token_id();
+    let update_nft = token_ids.update_nft_token_id.token_id();
+    let oracle_token = token_ids.oracle_token_id.token_id();
+    let reward_token = token_ids.reward_token_id.token_id();
+    let ballot_token = token_ids.ballot_token_id.token_id();
+    let pool_parameters = pool_config
+        .pool_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let refresh_parameters = pool_config
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let update_parameters = pool_config
+        .update_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let oracle_parameters = pool_config
+        .oracle_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let ballot_parameters = pool_config
+        .ballot_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters();
+    let mut report = PoolVerificationReport::default();
+    verify_nft_box(
+        &mut report,
+        snapshot.boxes(&String::from(pool_nft)),
+        &ExpectedBoxes {
+            kind: "pool box",
+            ergo_tree_bytes: pool_parameters.ergo_tree_bytes(),
+            tokens: vec![
+                ("pool NFT", pool_nft, Some(1)),
+                ("reward token", reward_token, None),
+            ],
+            token_constants: vec![
+                (
+                    "refresh_nft_index",
+                    pool_parameters.refresh_nft_index(),
+                    "refresh NFT",
+                    refresh_nft,
+                ),
+                (
+                    "update_nft_index",
+                    pool_parameters.update_nft_index(),
+                    "update NFT",
+                    update_nft,
+                ),
+            ],
+        },
+    );
+    verify_nft_box(
+        &mut report,
+        snapshot.boxes(&String::from(refresh_nft)),
+        &ExpectedBoxes {
+            kind: "refresh box",
+            ergo_tree_bytes: refresh_parameters.ergo_tree_bytes(),
+            tokens: vec![("refresh NFT", refresh_nft, Some(1))],
+            token_constants: vec![
+                (
+                    "pool_nft_index",
+                    refresh_parameters.pool_nft_index(),
+                    "pool NFT",
+                    pool_nft,
+                ),
+                (
+                    "oracle_token_id_index",
+                    refresh_parameters.oracle_token_id_index(),
+                    "oracle token",
+                    oracle_token,
+                ),
+            ],
+        },
+    );
+    verify_nft_box(
+        &mut report,
+        snapshot.boxes(&String::from(update_nft)),
+        &ExpectedBoxes {
+            kind: "update box",
+            ergo_tree_bytes: update_parameters.ergo_tree_bytes(),
+            tokens: vec![("update NFT", update_nft, Some(1))],
+            token_constants: vec![
+                (
+                    "pool_nft_index",
+                    update_parameters.pool_nft_index(),
+                    "pool NFT",
+                    pool_nft,
+                ),
+                (
+                    "ballot_token_index",
+                    update_parameters.ballot_token_index(),
+                    "ballot token",
+                    ballot_token,
+                ),
+            ],
+        },
+    );
+    verify_token_boxes(
+        &mut report,
+        snapshot.boxes(&String::from(oracle_token)),
+        &ExpectedBoxes {
+            kind: "oracle boxes",
+            ergo_tree_bytes: oracle_parameters.ergo_tree_bytes(),
+            tokens: vec![
+                ("oracle token", oracle_token, Some(1)),
+                ("reward token", reward_token, None),
+            ],
+            token_constants: vec![(
+                "pool_nft_index",
+                oracle_parameters.pool_nft_index,
+                "pool NFT",
+                pool_nft,
+            )],
+        },
+    );
+    verify_token_boxes(
+        &mut report,
+        snapshot.boxes(&String::from(ballot_token)),
+        &ExpectedBoxes {
+            kind: "ballot boxes",
+            ergo_tree_bytes: ballot_parameters.ergo_tree_bytes(),
+            tokens: vec![("ballot token", ballot_token, None)],
+            token_constants: vec![(
+                "update_nft_index",
+                ballot_parameters.update_nft_index(),
+                "update NFT",
+                update_nft,
+            )],
+        },
+    );
+    verify_token_emission(
+        &mut report,
+        "oracle tokens",
+        snapshot.boxes(&String::from(oracle_token)),
+        oracle_token,
+        pool_config.oracle_tokens_emission,
+    );
+    verify_token_emission(
+        &mut report,
+        "ballot tokens",
+        snapshot.boxes(&String::from(ballot_token)),
+        ballot_token,
+        pool_config.ballot_tokens_emission,
+    );
+    report
+}
+
+/// The box holding the NFT must be the only one
+fn verify_nft_box(
+    report: &mut PoolVerificationReport,
+    boxes: Vec<ErgoBox>,
+    expected: &ExpectedBoxes,
+) {
+    let nft_name = expected.tokens[0].0;
+    let nft_id = String::from(expected.tokens[0].1);
+    match boxes.len() {
+        0 => report.check(
+            format!("{}: found", expected.kind),
+            vec![format!("no box holds the {} {}", nft_name, nft_id)],
+        ),
+        1 => verify_boxes(report, expected.kind.to_string(), &boxes, expected),
+        count => report.check(
+            format!("{}: found", expected.kind),
+            vec![format!(
+                "{} boxes hold the {} {}: {}",
+                count,
+                nft_name,
+                nft_id,
+                box_ids(&boxes)
+            )],
+        ),
+    }
+}
+
+/// The boxes holding the token in a wallet (e.g. the oracle token before the first datapoint) are
+/// not checked
+fn verify_token_boxes(
+    report: &mut PoolVerificationReport,
+    boxes: Vec<ErgoBox>,
+    expected: &ExpectedBoxes,
+) {
+    let (wallet_boxes, contract_boxes): (Vec<ErgoBox>, Vec<ErgoBox>) =
+        boxes.into_iter().partition(|b| {
+            matches!(
+                Address::recreate_from_ergo_tree(&b.ergo_tree),
+                Ok(Address::P2Pk(_))
+            )
+        });
+    let kind = format!(
+        "{} ({} in contracts, {} in wallets)",
+        expected.kind,
+        contract_boxes.len(),
+        wallet_boxes.len()
+    );
+    verify_boxes(report, kind, &contract_boxes, expected);
+}
+
+/// The boxes holding the token (in contracts and in wallets) must hold all the minted tokens,
+/// only the total is reported if the pool config doesn't have the minted amount
+fn verify_token_emission(
+    report: &mut PoolVerificationReport,
+    name: &str,
+    boxes: Vec<ErgoBox>,
+    token_id: TokenId,
+    emission: Option<u64>,
+) {
+    let total: u64 = boxes
+        .iter()
+        .flat_map(|b| {
+            b.tokens
+                .as_ref()
+                .map(|tokens| tokens.as_vec().clone())
+                .unwrap_or_default()
+        })
+        .filter(|token| token.token_id == token_id)
+        .map(|token| *token.amount.as_u64())
+        .sum();
+    match emission {
+        Some(emission) => report.check(
+            format!("{}: total {} minted", name, emission),
+            if total == emission {
+                vec![]
+            } else {
+                vec![format!(
+                    "the boxes hold {} {} {}, expected {}",
+                    total,
+                    name,
+                    String::from(token_id),
+                    emission
+                )]
+            },
+        ),
+        None => report.check(
+            format!(
+                "{}: total {} (the minted amount is not in the pool config)",
+                name, total
+            ),
+            vec![],
+        ),
+    }
+}
+
+fn verify_boxes(
+    report: &mut PoolVerificationReport,
+    kind: String,
+    boxes: &[ErgoBox],
+    expected: &ExpectedBoxes,
+) {
+    let expected_tree_bytes = &expected.ergo_tree_bytes;
+    report.check(
+        format!("{}: contract", kind),
+        boxes
+            .iter()
+            .filter_map(|b| contract_mismatch(b, expected_tree_bytes))
+            .collect(),
+    );
+    report.check(
+        format!("{}: tokens", kind),
+        boxes
+            .iter()
+            .flat_map(|b| token_mismatches(b, &expected.tokens))
+            .collect(),
+    );
+    report.check(
+        format!("{}: token id constants", kind),
+        boxes
+            .iter()
+            .flat_map(|b| token_constant_mismatches(b, &expected.token_constants))
+            .collect(),
+    );
+}
+
+/// The ErgoTree of the box differs from the one in the contract parameters, the differing
+/// constants are listed if the trees have the same number of them
+fn contract_mismatch(b: &ErgoBox, expected_tree_bytes: &[u8]) -> Option<String> {
+    let tree_bytes = b.ergo_tree.sigma_serialize_bytes().unwrap_or_default();
+    if tree_bytes == expected_tree_bytes {
+        return None;
+    }
+    let box_id = String::from(b.box_id());
+    let details = match (
+        b.ergo_tree.get_constants(),
+        ErgoTree::sigma_parse_bytes(expected_tree_bytes).map(|tree| tree.get_constants()),
+    ) {
+        (Ok(constants), Ok(Ok(expected_constants)))
+            if constants.len() == expected_constants.len() =>
+        {
+            let differing: Vec<String> = constants
+                .iter()
+                .zip(expected_constants.iter())
+                .enumerate()
+                .filter(|(_, (constant, expected))| constant != expected)
+                .map(|(i, (constant, expected))| {
+                    format!("constant {} is {:?}, expected {:?}", i, constant, expected)
+                })
+                .collect();
+            if differing.is_empty() {
+                "the script differs".to_string()
+            } else {
+                differing.join("; ")
+            }
+        }
+        _ => "the script differs".to_string(),
+    };
+    Some(format!(
+        "box {}: ErgoTree {} doesn't match the contract parameters {} ({})",
+        box_id,
+        base16::encode_lower(&tree_bytes),
+        base16::encode_lower(expected_tree_bytes),
+        details
+    ))
+}
+
+fn token_mismatches(b: &ErgoBox, expected: &[(&'static str, TokenId, Option<u64>)]) -> Vec<String> {
+    let box_id = String::from(b.box_id());
+    let tokens = b
+        .tokens
+        .as_ref()
+        .map(|tokens| tokens.as_vec().clone())
+        .unwrap_or_default();
+    expected
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (name, token_id, amount))| match tokens.get(i) {
+            None => Some(format!(
+                "box {}: no tokens({}), expected the {} {}",
+                box_id,
+                i,
+                name,
+                String::from(*token_id)
+            )),
+            Some(token) if token.token_id != *token_id => Some(format!(
+                "box {}: tokens({}) is {}, expected the {} {}",
+                box_id,
+                i,
+                String::from(token.token_id),
+                name,
+                String::from(*token_id)
+            )),
+            Some(token) => match amount {
+                Some(amount) if *token.amount.as_u64() != *amount => Some(format!(
+                    "box {}: {} amount of the {}, expected {}",
+                    box_id,
+                    token.amount.as_u64(),
+                    name,
+                    amount
+                )),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+fn token_constant_mismatches(
+    b: &ErgoBox,
+    expected: &[(&'static str, usize, &'static str, TokenId)],
+) -> Vec<String> {
+    let box_id = String::from(b.box_id());
+    expected
+        .iter()
+        .filter_map(|(index_name, index, token_name, token_id)| {
+            match token_id_constant(&b.ergo_tree, *index) {
+                Some(actual) if actual == *token_id => None,
+                Some(actual) => Some(format!(
+                    "box {}: constant {} ({}) is {}, expected the {} {}",
+                    box_id,
+                    index,
+                    index_name,
+                    String::from(actual),
+                    token_name,
+                    String::from(*token_id)
+                )),
+                None => Some(format!(
+                    "box {}: constant {} ({}) is not a token id, expected the {} {}",
+                    box_id,
+                    index,
+                    index_name,
+                    token_name,
+                    String::from(*token_id)
+                )),
+            }
+        })
+        .collect()
+}
+
+fn token_id_constant(ergo_tree: &ErgoTree, index: usize) -> Option<TokenId> {
+    ergo_tree
+        .get_constant(index)
+        .ok()
+        .flatten()
+        .and_then(|constant| constant.try_extract_into::<TokenId>().ok())
+}
+
+fn box_ids(boxes: &[ErgoBox]) -> String {
+    boxes
+        .iter()
+        .map(|b| String::from(b.box_id()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -7,7 +7,7 @@ use ergo_lib::{
     },
     ergo_chain_types::{Digest32, DigestNError, EcPoint},
     ergotree_interpreter::sigma_protocol::prover::ContextExtension,
-    ergotree_ir::chain::address::Address,
+    ergotree_ir::chain::{address::Address, ergo_box::box_value::BoxValue},
     wallet::{
         box_selector::{BoxSelection, BoxSelector, BoxSelectorError, SimpleBoxSelector},
         tx_builder::{TxBuilder, TxBuilderError},
@@ -17,15 +17,15 @@ use ergo_node_interface::node_interface::NodeError;
 
 use crate::{
     box_kind::{make_local_ballot_box_candidate, BallotBox, BallotBoxWrapper},
+    context::OracleContext,
     contracts::ballot::{
         BallotContract, BallotContractError, BallotContractInputs, BallotContractParameters,
     },
     explorer_api::ergo_explorer_transaction_link,
-    node_interface::{SignTransaction, SubmitTransaction},
-    oracle_config::{BASE_FEE, ORACLE_CONFIG},
+    node_interface::{SignTransaction, SubmitTransaction, VerifyTransaction},
     oracle_state::{DataSourceError, LocalBallotBoxSource},
     oracle_types::BlockHeight,
-    pool_config::{TokenIds, POOL_CONFIG},
+    pool_config::{PoolConfig, TokenIds},
     spec_token::{RewardTokenId, SpecToken, TokenIdKind},
     wallet::{WalletDataError, WalletDataSource},
 };
@@ -59,7 +59,10 @@ pub enum VoteUpdatePoolError {
 
 #[allow(clippy::too_many_arguments)]
 pub fn vote_update_pool(
+    ctx: &OracleContext,
+    pool_config: &PoolConfig,
     wallet: &dyn WalletDataSource,
+    tx_verifier: &dyn VerifyTransaction,
     tx_signer: &dyn SignTransaction,
     tx_submit: &dyn SubmitTransaction,
     local_ballot_box_source: &dyn LocalBallotBoxSource,
@@ -73,7 +76,7 @@ pub fn vote_update_pool(
     let network_prefix = change_network_address.network();
     let new_pool_box_address_hash = Digest32::try_from(new_pool_box_address_hash_str)?;
     let ballot_token_owner =
-        if let Address::P2Pk(ballot_token_owner) = ORACLE_CONFIG.oracle_address.address() {
+        if let Address::P2Pk(ballot_token_owner) = ctx.oracle_config.oracle_address.address() {
             ballot_token_owner.h
         } else {
             return Err(VoteUpdatePoolError::IncorrectBallotTokenOwnerAddress.into());
@@ -92,6 +95,7 @@ pub fn vote_update_pool(
             height,
             change_network_address.address(),
             ballot_token_owner.as_ref(),
+            ctx.base_fee(),
         )?
     } else {
         log::debug!("Not found local ballot box, looking for a ballot token in the wallet");
@@ -103,15 +107,17 @@ pub fn vote_update_pool(
             reward_token_opt.clone(),
             update_box_creation_height,
             ballot_token_owner.as_ref(),
-            POOL_CONFIG
+            pool_config
                 .ballot_box_wrapper_inputs
                 .contract_inputs
                 .contract_parameters(),
-            &POOL_CONFIG.token_ids,
+            &pool_config.token_ids,
             height,
             change_network_address.address(),
+            ctx.base_fee(),
         )?
     };
+    tx_verifier.verify_transaction(&unsigned_tx, pool_config)?;
     println!(
         "YOU WILL BE CASTING A VOTE FOR THE FOLLOWING ITEMS:\
            - Hash of new pool box contract: {}",
@@ -139,10 +145,10 @@ pub fn vote_update_pool(
             &serde_json::to_string_pretty(&signed_tx)
         );
         let tx_id_str = tx_submit.submit_transaction(&signed_tx)?;
-        crate::explorer_api::wait_for_tx_confirmation(signed_tx.id());
+        crate::explorer_api::wait_for_tx_confirmation(&ctx.oracle_config, signed_tx.id());
         println!(
             "Transaction made. Check status here: {}",
-            ergo_explorer_transaction_link(tx_id_str, network_prefix)
+            ergo_explorer_transaction_link(&ctx.oracle_config, tx_id_str, network_prefix)
         );
     } else {
         println!("Aborting the transaction.")
@@ -161,6 +167,7 @@ fn build_tx_with_existing_ballot_box(
     height: BlockHeight,
     change_address: Address,
     ballot_token_owner_pk: &EcPoint,
+    tx_fee: BoxValue,
 ) -> Result<UnsignedTransaction, VoteUpdatePoolError> {
     let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
     #[allow(clippy::todo)]
@@ -175,7 +182,7 @@ fn build_tx_with_existing_ballot_box(
         height,
     )?;
     let box_selector = SimpleBoxSelector::new();
-    let selection = box_selector.select(unspent_boxes, *BASE_FEE, &[])?;
+    let selection = box_selector.select(unspent_boxes, tx_fee, &[])?;
     let mut input_boxes = vec![in_ballot_box.get_box().clone()];
     input_boxes.append(selection.boxes.as_vec().clone().as_mut());
     let box_selection = BoxSelection {
@@ -186,7 +193,7 @@ fn build_tx_with_existing_ballot_box(
         box_selection,
         vec![ballot_box_candidate],
         height.0,
-        *BASE_FEE,
+        tx_fee,
         change_address,
     );
     // The following context value ensures that `outIndex` in the ballot contract is properly set.
@@ -209,6 +216,7 @@ fn build_tx_for_first_ballot_box(
     token_ids: &TokenIds,
     height: BlockHeight,
     change_address: Address,
+    tx_fee: BoxValue,
 ) -> Result<UnsignedTransaction, VoteUpdatePoolError> {
     let unspent_boxes = wallet.get_unspent_wallet_boxes()?;
     let out_ballot_box_value = ballot_contract_parameters.min_storage_rent();
@@ -232,7 +240,7 @@ fn build_tx_for_first_ballot_box(
         height,
     )?;
     let box_selector = SimpleBoxSelector::new();
-    let selection_target_balance = out_ballot_box_value.checked_add(&BASE_FEE).unwrap();
+    let selection_target_balance = out_ballot_box_value.checked_add(&tx_fee).unwrap();
     let selection = box_selector.select(
         unspent_boxes,
         selection_target_balance,
@@ -246,7 +254,7 @@ fn build_tx_for_first_ballot_box(
         box_selection,
         vec![ballot_box_candidate],
         height.0,
-        *BASE_FEE,
+        tx_fee,
         change_address,
     );
     // The following context value ensures that `outIndex` in the ballot contract is properly set.
@@ -271,14 +279,13 @@ mod tests {
             ergo_box::{box_value::BoxValue, BoxTokens, ErgoBox},
             token::{Token, TokenId},
         },
-        wallet::{signing::TransactionContext, Wallet},
+        wallet::{signing::TransactionContext, tx_builder::SUGGESTED_TX_FEE, Wallet},
     };
     use sigma_test_util::force_any_val;
 
     use crate::{
         box_kind::{make_local_ballot_box_candidate, BallotBoxWrapper, BallotBoxWrapperInputs},
         contracts::ballot::{BallotContract, BallotContractInputs, BallotContractParameters},
-        oracle_config::BASE_FEE,
         oracle_types::{BlockHeight, EpochLength},
         pool_commands::test_utils::{
             find_input_boxes, generate_token_ids, make_wallet_unspent_box, WalletDataMock,
@@ -315,7 +322,7 @@ mod tests {
         };
         let wallet_unspent_box = make_wallet_unspent_box(
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(100_000_000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(100_000_000).unwrap(),
             Some(BoxTokens::from_vec(vec![ballot_token]).unwrap()),
         );
         let wallet_mock = WalletDataMock {
@@ -344,6 +351,7 @@ mod tests {
             &token_ids,
             height,
             change_address.address(),
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
@@ -407,7 +415,7 @@ mod tests {
         let ballot_box = BallotBoxWrapper::new(in_ballot_box.clone(), &inputs).unwrap();
         let wallet_unspent_box = make_wallet_unspent_box(
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(100_000_000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(100_000_000).unwrap(),
             None,
         );
         let wallet_mock = WalletDataMock {
@@ -427,6 +435,7 @@ mod tests {
             height,
             change_address.address(),
             secret.public_image().h.as_ref(),
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
@@ -0,0 +1,622 @@
+//! Reloading of the oracle and pool configs of a running oracle. A reload is requested with
+//! SIGHUP (on Unix) or by changing one of the config files. The new configs are loaded and
+//! validated first and the running pools are replaced only if all of them are started with the
+//! new configs, otherwise the oracle keeps running with the old ones.
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use serde::Serialize;
+
+use crate::context::OracleContext;
+use crate::node_interface::node_api::NodeApi;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_config::OracleSecrets;
+use crate::pool_config::PoolConfig;
+use crate::running_pool::check_box_source;
+use crate::running_pool::deregister_backup_node_scans;
+use crate::running_pool::load_pool_configs;
+use crate::running_pool::pool_datapoint_source;
+use crate::running_pool::pool_scans_file_path;
+use crate::running_pool::PoolSpec;
+use crate::running_pool::RunningPool;
+use crate::scans::NodeScanRegistry;
+use crate::wallet::OracleWallet;
+
+/// The config files the running oracle was started with
+#[derive(Debug, Clone)]
+pub struct ConfigFiles {
+    pub oracle_config_path: PathBuf,
+    pub pools: Vec<PoolSpec>,
+}
+
+impl ConfigFiles {
+    fn paths(&self) -> Vec<&Path> {
+        std::iter::once(self.oracle_config_path.as_path())
+            .chain(self.pools.iter().map(|p| p.config_file_path.as_path()))
+            .collect()
+    }
+}
+
+/// Detects changes of the config files by their modification time
+#[derive(Debug)]
+pub struct ConfigFilesWatcher {
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigFilesWatcher {
+    pub fn new(files: &ConfigFiles) -> Self {
+        Self {
+            modified: files
+                .paths()
+                .into_iter()
+                .map(|path| (path.to_path_buf(), modified_time(path)))
+                .collect(),
+        }
+    }
+
+    /// Returns true if any of the files was modified, created or removed since the last call
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in self.modified.iter_mut() {
+            let modified = modified_time(path);
+            if modified != *last_modified {
+                log::debug!("{} is changed", path.display());
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Set by the signal handler, checked by the main loop before each iteration
+#[derive(Debug, Clone, Default)]
+pub struct ReloadRequest {
+    requested: Arc<AtomicBool>,
+}
+
+impl ReloadRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if a reload was requested since the last call
+    pub fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Requests a reload on every SIGHUP. Does nothing on platforms without SIGHUP.
+pub async fn listen_for_reload_signals(reload_request: ReloadRequest) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::signal;
+        use tokio::signal::unix::SignalKind;
+        let mut sighup = signal(SignalKind::hangup())?;
+        while sighup.recv().await.is_some() {
+            log::info!("SIGHUP received, reloading the config");
+            reload_request.request();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = reload_request;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ReloadTrigger {
+    Signal,
+    FileChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ConfigReloadOutcome {
+    /// The new config is in use
+    Applied { changes: Vec<String> },
+    /// The config files have no changes affecting the running oracle
+    Unchanged,
+    /// The new config is invalid or the pools failed to start with it, the old config is in use
+    Rejected { error: String },
+}
+
+/// Outcome of the last config reload, served by the /configReload REST API endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigReloadReport {
+    /// Unix time (in seconds) of the reload
+    pub timestamp: u64,
+    pub trigger: ReloadTrigger,
+    pub outcome: ConfigReloadOutcome,
+}
+
+impl ConfigReloadReport {
+    pub fn new(trigger: ReloadTrigger, outcome: ConfigReloadOutcome) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            trigger,
+            outcome,
+        }
+    }
+}
+
+/// The oracle context and the pools started with the new config
+pub struct ReloadedConfig {
+    pub ctx: Arc<OracleContext>,
+    pub running_pools: Vec<RunningPool>,
+    pub wallet: OracleWallet,
+    pub change_address: NetworkAddress,
+    pub changes: Vec<String>,
+}
+
+/// Loads the configs from `files` and starts the pools with them. Scans of the pools whose token
+/// ids changed are registered anew, the old scans are deregistered only after all the pools are
+/// started. If any pool fails to start, the running pools are left untouched and the error is
+/// returned. Returns None if there is nothing to change.
+pub fn reload_config(
+    ctx: &OracleContext,
+    files: &ConfigFiles,
+    running_pools: &[RunningPool],
+    read_only: bool,
+    shadow: bool,
+) -> Result<Option<ReloadedConfig>, anyhow::Error> {
+    let oracle_config = OracleConfig::load_from_file(&files.oracle_config_path)?;
+    oracle_config.oracle_address_p2pk()?;
+    check_box_source(&oracle_config, read_only, shadow)?;
+    let secrets = if oracle_config.backup_nodes.len() > ctx.secrets.backup_node_api_keys.len() {
+        // the API keys of the added backup nodes
+        OracleSecrets::load(&oracle_config)?
+    } else {
+        ctx.secrets.clone()
+    };
+    let new_ctx = OracleContext::new(oracle_config, secrets, ctx.data_dir.clone());
+    let pool_configs = load_pool_configs(&files.pools)?;
+    for (name, pool_config) in &pool_configs {
+        pool_datapoint_source(&new_ctx, name, pool_config)
+            .with_context(|| format!("invalid datapoint source for pool {name}"))?;
+    }
+
+    let running_pool_configs: Vec<(String, PoolConfig)> = running_pools
+        .iter()
+        .map(|p| (p.name.clone(), p.oracle_pool.pool_config().clone()))
+        .collect();
+    let changes = config_changes(
+        &ctx.oracle_config,
+        &running_pool_configs,
+        &new_ctx.oracle_config,
+        &pool_configs,
+    );
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let node_api = new_ctx.node_api();
+    let wallet = new_ctx.wallet()?;
+    let change_address = new_ctx
+        .change_address(&wallet)
+        .context("failed to get change address from the wallet")?;
+    // The pools whose token ids changed register their new scans to a staged scans file, the old
+    // pool keeps running with its scans until all the new pools are started
+    let staged_scans_file = |name: &str, pool_config: &PoolConfig| -> Option<PathBuf> {
+        let token_ids_changed = running_pool_configs
+            .iter()
+            .any(|(old_name, old)| old_name == name && old.token_ids != pool_config.token_ids);
+        let scans_file_path = pool_scans_file_path(&new_ctx, name);
+        // import-pool-update removes the scans file itself
+        if token_ids_changed && scans_file_path.exists() {
+            Some(staged_scans_file_path(&scans_file_path))
+        } else {
+            None
+        }
+    };
+    let started_pools = start_all(
+        pool_configs,
+        |(name, pool_config)| {
+            let scans_file_path = staged_scans_file(name, pool_config)
+                .unwrap_or_else(|| pool_scans_file_path(&new_ctx, name));
+            RunningPool::start_with_scans_file(
+                &new_ctx,
+                name.clone(),
+                pool_config,
+                shadow,
+                scans_file_path,
+            )
+        },
+        |(name, pool_config)| {
+            if let Some(path) = staged_scans_file(name, pool_config) {
+                remove_scans(&node_api, &path);
+            }
+        },
+    )?;
+
+    let mut new_running_pools = vec![];
+    for ((name, pool_config), mut pool) in started_pools {
+        if staged_scans_file(&name, &pool_config).is_some() {
+            let scans_file_path = pool_scans_file_path(&new_ctx, &name);
+            log::info!("Token ids of pool {name} changed, deregistering its old scans");
+            remove_scans(&node_api, &scans_file_path);
+            deregister_backup_node_scans(&node_api, &scans_file_path);
+            match std::fs::rename(&pool.scans_file_path, &scans_file_path) {
+                Ok(()) => pool.scans_file_path = scans_file_path,
+                Err(e) => log::error!(
+                    "Failed to move {} to {}: {}",
+                    pool.scans_file_path.display(),
+                    scans_file_path.display(),
+                    e
+                ),
+            }
+        }
+        pool.ensure_backup_node_scans(&new_ctx, &node_api);
+        // keep the reports if the pool is the same
+        if let Some(old) = running_pools.iter().find(|p| {
+            p.name == pool.name
+                && p.oracle_pool.pool_config().token_ids.pool_nft_token_id
+                    == pool_config.token_ids.pool_nft_token_id
+        }) {
+            pool.action_report_storage = old.action_report_storage.clone();
+            pool.shadow_report_storage = old.shadow_report_storage.clone();
+        }
+        new_running_pools.push(pool);
+    }
+    Ok(Some(ReloadedConfig {
+        ctx: Arc::new(new_ctx),
+        running_pools: new_running_pools,
+        wallet,
+        change_address,
+        changes,
+    }))
+}
+
+/// Starts a pool for each of `configs`, or none of them. If a pool fails to start, `discard` is
+/// called for it and every pool started before it, and the error is returned.
+fn start_all<C, P>(
+    configs: Vec<C>,
+    mut start: impl FnMut(&C) -> Result<P, anyhow::Error>,
+    mut discard: impl FnMut(&C),
+) -> Result<Vec<(C, P)>, anyhow::Error> {
+    let mut started: Vec<(C, P)> = vec![];
+    for config in configs {
+        match start(&config) {
+            Ok(pool) => started.push((config, pool)),
+            Err(e) => {
+                discard(&config);
+                for (config, _) in started.iter().rev() {
+                    discard(config);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(started)
+}
+
+/// Scans file the new scans of a pool are registered to while the old pool is running
+fn staged_scans_file_path(scans_file_path: &Path) -> PathBuf {
+    let mut file_name = scans_file_path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    file_name.push(".new");
+    scans_file_path.with_file_name(file_name)
+}
+
+/// Deregisters the scans kept in the scans file from the primary node and removes the file. The
+/// scans of a node which can't be reached are left behind.
+fn remove_scans(node_api: &NodeApi, scans_file_path: &Path) {
+    if !scans_file_path.exists() {
+        return;
+    }
+    let res = NodeScanRegistry::load_from_file(scans_file_path)
+        .and_then(|registry| Ok(registry.deregister_all_scans(node_api)?));
+    if let Err(e) = res {
+        log::warn!(
+            "Failed to deregister the scans of {}: {:?}",
+            scans_file_path.display(),
+            e
+        );
+    }
+    if let Err(e) = std::fs::remove_file(scans_file_path) {
+        log::warn!("Failed to remove {}: {}", scans_file_path.display(), e);
+    }
+}
+
+/// Human readable list of the differences between the running and the new configs
+fn config_changes(
+    oracle_config: &OracleConfig,
+    pool_configs: &[(String, PoolConfig)],
+    new_oracle_config: &OracleConfig,
+    new_pool_configs: &[(String, PoolConfig)],
+) -> Vec<String> {
+    let mut changes: Vec<String> = changed_fields(oracle_config, new_oracle_config)
+        .into_iter()
+        .map(|field| format!("oracle config: {field} changed"))
+        .collect();
+    for (name, new_pool_config) in new_pool_configs {
+        match pool_configs.iter().find(|(old_name, _)| old_name == name) {
+            Some((_, old)) if old.token_ids != new_pool_config.token_ids => {
+                changes.push(format!("pool {name}: token ids changed"))
+            }
+            Some((_, old)) => changes.extend(
+                changed_fields(old, new_pool_config)
+                    .into_iter()
+                    .map(|field| format!("pool {name}: {field} changed")),
+            ),
+            None => changes.push(format!("pool {name}: added")),
+        }
+    }
+    changes
+}
+
+/// Names of the top-level fields with different values in the serialized `old` and `new`
+fn changed_fields<T: Serialize>(old: &T, new: &T) -> Vec<String> {
+    let (old, new) = match (serde_json::to_value(old), serde_json::to_value(new)) {
+        (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) => (old, new),
+        _ => return vec![],
+    };
+    let mut fields: Vec<String> = old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)))
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ergo_chain_sim::ChainSim;
+    use ergo_chain_sim::NodeSim;
+    use ergo_chain_sim::NodeSimServer;
+    use ergo_chain_sim::DEFAULT_API_KEY;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+    use ergo_node_interface::ScanId;
+    use log::LevelFilter;
+    use reqwest::Url;
+    use sigma_test_util::force_any_val;
+
+    use crate::cli_commands::bootstrap::BootstrapConfig;
+    use crate::oracle_config::BoxSourceConfig;
+    use crate::pool_commands::test_utils::generate_token_ids;
+
+    use super::*;
+
+    #[test]
+    fn test_oracle_config_changes() {
+        let old = OracleConfig::default();
+        let mut new = old.clone();
+        assert!(config_changes(&old, &[], &new, &[]).is_empty());
+        new.log_level = Some(LevelFilter::Debug);
+        new.base_fee += 1;
+        assert_eq!(
+            config_changes(&old, &[], &new, &[]),
+            vec![
+                "oracle config: base_fee changed".to_string(),
+                "oracle config: log_level changed".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_box_source_change() {
+        let old = OracleConfig::default();
+        let new = OracleConfig {
+            box_source: BoxSourceConfig::NodeIndexer,
+            ..old.clone()
+        };
+        assert!(serde_yaml::to_string(&new)
+            .unwrap()
+            .contains("box_source: node_indexer"));
+        assert_eq!(
+            config_changes(&old, &[], &new, &[]),
+            vec!["oracle config: box_source changed".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_changed_fields_of_added_and_removed_fields() {
+        let old = serde_json::json!({ "a": 1, "b": 2 });
+        let new = serde_json::json!({ "b": 3, "c": 4 });
+        assert_eq!(changed_fields(&old, &new), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_token_ids_serialize() {
+        // token id changes are detected by comparing TokenIds, the rest by serialized fields
+        let token_ids = generate_token_ids();
+        assert!(changed_fields(&token_ids, &token_ids.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_watcher_detects_file_changes() {
+        let dir = std::env::temp_dir().join(format!("oracle-core-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let oracle_config_path = dir.join("oracle_config.yaml");
+        let pool_config_path = dir.join("pool_config.yaml");
+        std::fs::write(&oracle_config_path, "a").unwrap();
+        let files = ConfigFiles {
+            oracle_config_path: oracle_config_path.clone(),
+            pools: vec![PoolSpec {
+                name: "default".to_string(),
+                config_file_path: pool_config_path.clone(),
+            }],
+        };
+        let mut watcher = ConfigFilesWatcher::new(&files);
+        assert!(!watcher.changed());
+        std::fs::write(&pool_config_path, "b").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        // make sure the modification time differs on file systems with coarse timestamps
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(&oracle_config_path, "c").unwrap();
+        assert!(watcher.changed());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_start_all_discards_started_pools() {
+        let mut discarded = vec![];
+        let res = start_all(
+            vec!["erg-usd", "erg-btc", "erg-eur"],
+            |name| {
+                if *name == "erg-btc" {
+                    Err(anyhow::anyhow!("failed to start"))
+                } else {
+                    Ok(format!("{name} (new)"))
+                }
+            },
+            |name| discarded.push(name.to_string()),
+        );
+        assert!(res.is_err());
+        // the pool which failed and the ones started before it are cleaned up, the pools after
+        // it are not started at all
+        assert_eq!(discarded, vec!["erg-btc", "erg-usd"]);
+
+        let started = start_all(vec!["erg-usd"], |name| Ok(name.len()), |_| panic!()).unwrap();
+        assert_eq!(started, vec![("erg-usd", 7)]);
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_old_pools() {
+        let secret = force_any_val::<DlogProverInput>();
+        let oracle_address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &Address::P2Pk(secret.public_image()),
+        );
+        let server = NodeSimServer::start(NodeSim::new(
+            ChainSim::new(),
+            vec![secret.into()],
+            NetworkPrefix::Mainnet,
+        ))
+        .unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("oracle-core-failed-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let oracle_config = OracleConfig {
+            node_url: Some(Url::parse(&server.url()).unwrap()),
+            oracle_address,
+            box_source: BoxSourceConfig::NodeScans,
+            ..OracleConfig::default()
+        };
+        let oracle_config_path = dir.join("oracle_config.yaml");
+        std::fs::write(
+            &oracle_config_path,
+            serde_yaml::to_string(&oracle_config).unwrap(),
+        )
+        .unwrap();
+        let ctx = OracleContext::new(
+            oracle_config,
+            OracleSecrets {
+                node_api_key: Some(DEFAULT_API_KEY.to_string()),
+                wallet_password: None,
+                wallet_mnemonic: None,
+                wallet_mnemonic_password: None,
+                wallet_secret_file_password: None,
+                backup_node_api_keys: vec![],
+            },
+            dir.clone(),
+        );
+        let pool_spec = |name: &str| PoolSpec {
+            name: name.to_string(),
+            config_file_path: dir.join(format!("{name}.yaml")),
+        };
+        let write_pool_config = |name: &str| {
+            let pool_config =
+                PoolConfig::create(BootstrapConfig::default(), generate_token_ids()).unwrap();
+            pool_config.save(&pool_spec(name).config_file_path).unwrap();
+            pool_config
+        };
+        let pool_config = write_pool_config("erg-usd");
+        let running_pools =
+            vec![RunningPool::start(&ctx, "erg-usd".to_string(), &pool_config, false).unwrap()];
+        let scans_file_path = pool_scans_file_path(&ctx, "erg-usd");
+        let scans_file = std::fs::read_to_string(&scans_file_path).unwrap();
+        let scans = ctx.node_api().list_scans().unwrap().len();
+        let files = ConfigFiles {
+            oracle_config_path,
+            pools: vec![pool_spec("erg-usd"), pool_spec("erg-btc")],
+        };
+        let assert_unchanged = |running_pools: &[RunningPool]| {
+            assert_eq!(running_pools.len(), 1);
+            assert_eq!(
+                running_pools[0].oracle_pool.pool_config().token_ids,
+                pool_config.token_ids
+            );
+            assert_eq!(running_pools[0].scans_file_path, scans_file_path);
+            assert_eq!(
+                std::fs::read_to_string(&scans_file_path).unwrap(),
+                scans_file
+            );
+            assert!(!staged_scans_file_path(&scans_file_path).exists());
+        };
+
+        // the new pool config can't be parsed
+        std::fs::write(&pool_spec("erg-btc").config_file_path, "token_ids: 1").unwrap();
+        assert!(reload_config(&ctx, &files, &running_pools, false, false).is_err());
+        assert_unchanged(&running_pools);
+        assert_eq!(ctx.node_api().list_scans().unwrap().len(), scans);
+
+        // the running pool gets new token ids and so new scans, the new pool fails to register
+        // its scans
+        write_pool_config("erg-usd");
+        write_pool_config("erg-btc");
+        server.node().max_scans = Some(2 * scans + 1);
+        assert!(reload_config(&ctx, &files, &running_pools, false, false).is_err());
+        assert_unchanged(&running_pools);
+        // the scans of the running pool are kept, the staged ones are deregistered
+        let registered: Vec<ScanId> = ctx
+            .node_api()
+            .list_scans()
+            .unwrap()
+            .into_iter()
+            .map(|scan| ScanId::from(scan.scan_id))
+            .collect();
+        assert!(running_pools[0]
+            .node_scan_registry
+            .as_ref()
+            .unwrap()
+            .scan_ids()
+            .iter()
+            .all(|scan_id| registered.contains(scan_id)));
+        assert!(registered.len() < 2 * scans);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_staged_scans_file_path() {
+        assert_eq!(
+            staged_scans_file_path(Path::new("/data/scanIDs-erg-usd.json")),
+            PathBuf::from("/data/scanIDs-erg-usd.json.new")
+        );
+    }
+
+    #[test]
+    fn test_reload_request() {
+        let request = ReloadRequest::new();
+        assert!(!request.take());
+        request.clone().request();
+        assert!(request.take());
+        assert!(!request.take());
+    }
+}
@@ -0,0 +1,159 @@
+//! The configuration of a running oracle. It is loaded once by the caller (the `oracle-core`
+//! binary or a service embedding the library) and passed down explicitly to the components.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+
+use crate::explorer_api::ExplorerApi;
+use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_endpoints::NodeEndpoints;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_config::OracleSecrets;
+use crate::oracle_config::SignerConfig;
+use crate::recording::MainLoopRecording;
+use crate::scans::ScanRepairLog;
+use crate::wallet::external_signer::ExternalSigner;
+use crate::wallet::local_wallet::LocalWallet;
+use crate::wallet::OracleWallet;
+use crate::wallet::WalletDataSource;
+
+#[derive(Clone)]
+pub struct OracleContext {
+    pub oracle_config: OracleConfig,
+    pub secrets: OracleSecrets,
+    /// Folder for the data files (scanIDs.json, scan_repairs.jsonl, logs)
+    pub data_dir: PathBuf,
+    /// The primary and the backup nodes with the one the requests currently go to
+    pub nodes: Arc<NodeEndpoints>,
+}
+
+impl OracleContext {
+    pub fn new(oracle_config: OracleConfig, secrets: OracleSecrets, data_dir: PathBuf) -> Self {
+        let nodes = Arc::new(NodeEndpoints::from_config(&oracle_config, &secrets));
+        Self {
+            oracle_config,
+            secrets,
+            data_dir,
+            nodes,
+        }
+    }
+
+    pub fn base_fee(&self) -> BoxValue {
+        self.oracle_config.base_fee()
+    }
+
+    pub fn network_prefix(&self) -> NetworkPrefix {
+        self.oracle_config.oracle_address.network()
+    }
+
+    /// A new client for the nodes from the oracle config, the requests go to the active node
+    pub fn node_api(&self) -> NodeApi {
+        NodeApi::with_endpoints(self.nodes.clone(), self.secrets.wallet_password.clone())
+    }
+
+    /// The wallet from the `signer` setting of the oracle config. A local wallet has to hold the
+    /// key of the oracle address.
+    pub fn wallet(&self) -> Result<OracleWallet, anyhow::Error> {
+        let secret_file = match &self.oracle_config.signer {
+            None | Some(SignerConfig::NodeWallet) => {
+                return Ok(OracleWallet::Node(self.node_api()))
+            }
+            Some(SignerConfig::External { url }) => {
+                return Ok(OracleWallet::External(ExternalSigner::new(
+                    url.parse()?,
+                    self.oracle_config.oracle_address.clone(),
+                    self.node_api(),
+                )))
+            }
+            Some(SignerConfig::Local { secret_file }) => secret_file,
+        };
+        let local_wallet = match secret_file {
+            Some(path) => {
+                let password = self
+                    .secrets
+                    .wallet_secret_file_password
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("ORACLE_WALLET_SECRET_FILE_PASSWORD is not set"))?;
+                LocalWallet::from_secret_file(
+                    path,
+                    password,
+                    self.network_prefix(),
+                    self.node_api(),
+                )
+                .with_context(|| format!("failed to load wallet from {}", path.display()))?
+            }
+            None => {
+                let mnemonic = self
+                    .secrets
+                    .wallet_mnemonic
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("ORACLE_WALLET_MNEMONIC is not set"))?;
+                LocalWallet::from_mnemonic(
+                    mnemonic,
+                    self.secrets
+                        .wallet_mnemonic_password
+                        .as_deref()
+                        .unwrap_or_default(),
+                    self.network_prefix(),
+                    self.node_api(),
+                )?
+            }
+        };
+        if local_wallet.address().to_base58() != self.oracle_config.oracle_address.to_base58() {
+            return Err(anyhow!(
+                "the local wallet address {} is not the oracle address {}",
+                local_wallet.address().to_base58(),
+                self.oracle_config.oracle_address.to_base58()
+            ));
+        }
+        Ok(OracleWallet::Local(local_wallet))
+    }
+
+    pub fn explorer_api(&self) -> ExplorerApi {
+        ExplorerApi::new(self.oracle_config.explorer_api_url())
+    }
+
+    /// Height of the chain the pool boxes are looked up in
+    pub fn current_height(&self) -> Result<u32, anyhow::Error> {
+        if self.oracle_config.box_source.requires_node() {
+            Ok(self.node_api().node().current_block_height()? as u32)
+        } else {
+            Ok(self.explorer_api().get_current_height()?)
+        }
+    }
+
+    /// Address the change of the txs goes to. The wallet is not asked if there is no node (see
+    /// [`crate::oracle_config::BoxSourceConfig::Explorer`]), no txs are made then.
+    pub fn change_address(&self, wallet: &OracleWallet) -> Result<NetworkAddress, anyhow::Error> {
+        if self.oracle_config.box_source.requires_node() {
+            Ok(wallet.get_change_address()?)
+        } else {
+            Ok(self.oracle_config.oracle_address.clone())
+        }
+    }
+
+    /// Scan IDs of the pool loaded from the pool config file given with `--pool-config-file`
+    pub fn scans_file_path(&self) -> PathBuf {
+        self.data_dir.join("scanIDs.json")
+    }
+
+    /// Scan IDs of a named pool (when running multiple pools from one process)
+    pub fn pool_scans_file_path(&self, pool_name: &str) -> PathBuf {
+        self.data_dir.join(format!("scanIDs-{pool_name}.json"))
+    }
+
+    /// Record of the scans registered anew after they went missing on a node
+    pub fn scan_repair_log(&self) -> ScanRepairLog {
+        ScanRepairLog::new(self.data_dir.join("scan_repairs.jsonl"))
+    }
+
+    /// Where `run --record` writes the main loop iterations to
+    pub fn main_loop_recording(&self) -> MainLoopRecording {
+        MainLoopRecording::new(self.data_dir.join("main_loop_recording.jsonl"))
+    }
+}
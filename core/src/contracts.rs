@@ -2,4 +2,5 @@ pub mod ballot;
 pub mod oracle;
 pub mod pool;
 pub mod refresh;
+pub mod registry;
 pub mod update;
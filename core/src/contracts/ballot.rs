@@ -13,6 +13,10 @@ use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializationError;
 use thiserror::Error;
 
+use crate::contracts::registry::warn_if_unknown;
+use crate::contracts::registry::ContractKind;
+use crate::contracts::registry::MIN_STORAGE_RENT;
+use crate::contracts::registry::UPDATE_NFT;
 use crate::spec_token::TokenIdKind;
 use crate::spec_token::UpdateTokenId;
 
@@ -277,6 +281,14 @@ impl BallotContractParameters {
                 actual: actual_min_storage_rent,
             });
         }
+        warn_if_unknown(
+            ContractKind::Ballot,
+            &ergo_tree,
+            &[
+                (MIN_STORAGE_RENT, min_storage_rent_index),
+                (UPDATE_NFT, update_nft_index),
+            ],
+        );
 
         Ok(Self {
             ergo_tree_bytes,
@@ -12,6 +12,10 @@ use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializationError;
 use thiserror::Error;
 
+use crate::contracts::registry::warn_if_unknown;
+use crate::contracts::registry::ContractKind;
+use crate::contracts::registry::MIN_STORAGE_RENT;
+use crate::contracts::registry::POOL_NFT;
 use crate::spec_token::PoolTokenId;
 use crate::spec_token::TokenIdKind;
 
@@ -239,6 +243,14 @@ impl OracleContractParameters {
             .map_err(|_| OracleContractParametersError::NoPoolNftId)?
             .ok_or(OracleContractParametersError::NoPoolNftId)?
             .try_extract_into::<TokenId>()?;
+        warn_if_unknown(
+            ContractKind::Oracle,
+            &ergo_tree,
+            &[
+                (POOL_NFT, pool_nft_index),
+                (MIN_STORAGE_RENT, min_storage_rent_index),
+            ],
+        );
         Ok(Self {
             ergo_tree_bytes,
             pool_nft_index,
@@ -8,6 +8,10 @@ use ergo_lib::ergotree_ir::serialization::SigmaParsingError;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
 use thiserror::Error;
 
+use crate::contracts::registry::warn_if_unknown;
+use crate::contracts::registry::ContractKind;
+use crate::contracts::registry::REFRESH_NFT;
+use crate::contracts::registry::UPDATE_NFT;
 use crate::spec_token::RefreshTokenId;
 use crate::spec_token::TokenIdKind;
 use crate::spec_token::UpdateTokenId;
@@ -224,6 +228,14 @@ impl PoolContractParameters {
             .map_err(|_| PoolContractParametersError::NoUpdateNftId)?
             .ok_or(PoolContractParametersError::NoUpdateNftId)?
             .try_extract_into::<TokenId>()?;
+        warn_if_unknown(
+            ContractKind::Pool,
+            &ergo_tree,
+            &[
+                (REFRESH_NFT, refresh_nft_index),
+                (UPDATE_NFT, update_nft_index),
+            ],
+        );
         Ok(Self {
             ergo_tree_bytes,
             refresh_nft_index,
@@ -9,6 +9,14 @@ use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializationError;
 use thiserror::Error;
 
+use crate::contracts::registry::warn_if_unknown;
+use crate::contracts::registry::ContractKind;
+use crate::contracts::registry::BUFFER_LENGTH;
+use crate::contracts::registry::EPOCH_LENGTH;
+use crate::contracts::registry::MAX_DEVIATION_PERCENT;
+use crate::contracts::registry::MIN_DATA_POINTS;
+use crate::contracts::registry::ORACLE_TOKEN_ID;
+use crate::contracts::registry::POOL_NFT;
 use crate::oracle_types::EpochLength;
 use crate::oracle_types::MinDatapoints;
 use crate::spec_token::OracleTokenId;
@@ -552,6 +560,18 @@ impl RefreshContractParameters {
             .map_err(|_| RefreshContractParametersError::NoOracleTokenId)?
             .ok_or(RefreshContractParametersError::NoOracleTokenId)?
             .try_extract_into::<TokenId>()?;
+        warn_if_unknown(
+            ContractKind::Refresh,
+            &ergo_tree,
+            &[
+                (POOL_NFT, inputs.pool_nft_index),
+                (ORACLE_TOKEN_ID, inputs.oracle_token_id_index),
+                (MIN_DATA_POINTS, inputs.min_data_points_index),
+                (BUFFER_LENGTH, inputs.buffer_length_index),
+                (MAX_DEVIATION_PERCENT, inputs.max_deviation_percent_index),
+                (EPOCH_LENGTH, inputs.epoch_length_index),
+            ],
+        );
         Ok(Self {
             ergo_tree_bytes: base16::decode(&ergo_tree.to_base16_bytes()?)?,
             pool_nft_index: inputs.pool_nft_index,
@@ -0,0 +1,321 @@
+//! Registry of the known versions of the oracle pool contracts. A contract is identified by the
+//! hash of its ErgoTree template (the tree without the segregated constants, the same hash as the
+//! `ergoTreeTemplateHash` of the Explorer), so the contracts of a pool are recognised whatever
+//! token ids and parameter values were put in their constants. The registry knows where each
+//! contract keeps its parameters in the constants, the indexes can be left out of the pool config
+//! for the known contracts.
+use std::fmt::Display;
+
+use ergo_lib::ergo_chain_types::blake2b256_hash;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTreeError;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContractKind {
+    Pool,
+    Refresh,
+    Oracle,
+    Ballot,
+    Update,
+}
+
+impl Display for ContractKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ContractKind::Pool => "pool",
+            ContractKind::Refresh => "refresh",
+            ContractKind::Oracle => "oracle",
+            ContractKind::Ballot => "ballot",
+            ContractKind::Update => "update",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Names of the contract constants, the same as the `*_index` fields of the contract parameters
+pub const REFRESH_NFT: &str = "refresh_nft";
+pub const UPDATE_NFT: &str = "update_nft";
+pub const POOL_NFT: &str = "pool_nft";
+pub const ORACLE_TOKEN_ID: &str = "oracle_token_id";
+pub const BALLOT_TOKEN: &str = "ballot_token";
+pub const MIN_DATA_POINTS: &str = "min_data_points";
+pub const BUFFER_LENGTH: &str = "buffer_length";
+pub const MAX_DEVIATION_PERCENT: &str = "max_deviation_percent";
+pub const EPOCH_LENGTH: &str = "epoch_length";
+pub const MIN_STORAGE_RENT: &str = "min_storage_rent";
+pub const MIN_VOTES: &str = "min_votes";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownContract {
+    pub kind: ContractKind,
+    pub version: &'static str,
+    /// Base16-encoded blake2b256 hash of the ErgoTree template bytes
+    pub template_hash: &'static str,
+    /// Names of the parameters and their indexes in the ErgoTree constants
+    pub constants: &'static [(&'static str, usize)],
+}
+
+impl KnownContract {
+    pub fn constant_index(&self, name: &str) -> Option<usize> {
+        self.constants
+            .iter()
+            .find(|(constant, _)| *constant == name)
+            .map(|(_, index)| *index)
+    }
+}
+
+/// Add new contract versions here. The template hash of a contract is printed by the
+/// `print-contract-hashes` command.
+pub const KNOWN_CONTRACTS: &[KnownContract] = &[
+    KnownContract {
+        kind: ContractKind::Pool,
+        version: "EIP-23 (oracle-core v2.0)",
+        template_hash: "3ffc5d9ca2e84acd90370dbcbdd3e1b632594a599d67d16e4ad7d41a59fb6553",
+        constants: &[(REFRESH_NFT, 2), (UPDATE_NFT, 3)],
+    },
+    KnownContract {
+        kind: ContractKind::Refresh,
+        version: "EIP-23 (oracle-core v2.0)",
+        template_hash: "559d56ff1e09a3365c2b84c6efecbcbdabaee08f48ab7ffd65758d462b872920",
+        constants: &[
+            (POOL_NFT, 17),
+            (ORACLE_TOKEN_ID, 3),
+            (MIN_DATA_POINTS, 13),
+            (BUFFER_LENGTH, 21),
+            (MAX_DEVIATION_PERCENT, 15),
+            (EPOCH_LENGTH, 0),
+        ],
+    },
+    KnownContract {
+        kind: ContractKind::Oracle,
+        version: "EIP-23 (oracle-core v2.0)",
+        template_hash: "943c531f9d3350b2fde2174d025c173e4b68bf954138258fe37e254c6781a7b1",
+        constants: &[(POOL_NFT, 5), (MIN_STORAGE_RENT, 2)],
+    },
+    KnownContract {
+        kind: ContractKind::Ballot,
+        version: "EIP-23 (oracle-core v2.0)",
+        template_hash: "74b398d14097c847305fe634ffec17d045eff93a297128e96c8c511c9cc22090",
+        constants: &[(MIN_STORAGE_RENT, 0), (UPDATE_NFT, 6)],
+    },
+    KnownContract {
+        kind: ContractKind::Update,
+        version: "EIP-23 (oracle-core v2.0)",
+        template_hash: "3bb328bdb342d541014b91a3ac6cff7c7a47aa9d7cc1f4dade1727f7ab531fb5",
+        constants: &[(POOL_NFT, 6), (BALLOT_TOKEN, 10), (MIN_VOTES, 14)],
+    },
+    // The pool, refresh and oracle contracts of EIP-23 v1 have the same templates as above
+    KnownContract {
+        kind: ContractKind::Ballot,
+        version: "EIP-23 v1",
+        template_hash: "4cea1b90e8cb56f4d517ad1fec142741bb5fb3cf1e7f47aea55d2943dbfe88da",
+        constants: &[(MIN_STORAGE_RENT, 0), (UPDATE_NFT, 6)],
+    },
+    KnownContract {
+        kind: ContractKind::Update,
+        version: "EIP-23 v1",
+        template_hash: "708708928c7063b5d6eb3b8ed823ac85472cdf5df838b2fbcce24544ddafcff0",
+        constants: &[(POOL_NFT, 5), (BALLOT_TOKEN, 9), (MIN_VOTES, 13)],
+    },
+];
+
+/// Base16-encoded blake2b256 hash of the ErgoTree template bytes
+pub fn template_hash(ergo_tree: &ErgoTree) -> Result<String, ErgoTreeError> {
+    let template_bytes = ergo_tree.template_bytes()?;
+    Ok(String::from(blake2b256_hash(&template_bytes)))
+}
+
+/// The known version of the contract, none if the template is not in the registry
+pub fn identify(kind: ContractKind, ergo_tree: &ErgoTree) -> Option<&'static KnownContract> {
+    let hash = template_hash(ergo_tree).ok()?;
+    KNOWN_CONTRACTS
+        .iter()
+        .find(|known| known.kind == kind && known.template_hash == hash)
+}
+
+/// Same as [`identify`] for the serialized ErgoTree
+pub fn identify_bytes(
+    kind: ContractKind,
+    ergo_tree_bytes: &[u8],
+) -> Option<&'static KnownContract> {
+    let ergo_tree = ErgoTree::sigma_parse_bytes(ergo_tree_bytes).ok()?;
+    identify(kind, &ergo_tree)
+}
+
+/// Logs a warning if the contract is not a known version, or if the given constant indexes
+/// differ from the layout of the known version
+pub fn warn_if_unknown(kind: ContractKind, ergo_tree: &ErgoTree, indexes: &[(&str, usize)]) {
+    let known = match identify(kind, ergo_tree) {
+        Some(known) => known,
+        None => {
+            log::warn!(
+                "{} contract: unrecognised ErgoTree template (hash {}), not a known contract \
+                 version. Check the contract before using the pool",
+                kind,
+                template_hash(ergo_tree).unwrap_or_else(|e| format!("unavailable: {:?}", e))
+            );
+            return;
+        }
+    };
+    for (name, index) in indexes {
+        if let Some(known_index) = known.constant_index(name) {
+            if known_index != *index {
+                log::warn!(
+                    "{} contract ({}): {} index is {}, expected {}",
+                    kind,
+                    known.version,
+                    name,
+                    index,
+                    known_index
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
+
+    use crate::contracts::ballot::BallotContractParameters;
+    use crate::contracts::oracle::OracleContractParameters;
+    use crate::contracts::pool::PoolContractParameters;
+    use crate::contracts::refresh::RefreshContractParameters;
+    use crate::contracts::update::UpdateContractParameters;
+
+    use super::*;
+
+    #[test]
+    fn test_default_contracts_are_known() {
+        let pool = PoolContractParameters::default();
+        let known = identify_bytes(ContractKind::Pool, &pool.ergo_tree_bytes()).unwrap();
+        assert_eq!(
+            known.constant_index(REFRESH_NFT),
+            Some(pool.refresh_nft_index())
+        );
+        assert_eq!(
+            known.constant_index(UPDATE_NFT),
+            Some(pool.update_nft_index())
+        );
+
+        let refresh = RefreshContractParameters::default();
+        let known = identify_bytes(ContractKind::Refresh, &refresh.ergo_tree_bytes()).unwrap();
+        assert_eq!(
+            known.constant_index(POOL_NFT),
+            Some(refresh.pool_nft_index())
+        );
+        assert_eq!(
+            known.constant_index(ORACLE_TOKEN_ID),
+            Some(refresh.oracle_token_id_index())
+        );
+        assert_eq!(
+            known.constant_index(MIN_DATA_POINTS),
+            Some(refresh.min_data_points_index())
+        );
+        assert_eq!(
+            known.constant_index(BUFFER_LENGTH),
+            Some(refresh.buffer_length_index())
+        );
+        assert_eq!(
+            known.constant_index(MAX_DEVIATION_PERCENT),
+            Some(refresh.max_deviation_percent_index())
+        );
+        assert_eq!(
+            known.constant_index(EPOCH_LENGTH),
+            Some(refresh.epoch_length_index())
+        );
+
+        let oracle = OracleContractParameters::default();
+        let known = identify_bytes(ContractKind::Oracle, &oracle.ergo_tree_bytes()).unwrap();
+        assert_eq!(known.constant_index(POOL_NFT), Some(oracle.pool_nft_index));
+        assert_eq!(
+            known.constant_index(MIN_STORAGE_RENT),
+            Some(oracle.min_storage_rent_index)
+        );
+
+        let ballot = BallotContractParameters::default();
+        let known = identify_bytes(ContractKind::Ballot, &ballot.ergo_tree_bytes()).unwrap();
+        assert_eq!(
+            known.constant_index(MIN_STORAGE_RENT),
+            Some(ballot.min_storage_rent_index())
+        );
+        assert_eq!(
+            known.constant_index(UPDATE_NFT),
+            Some(ballot.update_nft_index())
+        );
+
+        let update = UpdateContractParameters::default();
+        let known = identify_bytes(ContractKind::Update, &update.ergo_tree_bytes()).unwrap();
+        assert_eq!(
+            known.constant_index(POOL_NFT),
+            Some(update.pool_nft_index())
+        );
+        assert_eq!(
+            known.constant_index(BALLOT_TOKEN),
+            Some(update.ballot_token_index())
+        );
+        assert_eq!(
+            known.constant_index(MIN_VOTES),
+            Some(update.min_votes_index())
+        );
+    }
+
+    #[test]
+    fn test_identify_ignores_constant_values() {
+        let refresh = RefreshContractParameters::default();
+        let ergo_tree = ErgoTree::sigma_parse_bytes(&refresh.ergo_tree_bytes()).unwrap();
+        let changed = ergo_tree
+            .clone()
+            .with_constant(refresh.epoch_length_index(), 60i32.into())
+            .unwrap();
+        assert_ne!(changed, ergo_tree);
+        assert_eq!(
+            identify(ContractKind::Refresh, &changed),
+            identify(ContractKind::Refresh, &ergo_tree)
+        );
+        assert!(identify(ContractKind::Refresh, &changed).is_some());
+    }
+
+    #[test]
+    fn test_identify_v1_contracts() {
+        let update_v1 = base16::decode("100e040004000400040204020e20472b4b6250655368566d597133743677397a24432646294a404d635166546a570400040004000e203f4428472d4b6150645367566b5970337336763979244226452948404d625165010005000400040cd806d601b2a4730000d602b2db63087201730100d603b2a5730200d604db63087203d605b2a5730300d606b27204730400d1ededed938c7202017305ededededed937202b27204730600938cc77201018cc772030193c17201c1720393c672010405c67203040593c672010504c672030504efe6c672030661edededed93db63087205db6308a793c27205c2a792c17205c1a7918cc77205018cc7a701efe6c67205046192b0b5a4d9010763d801d609db630872079591b172097307edededed938cb2720973080001730993e4c6720705048cc7a70193e4c67207060ecbc2720393e4c67207070e8c72060193e4c6720708058c720602730a730bd9010741639a8c7207018cb2db63088c720702730c00027e730d05").unwrap();
+        let known = identify_bytes(ContractKind::Update, &update_v1).unwrap();
+        assert_eq!(known.version, "EIP-23 v1");
+        assert_eq!(known.constant_index(POOL_NFT), Some(5));
+        assert_eq!(known.constant_index(BALLOT_TOKEN), Some(9));
+        assert_eq!(known.constant_index(MIN_VOTES), Some(13));
+        let update = UpdateContractParameters::build_with(update_v1, 5, 9, 13, 2).unwrap();
+        assert_eq!(update.min_votes(), 2);
+
+        let ballot_v1 = base16::decode("10070580dac409040204020400040204000e206251655468576d5a7134743777217a25432a462d4a404e635266556a586e3272d803d601b2a5e4e3000400d602c672010407d603e4c6a70407ea02d1ededede6720293c27201c2a793db63087201db6308a792c172017300eb02cd7203d1ededededed91b1a4730191b1db6308b2a47302007303938cb2db6308b2a473040073050001730693e47202720392c17201c1a7efe6c672010561").unwrap();
+        let known = identify_bytes(ContractKind::Ballot, &ballot_v1).unwrap();
+        assert_eq!(known.version, "EIP-23 v1");
+        assert_eq!(known.constant_index(MIN_STORAGE_RENT), Some(0));
+        assert_eq!(known.constant_index(UPDATE_NFT), Some(6));
+        // the v1 ballot is not mistaken for the current one
+        assert_ne!(
+            identify_bytes(
+                ContractKind::Ballot,
+                &BallotContractParameters::default().ergo_tree_bytes()
+            ),
+            Some(known)
+        );
+    }
+
+    #[test]
+    fn test_unknown_contracts() {
+        let pool = PoolContractParameters::default();
+        // the pool contract is not a known refresh contract
+        assert!(identify_bytes(ContractKind::Refresh, &pool.ergo_tree_bytes()).is_none());
+        let p2pk = AddressEncoder::unchecked_parse_network_address_from_str(
+            "9iHyKxXs2ZNLMp9N9gbUT9V8gTbsV7HED1C1VhttMfBUMPDyF7r",
+        )
+        .unwrap()
+        .address()
+        .script()
+        .unwrap();
+        assert!(identify(ContractKind::Pool, &p2pk).is_none());
+        assert!(identify_bytes(ContractKind::Pool, &[0, 1, 2]).is_none());
+    }
+}
@@ -10,6 +10,11 @@ use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
 use ergo_lib::ergotree_ir::serialization::SigmaSerializationError;
 use thiserror::Error;
 
+use crate::contracts::registry::warn_if_unknown;
+use crate::contracts::registry::ContractKind;
+use crate::contracts::registry::BALLOT_TOKEN;
+use crate::contracts::registry::MIN_VOTES;
+use crate::contracts::registry::POOL_NFT;
 use crate::spec_token::BallotTokenId;
 use crate::spec_token::PoolTokenId;
 use crate::spec_token::TokenIdKind;
@@ -315,6 +320,15 @@ impl UpdateContractParameters {
             .map_err(|_| UpdateContractParametersError::NoBallotTokenId)?
             .ok_or(UpdateContractParametersError::NoBallotTokenId)?
             .try_extract_into::<TokenId>()?;
+        warn_if_unknown(
+            ContractKind::Update,
+            &ergo_tree,
+            &[
+                (POOL_NFT, pool_nft_index),
+                (BALLOT_TOKEN, ballot_token_index),
+                (MIN_VOTES, min_votes_index),
+            ],
+        );
 
         Ok(Self {
             ergo_tree_bytes,
@@ -37,6 +37,9 @@ pub enum DataPointSourceError {
     JsonMissingField { field: String, json: String },
     #[error("No datapoints from any source")]
     NoDataPoints,
+    /// The error of the source in a main loop recording (see [`crate::recording`])
+    #[error("recorded error: {0}")]
+    Recorded(String),
 }
 
 pub enum RuntimeDataPointSource {
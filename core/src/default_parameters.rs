@@ -3,7 +3,10 @@
 use std::convert::TryInto;
 
 use ergo_lib::{
-    ergo_chain_types::blake2b256_hash, ergotree_ir::chain::ergo_box::box_value::BoxValue,
+    ergo_chain_types::blake2b256_hash,
+    ergotree_ir::{
+        chain::ergo_box::box_value::BoxValue, ergo_tree::ErgoTree, serialization::SigmaSerializable,
+    },
 };
 
 use crate::{
@@ -12,6 +15,7 @@ use crate::{
         oracle::OracleContractParameters,
         pool::PoolContractParameters,
         refresh::{RefreshContractParameters, RefreshContractParametersInputs},
+        registry::{identify, template_hash, ContractKind},
         update::UpdateContractParameters,
     },
     oracle_types::{EpochLength, MinDatapoints},
@@ -147,6 +151,28 @@ pub fn print_contract_hashes() {
         "Update contract encoded hash: {}\n",
         encoded_hash(update_ergo_tree_bytes)
     );
+
+    println!("BASE 16 ENCODING OF BLAKE2B HASH OF CONTRACT ERGO-TREE TEMPLATE BYTES");
+    println!("---------------------------------------------------------------------\n");
+
+    for (kind, ergo_tree_bytes) in [
+        (ContractKind::Pool, pool_ergo_tree_bytes),
+        (ContractKind::Refresh, refresh_ergo_tree_bytes),
+        (ContractKind::Oracle, oracle_ergo_tree_bytes),
+        (ContractKind::Ballot, ballot_ergo_tree_bytes),
+        (ContractKind::Update, update_ergo_tree_bytes),
+    ] {
+        let ergo_tree = ErgoTree::sigma_parse_bytes(ergo_tree_bytes).unwrap();
+        println!(
+            "{} contract template hash: {} ({})",
+            kind,
+            template_hash(&ergo_tree).unwrap(),
+            identify(kind, &ergo_tree)
+                .map(|known| known.version)
+                .unwrap_or("unknown version")
+        );
+    }
+    println!();
 }
 
 #[cfg(test)]
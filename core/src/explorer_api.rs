@@ -3,16 +3,18 @@ use std::time::Duration;
 use ergo_lib::chain::transaction::Transaction;
 use ergo_lib::chain::transaction::TxId;
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
 use reqwest::blocking::RequestBuilder;
 use reqwest::blocking::Response;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::Url;
+use serde::Deserialize;
 use thiserror::Error;
 use url::ParseError;
 
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::oracle_config::OracleConfig;
 
-use self::explorer_url::default_explorer_api_url;
 use self::explorer_url::default_explorer_url;
 
 pub mod explorer_url;
@@ -27,10 +29,17 @@ pub enum ExplorerApiError {
     InvalidExplorerUrl(#[from] ParseError),
 }
 
+#[derive(Debug)]
 pub struct ExplorerApi {
     pub url: url::Url,
 }
 
+/// A page of the paginated v1 endpoints
+#[derive(Debug, Deserialize)]
+struct ItemsPage<T> {
+    items: Vec<T>,
+}
+
 impl ExplorerApi {
     pub fn new(url: Url) -> Self {
         Self { url }
@@ -64,10 +73,51 @@ impl ExplorerApi {
         log::debug!("get_transaction_v1 response: {}", text);
         Ok(serde_json::from_str(&text)?)
     }
+
+    /// GET /api/v1/boxes/unspent/byTokenId/{id}, all the pages
+    pub fn get_unspent_boxes_by_token_id(
+        &self,
+        token_id: TokenId,
+    ) -> Result<Vec<ErgoBox>, ExplorerApiError> {
+        const PAGE_SIZE: usize = 100;
+        let token_id = String::from(token_id);
+        let mut boxes: Vec<ErgoBox> = vec![];
+        loop {
+            let endpoint = format!(
+                "/api/v1/boxes/unspent/byTokenId/{}?offset={}&limit={}",
+                token_id,
+                boxes.len(),
+                PAGE_SIZE
+            );
+            let text = self.send_get_req(&endpoint)?.text()?;
+            let page: ItemsPage<ErgoBox> = serde_json::from_str(&text)?;
+            let page_len = page.items.len();
+            boxes.extend(page.items);
+            if page_len < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(boxes)
+    }
+
+    /// Height of the last block indexed by the explorer (GET /api/v1/networkState)
+    pub fn get_current_height(&self) -> Result<u32, ExplorerApiError> {
+        #[derive(Deserialize)]
+        struct NetworkState {
+            height: u32,
+        }
+        let text = self.send_get_req("/api/v1/networkState")?.text()?;
+        let network_state: NetworkState = serde_json::from_str(&text)?;
+        Ok(network_state.height)
+    }
 }
 
-pub(crate) fn ergo_explorer_transaction_link(tx_id: TxId, prefix: NetworkPrefix) -> String {
-    let url = ORACLE_CONFIG
+pub(crate) fn ergo_explorer_transaction_link(
+    oracle_config: &OracleConfig,
+    tx_id: TxId,
+    prefix: NetworkPrefix,
+) -> String {
+    let url = oracle_config
         .explorer_url
         .clone()
         .unwrap_or_else(|| default_explorer_url(prefix));
@@ -79,18 +129,13 @@ pub(crate) fn ergo_explorer_transaction_link(tx_id: TxId, prefix: NetworkPrefix)
         .to_string()
 }
 
-pub fn wait_for_tx_confirmation(tx_id: TxId) {
-    wait_for_txs_confirmation(vec![tx_id]);
+pub fn wait_for_tx_confirmation(oracle_config: &OracleConfig, tx_id: TxId) {
+    wait_for_txs_confirmation(oracle_config, vec![tx_id]);
 }
 
-pub fn wait_for_txs_confirmation(tx_ids: Vec<TxId>) {
-    let network = ORACLE_CONFIG.oracle_address.network();
+pub fn wait_for_txs_confirmation(oracle_config: &OracleConfig, tx_ids: Vec<TxId>) {
     let timeout = Duration::from_secs(1200);
-    let explorer_url = ORACLE_CONFIG
-        .explorer_url
-        .clone()
-        .unwrap_or_else(|| default_explorer_api_url(network));
-    let explorer_api = ExplorerApi::new(explorer_url);
+    let explorer_api = ExplorerApi::new(oracle_config.explorer_api_url());
     let start_time = std::time::Instant::now();
     println!("Waiting for block confirmation from ExplorerApi for tx ids: {tx_ids:?} ...");
     let mut remaining_txs = tx_ids.clone();
@@ -127,3 +172,105 @@ pub fn wait_for_txs_confirmation(tx_ids: Vec<TxId>) {
         std::thread::sleep(std::time::Duration::from_secs(30));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisterId;
+    use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+
+    use crate::contracts::pool::PoolContractParameters;
+
+    use super::*;
+
+    /// GET /api/v1/boxes/unspent/byTokenId/{pool NFT}, the registers are objects with the
+    /// serialized value (not just the serialized value as in the node API)
+    const UNSPENT_POOL_BOXES_PAGE: &str = r#"{
+  "items": [
+    {
+      "boxId": "a171d224c44d93822d6682df9f8eca553864a63e905fb43d9edd82241826ed87",
+      "transactionId": "4e5c1a5d2fca2c8b8e4f5f0b6c8a3b2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b",
+      "blockId": "9d3b1f6e2a0c4d5b8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d",
+      "value": 1000000,
+      "index": 0,
+      "globalIndex": 38516472,
+      "creationHeight": 1125000,
+      "settlementHeight": 1125001,
+      "ergoTree": "1004040204000e20546a576e5a7234753778214125442a472d4b614e645267556b587032733576380e206251655468576d5a7134743777217a25432a462d4a404e635266556a586e3272d801d6018cb2db6308b2a473000073010001d1ec93720173029372017303",
+      "address": "PViBL5acX6PoP6BQPsYtyNzW9aPXwxpRaUkXo4nE7RkxcBbZXJECUEBQm4g3MQCb2QsQALqPkrDN9TvsKuQkChF8sZSfnH5fifgKAkXhW8ifAcAE1qA67n9mabB3Mb2R8xT2v3SN49eN8mQ8HN95",
+      "assets": [
+        {
+          "tokenId": "011d3364de07e5a26f0c4eef0852cddb387039a921b7154ef3cab22c0eb5de21",
+          "index": 0,
+          "amount": 1,
+          "name": "Oracle Pool NFT",
+          "decimals": 0,
+          "type": "EIP-004"
+        },
+        {
+          "tokenId": "e8bf6a3c3a3d3d5b14ac3cd9a0c3a1a45aee3cfb6d7e8a1f1b7e5e8e3c2c9a11",
+          "index": 1,
+          "amount": 99948226,
+          "name": "Oracle Pool Reward Token",
+          "decimals": 0,
+          "type": "EIP-004"
+        }
+      ],
+      "additionalRegisters": {
+        "R4": {
+          "serializedValue": "05c8cfcaa402",
+          "sigmaType": "SLong",
+          "renderedValue": "306795492"
+        },
+        "R5": {
+          "serializedValue": "04d68f09",
+          "sigmaType": "SInt",
+          "renderedValue": "74731"
+        }
+      },
+      "spentTransactionId": null,
+      "mainChain": true
+    }
+  ],
+  "total": 1
+}"#;
+
+    #[test]
+    fn test_parse_unspent_boxes_page() {
+        let page: ItemsPage<ErgoBox> = serde_json::from_str(UNSPENT_POOL_BOXES_PAGE).unwrap();
+        assert_eq!(page.items.len(), 1);
+        let pool_box = &page.items[0];
+        // the box id is checked against the one computed from the parsed box
+        assert_eq!(
+            String::from(pool_box.box_id()),
+            "a171d224c44d93822d6682df9f8eca553864a63e905fb43d9edd82241826ed87"
+        );
+        assert_eq!(*pool_box.value.as_u64(), 1_000_000);
+        assert_eq!(pool_box.creation_height, 1_125_000);
+        assert_eq!(
+            pool_box.ergo_tree.sigma_serialize_bytes().unwrap(),
+            PoolContractParameters::default().ergo_tree_bytes()
+        );
+        let tokens = pool_box.tokens.as_ref().unwrap();
+        assert_eq!(
+            String::from(tokens.first().token_id),
+            "011d3364de07e5a26f0c4eef0852cddb387039a921b7154ef3cab22c0eb5de21"
+        );
+        assert_eq!(*tokens.get(1).unwrap().amount.as_u64(), 99_948_226);
+        assert_eq!(
+            pool_box
+                .get_register(NonMandatoryRegisterId::R4.into())
+                .unwrap()
+                .try_extract_into::<i64>()
+                .unwrap(),
+            306_795_492
+        );
+        assert_eq!(
+            pool_box
+                .get_register(NonMandatoryRegisterId::R5.into())
+                .unwrap()
+                .try_extract_into::<i32>()
+                .unwrap(),
+            74_731
+        );
+    }
+}
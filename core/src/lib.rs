@@ -0,0 +1,64 @@
+//! Oracle core library. The `oracle-core` binary is a thin command line wrapper around it.
+//!
+//! The components take the [`context::OracleContext`] (oracle config, node secrets and data
+//! folder) as a parameter, so the oracle can be embedded and several instances can be created in
+//! one process (e.g. in tests).
+
+// Coding conventions
+#![allow(dead_code)]
+#![allow(clippy::redundant_clone)]
+#![allow(clippy::ptr_arg)]
+#![allow(clippy::unit_arg)]
+#![forbid(unsafe_code)]
+#![deny(non_upper_case_globals)]
+#![deny(non_camel_case_types)]
+#![deny(non_snake_case)]
+#![deny(unused_mut)]
+#![deny(unused_imports)]
+#![deny(clippy::wildcard_enum_match_arm)]
+#![deny(clippy::todo)]
+#![deny(clippy::unimplemented)]
+// #![allow(clippy::correctness)]
+// #![allow(clippy::almost_swapped)]
+
+pub mod action_report;
+pub mod actions;
+pub mod address_util;
+pub mod api;
+pub mod box_kind;
+pub mod cli_commands;
+pub mod config_reload;
+pub mod context;
+pub mod contracts;
+pub mod datapoint_source;
+pub mod default_parameters;
+pub mod explorer_api;
+pub mod logging;
+pub mod metrics;
+pub mod migrate;
+pub mod monitor;
+pub mod node_interface;
+pub mod oracle_config;
+pub mod oracle_state;
+pub mod oracle_types;
+pub mod pool_commands;
+pub mod pool_config;
+pub mod recording;
+pub mod runner;
+pub mod running_pool;
+pub mod scans;
+pub mod scheduler;
+pub mod secrets;
+pub mod serde;
+pub mod shadow_mode;
+pub mod spec_token;
+pub mod state;
+pub mod templates;
+pub mod tx_verifier;
+pub mod util;
+pub mod wallet;
+pub mod wallet_consolidation;
+pub mod wallet_funds;
+
+#[cfg(test)]
+mod tests;
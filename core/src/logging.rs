@@ -1,4 +1,5 @@
 use std::path::Path;
+use std::path::PathBuf;
 
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
@@ -10,12 +11,46 @@ use log4rs::config::Appender;
 use log4rs::config::Logger;
 use log4rs::config::Root;
 use log4rs::Config;
+use log4rs::Handle;
+
+/// Allows to change the log level from the oracle config of a running oracle
+pub struct LogHandle {
+    handle: Handle,
+    cmdline_log_level: Option<LevelFilter>,
+    data_dir: PathBuf,
+}
+
+impl LogHandle {
+    pub fn set_config_log_level(&self, config_log_level: Option<LevelFilter>) {
+        self.handle.set_config(build_log_config(
+            self.cmdline_log_level,
+            config_log_level,
+            &self.data_dir,
+        ));
+    }
+}
 
 pub fn setup_log(
     cmdline_log_level: Option<LevelFilter>,
     config_log_level: Option<LevelFilter>,
     data_dir: &Path,
-) {
+) -> LogHandle {
+    let config = build_log_config(cmdline_log_level, config_log_level, data_dir);
+    let handle = log4rs::init_config(config).unwrap();
+
+    log_panics::init();
+    LogHandle {
+        handle,
+        cmdline_log_level,
+        data_dir: data_dir.to_path_buf(),
+    }
+}
+
+fn build_log_config(
+    cmdline_log_level: Option<LevelFilter>,
+    config_log_level: Option<LevelFilter>,
+    data_dir: &Path,
+) -> Config {
     let stdout = ConsoleAppender::builder().build();
 
     // via https://stackoverflow.com/questions/56345288/how-do-i-use-log4rs-rollingfileappender-to-incorporate-rolling-logging#
@@ -44,7 +79,7 @@ pub fn setup_log(
         config_log_level
     };
 
-    let config = Config::builder()
+    Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .appender(
             Appender::builder().build(
@@ -69,9 +104,5 @@ pub fn setup_log(
                 .appender("logfile")
                 .build(LevelFilter::Info),
         )
-        .unwrap();
-
-    let _ = log4rs::init_config(config).unwrap();
-
-    log_panics::init();
+        .unwrap()
 }
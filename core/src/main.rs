@@ -1,8 +1,4 @@
 // Coding conventions
-#![allow(dead_code)]
-#![allow(clippy::redundant_clone)]
-#![allow(clippy::ptr_arg)]
-#![allow(clippy::unit_arg)]
 #![forbid(unsafe_code)]
 #![deny(non_upper_case_globals)]
 #![deny(non_camel_case_types)]
@@ -12,102 +8,55 @@
 #![deny(clippy::wildcard_enum_match_arm)]
 #![deny(clippy::todo)]
 #![deny(clippy::unimplemented)]
-// #![allow(clippy::correctness)]
-// #![allow(clippy::almost_swapped)]
-
-#[macro_use]
-extern crate lazy_static;
-
-mod action_report;
-mod actions;
-mod address_util;
-mod api;
-mod box_kind;
-mod cli_commands;
-mod contracts;
-mod datapoint_source;
-mod default_parameters;
-mod explorer_api;
-mod logging;
-mod metrics;
-mod migrate;
-mod monitor;
-mod node_interface;
-mod oracle_config;
-mod oracle_state;
-mod oracle_types;
-mod pool_commands;
-mod pool_config;
-mod scans;
-mod serde;
-mod spec_token;
-mod state;
-mod templates;
-mod util;
-mod wallet;
-
-#[cfg(test)]
-mod tests;
-
-use action_report::ActionReportStorage;
-use action_report::PoolActionReport;
-use actions::PoolAction;
-use anyhow::anyhow;
-use anyhow::Context;
+
+use std::convert::TryFrom;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
 use clap::{Parser, Subcommand};
-use crossbeam::channel::bounded;
-use datapoint_source::RuntimeDataPointSource;
 use ergo_lib::ergo_chain_types::Digest32;
-use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
 use ergo_lib::ergotree_ir::chain::token::TokenAmount;
 use ergo_lib::ergotree_ir::chain::token::TokenId;
 use log::error;
 use log::LevelFilter;
-use metrics::start_metrics_server;
-use metrics::update_metrics;
-use node_interface::node_api::NodeApi;
-use node_interface::try_ensure_wallet_unlocked;
-use oracle_config::ORACLE_CONFIG;
-use oracle_config::ORACLE_SECRETS;
-use oracle_state::OraclePool;
-use oracle_types::BlockHeight;
-use pool_commands::build_action;
-use pool_commands::publish_datapoint::PublishDatapointActionError;
-use pool_commands::refresh::RefreshActionError;
-use pool_commands::PoolCommandError;
-use pool_config::DEFAULT_POOL_CONFIG_FILE_NAME;
-use pool_config::POOL_CONFIG;
-use scans::get_scans_file_path;
-use scans::wait_for_node_rescan;
-use spec_token::RewardTokenId;
-use spec_token::SpecToken;
-use spec_token::TokenIdKind;
-use state::process;
-use state::PoolState;
-use std::convert::TryFrom;
-use std::env;
-use std::path::Path;
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::sync::Arc;
-use std::sync::RwLock;
-use std::thread;
-use std::time::Duration;
-
-use crate::actions::execute_action;
-use crate::address_util::pks_to_network_addresses;
-use crate::api::start_rest_server;
-use crate::box_kind::BallotBox;
-use crate::contracts::ballot::BallotContract;
-use crate::default_parameters::print_contract_hashes;
-use crate::migrate::check_migration_to_split_config;
-use crate::oracle_config::OracleConfig;
-use crate::oracle_config::DEFAULT_ORACLE_CONFIG_FILE_NAME;
-use crate::oracle_config::ORACLE_CONFIG_FILE_PATH;
-use crate::oracle_config::ORACLE_CONFIG_OPT;
-use crate::pool_config::POOL_CONFIG_FILE_PATH;
-use crate::scans::NodeScanRegistry;
+use oracle_core::cli_commands;
+use oracle_core::config_reload::ConfigFiles;
+use oracle_core::context::OracleContext;
+use oracle_core::contracts::ballot::BallotContract;
+use oracle_core::default_parameters::print_contract_hashes;
+use oracle_core::logging;
+use oracle_core::migrate::check_migration_to_split_config;
+use oracle_core::node_interface::node_api::NodeApi;
+use oracle_core::node_interface::try_ensure_wallet_unlocked;
+use oracle_core::node_interface::try_unlock_backup_node_wallets;
+use oracle_core::oracle_config::BoxSourceConfig;
+use oracle_core::oracle_config::OracleConfig;
+use oracle_core::oracle_config::OracleSecrets;
+use oracle_core::oracle_config::DEFAULT_ORACLE_CONFIG_FILE_NAME;
+use oracle_core::oracle_state::OraclePool;
+use oracle_core::oracle_types::BlockHeight;
+use oracle_core::pool_config::PoolConfig;
+use oracle_core::pool_config::DEFAULT_POOL_CONFIG_FILE_NAME;
+use oracle_core::recording::DEFAULT_RECORDING_MAX_SIZE_MB;
+use oracle_core::runner;
+use oracle_core::running_pool::PoolSpec;
+use oracle_core::running_pool::DEFAULT_POOL_NAME;
+use oracle_core::scans::wait_for_node_rescan;
+use oracle_core::scans::NodeScanRegistry;
+use oracle_core::scans::PoolTokenBoxes;
+use oracle_core::secrets::encrypt_secrets_file;
+use oracle_core::secrets::Secret;
+use oracle_core::secrets::SecretSources;
+use oracle_core::spec_token::RewardTokenId;
+use oracle_core::spec_token::SpecToken;
+use oracle_core::spec_token::TokenIdKind;
+use oracle_core::wallet::token_guard::TokenGuardedWallet;
+use oracle_core::wallet::OracleWallet;
+use oracle_core::wallet::WalletDataSource;
+use oracle_core::wallet_consolidation::WalletConsolidationConfig;
 
 const APP_VERSION: &str = concat!(
     "v",
@@ -157,6 +106,22 @@ enum Command {
         /// Run in read-only mode
         #[clap(long)]
         read_only: bool,
+        /// Run in shadow mode (implies read-only). Transactions are built but not submitted and
+        /// are compared with the on-chain outcome, see the /shadowReport REST API endpoint.
+        #[clap(long)]
+        shadow: bool,
+        /// Record everything each main loop iteration saw and built to main_loop_recording.jsonl
+        /// in the data folder, see the replay command.
+        #[clap(long)]
+        record: bool,
+        /// Size in MB the recording is rotated at, the iterations recorded before the last
+        /// rotation are kept in main_loop_recording.jsonl.1
+        #[clap(long, value_name = "MB", default_value_t = DEFAULT_RECORDING_MAX_SIZE_MB)]
+        record_max_size_mb: u64,
+        /// Run the given pool instead of the one from --pool-config-file. Can be repeated to run
+        /// several pools sharing the node, the wallet and the REST API.
+        #[clap(long = "pool", value_name = "NAME=POOL_CONFIG_FILE")]
+        pools: Vec<PoolSpec>,
         #[clap(long)]
         /// Set this flag to enable the REST API. NOTE: SSL is not used!
         enable_rest_api: bool,
@@ -168,6 +133,19 @@ enum Command {
         rewards_address: String,
     },
 
+    /// Merge the small wallet boxes without tokens into one box
+    ConsolidateWallet {
+        /// Don't consolidate if the wallet has less small boxes
+        #[clap(long)]
+        min_boxes: Option<usize>,
+        /// Boxes with more coins (in nanoERG) are not consolidated
+        #[clap(long)]
+        max_box_value: Option<u64>,
+        /// Max number of boxes merged in one tx
+        #[clap(long)]
+        max_inputs: Option<usize>,
+    },
+
     /// Print the number of reward tokens earned by the oracle (in the last posted/collected oracle box)
     PrintRewardTokens,
 
@@ -204,8 +182,33 @@ enum Command {
         update_file: String,
     },
 
+    /// Encrypt the secrets file with the password in ORACLE_SECRETS_FILE_PASSWORD. Set
+    /// `secrets_file` in the oracle config to the encrypted file and delete the plain one.
+    EncryptSecretsFile {
+        /// The plain YAML secrets file
+        secrets_file: PathBuf,
+        /// Where to write the encrypted file (must not exist)
+        encrypted_file: PathBuf,
+    },
+
     /// Print base 64 encodings of the blake2b hash of ergo-tree bytes of each contract
     PrintContractHashes,
+    /// Check the live pool, refresh, update, oracle and ballot boxes against the contract
+    /// parameters and token ids in the pool config and print a pass/fail report
+    VerifyPool,
+    /// Replay the main loop iterations recorded with `run --record` offline and compare the
+    /// decisions with the recorded ones
+    Replay {
+        /// The recording file. Default is main_loop_recording.jsonl in the data folder.
+        #[clap(long)]
+        recording_file: Option<String>,
+        /// Replay the iterations of the given pool (the name from `run --pool`)
+        #[clap(long, default_value = DEFAULT_POOL_NAME)]
+        pool: String,
+        /// Write the replayed decisions to the given file as JSON lines
+        #[clap(long)]
+        output: Option<String>,
+    },
 
     ImportPoolUpdate {
         /// Name of the pool config file (.yaml) with new contract parameters
@@ -216,38 +219,32 @@ enum Command {
 fn main() {
     let args = Args::parse();
 
-    ORACLE_CONFIG_FILE_PATH
-        .set(
-            PathBuf::from_str(
-                &args
-                    .oracle_config_file
-                    .unwrap_or_else(|| DEFAULT_ORACLE_CONFIG_FILE_NAME.to_string()),
-            )
-            .unwrap(),
-        )
-        .unwrap();
-    POOL_CONFIG_FILE_PATH
-        .set(
-            PathBuf::from_str(
-                &args
-                    .pool_config_file
-                    .unwrap_or_else(|| DEFAULT_POOL_CONFIG_FILE_NAME.to_string()),
-            )
-            .unwrap(),
-        )
-        .unwrap();
+    if let Command::EncryptSecretsFile {
+        secrets_file,
+        encrypted_file,
+    } = &args.command
+    {
+        encrypt_secrets_file_or_exit(secrets_file, encrypted_file);
+        return;
+    }
 
-    let pool_config_path = POOL_CONFIG_FILE_PATH.get().unwrap();
-    let oracle_config_path = ORACLE_CONFIG_FILE_PATH.get().unwrap();
+    let oracle_config_path = PathBuf::from(
+        args.oracle_config_file
+            .unwrap_or_else(|| DEFAULT_ORACLE_CONFIG_FILE_NAME.to_string()),
+    );
+    let pool_config_path = PathBuf::from(
+        args.pool_config_file
+            .unwrap_or_else(|| DEFAULT_POOL_CONFIG_FILE_NAME.to_string()),
+    );
 
     if !pool_config_path.exists() && oracle_config_path.exists() {
-        if let Err(e) = check_migration_to_split_config(oracle_config_path, pool_config_path) {
+        if let Err(e) = check_migration_to_split_config(&oracle_config_path, &pool_config_path) {
             eprintln!("Failed to migrate to split config: {}", e);
         }
     }
 
     if !oracle_config_path.exists() {
-        OracleConfig::write_default_config_file(oracle_config_path);
+        OracleConfig::write_default_config_file(&oracle_config_path);
         println!(
             "{} not found. Default config file is generated.",
             oracle_config_path.display()
@@ -269,39 +266,93 @@ fn main() {
         env::current_dir().unwrap()
     };
 
-    let config_log_level = ORACLE_CONFIG_OPT
+    let oracle_config_res = OracleConfig::load_from_file(&oracle_config_path);
+    let config_log_level = oracle_config_res
         .as_ref()
         .map(|c| c.log_level)
         .ok()
         .flatten();
-    logging::setup_log(cmdline_log_level, config_log_level, &data_dir_path);
-
-    scans::SCANS_DIR_PATH.set(data_dir_path).unwrap();
-
-    let action_report_storage: Arc<RwLock<ActionReportStorage>> =
-        Arc::new(RwLock::new(ActionReportStorage::new()));
-
-    log_on_launch();
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &ORACLE_CONFIG.node_url,
-    );
-    try_ensure_wallet_unlocked(&node_api);
-    wait_for_node_rescan(&node_api).unwrap();
+    let log_handle = logging::setup_log(cmdline_log_level, config_log_level, &data_dir_path);
+    let oracle_config = match oracle_config_res {
+        Ok(oracle_config) => oracle_config,
+        Err(e) => {
+            error!(
+                "Failed to load oracle config from {}: {:?}",
+                oracle_config_path.display(),
+                e
+            );
+            std::process::exit(exitcode::CONFIG);
+        }
+    };
 
-    let pool_config = &POOL_CONFIG;
+    log_on_launch(&oracle_config);
+    let secrets = OracleSecrets::load(&oracle_config).unwrap_or_else(|e| {
+        error!("Failed to load the secrets: {}", e);
+        std::process::exit(exitcode::CONFIG);
+    });
+    let ctx = OracleContext::new(oracle_config, secrets, data_dir_path);
+    // the replay doesn't use the node
+    if let Command::Replay {
+        recording_file,
+        pool,
+        output,
+    } = &args.command
+    {
+        let recording_path = match recording_file {
+            Some(recording_file) => PathBuf::from(recording_file),
+            None => ctx.main_loop_recording().path().to_path_buf(),
+        };
+        let pool_config = load_pool_config_or_exit(&pool_config_path);
+        if let Err(e) = cli_commands::replay::replay(
+            &ctx,
+            &pool_config,
+            &recording_path,
+            pool,
+            output.as_deref().map(Path::new),
+        ) {
+            error!("Fatal replay error: {:?}", e);
+            std::process::exit(exitcode::SOFTWARE);
+        }
+        return;
+    }
+    let requires_node = ctx.oracle_config.box_source.requires_node();
+    if !requires_node
+        && !matches!(
+            args.command,
+            Command::Run { .. } | Command::GenerateOracleConfig | Command::PrintContractHashes
+        )
+    {
+        error!("box_source explorer doesn't use a node, only run --read-only is supported");
+        std::process::exit(exitcode::CONFIG);
+    }
+    let node_api = ctx.node_api();
+    let wallet = ctx.wallet().unwrap_or_else(|e| {
+        error!("Failed to set up the wallet: {:?}", e);
+        std::process::exit(exitcode::CONFIG);
+    });
+    if requires_node {
+        if let OracleWallet::Node(node_api) = &wallet {
+            try_ensure_wallet_unlocked(node_api);
+            try_unlock_backup_node_wallets(node_api);
+        }
+    }
+    // the node wallet rescan matters for the scans and the node wallet boxes only
+    if ctx.oracle_config.box_source == BoxSourceConfig::NodeScans
+        || (requires_node && matches!(wallet, OracleWallet::Node(_)))
+    {
+        wait_for_node_rescan(&node_api).unwrap();
+    }
 
-    let change_address = node_api
-        .get_change_address()
-        .expect("failed to get change address from the node");
+    let change_address = ctx
+        .change_address(&wallet)
+        .expect("failed to get change address from the wallet");
     let network_prefix = change_address.network();
 
     #[allow(clippy::wildcard_enum_match_arm)]
     match args.command {
         Command::GenerateOracleConfig => {
             if !oracle_config_path.exists() {
-                OracleConfig::write_default_config_file(oracle_config_path);
+                OracleConfig::write_default_config_file(&oracle_config_path);
                 println!("Default oracle_config.yaml file is generated.");
                 println!("Please, set the required parameters (node credentials, oracle_address)");
             } else {
@@ -316,7 +367,7 @@ fn main() {
                 if generate_config_template {
                     cli_commands::bootstrap::generate_bootstrap_config_template(yaml_config_name)?;
                 } else {
-                    cli_commands::bootstrap::bootstrap(yaml_config_name)?;
+                    cli_commands::bootstrap::bootstrap(&ctx, yaml_config_name)?;
                 }
                 Ok(())
             })() {
@@ -331,72 +382,122 @@ fn main() {
         }
         Command::Run {
             read_only,
+            shadow,
+            record,
+            record_max_size_mb,
+            pools,
             enable_rest_api,
         } => {
-            let tokio_runtime = tokio::runtime::Runtime::new().unwrap();
-            let (_, repost_receiver) = bounded::<bool>(1);
-
-            let node_scan_registry =
-                NodeScanRegistry::ensure_node_registered_scans(&node_api, pool_config).unwrap();
-            let oracle_pool = Arc::new(OraclePool::new(&node_scan_registry).unwrap());
-            let datapoint_source = RuntimeDataPointSource::new(
-                POOL_CONFIG.data_point_source,
-                ORACLE_CONFIG.data_point_source_custom_script.clone(),
-            )
-            .unwrap();
-
-            // Start Oracle Core GET API Server
-            if enable_rest_api {
-                let op_clone = oracle_pool.clone();
-                tokio_runtime.spawn(async {
-                    if let Err(e) =
-                        start_rest_server(repost_receiver, op_clone, ORACLE_CONFIG.core_api_port)
-                            .await
-                    {
-                        error!("An error occurred while starting the REST server: {}", e);
-                        std::process::exit(exitcode::SOFTWARE);
-                    }
-                });
-            }
-            if let Some(metrics_port) = ORACLE_CONFIG.metrics_port {
-                tokio_runtime.spawn(async move {
-                    if let Err(e) = start_metrics_server(metrics_port).await {
-                        error!("An error occurred while starting the metrics server: {}", e);
-                        std::process::exit(exitcode::SOFTWARE);
-                    }
-                });
+            let pools = if pools.is_empty() {
+                vec![PoolSpec {
+                    name: DEFAULT_POOL_NAME.to_string(),
+                    config_file_path: pool_config_path,
+                }]
+            } else {
+                pools
+            };
+            let config_files = ConfigFiles {
+                oracle_config_path,
+                pools,
+            };
+            let recording = record.then(|| {
+                ctx.main_loop_recording()
+                    .with_max_size(record_max_size_mb * 1024 * 1024)
+            });
+            if let Err(e) = runner::run(
+                Arc::new(ctx),
+                config_files,
+                Some(log_handle),
+                read_only,
+                shadow,
+                recording,
+                enable_rest_api,
+            ) {
+                error!("Fatal error: {:?}", e);
+                std::process::exit(exitcode::SOFTWARE);
             }
-            loop {
-                if let Err(e) = main_loop_iteration(
-                    oracle_pool.clone(),
-                    read_only,
-                    &datapoint_source,
-                    &node_api,
-                    action_report_storage.clone(),
-                    &change_address,
-                ) {
-                    error!("error: {:?}", e);
-                }
-                // Delay loop restart
-                thread::sleep(Duration::new(30, 0));
+        }
+        Command::ConsolidateWallet {
+            min_boxes,
+            max_box_value,
+            max_inputs,
+        } => {
+            let defaults = ctx.oracle_config.wallet_consolidation.unwrap_or_default();
+            let config = WalletConsolidationConfig {
+                min_boxes: min_boxes.unwrap_or(defaults.min_boxes),
+                max_box_value_nano_ergs: max_box_value.unwrap_or(defaults.max_box_value_nano_ergs),
+                max_inputs: max_inputs.unwrap_or(defaults.max_inputs),
+            };
+            let height = BlockHeight(node_api.node().current_block_height().unwrap() as u32);
+            if let Err(e) = cli_commands::consolidate_wallet::consolidate_wallet(
+                &ctx, &wallet, &wallet, &wallet, config, height,
+            ) {
+                error!("Fatal consolidate-wallet error: {:?}", e);
+                std::process::exit(exitcode::SOFTWARE);
             }
         }
-        oracle_command => handle_pool_command(oracle_command, &node_api, network_prefix),
+        oracle_command => handle_pool_command(
+            &ctx,
+            oracle_command,
+            &pool_config_path,
+            &node_api,
+            &wallet,
+            network_prefix,
+        ),
     }
 }
 
+fn load_pool_config_or_exit(pool_config_path: &Path) -> PoolConfig {
+    PoolConfig::load_from_file(pool_config_path).unwrap_or_else(|e| {
+        error!(
+            "Failed to load pool config from {}: {:?}",
+            pool_config_path.display(),
+            e
+        );
+        std::process::exit(exitcode::CONFIG);
+    })
+}
+
 /// Handle all other commands
-fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: NetworkPrefix) {
-    let height = BlockHeight(node_api.node.current_block_height().unwrap() as u32);
-    let node_scan_registry = NodeScanRegistry::load().unwrap();
-    let op = OraclePool::new(&node_scan_registry).unwrap();
+fn handle_pool_command(
+    ctx: &OracleContext,
+    command: Command,
+    pool_config_path: &Path,
+    node_api: &NodeApi,
+    wallet: &OracleWallet,
+    network_prefix: NetworkPrefix,
+) {
+    let height = BlockHeight(node_api.node().current_block_height().unwrap() as u32);
+    let pool_config = load_pool_config_or_exit(pool_config_path);
+    let (node_scan_registry, token_boxes) = match ctx.oracle_config.box_source {
+        BoxSourceConfig::NodeScans => {
+            let node_scan_registry =
+                NodeScanRegistry::load_from_file(&ctx.scans_file_path()).unwrap();
+            let token_boxes = PoolTokenBoxes::from_scans(&node_scan_registry);
+            (Some(node_scan_registry), token_boxes)
+        }
+        BoxSourceConfig::NodeIndexer => (None, PoolTokenBoxes::from_indexer(&pool_config)),
+        BoxSourceConfig::Explorer => (
+            None,
+            PoolTokenBoxes::from_explorer(&pool_config, Arc::new(ctx.explorer_api())),
+        ),
+    };
+    let op = OraclePool::new(
+        Arc::new(ctx.node_api()),
+        &token_boxes,
+        &pool_config,
+        ctx.oracle_config.oracle_address_p2pk().unwrap(),
+    )
+    .unwrap();
+    // the wallet boxes holding the pool tokens are only spent by the commands moving them
+    let wallet_boxes = TokenGuardedWallet::new(wallet).protect_pool(&pool_config.token_ids);
     match command {
         Command::ExtractRewardTokens { rewards_address } => {
             if let Err(e) = cli_commands::extract_reward_tokens::extract_reward_tokens(
-                // TODO: pass the NodeApi instance instead of these three
-                node_api,
-                &node_api.node,
-                &node_api.node,
+                ctx,
+                &wallet_boxes,
+                wallet,
+                wallet,
                 op.get_local_datapoint_box_source(),
                 rewards_address,
                 height,
@@ -406,6 +507,19 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
             }
         }
 
+        Command::VerifyPool => match cli_commands::verify_pool::verify_pool(&op) {
+            Ok(report) => {
+                print!("{}", report);
+                if !report.passed() {
+                    std::process::exit(exitcode::DATAERR);
+                }
+            }
+            Err(e) => {
+                error!("Fatal verify-pool error: {:?}", e);
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        },
+
         Command::PrintRewardTokens => {
             if let Err(e) = cli_commands::print_reward_tokens::print_reward_tokens(
                 op.get_local_datapoint_box_source(),
@@ -419,9 +533,10 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
             oracle_token_address,
         } => {
             if let Err(e) = cli_commands::transfer_oracle_token::transfer_oracle_token(
-                node_api,
-                &node_api.node,
-                &node_api.node,
+                ctx,
+                &wallet_boxes,
+                wallet,
+                wallet,
                 op.get_local_datapoint_box_source(),
                 oracle_token_address,
                 height,
@@ -451,13 +566,18 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
                     .collect::<Vec<_>>()
             );
             let ballot_contract = BallotContract::checked_load(
-                &POOL_CONFIG.ballot_box_wrapper_inputs.contract_inputs,
+                &pool_config.ballot_box_wrapper_inputs.contract_inputs,
             )
             .unwrap();
+            // the first vote moves the ballot token from the wallet to a ballot box
+            let wallet_boxes = wallet_boxes.allow(pool_config.token_ids.ballot_token_id.token_id());
             if let Err(e) = cli_commands::vote_update_pool::vote_update_pool(
+                ctx,
+                &pool_config,
+                &wallet_boxes,
                 node_api,
-                &node_api.node,
-                &node_api.node,
+                wallet,
+                wallet,
                 op.get_local_ballot_box_source(),
                 new_pool_box_address_hash_str,
                 reward_token_opt,
@@ -475,10 +595,12 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
         } => {
             let reward_token_opt = check_reward_token_opt(reward_token_id, reward_token_amount);
             if let Err(e) = cli_commands::update_pool::update_pool(
+                ctx,
                 &op,
+                &wallet_boxes,
                 node_api,
-                &node_api.node,
-                &node_api.node,
+                wallet,
+                wallet,
                 reward_token_opt,
                 height,
             ) {
@@ -488,7 +610,7 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
         }
         Command::PrepareUpdate { update_file } => {
             if let Err(e) =
-                cli_commands::prepare_update::prepare_update(update_file, node_api, height)
+                cli_commands::prepare_update::prepare_update(ctx, &op, update_file, wallet, height)
             {
                 error!("Fatal update error : {:?}", e);
                 std::process::exit(exitcode::SOFTWARE);
@@ -501,117 +623,54 @@ fn handle_pool_command(command: Command, node_api: &NodeApi, network_prefix: Net
             }
             if let Err(e) = cli_commands::import_pool_update::import_pool_update(
                 pool_config_file,
-                &POOL_CONFIG.token_ids.oracle_token_id,
-                &POOL_CONFIG.token_ids.reward_token_id,
-                POOL_CONFIG_FILE_PATH.get().unwrap(),
+                &pool_config,
+                pool_config_path,
                 op.get_local_datapoint_box_source(),
-                &get_scans_file_path(),
+                &ctx.scans_file_path(),
                 node_scan_registry,
                 node_api,
             ) {
                 error!("Fatal import pool update error : {:?}", e);
                 std::process::exit(exitcode::SOFTWARE);
             } else {
-                log::info!("pool config update imported successfully. Please, restart the oracle");
+                log::info!("pool config update imported successfully. A running oracle reloads it automatically");
                 std::process::exit(exitcode::OK);
             }
         }
         Command::Bootstrap { .. }
+        | Command::ConsolidateWallet { .. }
+        | Command::EncryptSecretsFile { .. }
         | Command::PrintContractHashes
         | Command::GenerateOracleConfig
+        | Command::Replay { .. }
         | Command::Run { .. } => unreachable!(),
     }
 }
 
-fn main_loop_iteration(
-    oracle_pool: Arc<OraclePool>,
-    read_only: bool,
-    datapoint_source: &RuntimeDataPointSource,
-    node_api: &NodeApi,
-    report_storage: Arc<RwLock<ActionReportStorage>>,
-    change_address: &NetworkAddress,
-) -> std::result::Result<(), anyhow::Error> {
-    if !node_api.node.wallet_status()?.unlocked {
-        return Err(anyhow!("Wallet is locked!"));
+fn encrypt_secrets_file_or_exit(secrets_file: &Path, encrypted_file: &Path) {
+    let password = SecretSources::load(None)
+        .and_then(|sources| sources.get_required(Secret::SecretsFilePassword))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(exitcode::CONFIG);
+        });
+    if let Err(e) = encrypt_secrets_file(secrets_file, encrypted_file, &password) {
+        eprintln!("{}", e);
+        std::process::exit(exitcode::SOFTWARE);
     }
-    let height = BlockHeight(
-        node_api
-            .node
-            .current_block_height()
-            .context("Failed to get the current height")? as u32,
+    println!(
+        "Encrypted secrets written to {}. Set `secrets_file` in the oracle config to it and delete {}",
+        encrypted_file.display(),
+        secrets_file.display()
     );
-    let pool_state = match oracle_pool.get_live_epoch_state() {
-        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
-        Err(error) => {
-            log::error!("error getting live epoch state: {:?}", error);
-            PoolState::NeedsBootstrap
-        }
-    };
-    let epoch_length = POOL_CONFIG
-        .refresh_box_wrapper_inputs
-        .contract_inputs
-        .contract_parameters()
-        .epoch_length();
-    if let Some(cmd) = process(pool_state, epoch_length, height) {
-        log::debug!("Height {height}. Building action for command: {:?}", cmd);
-        let build_action_tuple_res = build_action(
-            cmd,
-            &oracle_pool,
-            node_api,
-            height,
-            change_address.address(),
-            datapoint_source,
-        );
-        if let Some((action, report)) =
-            log_and_continue_if_non_fatal(change_address.network(), build_action_tuple_res)?
-        {
-            if !read_only {
-                execute_action(action, node_api)?;
-                report_storage.write().unwrap().add(report);
-            }
-        };
-    }
-    update_metrics(oracle_pool)?;
-    Ok(())
-}
-
-fn log_and_continue_if_non_fatal(
-    network_prefix: NetworkPrefix,
-    res: Result<(PoolAction, PoolActionReport), PoolCommandError>,
-) -> Result<Option<(PoolAction, PoolActionReport)>, anyhow::Error> {
-    match res {
-        Ok(tuple) => Ok(Some(tuple)),
-        Err(PoolCommandError::RefreshActionError(RefreshActionError::FailedToReachConsensus {
-            expected,
-            found_public_keys,
-            found_num,
-        })) => {
-            let found_oracle_addresses: String =
-                pks_to_network_addresses(found_public_keys, network_prefix)
-                    .into_iter()
-                    .map(|net_addr| net_addr.to_base58())
-                    .collect::<Vec<String>>()
-                    .join(", ");
-            log::error!("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from addresses {found_oracle_addresses},");
-            Ok(None)
-        }
-        Err(PoolCommandError::PublishDatapointActionError(
-            PublishDatapointActionError::DataPointSource(e),
-        )) => {
-            log::error!("Failed to get datapoint with error: {}", e);
-            Ok(None)
-        }
-        Err(e) => Err(e.into()),
-    }
 }
 
-fn log_on_launch() {
+fn log_on_launch(oracle_config: &OracleConfig) {
     log::info!("{}", APP_VERSION);
-    let oracle_address_opt = ORACLE_CONFIG_OPT.as_ref().map(|c| c.oracle_address.clone());
-    if let Ok(oracle_address) = oracle_address_opt {
-        // log::info!("Token ids: {:?}", config.token_ids);
-        log::info!("Oracle address: {}", oracle_address.to_base58());
-    }
+    log::info!(
+        "Oracle address: {}",
+        oracle_config.oracle_address.to_base58()
+    );
 }
 
 fn check_reward_token_opt(
@@ -6,9 +6,11 @@ use axum::response::IntoResponse;
 use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
 use ergo_node_interface::scanning::NodeError;
 use once_cell::sync::Lazy;
 use prometheus::Encoder;
+use prometheus::IntCounterVec;
 use prometheus::IntGauge;
 use prometheus::IntGaugeVec;
 use prometheus::Opts;
@@ -21,41 +23,46 @@ use crate::monitor::check_oracle_health;
 use crate::monitor::check_pool_health;
 use crate::monitor::OracleHealth;
 use crate::monitor::PoolHealth;
-use crate::node_interface::node_api::NodeApi;
-use crate::oracle_config::ORACLE_CONFIG;
-use crate::oracle_config::ORACLE_SECRETS;
+use crate::node_interface::node_endpoints::NodeEndpoints;
+use crate::node_interface::node_endpoints::NodeHealth;
 use crate::oracle_state::OraclePool;
+use crate::oracle_types::EpochLength;
+use crate::scheduler::ShutdownSignal;
+use crate::wallet_funds::WalletFundsReport;
 
-static POOL_BOX_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static POOL_BOX_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new("pool_box_height", "The height of the pool box")
             .namespace("ergo")
             .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static POOL_BOX_RATE: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static POOL_BOX_RATE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new("pool_box_rate", "exchange rate from the pool box")
             .namespace("ergo")
             .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static POOL_BOX_REWARD_TOKEN_AMOUNT: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static POOL_BOX_REWARD_TOKEN_AMOUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "pool_box_reward_token_amount",
             "The amount of reward token in the pool box",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
@@ -73,39 +80,42 @@ static CURRENT_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     m
 });
 
-static EPOCH_LENGTH: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static EPOCH_LENGTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new("epoch_length", "The epoch length")
             .namespace("ergo")
             .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static POOL_IS_HEALTHY: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static POOL_IS_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "pool_is_healthy",
             "The health status of the pool, 1 for Ok and 0 for Down",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static ORACLE_IS_HEALTHY: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static ORACLE_IS_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "oracle_is_healthy",
             "The health status of the oracle, 1 for Ok and 0 for Down",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
@@ -120,21 +130,22 @@ static MY_ORACLE_BOX_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
         )
         .namespace("ergo")
         .subsystem("oracle"),
-        &["box_type"],
+        &["pool", "box_type"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static MY_ORACLE_CLAIMABLE_REWARDS: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static MY_ORACLE_CLAIMABLE_REWARDS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "oracle_claimable_rewards",
             "The amount of claimable rewards for this oracle",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
 
@@ -150,7 +161,7 @@ static ALL_ORACLE_BOX_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
         )
         .namespace("ergo")
         .subsystem("oracle"),
-        &["box_type", "oracle_address"],
+        &["pool", "box_type", "oracle_address"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
@@ -165,7 +176,7 @@ static ALL_ORACLE_CLAIMABLE_REWARDS: Lazy<IntGaugeVec> = Lazy::new(|| {
         )
         .namespace("ergo")
         .subsystem("oracle"),
-        &["oracle_address"],
+        &["pool", "oracle_address"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
@@ -180,43 +191,46 @@ static ACTIVE_ORACLE_BOX_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
         )
         .namespace("ergo")
         .subsystem("oracle"),
-        &["box_type", "oracle_address"],
+        &["pool", "box_type", "oracle_address"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static ACTIVE_ORACLE_COUNT: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static ACTIVE_ORACLE_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new("active_oracle_count", "The number of active oracles")
             .namespace("ergo")
             .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static TOTAL_ORACLE_COUNT: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static TOTAL_ORACLE_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new("total_oracle_count", "The total number of oracle tokens")
             .namespace("ergo")
             .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-static REQUIRED_ORACLE_COUNT: Lazy<IntGauge> = Lazy::new(|| {
-    let m = IntGauge::with_opts(
+static REQUIRED_ORACLE_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "required_oracle_count",
             "The minimum number of active oracles",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
@@ -227,7 +241,7 @@ static ORACLE_NODE_WALLET_BALANCE: Lazy<IntGauge> = Lazy::new(|| {
     let m = IntGauge::with_opts(
         Opts::new(
             "oracle_node_wallet_nano_erg",
-            "Coins in the oracle's node wallet",
+            "Coins in the oracle's wallet",
         )
         .namespace("ergo")
         .subsystem("oracle"),
@@ -237,53 +251,227 @@ static ORACLE_NODE_WALLET_BALANCE: Lazy<IntGauge> = Lazy::new(|| {
     m
 });
 
-static REWARD_TOKENS_IN_BUYBACK_BOX: Lazy<IntGauge> = Lazy::new(|| {
+static ORACLE_WALLET_BOX_COUNT: Lazy<IntGauge> = Lazy::new(|| {
     let m = IntGauge::with_opts(
+        Opts::new(
+            "oracle_wallet_box_count",
+            "The number of unspent boxes in the oracle's wallet",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static ORACLE_WALLET_LOW_BALANCE: Lazy<IntGauge> = Lazy::new(|| {
+    let m = IntGauge::with_opts(
+        Opts::new(
+            "oracle_wallet_low_balance",
+            "1 if the coins in the oracle's wallet are below the low balance threshold",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static ORACLE_WALLET_LOW_BALANCE_THRESHOLD: Lazy<IntGauge> = Lazy::new(|| {
+    let m = IntGauge::with_opts(
+        Opts::new(
+            "oracle_wallet_low_balance_threshold_nano_erg",
+            "The low balance threshold of the oracle's wallet",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static ORACLE_WALLET_SPEND_PER_EPOCH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "oracle_wallet_spend_per_epoch_nano_erg",
+            "The observed spend of the oracle's wallet per epoch",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+        &["pool"],
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static ORACLE_WALLET_RUNWAY_EPOCHS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "oracle_wallet_runway_epochs",
+            "The estimated number of epochs until the oracle's wallet runs out of coins (-1 if no spend is observed)",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+        &["pool"],
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static REWARD_TOKENS_IN_BUYBACK_BOX: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
         Opts::new(
             "reward_tokens_in_buyback_box",
             "The amount of reward tokens in the buyback box",
         )
         .namespace("ergo")
         .subsystem("oracle"),
+        &["pool"],
     )
     .unwrap();
     prometheus::register(Box::new(m.clone())).expect("Failed to register");
     m
 });
 
-fn update_pool_health(pool_health: &PoolHealth) {
-    POOL_BOX_HEIGHT.set(pool_health.details.pool_box_height.into());
+static ACTIVE_NODE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "active_node",
+            "1 for the node the requests go to, 0 for the other nodes",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+        &["node"],
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static NODE_IS_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    let m = IntGaugeVec::new(
+        Opts::new(
+            "node_is_healthy",
+            "The health status of the node, 1 for Ok and 0 for Down",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+        &["node"],
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+static TX_INPUT_BOX_NOT_FOUND: Lazy<IntCounterVec> = Lazy::new(|| {
+    let m = IntCounterVec::new(
+        Opts::new(
+            "tx_input_box_not_found",
+            "The number of txs skipped because an input box was not found on the node",
+        )
+        .namespace("ergo")
+        .subsystem("oracle"),
+        &["pool"],
+    )
+    .unwrap();
+    prometheus::register(Box::new(m.clone())).expect("Failed to register");
+    m
+});
+
+/// Counts a tx not submitted because its input box is spent or not yet known to the node
+pub fn inc_tx_input_box_not_found(pool_name: &str) {
+    TX_INPUT_BOX_NOT_FOUND.with_label_values(&[pool_name]).inc();
+}
+
+/// Updates the node metrics with the health from [`NodeEndpoints::check_and_select`]
+pub fn update_node_metrics(nodes: &NodeEndpoints, health: &[NodeHealth]) {
+    let active = nodes.active_index();
+    for (index, (endpoint, health)) in nodes.endpoints().iter().zip(health).enumerate() {
+        ACTIVE_NODE
+            .with_label_values(&[endpoint.name()])
+            .set((index == active) as i64);
+        NODE_IS_HEALTHY
+            .with_label_values(&[endpoint.name()])
+            .set((*health == NodeHealth::Healthy) as i64);
+    }
+}
+
+fn update_pool_health(pool_name: &str, pool_health: &PoolHealth) {
+    POOL_BOX_HEIGHT
+        .with_label_values(&[pool_name])
+        .set(pool_health.details.pool_box_height.into());
     CURRENT_HEIGHT.set(pool_health.details.current_height.into());
-    EPOCH_LENGTH.set(pool_health.details.epoch_length.into());
-    POOL_IS_HEALTHY.set(pool_health.status as i64);
+    EPOCH_LENGTH
+        .with_label_values(&[pool_name])
+        .set(pool_health.details.epoch_length.into());
+    POOL_IS_HEALTHY
+        .with_label_values(&[pool_name])
+        .set(pool_health.status as i64);
     for oracle in &pool_health.details.all_oracle_boxes {
         let box_type = oracle.box_height.label_name();
         let box_height = oracle.box_height.oracle_box_height().into();
         ALL_ORACLE_BOX_HEIGHT
-            .with_label_values(&[box_type, &oracle.address.to_base58()])
+            .with_label_values(&[pool_name, box_type, &oracle.address.to_base58()])
             .set(box_height);
     }
     for oracle in &pool_health.details.active_oracle_boxes {
         let box_type = oracle.box_height.label_name();
         let box_height = oracle.box_height.oracle_box_height().into();
         ACTIVE_ORACLE_BOX_HEIGHT
-            .with_label_values(&[box_type, &oracle.address.to_base58()])
+            .with_label_values(&[pool_name, box_type, &oracle.address.to_base58()])
             .set(box_height);
     }
-    ACTIVE_ORACLE_COUNT.set(pool_health.details.active_oracle_boxes.len() as i64);
-    REQUIRED_ORACLE_COUNT.set(pool_health.details.min_data_points.into());
-    TOTAL_ORACLE_COUNT.set(pool_health.details.total_oracle_token_count as i64);
+    ACTIVE_ORACLE_COUNT
+        .with_label_values(&[pool_name])
+        .set(pool_health.details.active_oracle_boxes.len() as i64);
+    REQUIRED_ORACLE_COUNT
+        .with_label_values(&[pool_name])
+        .set(pool_health.details.min_data_points.into());
+    TOTAL_ORACLE_COUNT
+        .with_label_values(&[pool_name])
+        .set(pool_health.details.total_oracle_token_count as i64);
 }
 
-fn update_oracle_health(oracle_health: &OracleHealth) {
+fn update_oracle_health(pool_name: &str, oracle_health: &OracleHealth) {
     let box_type = oracle_health.details.box_details.label_name();
     MY_ORACLE_BOX_HEIGHT
-        .with_label_values(&[box_type])
+        .with_label_values(&[pool_name, box_type])
         .set(oracle_health.details.box_details.oracle_box_height().into());
-    ORACLE_IS_HEALTHY.set(oracle_health.status as i64);
+    ORACLE_IS_HEALTHY
+        .with_label_values(&[pool_name])
+        .set(oracle_health.status as i64);
+}
+
+fn update_wallet_funds(
+    pool_name: &str,
+    wallet_funds: &WalletFundsReport,
+    epoch_length: EpochLength,
+) {
+    ORACLE_NODE_WALLET_BALANCE.set(wallet_funds.nano_ergs as i64);
+    ORACLE_WALLET_BOX_COUNT.set(wallet_funds.box_count as i64);
+    ORACLE_WALLET_LOW_BALANCE.set(wallet_funds.low_balance as i64);
+    ORACLE_WALLET_LOW_BALANCE_THRESHOLD.set(wallet_funds.low_balance_threshold as i64);
+    if let Some(spend_per_epoch) = wallet_funds.spend_per_epoch(epoch_length) {
+        ORACLE_WALLET_SPEND_PER_EPOCH
+            .with_label_values(&[pool_name])
+            .set(spend_per_epoch as i64);
+    }
+    ORACLE_WALLET_RUNWAY_EPOCHS
+        .with_label_values(&[pool_name])
+        .set(
+            wallet_funds
+                .runway_epochs(epoch_length)
+                .map(|epochs| epochs as i64)
+                .unwrap_or(-1),
+        );
 }
 
-fn update_reward_tokens_in_buyback_box(oracle_pool: Arc<OraclePool>) {
+fn update_reward_tokens_in_buyback_box(pool_name: &str, oracle_pool: Arc<OraclePool>) {
     if let Some(buyback_box) = oracle_pool
         .get_buyback_box_source()
         .map(|s| s.get_buyback_box())
@@ -296,28 +484,30 @@ fn update_reward_tokens_in_buyback_box(oracle_pool: Arc<OraclePool>) {
             .reward_token()
             .map(|t| t.amount.into())
             .unwrap_or(0);
-        REWARD_TOKENS_IN_BUYBACK_BOX.set(reward_token_amount);
+        REWARD_TOKENS_IN_BUYBACK_BOX
+            .with_label_values(&[pool_name])
+            .set(reward_token_amount);
     }
 }
 
-fn update_oracle_claimable_reward_tokens(pool_health: &PoolHealth) {
+fn update_oracle_claimable_reward_tokens(pool_name: &str, pool_health: &PoolHealth) {
     for oracle in &pool_health.details.all_oracle_boxes {
         let reward_tokens = oracle.reward_tokens;
 
         if reward_tokens > 0 {
             let claimable_tokens = reward_tokens - 1;
             ALL_ORACLE_CLAIMABLE_REWARDS
-                .with_label_values(&[&oracle.address.to_base58()])
+                .with_label_values(&[pool_name, &oracle.address.to_base58()])
                 .set(claimable_tokens as i64);
         } else {
             ALL_ORACLE_CLAIMABLE_REWARDS
-                .with_label_values(&[&oracle.address.to_base58()])
+                .with_label_values(&[pool_name, &oracle.address.to_base58()])
                 .set(0);
         }
     }
 }
 
-fn update_my_claimable_reward_tokens(oracle_pool: Arc<OraclePool>) {
+fn update_my_claimable_reward_tokens(pool_name: &str, oracle_pool: Arc<OraclePool>) {
     if let Some(oracle_box) = oracle_pool
         .get_local_datapoint_box_source()
         .get_local_oracle_datapoint_box()
@@ -325,27 +515,31 @@ fn update_my_claimable_reward_tokens(oracle_pool: Arc<OraclePool>) {
         .flatten()
     {
         let num_tokens = *oracle_box.reward_token().amount.as_u64();
-        if num_tokens == 0 {
-            MY_ORACLE_CLAIMABLE_REWARDS.set(num_tokens as i64)
+        let claimable_tokens = if num_tokens == 0 {
+            num_tokens
         } else {
-            let claimable_tokens = num_tokens - 1;
-            MY_ORACLE_CLAIMABLE_REWARDS.set(claimable_tokens as i64)
-        }
+            num_tokens - 1
+        };
+        MY_ORACLE_CLAIMABLE_REWARDS
+            .with_label_values(&[pool_name])
+            .set(claimable_tokens as i64)
     }
 }
 
-pub fn update_metrics(oracle_pool: Arc<OraclePool>) -> Result<(), anyhow::Error> {
-    let node_api = NodeApi::new(
-        ORACLE_SECRETS.node_api_key.clone(),
-        ORACLE_SECRETS.wallet_password.clone(),
-        &ORACLE_CONFIG.node_url,
-    );
-    let current_height = (node_api.node.current_block_height()? as u32).into();
-    let network_prefix = node_api.get_change_address()?.network();
+/// Updates the metrics of the given pool, labeled with `pool_name`
+pub fn update_metrics(
+    pool_name: &str,
+    oracle_pool: Arc<OraclePool>,
+    network_prefix: NetworkPrefix,
+    wallet_funds: Option<&WalletFundsReport>,
+) -> Result<(), anyhow::Error> {
+    let current_height = oracle_pool.current_height()?;
     let pool_box = &oracle_pool.get_pool_box_source().get_pool_box()?;
     {
         let rate = pool_box.rate();
-        POOL_BOX_RATE.set(rate.into());
+        POOL_BOX_RATE
+            .with_label_values(&[pool_name])
+            .set(rate.into());
     };
     let pool_box_height = pool_box.get_box().creation_height.into();
     let pool_health = check_pool_health(
@@ -355,20 +549,24 @@ pub fn update_metrics(oracle_pool: Arc<OraclePool>) -> Result<(), anyhow::Error>
         oracle_pool.clone(),
         network_prefix,
     )?;
-    update_pool_health(&pool_health);
+    update_pool_health(pool_name, &pool_health);
     let oracle_health = check_oracle_health(
         oracle_pool.clone(),
         pool_box_height,
         current_height,
         pool_health.details.epoch_length,
+        wallet_funds,
     )?;
-    update_oracle_health(&oracle_health);
-    let wallet_balance: i64 = node_api.node.wallet_nano_ergs_balance()? as i64;
-    ORACLE_NODE_WALLET_BALANCE.set(wallet_balance);
-    POOL_BOX_REWARD_TOKEN_AMOUNT.set(pool_box.reward_token().amount.into());
-    update_reward_tokens_in_buyback_box(oracle_pool.clone());
-    update_my_claimable_reward_tokens(oracle_pool);
-    update_oracle_claimable_reward_tokens(&pool_health);
+    update_oracle_health(pool_name, &oracle_health);
+    if let Some(wallet_funds) = wallet_funds {
+        update_wallet_funds(pool_name, wallet_funds, pool_health.details.epoch_length);
+    }
+    POOL_BOX_REWARD_TOKEN_AMOUNT
+        .with_label_values(&[pool_name])
+        .set(pool_box.reward_token().amount.into());
+    update_reward_tokens_in_buyback_box(pool_name, oracle_pool.clone());
+    update_my_claimable_reward_tokens(pool_name, oracle_pool);
+    update_oracle_claimable_reward_tokens(pool_name, &pool_health);
     Ok(())
 }
 
@@ -386,7 +584,10 @@ async fn serve_metrics() -> impl IntoResponse {
         .unwrap()
 }
 
-pub async fn start_metrics_server(port_num: u16) -> Result<(), anyhow::Error> {
+pub async fn start_metrics_server(
+    port_num: u16,
+    shutdown: ShutdownSignal,
+) -> Result<(), anyhow::Error> {
     let app = Router::new().route("/metrics", get(serve_metrics)).layer(
         CorsLayer::new()
             .allow_origin(tower_http::cors::Any)
@@ -396,7 +597,9 @@ pub async fn start_metrics_server(port_num: u16) -> Result<(), anyhow::Error> {
     log::info!("Starting metrics server on {}", addr);
     axum::Server::try_bind(&addr)?
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown.triggered())
         .await?;
+    log::info!("Metrics server stopped");
     Ok(())
 }
 
@@ -13,7 +13,8 @@ use crate::oracle_types::BlockHeight;
 use crate::oracle_types::EpochLength;
 use crate::oracle_types::MinDatapoints;
 use crate::oracle_types::Rate;
-use crate::pool_config::POOL_CONFIG;
+use crate::wallet_funds::PoolWalletFunds;
+use crate::wallet_funds::WalletFundsReport;
 
 #[derive(Debug, serde::Serialize, Copy, Clone, PartialEq, Eq)]
 pub enum HealthStatus {
@@ -68,14 +69,13 @@ pub fn check_pool_health(
     oracle_pool: Arc<OraclePool>,
     network_prefix: NetworkPrefix,
 ) -> Result<PoolHealth, anyhow::Error> {
-    let pool_conf = &POOL_CONFIG;
-    let epoch_length = pool_conf
+    let refresh_contract_parameters = oracle_pool
+        .pool_config()
         .refresh_box_wrapper_inputs
         .contract_inputs
-        .contract_parameters()
-        .epoch_length()
-        .0
-        .into();
+        .contract_parameters();
+    let epoch_length = refresh_contract_parameters.epoch_length().0.into();
+    let min_data_points = refresh_contract_parameters.min_data_points();
     let acceptable_pool_box_delay_blocks = 3;
     let is_healthy = pool_box_height >= current_height - epoch_length - acceptable_pool_box_delay_blocks
         // on bootstrap pool box created with rate 0
@@ -101,11 +101,7 @@ pub fn check_pool_health(
             epoch_length,
             all_oracle_boxes: all_oracles,
             active_oracle_boxes: active_oracles,
-            min_data_points: pool_conf
-                .refresh_box_wrapper_inputs
-                .contract_inputs
-                .contract_parameters()
-                .min_data_points(),
+            min_data_points,
             total_oracle_token_count,
         },
     })
@@ -210,6 +206,8 @@ impl From<CollectedOracleBox> for OracleBoxDetails {
 pub struct OracleHealthDetails {
     pub pool_box_height: BlockHeight,
     pub box_details: OracleBoxDetails,
+    /// None until the wallet is checked by the main loop
+    pub wallet_funds: Option<PoolWalletFunds>,
 }
 
 pub fn check_oracle_health(
@@ -217,8 +215,14 @@ pub fn check_oracle_health(
     pool_box_height: BlockHeight,
     current_height: BlockHeight,
     epoch_length: EpochLength,
+    wallet_funds: Option<&WalletFundsReport>,
 ) -> Result<OracleHealth, anyhow::Error> {
     let min_healthy_height = current_height - epoch_length;
+    let wallet_funds = wallet_funds.map(|f| f.pool_funds(epoch_length));
+    let has_funds = !wallet_funds
+        .as_ref()
+        .map(|f| f.low_balance)
+        .unwrap_or(false);
     let health = match oracle_pool
         .get_local_datapoint_box_source()
         .get_local_oracle_datapoint_box()?
@@ -226,23 +230,25 @@ pub fn check_oracle_health(
     {
         OracleBoxWrapper::Posted(posted_box) => {
             let posted_box_height = posted_box.get_box().creation_height.into();
-            let is_healthy = posted_box_height > min_healthy_height;
+            let is_healthy = posted_box_height > min_healthy_height && has_funds;
             OracleHealth {
                 status: HealthStatus::from_bool(is_healthy),
                 details: OracleHealthDetails {
                     pool_box_height,
                     box_details: OracleBoxDetails::PostedBox(posted_box_height),
+                    wallet_funds,
                 },
             }
         }
         OracleBoxWrapper::Collected(collected_box) => {
             let collected_box_height = collected_box.get_box().creation_height.into();
-            let is_healthy = collected_box_height > min_healthy_height;
+            let is_healthy = collected_box_height > min_healthy_height && has_funds;
             OracleHealth {
                 status: HealthStatus::from_bool(is_healthy),
                 details: OracleHealthDetails {
                     pool_box_height,
                     box_details: OracleBoxDetails::CollectedBox(collected_box_height),
+                    wallet_funds,
                 },
             }
         }
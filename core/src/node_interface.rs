@@ -1,4 +1,6 @@
 use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_api::NodeApiError;
+use crate::pool_config::PoolConfig;
 use ergo_lib::{
     chain::transaction::{unsigned::UnsignedTransaction, Transaction, TxId, TxIoVec},
     ergotree_ir::chain::ergo_box::ErgoBox,
@@ -6,8 +8,11 @@ use ergo_lib::{
 use ergo_node_interface::node_interface::{NodeError, NodeInterface};
 use log::debug;
 use log::error;
+use log::warn;
 
+pub mod async_node;
 pub mod node_api;
+pub mod node_endpoints;
 
 pub type Result<T> = std::result::Result<T, NodeError>;
 
@@ -28,6 +33,15 @@ pub trait SignTransaction {
     fn sign_transaction(&self, unsigned_tx: &UnsignedTransaction) -> Result<Transaction>;
 }
 
+/// Evaluates the input scripts of a transaction locally before it is signed and submitted
+pub trait VerifyTransaction {
+    fn verify_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        pool_config: &PoolConfig,
+    ) -> std::result::Result<(), NodeApiError>;
+}
+
 // Note that we need the following trait implementations for `NodeInterface` because we can't rely
 // on any of the functions in the `crate::node_interface` module since they all implicitly rely on
 // the existence of an oracle-pool `yaml` config file.
@@ -64,7 +78,7 @@ impl SignTransactionWithInputs for NodeInterface {
 }
 
 pub fn try_ensure_wallet_unlocked(node: &NodeApi) {
-    let unlocked = node.node.wallet_status().unwrap().unlocked;
+    let unlocked = node.node().wallet_status().unwrap().unlocked;
 
     if !unlocked {
         if let Some(wallet_pass) = &node.wallet_pass {
@@ -80,3 +94,29 @@ pub fn try_ensure_wallet_unlocked(node: &NodeApi) {
         debug!("Wallet unlocked");
     }
 }
+
+/// Unlocks the wallets of the backup nodes, so they can sign when the node wallet signer fails
+/// over to them. Unlike the primary node, a backup node failing to unlock is not fatal.
+pub fn try_unlock_backup_node_wallets(node: &NodeApi) {
+    let wallet_pass = match &node.wallet_pass {
+        Some(wallet_pass) => wallet_pass,
+        None => return,
+    };
+    for (index, endpoint) in node.endpoints().endpoints().iter().enumerate().skip(1) {
+        let backup_node = node.pinned_to(index);
+        let unlocked = backup_node
+            .node()
+            .wallet_status()
+            .map(|status| status.unlocked)
+            .unwrap_or(false);
+        if !unlocked {
+            if let Err(e) = backup_node.wallet_unlock(wallet_pass) {
+                warn!(
+                    "Failed to unlock the wallet of backup node {}: {:?}",
+                    endpoint.name(),
+                    e
+                );
+            }
+        }
+    }
+}
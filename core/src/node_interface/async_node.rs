@@ -0,0 +1,145 @@
+//! Async client for the read requests of the box sources, used by the REST server to fetch the
+//! pool boxes without tying up a blocking thread per node request. The requests go to the active
+//! node of the shared [`NodeEndpoints`].
+use std::sync::Arc;
+
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_node_interface::ScanId;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::node_api::NodeApiError;
+use super::node_endpoints::NodeEndpoints;
+
+/// An entry of GET /scan/unspentBoxes/{scanId}
+#[derive(Deserialize)]
+struct ScanBox {
+    #[serde(rename = "box")]
+    ergo_box: ErgoBox,
+}
+
+pub struct AsyncNodeClient {
+    client: reqwest::Client,
+    nodes: Arc<NodeEndpoints>,
+}
+
+impl AsyncNodeClient {
+    pub fn new(nodes: Arc<NodeEndpoints>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            nodes,
+        }
+    }
+
+    /// GET request to the active node, `endpoint` is relative to the node URL
+    async fn get_json<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T, NodeApiError> {
+        let node = self.nodes.active();
+        let res = self
+            .client
+            .get(endpoint_url(&node.url, endpoint)?)
+            .header("accept", "application/json")
+            .header("api_key", node.api_key())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(serde_json::from_str(&res.text().await?)?)
+    }
+
+    pub async fn current_block_height(&self) -> Result<u32, NodeApiError> {
+        #[derive(Deserialize)]
+        struct Info {
+            #[serde(rename = "fullHeight")]
+            full_height: u32,
+        }
+        let info: Info = self.get_json("info").await?;
+        Ok(info.full_height)
+    }
+
+    /// Boxes of the scan registered on the primary node with `scan_id` (see
+    /// [`super::node_api::NodeApi::scan_boxes`])
+    pub async fn scan_boxes(&self, scan_id: ScanId) -> Result<Vec<ErgoBox>, NodeApiError> {
+        let index = self.nodes.active_index();
+        let node_scan_id = self.nodes.node_scan_id(index, scan_id).ok_or_else(|| {
+            NodeApiError::ScanNotRegistered {
+                scan_id: scan_id.to_string(),
+                node: self.nodes.endpoints()[index].name().to_string(),
+            }
+        })?;
+        let scan_boxes: Vec<ScanBox> = self
+            .get_json(&format!("scan/unspentBoxes/{node_scan_id}"))
+            .await?;
+        Ok(scan_boxes.into_iter().map(|b| b.ergo_box).collect())
+    }
+
+    /// Unspent boxes holding the token from the node extra index
+    pub async fn get_unspent_boxes_by_token_id(
+        &self,
+        token_id: TokenId,
+    ) -> Result<Vec<ErgoBox>, NodeApiError> {
+        const PAGE_SIZE: usize = 100;
+        let token_id = String::from(token_id);
+        let mut boxes: Vec<ErgoBox> = vec![];
+        loop {
+            let page: Vec<ErgoBox> = self
+                .get_json(&format!(
+                    "blockchain/box/unspent/byTokenId/{}?offset={}&limit={}",
+                    token_id,
+                    boxes.len(),
+                    PAGE_SIZE
+                ))
+                .await?;
+            let page_len = page.len();
+            boxes.extend(page);
+            if page_len < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(boxes)
+    }
+}
+
+/// URL of the endpoint of the node at `node_url`. The node can be served under a path prefix
+/// (e.g. `http://host/ergo/`), so the endpoint is joined as a relative path onto the node URL
+/// with a trailing slash.
+fn endpoint_url(node_url: &Url, endpoint: &str) -> Result<Url, url::ParseError> {
+    let mut base = node_url.clone();
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    base.join(endpoint.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_url() {
+        let url = |node_url: &str, endpoint: &str| {
+            endpoint_url(&Url::parse(node_url).unwrap(), endpoint)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            url("http://127.0.0.1:9053", "info"),
+            "http://127.0.0.1:9053/info"
+        );
+        assert_eq!(
+            url("https://example.com/ergo/", "info"),
+            "https://example.com/ergo/info"
+        );
+        assert_eq!(
+            url("https://example.com/ergo", "scan/unspentBoxes/10"),
+            "https://example.com/ergo/scan/unspentBoxes/10"
+        );
+        assert_eq!(
+            url(
+                "https://example.com/ergo/",
+                "blockchain/box/unspent/byTokenId/abc?offset=0&limit=100"
+            ),
+            "https://example.com/ergo/blockchain/box/unspent/byTokenId/abc?offset=0&limit=100"
+        );
+    }
+}
@@ -1,35 +1,166 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
 use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
 use ergo_lib::chain::transaction::TxId;
+use ergo_lib::ergo_chain_types::Header;
+use ergo_lib::ergo_chain_types::PreHeader;
 use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
 use ergo_lib::ergotree_ir::chain::address::AddressEncoderError;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
 use ergo_node_interface::scanning::NodeError;
 use ergo_node_interface::NodeInterface;
 use ergo_node_interface::ScanId;
 use log::info;
+use reqwest::blocking::Response;
 use reqwest::Url;
+use serde::Deserialize;
 use serde_json::json;
 use thiserror::Error;
 
+use crate::pool_config::PoolConfig;
 use crate::scans::ScanID;
+use crate::tx_verifier::verify_tx_input_scripts;
+use crate::tx_verifier::TxVerifierError;
 use crate::wallet::WalletDataError;
 use crate::wallet::WalletDataSource;
 
+use super::node_endpoints::NodeEndpoints;
+use super::VerifyTransaction;
+
+/// A scan as listed by the node
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeScan {
+    pub scan_id: u64,
+    pub scan_name: String,
+    pub tracking_rule: serde_json::Value,
+}
+
 pub struct NodeApi {
-    pub node: NodeInterface,
+    nodes: Arc<NodeEndpoints>,
+    /// Send the requests to this node instead of the active one
+    pinned: Option<usize>,
     pub wallet_pass: Option<String>,
 }
 
+impl std::fmt::Debug for NodeApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't print the API key and the wallet password
+        f.debug_struct("NodeApi").finish_non_exhaustive()
+    }
+}
+
 impl NodeApi {
     pub fn new(api_key: String, wallet_pass: Option<String>, node_url: &Url) -> Self {
-        let node = NodeInterface::from_url(&api_key, node_url.clone());
-        Self { node, wallet_pass }
+        Self::with_endpoints(
+            Arc::new(NodeEndpoints::single(&api_key, node_url.clone())),
+            wallet_pass,
+        )
+    }
+
+    /// A client sending the requests to the active node of `nodes`
+    pub fn with_endpoints(nodes: Arc<NodeEndpoints>, wallet_pass: Option<String>) -> Self {
+        Self {
+            nodes,
+            pinned: None,
+            wallet_pass,
+        }
+    }
+
+    /// A client sending all the requests to the node `index` of the endpoints
+    pub fn pinned_to(&self, index: usize) -> Self {
+        Self {
+            nodes: self.nodes.clone(),
+            pinned: Some(index),
+            wallet_pass: self.wallet_pass.clone(),
+        }
+    }
+
+    pub fn endpoints(&self) -> &Arc<NodeEndpoints> {
+        &self.nodes
+    }
+
+    fn endpoint_index(&self) -> usize {
+        self.pinned.unwrap_or_else(|| self.nodes.active_index())
+    }
+
+    /// The node the requests go to
+    pub fn node(&self) -> &NodeInterface {
+        &self.nodes.endpoints()[self.endpoint_index()].node
+    }
+
+    /// Label of the node the requests go to
+    pub fn node_name(&self) -> &str {
+        self.nodes.endpoints()[self.endpoint_index()].name()
+    }
+
+    /// Runs an idempotent read on the node the requests go to. If the node fails to respond (see
+    /// [`NodeApiError::is_node_failure`]) it's marked unhealthy and the read is retried on the
+    /// next healthy node. A client pinned to a node doesn't fail over.
+    fn read<T>(
+        &self,
+        read: impl Fn(&NodeApi) -> Result<T, NodeApiError>,
+    ) -> Result<T, NodeApiError> {
+        if self.pinned.is_some() {
+            return read(self);
+        }
+        let mut tried: Vec<usize> = vec![];
+        loop {
+            let index = self.nodes.active_index();
+            let e = match read(&self.pinned_to(index)) {
+                Err(e) if e.is_node_failure() => e,
+                res => return res,
+            };
+            tried.push(index);
+            match self.nodes.mark_unhealthy(index, &e.to_string()) {
+                Some(next) if !tried.contains(&next) => log::warn!(
+                    "Request to node {} failed ({}), retrying on node {}",
+                    self.nodes.endpoints()[index].name(),
+                    e,
+                    self.nodes.endpoints()[next].name()
+                ),
+                Some(_) | None => return Err(e),
+            }
+        }
+    }
+
+    /// Fails with `ServerError` if the node responded with a 5xx status
+    fn check_server_error(&self, res: Response) -> Result<Response, NodeApiError> {
+        let status = res.status();
+        if status.is_server_error() {
+            return Err(NodeApiError::ServerError {
+                node: self.node_name().to_string(),
+                status: status.to_string(),
+                error: res.text().unwrap_or_default(),
+            });
+        }
+        Ok(res)
+    }
+
+    /// Unspent boxes of the scan registered on the primary node with `scan_id`
+    pub fn scan_boxes(&self, scan_id: ScanId) -> Result<Vec<ErgoBox>, NodeApiError> {
+        self.read(|node_api| {
+            let index = node_api.endpoint_index();
+            let endpoint = &node_api.nodes.endpoints()[index];
+            let node_scan_id = node_api.nodes.node_scan_id(index, scan_id).ok_or_else(|| {
+                NodeApiError::ScanNotRegistered {
+                    scan_id: scan_id.to_string(),
+                    node: endpoint.name().to_string(),
+                }
+            })?;
+            Ok(endpoint.node.scan_boxes(node_scan_id)?)
+        })
     }
 
     pub fn get_change_address(&self) -> Result<NetworkAddress, NodeApiError> {
         let change_address_str = self
-            .node
+            .node()
             .wallet_status()?
             .change_address
             .ok_or(NodeApiError::NoChangeAddressSetInNode)?;
@@ -39,7 +170,7 @@ impl NodeApi {
 
     /// Registers a scan with the node and either returns the `scan_id` or an error
     pub fn register_scan_raw(&self, scan_json: serde_json::Value) -> Result<ScanID, NodeApiError> {
-        let scan_id = self.node.register_scan(scan_json)?;
+        let scan_id = self.node().register_scan(scan_json)?;
         Ok(scan_id.to_string())
     }
 
@@ -65,15 +196,22 @@ impl NodeApi {
         Ok(scan_id)
     }
 
+    /// All the scans registered on the node
+    pub fn list_scans(&self) -> Result<Vec<NodeScan>, NodeApiError> {
+        let res = self.node().send_get_req("/scan/listAll")?;
+        let json = self.node().parse_response_to_json(Ok(res))?;
+        Ok(serde_json::from_str(&json.dump())?)
+    }
+
     pub fn deregister_scan(&self, scan_id: ScanId) -> Result<ScanId, NodeApiError> {
         log::info!("Deregistering Scan: {}", scan_id);
-        let scan_id = self.node.deregister_scan(scan_id)?;
+        let scan_id = self.node().deregister_scan(scan_id)?;
         Ok(scan_id)
     }
 
     pub fn rescan_from_height(&self, height: u32) -> Result<(), NodeApiError> {
         log::info!("Triggering wallet rescan");
-        self.node.send_post_req(
+        self.node().send_post_req(
             "/wallet/rescan",
             format!("{{ \"fromHeight\": {} }} ", height),
         )?;
@@ -89,12 +227,179 @@ impl NodeApi {
             "Signing transaction: {}",
             serde_json::to_string_pretty(&unsigned_tx).unwrap()
         );
-        let signed_tx = self.node.sign_transaction(unsigned_tx, None, None)?;
+        let signed_tx = self.node().sign_transaction(unsigned_tx, None, None)?;
         log::trace!(
             "Submitting signed transaction: {}",
             serde_json::to_string_pretty(&signed_tx).unwrap()
         );
-        Ok(self.node.submit_transaction(&signed_tx)?)
+        Ok(self.node().submit_transaction(&signed_tx)?)
+    }
+
+    /// Get a box from the UTXO set or the mempool by its id. Fails with `BoxNotFound` only if
+    /// the node responds with 404 Not Found.
+    pub fn get_box_with_pool(&self, box_id: BoxId) -> Result<ErgoBox, NodeApiError> {
+        self.read(|node_api| {
+            let box_id_str = String::from(box_id);
+            let endpoint = format!("/utxo/withPool/byId/{}", box_id_str);
+            let res = node_api.check_server_error(node_api.node().send_get_req(&endpoint)?)?;
+            let status = res.status();
+            if status.as_u16() == 404 {
+                return Err(NodeApiError::BoxNotFound(box_id_str));
+            }
+            if !status.is_success() {
+                return Err(NodeApiError::BoxRequestFailed {
+                    box_id: box_id_str,
+                    status: status.to_string(),
+                    error: res.text().unwrap_or_default(),
+                });
+            }
+            let json = node_api.node().parse_response_to_json(Ok(res))?;
+            Ok(serde_json::from_str(&json.dump())?)
+        })
+    }
+
+    /// Get the unspent boxes of an address. Requires the node to be run with the extra index
+    /// (`ergo.node.extraIndex = true`). Boxes spent by the transactions in the mempool are
+    /// skipped.
+    pub fn get_unspent_boxes_by_address(
+        &self,
+        address: &NetworkAddress,
+    ) -> Result<Vec<ErgoBox>, NodeApiError> {
+        const PAGE_SIZE: usize = 100;
+        self.read(|node_api| {
+            let mut boxes: Vec<ErgoBox> = vec![];
+            loop {
+                let endpoint = format!(
+                    "/blockchain/box/unspent/byAddress?offset={}&limit={}",
+                    boxes.len(),
+                    PAGE_SIZE
+                );
+                let res = node_api.check_server_error(
+                    node_api
+                        .node()
+                        .send_post_req(&endpoint, json!(address.to_base58()).to_string())?,
+                )?;
+                let json = node_api.node().parse_response_to_json(Ok(res))?;
+                let page: Vec<ErgoBox> = serde_json::from_str(&json.dump())?;
+                let page_len = page.len();
+                boxes.extend(page);
+                if page_len < PAGE_SIZE {
+                    break;
+                }
+            }
+            let spent_in_mempool = node_api.get_box_ids_spent_in_mempool(address)?;
+            Ok(boxes
+                .into_iter()
+                .filter(|b| !spent_in_mempool.contains(&b.box_id()))
+                .collect())
+        })
+    }
+
+    /// Unspent boxes holding the token from the node extra index (requires `extraIndex = true` in
+    /// the node config)
+    pub fn get_unspent_boxes_by_token_id(
+        &self,
+        token_id: TokenId,
+    ) -> Result<Vec<ErgoBox>, NodeApiError> {
+        let token_id = String::from(token_id);
+        const PAGE_SIZE: usize = 100;
+        self.read(|node_api| {
+            let mut boxes: Vec<ErgoBox> = vec![];
+            loop {
+                let endpoint = format!(
+                    "/blockchain/box/unspent/byTokenId/{}?offset={}&limit={}",
+                    token_id,
+                    boxes.len(),
+                    PAGE_SIZE
+                );
+                let res = node_api.check_server_error(node_api.node().send_get_req(&endpoint)?)?;
+                let json = node_api.node().parse_response_to_json(Ok(res))?;
+                let page: Vec<ErgoBox> = serde_json::from_str(&json.dump())?;
+                let page_len = page.len();
+                boxes.extend(page);
+                if page_len < PAGE_SIZE {
+                    break;
+                }
+            }
+            Ok(boxes)
+        })
+    }
+
+    /// Height the node extra index is synced to, fails if the index is disabled
+    pub fn indexed_height(&self) -> Result<u32, NodeApiError> {
+        self.read(|node_api| {
+            let res = node_api
+                .check_server_error(node_api.node().send_get_req("/blockchain/indexedHeight")?)?;
+            let json = node_api.node().parse_response_to_json(Ok(res))?;
+            json["indexedHeight"]
+                .as_u32()
+                .ok_or_else(|| NodeApiError::IndexerNotAvailable(json.dump()))
+        })
+    }
+
+    /// Ids of the boxes of the address spent by the transactions in the mempool
+    fn get_box_ids_spent_in_mempool(
+        &self,
+        address: &NetworkAddress,
+    ) -> Result<Vec<BoxId>, NodeApiError> {
+        let ergo_tree_hex = address
+            .address()
+            .script()
+            .map_err(|e| NodeApiError::InvalidAddress(e.to_string()))?
+            .to_base16_bytes()
+            .map_err(|e| NodeApiError::InvalidAddress(e.to_string()))?;
+        let res = self.check_server_error(self.node().send_post_req(
+            "/transactions/unconfirmed/byErgoTree",
+            json!(ergo_tree_hex).to_string(),
+        )?)?;
+        let json = self.node().parse_response_to_json(Ok(res))?;
+        let txs: Vec<Transaction> = serde_json::from_str(&json.dump())?;
+        Ok(txs
+            .iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.box_id))
+            .collect())
+    }
+
+    /// Build the state context for the next block from the last 10 block headers
+    pub fn get_state_context(&self) -> Result<ErgoStateContext, NodeApiError> {
+        state_context_from_headers(self.get_last_headers()?)
+    }
+
+    /// Input and data input boxes of the tx from the UTXO set and the mempool
+    pub fn get_tx_input_boxes(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<(Vec<ErgoBox>, Vec<ErgoBox>), NodeApiError> {
+        let input_boxes = unsigned_tx
+            .inputs
+            .iter()
+            .map(|input| self.get_box_with_pool(input.box_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let data_boxes = unsigned_tx
+            .data_inputs
+            .as_ref()
+            .map(|data_inputs| {
+                data_inputs
+                    .iter()
+                    .map(|data_input| self.get_box_with_pool(data_input.box_id))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok((input_boxes, data_boxes))
+    }
+
+    /// The last 10 block headers, the newest first
+    pub fn get_last_headers(&self) -> Result<Vec<Header>, NodeApiError> {
+        self.read(|node_api| {
+            let res = node_api
+                .check_server_error(node_api.node().send_get_req("/blocks/lastHeaders/10")?)?;
+            let json = node_api.node().parse_response_to_json(Ok(res))?;
+            let mut headers: Vec<Header> = serde_json::from_str(&json.dump())?;
+            // node returns headers in ascending order, state context expects the newest first
+            headers.sort_by(|a, b| b.height.cmp(&a.height));
+            Ok(headers)
+        })
     }
 
     /// Unlock wallet
@@ -104,12 +409,12 @@ impl NodeApi {
             "pass": password,
         });
 
-        let res = self.node.send_post_req(endpoint, body.to_string())?;
+        let res = self.node().send_post_req(endpoint, body.to_string())?;
 
         if res.status().is_success() {
             Ok(true)
         } else {
-            let json = self.node.parse_response_to_json(Ok(res))?;
+            let json = self.node().parse_response_to_json(Ok(res))?;
             Err(NodeApiError::NodeInterfaceError(NodeError::BadRequest(
                 json["error"].to_string(),
             )))
@@ -117,9 +422,31 @@ impl NodeApi {
     }
 }
 
+/// State context for the next block from the last 10 block headers (the newest first)
+pub fn state_context_from_headers(headers: Vec<Header>) -> Result<ErgoStateContext, NodeApiError> {
+    let last_header = headers
+        .first()
+        .cloned()
+        .ok_or(NodeApiError::NotEnoughHeaders(0))?;
+    let headers_len = headers.len();
+    let headers: [Header; 10] = headers
+        .try_into()
+        .map_err(|_| NodeApiError::NotEnoughHeaders(headers_len))?;
+    let pre_header = PreHeader {
+        version: last_header.version,
+        parent_id: last_header.id,
+        timestamp: last_header.timestamp,
+        n_bits: last_header.n_bits,
+        height: last_header.height + 1,
+        miner_pk: last_header.autolykos_solution.miner_pk.clone(),
+        votes: last_header.votes.clone(),
+    };
+    Ok(ErgoStateContext::new(pre_header, headers))
+}
+
 impl WalletDataSource for NodeApi {
     fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
-        self.node.unspent_boxes().map_err(Into::into)
+        self.node().unspent_boxes().map_err(Into::into)
     }
 
     fn get_change_address(&self) -> Result<NetworkAddress, WalletDataError> {
@@ -127,6 +454,26 @@ impl WalletDataSource for NodeApi {
     }
 }
 
+impl VerifyTransaction for NodeApi {
+    fn verify_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        pool_config: &PoolConfig,
+    ) -> Result<(), NodeApiError> {
+        let (input_boxes, data_boxes) = self.get_tx_input_boxes(unsigned_tx)?;
+        let state_context = self.get_state_context()?;
+        verify_tx_input_scripts(
+            unsigned_tx,
+            input_boxes,
+            data_boxes,
+            &state_context,
+            &pool_config.token_ids,
+            pool_config.buyback_token_id.as_ref(),
+        )?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum NodeApiError {
     #[error("Node error: {0}")]
@@ -137,4 +484,65 @@ pub enum NodeApiError {
     NoChangeAddressSetInNode,
     #[error("invalid scan id: {0}")]
     InvalidScanId(String),
+    #[error("failed to parse node response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("box {0} is not found in UTXO set or mempool")]
+    BoxNotFound(String),
+    #[error("failed to get box {box_id} from the node: {status} {error}")]
+    BoxRequestFailed {
+        box_id: String,
+        status: String,
+        error: String,
+    },
+    #[error("expected 10 last block headers, got {0}")]
+    NotEnoughHeaders(usize),
+    #[error("local tx verification failed: {0}")]
+    TxVerifier(#[from] TxVerifierError),
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("failed to sign tx: {0}")]
+    Signing(String),
+    #[error("scan {scan_id} is not registered on node {node}")]
+    ScanNotRegistered { scan_id: String, node: String },
+    #[error("node {node} responded with {status}: {error}")]
+    ServerError {
+        node: String,
+        status: String,
+        error: String,
+    },
+    #[error("node extra index is not available, got {0}")]
+    IndexerNotAvailable(String),
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("invalid node url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+}
+
+impl NodeApiError {
+    /// The node failed to respond (a connection error or a 5xx status), another node may serve
+    /// the same request
+    pub fn is_node_failure(&self) -> bool {
+        match self {
+            NodeApiError::NodeInterfaceError(e) => matches!(e, NodeError::NodeUnreachable),
+            NodeApiError::ServerError { .. } => true,
+            NodeApiError::Request(e) => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.status().map_or(false, |status| status.is_server_error())
+            }
+            NodeApiError::AddressEncoderError(_)
+            | NodeApiError::NoChangeAddressSetInNode
+            | NodeApiError::InvalidScanId(_)
+            | NodeApiError::Json(_)
+            | NodeApiError::BoxNotFound(_)
+            | NodeApiError::BoxRequestFailed { .. }
+            | NodeApiError::NotEnoughHeaders(_)
+            | NodeApiError::TxVerifier(_)
+            | NodeApiError::InvalidAddress(_)
+            | NodeApiError::Signing(_)
+            | NodeApiError::ScanNotRegistered { .. }
+            | NodeApiError::IndexerNotAvailable(_)
+            | NodeApiError::InvalidUrl(_) => false,
+        }
+    }
 }
@@ -0,0 +1,518 @@
+//! Failover between the nodes. The requests go to the primary node (`node_url` in the oracle
+//! config) while it is healthy, otherwise to the first healthy node of `backup_nodes`. The nodes
+//! are checked on every main loop iteration. A node is healthy if it responds, has applied the
+//! blocks of the headers it knows, is at most `max_node_height_lag` blocks behind the highest node
+//! and has the pool scans registered with the wallet rescan finished. The node wallet is checked
+//! only if the oracle uses it (for the scans or for signing), the nodes serving just the extra
+//! index may have no wallet at all.
+//!
+//! The box sources keep the IDs of the scans registered on the primary node. The pool scans are
+//! registered on every backup node as well, and the scan IDs are translated to the ones of the
+//! node the request goes to.
+//!
+//! The idempotent reads of [`crate::node_interface::node_api::NodeApi`] don't wait for the next
+//! check: a node failing to respond (a connection error or a 5xx status) is marked unhealthy
+//! right away and the read is retried on the next healthy node.
+use std::fmt;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::RwLock;
+
+use ergo_node_interface::node_interface::NodeError;
+use ergo_node_interface::NodeInterface;
+use ergo_node_interface::ScanId;
+use reqwest::Url;
+
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_config::OracleSecrets;
+use crate::oracle_config::SignerConfig;
+use crate::scans::NodeScanRegistry;
+
+/// A node a couple of blocks behind the others is still usable, its view differs only briefly
+pub const DEFAULT_MAX_NODE_HEIGHT_LAG: u32 = 2;
+
+pub struct NodeEndpoint {
+    pub url: Url,
+    pub node: NodeInterface,
+    api_key: String,
+    /// Scans of the pools registered on this node: the pool name and the (primary node scan ID,
+    /// this node scan ID) pairs. Set for the primary node only if its scans were registered anew
+    /// while the pool is running (see [`crate::scans::NodeScanRegistry::verify_and_repair`]).
+    pool_scans: RwLock<Vec<(String, Vec<(ScanId, ScanId)>)>>,
+}
+
+impl NodeEndpoint {
+    pub fn new(api_key: &str, url: Url) -> Self {
+        Self {
+            node: NodeInterface::from_url(api_key, url.clone()),
+            url,
+            api_key: api_key.to_string(),
+            pool_scans: RwLock::new(vec![]),
+        }
+    }
+
+    /// Label of the node in the logs and metrics
+    pub fn name(&self) -> &str {
+        self.url.as_str()
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+}
+
+/// The primary and the backup nodes, shared by all the node clients of the oracle
+pub struct NodeEndpoints {
+    /// The primary node first, then the backup nodes in the order of preference
+    endpoints: Vec<NodeEndpoint>,
+    /// Index of the node the requests go to
+    active: AtomicUsize,
+    /// Health of each node as of the last check, or the last failed request to it
+    health: RwLock<Vec<NodeHealth>>,
+    max_height_lag: u32,
+    /// Check the wallet rescan of the nodes, see [`uses_node_wallet`]
+    check_wallet: bool,
+}
+
+impl fmt::Debug for NodeEndpoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // don't print the API keys
+        f.debug_struct("NodeEndpoints")
+            .field("active", &self.active().name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl NodeEndpoints {
+    /// Just the primary node
+    pub fn single(api_key: &str, url: Url) -> Self {
+        Self {
+            endpoints: vec![NodeEndpoint::new(api_key, url)],
+            active: AtomicUsize::new(0),
+            health: RwLock::new(vec![NodeHealth::Healthy]),
+            max_height_lag: DEFAULT_MAX_NODE_HEIGHT_LAG,
+            check_wallet: true,
+        }
+    }
+
+    pub fn from_config(config: &OracleConfig, secrets: &OracleSecrets) -> Self {
+        let mut endpoints = vec![NodeEndpoint::new(
+            secrets.node_api_key.as_deref().unwrap_or_default(),
+            config.node_url(),
+        )];
+        endpoints.extend(
+            config
+                .backup_nodes
+                .iter()
+                .zip(&secrets.backup_node_api_keys)
+                .map(|(backup_node, api_key)| NodeEndpoint::new(api_key, backup_node.url.clone())),
+        );
+        Self {
+            health: RwLock::new(vec![NodeHealth::Healthy; endpoints.len()]),
+            endpoints,
+            active: AtomicUsize::new(0),
+            max_height_lag: config.max_node_height_lag(),
+            check_wallet: uses_node_wallet(config),
+        }
+    }
+
+    pub fn endpoints(&self) -> &[NodeEndpoint] {
+        &self.endpoints
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn active(&self) -> &NodeEndpoint {
+        &self.endpoints[self.active_index()]
+    }
+
+    /// ID on the node `index` of the scan registered on the primary node with `scan_id`
+    pub fn node_scan_id(&self, index: usize, scan_id: ScanId) -> Option<ScanId> {
+        self.endpoints[index]
+            .pool_scans
+            .read()
+            .unwrap()
+            .iter()
+            .flat_map(|(_, scan_ids)| scan_ids.iter())
+            .find(|(primary_scan_id, _)| *primary_scan_id == scan_id)
+            .map(|(_, node_scan_id)| *node_scan_id)
+            .or(if index == 0 { Some(scan_id) } else { None })
+    }
+
+    /// Records the scans of the pool registered on the node `index`, `primary_registry` holds the
+    /// scan IDs the box sources of the pool use
+    pub fn set_pool_scans(
+        &self,
+        index: usize,
+        pool_name: &str,
+        primary_registry: &NodeScanRegistry,
+        node_registry: &NodeScanRegistry,
+    ) {
+        let scan_ids = primary_registry
+            .scan_ids()
+            .into_iter()
+            .zip(node_registry.scan_ids())
+            .collect();
+        let mut pool_scans = self.endpoints[index].pool_scans.write().unwrap();
+        pool_scans.retain(|(name, _)| name != pool_name);
+        pool_scans.push((pool_name.to_string(), scan_ids));
+    }
+
+    /// Whether the scans of the pool are registered on the node `index`
+    pub fn has_pool_scans(&self, index: usize, pool_name: &str) -> bool {
+        index == 0
+            || self.endpoints[index]
+                .pool_scans
+                .read()
+                .unwrap()
+                .iter()
+                .any(|(name, _)| name == pool_name)
+    }
+
+    /// Checks the health of the nodes and switches to the first healthy one. The active node is
+    /// kept if none is healthy. Returns the health of each node.
+    pub fn check_and_select(&self, pool_names: &[String]) -> Vec<NodeHealth> {
+        let statuses: Vec<Result<NodeStatus, NodeError>> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| NodeStatus::query(&endpoint.node, self.check_wallet))
+            .collect();
+        let best_height = statuses
+            .iter()
+            .filter_map(|status| status.as_ref().ok()?.full_height)
+            .max()
+            .unwrap_or_default();
+        let health: Vec<NodeHealth> = statuses
+            .into_iter()
+            .enumerate()
+            .map(|(index, status)| match status {
+                Ok(status) => node_health(
+                    &status,
+                    best_height,
+                    self.max_height_lag,
+                    pool_names
+                        .iter()
+                        .all(|name| self.has_pool_scans(index, name)),
+                ),
+                Err(e) => NodeHealth::Unreachable(e.to_string()),
+            })
+            .collect();
+        let active = self.active_index();
+        match select_node(&health) {
+            Some(selected) if selected != active => {
+                if health[active] == NodeHealth::Healthy {
+                    log::info!(
+                        "Node {} is healthy again, switching to it from node {}",
+                        self.endpoints[selected].name(),
+                        self.endpoints[active].name()
+                    );
+                } else {
+                    log::warn!(
+                        "Node {} is {}, switching to node {}",
+                        self.endpoints[active].name(),
+                        health[active],
+                        self.endpoints[selected].name()
+                    );
+                }
+                self.active.store(selected, Ordering::Relaxed);
+            }
+            Some(_) => (),
+            None => log::error!(
+                "No healthy node, staying on node {} ({})",
+                self.endpoints[active].name(),
+                health[active]
+            ),
+        }
+        *self.health.write().unwrap() = health.clone();
+        health
+    }
+
+    /// Health of each node as of the last check, or the last failed request to it
+    pub fn health(&self) -> Vec<NodeHealth> {
+        self.health.read().unwrap().clone()
+    }
+
+    /// Marks the node `index` unreachable after a request to it failed and switches to the first
+    /// node still healthy (until the next check). Returns the node the requests go to now, None
+    /// if no node is healthy.
+    pub fn mark_unhealthy(&self, index: usize, error: &str) -> Option<usize> {
+        let mut health = self.health.write().unwrap();
+        health[index] = NodeHealth::Unreachable(error.to_string());
+        let selected = select_node(&health)?;
+        let active = self.active_index();
+        if selected != active {
+            log::warn!(
+                "Node {} is {}, switching to node {}",
+                self.endpoints[index].name(),
+                health[index],
+                self.endpoints[selected].name()
+            );
+            self.active.store(selected, Ordering::Relaxed);
+        }
+        Some(selected)
+    }
+}
+
+/// The state of a node the health is judged on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    /// Height of the last applied block, None until the first block is applied
+    pub full_height: Option<u32>,
+    pub headers_height: Option<u32>,
+    /// Height the node wallet (and so the scans) is synced to, None if the wallet isn't checked
+    pub wallet_height: Option<u32>,
+}
+
+impl NodeStatus {
+    /// Queries /info and, if `check_wallet` is set, /wallet/status
+    fn query(node: &NodeInterface, check_wallet: bool) -> Result<Self, NodeError> {
+        let res = node.send_get_req("/info")?;
+        let info = node.parse_response_to_json(Ok(res))?;
+        let wallet_height = if check_wallet {
+            Some(node.wallet_status()?.height as u32)
+        } else {
+            None
+        };
+        Ok(NodeStatus {
+            full_height: info["fullHeight"].as_u32(),
+            headers_height: info["headersHeight"].as_u32(),
+            wallet_height,
+        })
+    }
+}
+
+/// The node wallet is used if the pool boxes are found by the wallet scans or the txs are signed
+/// by the node wallet
+pub fn uses_node_wallet(config: &OracleConfig) -> bool {
+    config.box_source == BoxSourceConfig::NodeScans
+        || matches!(config.signer, None | Some(SignerConfig::NodeWallet))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeHealth {
+    Healthy,
+    Unreachable(String),
+    NotSynced {
+        full_height: Option<u32>,
+        headers_height: Option<u32>,
+    },
+    Lagging {
+        height: u32,
+        best_height: u32,
+    },
+    ScansNotRegistered,
+    Rescanning {
+        wallet_height: u32,
+        height: u32,
+    },
+}
+
+impl fmt::Display for NodeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeHealth::Healthy => write!(f, "healthy"),
+            NodeHealth::Unreachable(e) => write!(f, "unreachable: {e}"),
+            NodeHealth::NotSynced {
+                full_height,
+                headers_height,
+            } => write!(
+                f,
+                "not synced (blocks applied up to {:?}, headers up to {:?})",
+                full_height, headers_height
+            ),
+            NodeHealth::Lagging {
+                height,
+                best_height,
+            } => write!(f, "lagging (height {height}, best height {best_height})"),
+            NodeHealth::ScansNotRegistered => write!(f, "missing the pool scans"),
+            NodeHealth::Rescanning {
+                wallet_height,
+                height,
+            } => write!(f, "rescanning (scanned {wallet_height}/{height} blocks)"),
+        }
+    }
+}
+
+/// Health of a node given the highest height among the nodes
+pub fn node_health(
+    status: &NodeStatus,
+    best_height: u32,
+    max_height_lag: u32,
+    scans_registered: bool,
+) -> NodeHealth {
+    let height = match (status.full_height, status.headers_height) {
+        (Some(full_height), Some(headers_height))
+            if full_height + max_height_lag >= headers_height =>
+        {
+            full_height
+        }
+        (full_height, headers_height) => {
+            return NodeHealth::NotSynced {
+                full_height,
+                headers_height,
+            }
+        }
+    };
+    if height + max_height_lag < best_height {
+        return NodeHealth::Lagging {
+            height,
+            best_height,
+        };
+    }
+    if !scans_registered {
+        return NodeHealth::ScansNotRegistered;
+    }
+    match status.wallet_height {
+        Some(wallet_height) if wallet_height + max_height_lag < height => {
+            return NodeHealth::Rescanning {
+                wallet_height,
+                height,
+            }
+        }
+        Some(_) | None => (),
+    }
+    NodeHealth::Healthy
+}
+
+/// Index of the first healthy node in the order of preference
+pub fn select_node(health: &[NodeHealth]) -> Option<usize> {
+    health.iter().position(|h| *h == NodeHealth::Healthy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle_config::BackupNodeConfig;
+
+    fn status(full_height: u32, headers_height: u32, wallet_height: u32) -> NodeStatus {
+        NodeStatus {
+            full_height: Some(full_height),
+            headers_height: Some(headers_height),
+            wallet_height: Some(wallet_height),
+        }
+    }
+
+    #[test]
+    fn test_node_health() {
+        assert_eq!(
+            node_health(&status(100, 101, 100), 102, 2, true),
+            NodeHealth::Healthy
+        );
+        assert_eq!(
+            node_health(&status(90, 102, 90), 102, 2, true),
+            NodeHealth::NotSynced {
+                full_height: Some(90),
+                headers_height: Some(102)
+            }
+        );
+        let syncing_from_scratch = NodeStatus {
+            full_height: None,
+            headers_height: Some(50),
+            wallet_height: Some(0),
+        };
+        assert!(matches!(
+            node_health(&syncing_from_scratch, 102, 2, true),
+            NodeHealth::NotSynced { .. }
+        ));
+        assert_eq!(
+            node_health(&status(99, 99, 99), 102, 2, true),
+            NodeHealth::Lagging {
+                height: 99,
+                best_height: 102
+            }
+        );
+        assert_eq!(
+            node_health(&status(102, 102, 102), 102, 2, false),
+            NodeHealth::ScansNotRegistered
+        );
+        assert_eq!(
+            node_health(&status(102, 102, 50), 102, 2, true),
+            NodeHealth::Rescanning {
+                wallet_height: 50,
+                height: 102
+            }
+        );
+    }
+
+    #[test]
+    fn test_wallet_less_node_health() {
+        let config = OracleConfig {
+            box_source: BoxSourceConfig::NodeIndexer,
+            signer: Some(SignerConfig::Local { secret_file: None }),
+            ..OracleConfig::default()
+        };
+        assert!(!uses_node_wallet(&config));
+        // a node without a wallet has no wallet height
+        let no_wallet = NodeStatus {
+            full_height: Some(102),
+            headers_height: Some(102),
+            wallet_height: None,
+        };
+        assert_eq!(node_health(&no_wallet, 102, 2, true), NodeHealth::Healthy);
+
+        assert!(uses_node_wallet(&OracleConfig {
+            signer: Some(SignerConfig::NodeWallet),
+            ..config.clone()
+        }));
+        assert!(uses_node_wallet(&OracleConfig {
+            box_source: BoxSourceConfig::NodeScans,
+            ..config
+        }));
+    }
+
+    #[test]
+    fn test_mark_unhealthy() {
+        let url = |port: u16| Url::parse(&format!("http://127.0.0.1:{port}/")).unwrap();
+        let nodes = NodeEndpoints::from_config(
+            &OracleConfig {
+                node_url: Some(url(9053)),
+                backup_nodes: vec![BackupNodeConfig { url: url(9054) }],
+                ..OracleConfig::default()
+            },
+            &OracleSecrets {
+                node_api_key: Some("hello".to_string()),
+                wallet_password: None,
+                wallet_mnemonic: None,
+                wallet_mnemonic_password: None,
+                wallet_secret_file_password: None,
+                backup_node_api_keys: vec!["hello".to_string()],
+            },
+        );
+        assert_eq!(nodes.mark_unhealthy(0, "connection refused"), Some(1));
+        assert_eq!(nodes.active_index(), 1);
+        assert_eq!(
+            nodes.health(),
+            vec![
+                NodeHealth::Unreachable("connection refused".to_string()),
+                NodeHealth::Healthy
+            ]
+        );
+        // no healthy node left, the active one is kept
+        assert_eq!(nodes.mark_unhealthy(1, "connection refused"), None);
+        assert_eq!(nodes.active_index(), 1);
+    }
+
+    #[test]
+    fn test_select_node() {
+        let unreachable = NodeHealth::Unreachable("connection refused".to_string());
+        assert_eq!(
+            select_node(&[NodeHealth::Healthy, NodeHealth::Healthy]),
+            Some(0)
+        );
+        assert_eq!(
+            select_node(&[
+                unreachable.clone(),
+                NodeHealth::ScansNotRegistered,
+                NodeHealth::Healthy
+            ]),
+            Some(2)
+        );
+        assert_eq!(
+            select_node(&[unreachable, NodeHealth::ScansNotRegistered]),
+            None
+        );
+    }
+}
@@ -2,8 +2,10 @@ use std::{
     convert::TryFrom,
     io::Write,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
+use anyhow::anyhow;
 use anyhow::Context;
 use ergo_lib::{
     ergotree_ir::chain::address::NetworkAddress,
@@ -17,19 +19,37 @@ use ergo_lib::{
     wallet::tx_builder::{self, SUGGESTED_TX_FEE},
 };
 use log::{warn, LevelFilter};
-use once_cell::sync;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::explorer_api::explorer_url::default_explorer_api_url;
+use crate::node_interface::node_endpoints::DEFAULT_MAX_NODE_HEIGHT_LAG;
+use crate::scheduler::DEFAULT_MAX_MAIN_LOOP_INTERVAL_SECS;
+use crate::scheduler::DEFAULT_NODE_POLL_INTERVAL_SECS;
+use crate::secrets::Secret;
+use crate::secrets::SecretSources;
+use crate::secrets::SecretsError;
+use crate::wallet_consolidation::WalletConsolidationConfig;
+use crate::wallet_funds::DEFAULT_WALLET_LOW_BALANCE_NANO_ERGS;
 
 pub const DEFAULT_ORACLE_CONFIG_FILE_NAME: &str = "oracle_config.yaml";
+const DEFAULT_NODE_URL: &str = "http://127.0.0.1:9053";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OracleConfig {
-    pub node_url: Url,
+    /// Required unless the boxes are looked up in the Explorer (see [`BoxSourceConfig::Explorer`])
+    #[serde(default)]
+    pub node_url: Option<Url>,
+    /// Nodes to fail over to when the `node_url` node is unhealthy, in the order of preference
+    #[serde(default)]
+    pub backup_nodes: Vec<BackupNodeConfig>,
+    /// A node is unhealthy if it is more blocks behind the highest node
+    pub max_node_height_lag: Option<u32>,
     pub base_fee: u64,
+    /// Where the pool boxes are looked up, the scans registered on the node if not set
+    #[serde(default)]
+    pub box_source: BoxSourceConfig,
     pub scan_start_height: u32,
     pub log_level: Option<LevelFilter>,
     pub core_api_port: u16,
@@ -37,28 +57,107 @@ pub struct OracleConfig {
     pub data_point_source_custom_script: Option<String>,
     pub explorer_url: Option<Url>,
     pub metrics_port: Option<u16>,
+    /// How often (in seconds) the node is polled for a new best block height
+    pub node_poll_interval_secs: Option<u64>,
+    /// Max time (in seconds) between the main loop iterations if no new block is observed
+    pub max_main_loop_interval_secs: Option<u64>,
+    /// How the transactions are signed, the node wallet is used if not set
+    pub signer: Option<SignerConfig>,
+    /// The oracle is reported unhealthy if the wallet has less coins (in nanoERG)
+    pub wallet_low_balance_nano_ergs: Option<u64>,
+    /// Merge the small wallet boxes during the idle blocks, not done if not set
+    pub wallet_consolidation: Option<WalletConsolidationConfig>,
+    /// File with the secrets (node API key, wallet passwords), see [`crate::secrets`]
+    pub secrets_file: Option<PathBuf>,
 }
 
+/// The API key is in ORACLE_BACKUP_NODE_API_KEY_<n> (n starts from 1) or in
+/// `backup_node_api_keys` of the secrets file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BackupNodeConfig {
+    pub url: Url,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoxSourceConfig {
+    /// Scans registered on the node, their IDs are kept in the scans file (`scanIDs.json`)
+    #[default]
+    NodeScans,
+    /// Unspent boxes by token id from the node extra index (`extraIndex = true` in the node
+    /// config). No scans are registered and there is no wallet rescan to wait for.
+    NodeIndexer,
+    /// Unspent boxes by token id from the Explorer API (`explorer_url`). No node is used, so only
+    /// `run --read-only` is supported (e.g. for public status pages).
+    Explorer,
+}
+
+impl BoxSourceConfig {
+    pub fn requires_node(&self) -> bool {
+        *self != BoxSourceConfig::Explorer
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    /// The node wallet, has to be unlocked (see ORACLE_NODE_WALLET_PASSWORD)
+    NodeWallet,
+    /// Sign with the first key of the wallet restored from the ORACLE_WALLET_MNEMONIC mnemonic or,
+    /// if `secret_file` is set, from the encrypted node wallet secret file (the password is in
+    /// ORACLE_WALLET_SECRET_FILE_PASSWORD). The node is used for UTXO lookup by address (requires
+    /// the node extra index) and tx submission.
+    Local { secret_file: Option<PathBuf> },
+    /// Send the txs to the external signer holding the key of the oracle address, listening on
+    /// `http(s)://<host>:<port>` or `unix:<socket path>` (see `oracle-signer`). The node is used for
+    /// UTXO lookup by address (requires the node extra index) and tx submission.
+    External { url: String },
+}
+
+#[derive(Clone)]
 pub struct OracleSecrets {
-    pub node_api_key: String,
+    /// Not required if no node is used (see [`BoxSourceConfig::Explorer`])
+    pub node_api_key: Option<String>,
     pub wallet_password: Option<String>,
+    /// Mnemonic of the local signer wallet
+    pub wallet_mnemonic: Option<String>,
+    pub wallet_mnemonic_password: Option<String>,
+    /// Password of the local signer wallet secret file
+    pub wallet_secret_file_password: Option<String>,
+    /// In the order of `backup_nodes`
+    pub backup_node_api_keys: Vec<String>,
 }
 
 impl OracleSecrets {
-    pub fn load() -> Self {
-        let api_key = std::env::var("ORACLE_NODE_API_KEY").unwrap_or_else(|_| {
-            panic!("ORACLE_NODE_API_KEY environment variable for node API key is not set")
-        });
-
-        let wallet_pass = std::env::var("ORACLE_NODE_WALLET_PASSWORD").ok();
-        if wallet_pass.is_none() {
-            warn!("ORACLE_NODE_WALLET_PASSWORD environment variable for automatic unlock of node wallet is not set");
-        }
-
-        Self {
-            node_api_key: api_key,
-            wallet_password: wallet_pass,
+    /// Takes the secrets from the systemd credentials, the secrets file and the environment (see
+    /// [`crate::secrets`]). The API keys of the node and the backup nodes are required unless no
+    /// node is used.
+    pub fn load(config: &OracleConfig) -> Result<Self, SecretsError> {
+        let sources = SecretSources::load(config.secrets_file.as_deref())?;
+        let requires_node = config.box_source.requires_node();
+        let backup_node_count = if requires_node {
+            config.backup_nodes.len()
+        } else {
+            0
+        };
+        let wallet_password = sources.get(Secret::NodeWalletPassword)?;
+        if requires_node && wallet_password.is_none() {
+            warn!("ORACLE_NODE_WALLET_PASSWORD for automatic unlock of node wallet is not set");
         }
+        Ok(Self {
+            node_api_key: if requires_node {
+                Some(sources.get_required(Secret::NodeApiKey)?)
+            } else {
+                sources.get(Secret::NodeApiKey)?
+            },
+            wallet_password,
+            wallet_mnemonic: sources.get(Secret::WalletMnemonic)?,
+            wallet_mnemonic_password: sources.get(Secret::WalletMnemonicPassword)?,
+            wallet_secret_file_password: sources.get(Secret::WalletSecretFilePassword)?,
+            backup_node_api_keys: (1..=backup_node_count)
+                .map(|n| sources.get_required(Secret::BackupNodeApiKey(n)))
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
@@ -70,10 +169,7 @@ impl OracleConfig {
         file.write_all(yaml_str.as_bytes()).unwrap();
     }
 
-    fn load() -> Result<Self, anyhow::Error> {
-        let config_file_path = ORACLE_CONFIG_FILE_PATH.get().ok_or_else(|| {
-            OracleConfigFileError::IoError("ORACLE_CONFIG_FILE_PATH not set".to_string())
-        })?;
+    pub fn load_from_file(config_file_path: &Path) -> Result<Self, anyhow::Error> {
         log::info!("Loading oracle config from {}", config_file_path.display());
         let config_str = std::fs::read_to_string(config_file_path).context(format!(
             "failed to load oracle config file from {}",
@@ -84,6 +180,12 @@ impl OracleConfig {
         let _ = config
             .oracle_address_p2pk()
             .context("failed to parse oracle address")?;
+        BoxValue::try_from(config.base_fee).context("invalid base_fee")?;
+        if config.box_source.requires_node() && config.node_url.is_none() {
+            return Err(anyhow!(
+                "node_url is required unless box_source is explorer"
+            ));
+        }
         Ok(config)
     }
 
@@ -99,6 +201,51 @@ impl OracleConfig {
         Ok(())
     }
 
+    /// URL of the primary node. Without a node (see [`BoxSourceConfig::Explorer`]) it may be
+    /// unset, the default URL is used then and no requests are sent to it.
+    pub fn node_url(&self) -> Url {
+        self.node_url
+            .clone()
+            .unwrap_or_else(|| Url::parse(DEFAULT_NODE_URL).unwrap())
+    }
+
+    /// Fee for the transactions made by the oracle
+    pub fn base_fee(&self) -> BoxValue {
+        // checked on load, the fallback is only reachable for configs built in code
+        BoxValue::try_from(self.base_fee).unwrap_or_else(|_| SUGGESTED_TX_FEE())
+    }
+
+    pub fn node_poll_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.node_poll_interval_secs
+                .unwrap_or(DEFAULT_NODE_POLL_INTERVAL_SECS),
+        )
+    }
+
+    pub fn max_main_loop_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.max_main_loop_interval_secs
+                .unwrap_or(DEFAULT_MAX_MAIN_LOOP_INTERVAL_SECS),
+        )
+    }
+
+    /// `explorer_url` or the public Explorer API of the oracle address network
+    pub fn explorer_api_url(&self) -> Url {
+        self.explorer_url
+            .clone()
+            .unwrap_or_else(|| default_explorer_api_url(self.oracle_address.network()))
+    }
+
+    pub fn max_node_height_lag(&self) -> u32 {
+        self.max_node_height_lag
+            .unwrap_or(DEFAULT_MAX_NODE_HEIGHT_LAG)
+    }
+
+    pub fn wallet_low_balance_threshold(&self) -> u64 {
+        self.wallet_low_balance_nano_ergs
+            .unwrap_or(DEFAULT_WALLET_LOW_BALANCE_NANO_ERGS)
+    }
+
     pub fn oracle_address_p2pk(&self) -> Result<ProveDlog, OracleConfigFileError> {
         if let Address::P2Pk(public_key) = self.oracle_address.address() {
             Ok(public_key.clone())
@@ -130,21 +277,19 @@ impl Default for OracleConfig {
             scan_start_height: 0,
             data_point_source_custom_script: None,
             base_fee: *tx_builder::SUGGESTED_TX_FEE().as_u64(),
+            box_source: BoxSourceConfig::NodeScans,
             log_level: LevelFilter::Info.into(),
-            node_url: Url::parse("http://127.0.0.1:9053").unwrap(),
+            node_url: Some(Url::parse(DEFAULT_NODE_URL).unwrap()),
+            backup_nodes: vec![],
+            max_node_height_lag: Some(DEFAULT_MAX_NODE_HEIGHT_LAG),
             explorer_url: Some(default_explorer_api_url(address.network())),
             metrics_port: None,
+            node_poll_interval_secs: Some(DEFAULT_NODE_POLL_INTERVAL_SECS),
+            max_main_loop_interval_secs: Some(DEFAULT_MAX_MAIN_LOOP_INTERVAL_SECS),
+            signer: None,
+            wallet_low_balance_nano_ergs: Some(DEFAULT_WALLET_LOW_BALANCE_NANO_ERGS),
+            wallet_consolidation: None,
+            secrets_file: None,
         }
     }
 }
-
-pub static ORACLE_CONFIG_FILE_PATH: sync::OnceCell<PathBuf> = sync::OnceCell::new();
-lazy_static! {
-    pub static ref ORACLE_CONFIG: OracleConfig = OracleConfig::load().unwrap();
-    pub static ref ORACLE_SECRETS: OracleSecrets = OracleSecrets::load();
-    pub static ref ORACLE_CONFIG_OPT: Result<OracleConfig, anyhow::Error> = OracleConfig::load();
-    pub static ref BASE_FEE: BoxValue = ORACLE_CONFIG_OPT
-        .as_ref()
-        .map(|c| BoxValue::try_from(c.base_fee).unwrap())
-        .unwrap_or_else(|_| SUGGESTED_TX_FEE());
-}
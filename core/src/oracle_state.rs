@@ -6,16 +6,20 @@ use crate::box_kind::{
     UpdateBoxWrapper, UpdateBoxWrapperInputs, VoteBallotBoxWrapper,
 };
 use crate::datapoint_source::DataPointSourceError;
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::node_interface::async_node::AsyncNodeClient;
+use crate::node_interface::node_api::NodeApi;
 use crate::oracle_types::{BlockHeight, EpochCounter, Rate};
-use crate::pool_config::POOL_CONFIG;
-use crate::scans::{GenericTokenScan, NodeScanRegistry, ScanError, ScanGetBoxes};
+use crate::pool_config::PoolConfig;
+use crate::recording::BoxSnapshot;
+use crate::scans::{BlockBoxCache, PoolTokenBoxes, ScanError, TokenBoxes};
 use crate::spec_token::{
     BallotTokenId, BuybackTokenId, OracleTokenId, PoolTokenId, RefreshTokenId, RewardTokenId,
     TokenIdKind, UpdateTokenId,
 };
 use crate::util::get_token_count;
 use anyhow::Error;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use ergo_lib::ergotree_ir::mir::constant::TryExtractFromError;
 use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
@@ -98,55 +102,79 @@ pub struct OraclePool {
     ballot_boxes_scan: BallotBoxesScan,
     update_box_scan: UpdateBoxScan,
     buyback_box_scan: Option<BuybackBoxScan>,
+    pool_config: PoolConfig,
+    node_api: Arc<NodeApi>,
+    token_boxes: PoolTokenBoxes,
+    /// Boxes of the current block shared by the box sources
+    box_cache: Arc<BlockBoxCache>,
+    /// Held while prefetching, the concurrent REST API requests wait for the prefetch in flight
+    /// and find the boxes cached
+    prefetch_lock: tokio::sync::Mutex<()>,
 }
 
 #[derive(Debug)]
 pub struct OracleDatapointScan {
-    scan: GenericTokenScan<OracleTokenId>,
+    boxes: TokenBoxes<OracleTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     oracle_box_wrapper_inputs: OracleBoxWrapperInputs,
 }
 
 #[derive(Debug)]
 pub struct LocalOracleDatapointScan {
-    scan: GenericTokenScan<OracleTokenId>,
+    boxes: TokenBoxes<OracleTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     oracle_box_wrapper_inputs: OracleBoxWrapperInputs,
     oracle_pk: ProveDlog,
 }
 
 #[derive(Debug)]
 pub struct LocalBallotBoxScan {
-    scan: GenericTokenScan<BallotTokenId>,
+    boxes: TokenBoxes<BallotTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     ballot_box_wrapper_inputs: BallotBoxWrapperInputs,
     ballot_token_owner_pk: ProveDlog,
 }
 
 #[derive(Debug)]
 pub struct PoolBoxScan {
-    scan: GenericTokenScan<PoolTokenId>,
+    boxes: TokenBoxes<PoolTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     pool_box_wrapper_inputs: PoolBoxWrapperInputs,
 }
 
 #[derive(Debug)]
 pub struct RefreshBoxScan {
-    scan: GenericTokenScan<RefreshTokenId>,
+    boxes: TokenBoxes<RefreshTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     refresh_box_wrapper_inputs: RefreshBoxWrapperInputs,
 }
 
 #[derive(Debug)]
 pub struct BallotBoxesScan {
-    scan: GenericTokenScan<BallotTokenId>,
+    boxes: TokenBoxes<BallotTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     ballot_box_wrapper_inputs: BallotBoxWrapperInputs,
 }
 
 #[derive(Debug)]
 pub struct UpdateBoxScan {
-    scan: GenericTokenScan<UpdateTokenId>,
+    boxes: TokenBoxes<UpdateTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     update_box_wrapper_inputs: UpdateBoxWrapperInputs,
 }
 
 #[derive(Debug)]
 pub struct BuybackBoxScan {
-    scan: GenericTokenScan<BuybackTokenId>,
+    boxes: TokenBoxes<BuybackTokenId>,
+    node_api: Arc<NodeApi>,
+    cache: Arc<BlockBoxCache>,
     reward_token_id: RewardTokenId,
 }
 
@@ -172,58 +200,75 @@ pub enum LocalDatapointState {
 }
 
 impl OraclePool {
-    pub fn new(node_scan_registry: &NodeScanRegistry) -> std::result::Result<OraclePool, Error> {
-        let pool_config = &POOL_CONFIG;
-        let oracle_config = &ORACLE_CONFIG;
-        let oracle_pk = oracle_config.oracle_address_p2pk()?;
-
+    pub fn new(
+        node_api: Arc<NodeApi>,
+        token_boxes: &PoolTokenBoxes,
+        pool_config: &PoolConfig,
+        oracle_pk: ProveDlog,
+    ) -> std::result::Result<OraclePool, Error> {
+        let box_cache = Arc::new(BlockBoxCache::new());
         // Create all `Scan` structs for protocol
         let oracle_datapoint_scan = OracleDatapointScan {
-            scan: node_scan_registry.oracle_token_scan.clone(),
+            boxes: token_boxes.oracle_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             oracle_box_wrapper_inputs: pool_config.oracle_box_wrapper_inputs.clone(),
         };
         let local_oracle_datapoint_scan = LocalOracleDatapointScan {
-            scan: node_scan_registry.oracle_token_scan.clone(),
+            boxes: token_boxes.oracle_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             oracle_box_wrapper_inputs: pool_config.oracle_box_wrapper_inputs.clone(),
             oracle_pk: oracle_pk.clone(),
         };
 
         let local_ballot_box_scan = LocalBallotBoxScan {
-            scan: node_scan_registry.ballot_token_scan.clone(),
+            boxes: token_boxes.ballot_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             ballot_box_wrapper_inputs: pool_config.ballot_box_wrapper_inputs.clone(),
             ballot_token_owner_pk: oracle_pk.clone(),
         };
 
         let ballot_boxes_scan = BallotBoxesScan {
-            scan: node_scan_registry.ballot_token_scan.clone(),
+            boxes: token_boxes.ballot_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             ballot_box_wrapper_inputs: pool_config.ballot_box_wrapper_inputs.clone(),
         };
 
         let pool_box_scan = PoolBoxScan {
-            scan: node_scan_registry.pool_token_scan.clone(),
+            boxes: token_boxes.pool_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             pool_box_wrapper_inputs: pool_config.pool_box_wrapper_inputs.clone(),
         };
 
         let refresh_box_scan = RefreshBoxScan {
-            scan: node_scan_registry.refresh_token_scan.clone(),
+            boxes: token_boxes.refresh_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             refresh_box_wrapper_inputs: pool_config.refresh_box_wrapper_inputs.clone(),
         };
 
         let update_box_scan = UpdateBoxScan {
-            scan: node_scan_registry.update_token_scan.clone(),
+            boxes: token_boxes.update_token.clone(),
+            node_api: node_api.clone(),
+            cache: box_cache.clone(),
             update_box_wrapper_inputs: pool_config.update_box_wrapper_inputs.clone(),
         };
 
-        let buyback_box_scan =
-            node_scan_registry
-                .buyback_token_scan
-                .clone()
-                .map(|scan| BuybackBoxScan {
-                    scan,
-                    reward_token_id: pool_config.token_ids.reward_token_id.clone(),
-                });
+        let buyback_box_scan = token_boxes
+            .buyback_token
+            .clone()
+            .map(|boxes| BuybackBoxScan {
+                boxes,
+                node_api: node_api.clone(),
+                cache: box_cache.clone(),
+                reward_token_id: pool_config.token_ids.reward_token_id.clone(),
+            });
 
-        log::debug!("Scans loaded");
+        log::debug!("Box sources loaded");
 
         Ok(OraclePool {
             oracle_datapoint_scan,
@@ -234,13 +279,106 @@ impl OraclePool {
             refresh_box_scan,
             update_box_scan,
             buyback_box_scan,
+            pool_config: pool_config.clone(),
+            node_api,
+            token_boxes: token_boxes.clone(),
+            box_cache,
+            prefetch_lock: tokio::sync::Mutex::new(()),
         })
     }
 
-    /// Create a new `OraclePool` struct with loaded scans
-    pub fn load() -> std::result::Result<OraclePool, Error> {
-        let node_scan_registry = NodeScanRegistry::load()?;
-        Self::new(&node_scan_registry)
+    /// The config of the pool this struct tracks
+    pub fn pool_config(&self) -> &PoolConfig {
+        &self.pool_config
+    }
+
+    /// The node the boxes are queried from
+    pub fn node_api(&self) -> &NodeApi {
+        &self.node_api
+    }
+
+    /// Height of the chain the boxes of the pool are looked up in. The boxes are cached until a
+    /// new height is observed.
+    pub fn current_height(&self) -> Result<BlockHeight> {
+        let height = self.pool_box_scan.boxes.current_height(&self.node_api)?;
+        self.box_cache.set_height(height);
+        Ok(BlockHeight(height))
+    }
+
+    /// Drops the cached boxes, to be called after a tx spending the pool boxes is submitted
+    pub fn invalidate_box_cache(&self) {
+        self.box_cache.invalidate();
+    }
+
+    /// Fetches the boxes of the pool at the best height through the async node client, so the
+    /// box sources read them from the cache afterwards without blocking on the node. The boxes
+    /// already cached at the best height are not fetched again. Returns the best height.
+    pub async fn prefetch(&self, client: &AsyncNodeClient) -> Result<BlockHeight> {
+        let height = client
+            .current_block_height()
+            .await
+            .map_err(ScanError::from)?;
+        let _prefetching = self.prefetch_lock.lock().await;
+        self.box_cache.set_height(height);
+        let cache = &self.box_cache;
+        let token_boxes = &self.token_boxes;
+        futures::try_join!(
+            token_boxes.oracle_token.prefetch(client, cache, height),
+            token_boxes.pool_token.prefetch(client, cache, height),
+            token_boxes.ballot_token.prefetch(client, cache, height),
+            token_boxes.refresh_token.prefetch(client, cache, height),
+            token_boxes.update_token.prefetch(client, cache, height),
+            async {
+                match &token_boxes.buyback_token {
+                    Some(buyback_token) => buyback_token.prefetch(client, cache, height).await,
+                    None => Ok(()),
+                }
+            },
+        )?;
+        Ok(BlockHeight(height))
+    }
+
+    /// The boxes of all the pool tokens the box sources see at `height`, for the main loop
+    /// recording
+    pub fn box_snapshot(&self, height: BlockHeight) -> Result<BoxSnapshot> {
+        let node_api = &self.node_api;
+        let cache = &self.box_cache;
+        let token_boxes = &self.token_boxes;
+        let token_ids = &self.pool_config.token_ids;
+        let mut boxes = BTreeMap::new();
+        boxes.insert(
+            String::from(token_ids.oracle_token_id.token_id()),
+            token_boxes.oracle_token.get_boxes(node_api, cache)?,
+        );
+        boxes.insert(
+            String::from(token_ids.pool_nft_token_id.token_id()),
+            token_boxes.pool_token.get_boxes(node_api, cache)?,
+        );
+        boxes.insert(
+            String::from(token_ids.ballot_token_id.token_id()),
+            token_boxes.ballot_token.get_boxes(node_api, cache)?,
+        );
+        boxes.insert(
+            String::from(token_ids.refresh_nft_token_id.token_id()),
+            token_boxes.refresh_token.get_boxes(node_api, cache)?,
+        );
+        boxes.insert(
+            String::from(token_ids.update_nft_token_id.token_id()),
+            token_boxes.update_token.get_boxes(node_api, cache)?,
+        );
+        if let (Some(buyback_token_id), Some(buyback_token)) = (
+            &self.pool_config.buyback_token_id,
+            &token_boxes.buyback_token,
+        ) {
+            boxes.insert(
+                String::from(buyback_token_id.token_id()),
+                buyback_token.get_boxes(node_api, cache)?,
+            );
+        }
+        Ok(BoxSnapshot {
+            height,
+            token_boxes: boxes,
+        })
     }
 
     /// Get the state of the current oracle pool epoch
@@ -315,8 +453,8 @@ impl OraclePool {
     pub fn get_total_oracle_token_count(&self) -> Result<u64> {
         Ok(self
             .oracle_datapoint_scan
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.box_cache)?
             .into_iter()
             .map(|b| {
                 get_token_count(
@@ -334,8 +472,8 @@ impl OraclePool {
 impl PoolBoxSource for PoolBoxScan {
     fn get_pool_box(&self) -> Result<PoolBoxWrapper> {
         let box_wrapper = PoolBoxWrapper::new(
-            self.scan
-                .get_box()?
+            self.boxes
+                .get_box(&self.node_api, &self.cache)?
                 .ok_or(DataSourceError::PoolBoxNotFoundError)?,
             &self.pool_box_wrapper_inputs,
         )?;
@@ -346,8 +484,8 @@ impl PoolBoxSource for PoolBoxScan {
 impl LocalBallotBoxSource for LocalBallotBoxScan {
     fn get_ballot_box(&self) -> Result<Option<BallotBoxWrapper>> {
         Ok(self
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.cache)?
             .into_iter()
             .filter_map(|b| BallotBoxWrapper::new(b, &self.ballot_box_wrapper_inputs).ok())
             .find(|b| b.ballot_token_owner() == *self.ballot_token_owner_pk.h))
@@ -357,8 +495,8 @@ impl LocalBallotBoxSource for LocalBallotBoxScan {
 impl RefreshBoxSource for RefreshBoxScan {
     fn get_refresh_box(&self) -> Result<RefreshBoxWrapper> {
         let box_wrapper = RefreshBoxWrapper::new(
-            self.scan
-                .get_box()?
+            self.boxes
+                .get_box(&self.node_api, &self.cache)?
                 .ok_or(DataSourceError::RefreshBoxNotFoundError)?,
             &self.refresh_box_wrapper_inputs,
         )?;
@@ -369,8 +507,8 @@ impl RefreshBoxSource for RefreshBoxScan {
 impl LocalDatapointBoxSource for LocalOracleDatapointScan {
     fn get_local_oracle_datapoint_box(&self) -> Result<Option<OracleBoxWrapper>> {
         Ok(self
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.cache)?
             .into_iter()
             .filter_map(|b| OracleBoxWrapper::new(b, &self.oracle_box_wrapper_inputs).ok())
             .find(|b| b.public_key() == *self.oracle_pk.h))
@@ -380,8 +518,8 @@ impl LocalDatapointBoxSource for LocalOracleDatapointScan {
 impl VoteBallotBoxesSource for BallotBoxesScan {
     fn get_ballot_boxes(&self) -> Result<Vec<VoteBallotBoxWrapper>> {
         Ok(self
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.cache)?
             .into_iter()
             .filter_map(|ballot_box| {
                 VoteBallotBoxWrapper::new(ballot_box, &self.ballot_box_wrapper_inputs).ok()
@@ -393,8 +531,8 @@ impl VoteBallotBoxesSource for BallotBoxesScan {
 impl UpdateBoxSource for UpdateBoxScan {
     fn get_update_box(&self) -> Result<UpdateBoxWrapper> {
         let box_wrapper = UpdateBoxWrapper::new(
-            self.scan
-                .get_box()?
+            self.boxes
+                .get_box(&self.node_api, &self.cache)?
                 .ok_or(DataSourceError::UpdateBoxNotFoundError)?,
             &self.update_box_wrapper_inputs,
         )?;
@@ -405,8 +543,8 @@ impl UpdateBoxSource for UpdateBoxScan {
 impl PostedDatapointBoxesSource for OracleDatapointScan {
     fn get_posted_datapoint_boxes(&self) -> Result<Vec<PostedOracleBox>> {
         let posted_boxes = self
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.cache)?
             .into_iter()
             .filter_map(|b| OracleBoxWrapper::new(b, &self.oracle_box_wrapper_inputs).ok())
             .filter_map(|b| match b {
@@ -421,8 +559,8 @@ impl PostedDatapointBoxesSource for OracleDatapointScan {
 impl CollectedDatapointBoxesSource for OracleDatapointScan {
     fn get_collected_datapoint_boxes(&self) -> Result<Vec<CollectedOracleBox>> {
         let posted_boxes = self
-            .scan
-            .get_boxes()?
+            .boxes
+            .get_boxes(&self.node_api, &self.cache)?
             .into_iter()
             .filter_map(|b| OracleBoxWrapper::new(b, &self.oracle_box_wrapper_inputs).ok())
             .filter_map(|b| match b {
@@ -437,8 +575,8 @@ impl CollectedDatapointBoxesSource for OracleDatapointScan {
 impl BuybackBoxSource for BuybackBoxScan {
     fn get_buyback_box(&self) -> Result<Option<BuybackBoxWrapper>> {
         Ok(self
-            .scan
-            .get_box()?
+            .boxes
+            .get_box(&self.node_api, &self.cache)?
             .map(|ergo_box| BuybackBoxWrapper::new(ergo_box, self.reward_token_id.clone())))
     }
 }
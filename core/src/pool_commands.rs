@@ -1,15 +1,19 @@
 use ergo_lib::ergo_chain_types::DigestNError;
 use ergo_lib::ergotree_ir::chain::address::{Address, AddressEncoderError};
+use serde::Deserialize;
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::action_report::PoolActionReport;
 use crate::actions::PoolAction;
+use crate::box_kind::OracleBox;
 use crate::box_kind::PoolBox;
-use crate::datapoint_source::RuntimeDataPointSource;
-use crate::oracle_config::ORACLE_CONFIG;
+use crate::context::OracleContext;
+use crate::datapoint_source::DataPointSource;
 use crate::oracle_state::{DataSourceError, OraclePool};
 use crate::oracle_types::BlockHeight;
-use crate::pool_config::POOL_CONFIG;
+use crate::spec_token::TokenIdKind;
+use crate::wallet::token_guard::TokenGuardedWallet;
 use crate::wallet::WalletDataSource;
 
 use self::publish_datapoint::build_publish_first_datapoint_action;
@@ -24,7 +28,7 @@ pub mod refresh;
 #[cfg(test)]
 pub(crate) mod test_utils;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PoolCommand {
     Refresh,
     PublishFirstDataPoint,
@@ -50,31 +54,39 @@ pub enum PoolCommandError {
 }
 
 pub fn build_action(
+    ctx: &OracleContext,
     cmd: PoolCommand,
     op: &OraclePool,
     wallet: &dyn WalletDataSource,
     height: BlockHeight,
     change_address: Address,
-    datapoint_source: &RuntimeDataPointSource,
+    datapoint_source: &dyn DataPointSource,
 ) -> Result<(PoolAction, PoolActionReport), PoolCommandError> {
     let refresh_box_source = op.get_refresh_box_source();
     let datapoint_boxes_source = op.get_posted_datapoint_boxes_source();
     let pool_box = op.get_pool_box_source().get_pool_box()?;
     let current_epoch_counter = pool_box.epoch_counter();
+    let pool_config = op.pool_config();
+    let tx_fee = ctx.base_fee();
     let oracle_public_key =
-        if let Address::P2Pk(public_key) = ORACLE_CONFIG.oracle_address.address() {
+        if let Address::P2Pk(public_key) = ctx.oracle_config.oracle_address.address() {
             *public_key.h
         } else {
             return Err(PoolCommandError::WrongOracleAddressType);
         };
+    // the wallet boxes holding the pool tokens are only spent by the txs moving them
+    let guarded_wallet = TokenGuardedWallet::new(wallet).protect_pool(&pool_config.token_ids);
     match cmd {
         PoolCommand::PublishFirstDataPoint => build_publish_first_datapoint_action(
-            wallet,
+            &guarded_wallet
+                .allow(pool_config.token_ids.oracle_token_id.token_id())
+                .allow(pool_config.token_ids.reward_token_id.token_id()),
             height,
             change_address,
             oracle_public_key,
-            POOL_CONFIG.oracle_box_wrapper_inputs.clone(),
+            pool_config.oracle_box_wrapper_inputs.clone(),
             datapoint_source,
+            tx_fee,
         )
         .map_err(Into::into)
         .map(|(action, report)| (action.into(), report.into())),
@@ -84,14 +96,23 @@ pub fn build_action(
                 .get_local_oracle_datapoint_box()?
             {
                 let new_epoch_counter = current_epoch_counter;
+                // after a pool update with new reward tokens the oracle box gets one from the wallet
+                let guarded_wallet = if local_datapoint_box.reward_token().token_id
+                    != pool_config.token_ids.reward_token_id
+                {
+                    guarded_wallet.allow(pool_config.token_ids.reward_token_id.token_id())
+                } else {
+                    guarded_wallet
+                };
                 build_subsequent_publish_datapoint_action(
                     &local_datapoint_box,
-                    wallet,
+                    &guarded_wallet,
                     height,
                     change_address,
                     datapoint_source,
                     new_epoch_counter,
-                    &POOL_CONFIG.token_ids.reward_token_id,
+                    &pool_config.token_ids.reward_token_id,
+                    tx_fee,
                 )
                 .map_err(Into::into)
                 .map(|(action, report)| (action.into(), report.into()))
@@ -105,21 +126,22 @@ pub fn build_action(
             op.get_pool_box_source(),
             refresh_box_source,
             datapoint_boxes_source,
-            POOL_CONFIG
+            pool_config
                 .refresh_box_wrapper_inputs
                 .contract_inputs
                 .contract_parameters()
                 .max_deviation_percent() as u32,
-            POOL_CONFIG
+            pool_config
                 .refresh_box_wrapper_inputs
                 .contract_inputs
                 .contract_parameters()
                 .min_data_points(),
-            wallet,
+            &guarded_wallet,
             height,
             change_address,
             &oracle_public_key,
             op.get_buyback_box_source(),
+            tx_fee,
         )
         .map_err(Into::into)
         .map(|(action, report)| (action.into(), report.into())),
@@ -8,7 +8,6 @@ use crate::box_kind::PoolBoxWrapper;
 use crate::box_kind::PostedOracleBox;
 use crate::box_kind::RefreshBox;
 use crate::box_kind::RefreshBoxWrapper;
-use crate::oracle_config::BASE_FEE;
 use crate::oracle_state::BuybackBoxSource;
 use crate::oracle_state::DataSourceError;
 use crate::oracle_state::PoolBoxSource;
@@ -27,6 +26,7 @@ use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilderError;
 use ergo_lib::ergo_chain_types::EcPoint;
 use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
 use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
 use ergo_lib::ergotree_ir::chain::token::TokenAmount;
 use ergo_lib::wallet::box_selector::BoxSelection;
@@ -75,8 +75,8 @@ pub fn build_refresh_action(
     change_address: Address,
     my_oracle_pk: &EcPoint,
     buyback_box_source: Option<&dyn BuybackBoxSource>,
+    tx_fee: BoxValue,
 ) -> Result<(RefreshAction, RefreshActionReport), RefreshActionError> {
-    let tx_fee = *BASE_FEE;
     let in_pool_box = pool_box_source.get_pool_box()?;
     let in_refresh_box = refresh_box_source.get_refresh_box()?;
     let min_start_height = height - in_refresh_box.contract().epoch_length();
@@ -212,6 +212,7 @@ pub fn build_refresh_action(
             .iter()
             .map(|b| b.public_key())
             .collect(),
+        rate,
     };
     Ok((RefreshAction { tx }, report))
 }
@@ -241,7 +242,9 @@ where
     Ok(successful_boxes)
 }
 
-fn deviation_check(max_deviation_range: u32, datapoint_boxes: Vec<Rate>) -> bool {
+/// The refresh contract check of the collected datapoints: the min datapoint is within the max
+/// deviation range of the max one
+pub(crate) fn deviation_check(max_deviation_range: u32, datapoint_boxes: Vec<Rate>) -> bool {
     let min_datapoint = datapoint_boxes.clone().into_iter().min().unwrap();
     let max_datapoint = datapoint_boxes.into_iter().max().unwrap();
     let deviation_delta = max_datapoint * (max_deviation_range as i64) / 100;
@@ -395,7 +398,6 @@ mod tests {
     use crate::contracts::refresh::RefreshContract;
     use crate::contracts::refresh::RefreshContractInputs;
     use crate::contracts::refresh::RefreshContractParameters;
-    use crate::oracle_config::BASE_FEE;
     use crate::oracle_state::DataSourceError;
     use crate::oracle_types::EpochLength;
     use crate::pool_commands::test_utils::generate_token_ids;
@@ -406,6 +408,7 @@ mod tests {
     };
     use crate::pool_config::TokenIds;
     use crate::spec_token::TokenIdKind;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
 
     use super::*;
 
@@ -518,11 +521,12 @@ mod tests {
             contract_inputs: refresh_contract_inputs,
         };
         let pool_box_epoch_id = EpochCounter(1);
-        let in_refresh_box = make_refresh_box(*BASE_FEE, &inputs, height - EpochLength(32));
+        let in_refresh_box =
+            make_refresh_box(SUGGESTED_TX_FEE(), &inputs, height - EpochLength(32));
         let in_pool_box = make_pool_box(
             200,
             pool_box_epoch_id,
-            *BASE_FEE,
+            SUGGESTED_TX_FEE(),
             height - EpochLength(32), // from previous epoch
             &pool_contract_parameters,
             &token_ids,
@@ -544,7 +548,7 @@ mod tests {
             oracle_pub_keys.clone(),
             vec![199, 70, 196, 197, 198, 200],
             pool_box_epoch_id,
-            BASE_FEE.checked_mul_u32(100).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
             height - EpochLength(9),
             &oracle_contract_parameters,
             &token_ids,
@@ -568,7 +572,7 @@ mod tests {
         .unwrap();
         let wallet_unspent_box = make_wallet_unspent_box(
             secret.public_image(),
-            BASE_FEE.checked_mul_u32(10000).unwrap(),
+            SUGGESTED_TX_FEE().checked_mul_u32(10000).unwrap(),
             None,
         );
         let wallet_mock = WalletDataMock {
@@ -589,6 +593,7 @@ mod tests {
             change_address.address(),
             &oracle_pub_key,
             None,
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
@@ -619,7 +624,7 @@ mod tests {
                 oracle_pub_keys.clone(),
                 vec![199, 70, 196, 197, 198, 200],
                 EpochCounter(pool_box_epoch_id.0 + 1),
-                BASE_FEE.checked_mul_u32(100).unwrap(),
+                SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
                 height - EpochLength(9),
                 &oracle_contract_parameters,
                 &token_ids,
@@ -636,6 +641,7 @@ mod tests {
             change_address.address(),
             &oracle_pub_key,
             None,
+            SUGGESTED_TX_FEE(),
         );
         dbg!(&wrong_epoch_res);
         assert!(matches!(
@@ -651,7 +657,7 @@ mod tests {
 
         let buyback_box = make_wallet_unspent_box(
             secret.public_image(),
-            *BASE_FEE,
+            SUGGESTED_TX_FEE(),
             Some(
                 vec![
                     Token {
@@ -685,6 +691,7 @@ mod tests {
             change_address.address(),
             &oracle_pub_key,
             Some(&buyback_source),
+            SUGGESTED_TX_FEE(),
         )
         .unwrap();
 
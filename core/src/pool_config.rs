@@ -1,9 +1,6 @@
 use std::path::Path;
-use std::path::PathBuf;
 
-use anyhow::anyhow;
 use anyhow::Context;
-use once_cell::sync;
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
@@ -28,12 +25,6 @@ use crate::spec_token::RewardTokenId;
 use crate::spec_token::UpdateTokenId;
 
 pub const DEFAULT_POOL_CONFIG_FILE_NAME: &str = "pool_config.yaml";
-pub static POOL_CONFIG_FILE_PATH: sync::OnceCell<PathBuf> = sync::OnceCell::new();
-lazy_static! {
-    pub static ref POOL_CONFIG: PoolConfig = PoolConfig::load().unwrap();
-    pub static ref POOL_CONFIG_OPT: Result<PoolConfig, String> =
-        PoolConfig::load().map_err(|e| e.to_string());
-}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(
@@ -42,6 +33,9 @@ lazy_static! {
 )]
 pub struct PoolConfig {
     pub data_point_source: Option<PredefinedDataPointSource>,
+    /// Datapoint source script of the pool, takes precedence over `data_point_source` (see
+    /// [`crate::running_pool::pool_datapoint_source`])
+    pub data_point_source_custom_script: Option<String>,
     pub oracle_box_wrapper_inputs: OracleBoxWrapperInputs,
     pub pool_box_wrapper_inputs: PoolBoxWrapperInputs,
     pub refresh_box_wrapper_inputs: RefreshBoxWrapperInputs,
@@ -49,6 +43,12 @@ pub struct PoolConfig {
     pub ballot_box_wrapper_inputs: BallotBoxWrapperInputs,
     pub token_ids: TokenIds,
     pub buyback_token_id: Option<BuybackTokenId>,
+    /// Amount of the oracle tokens minted for the pool, None in the configs created before it
+    /// was recorded
+    pub oracle_tokens_emission: Option<u64>,
+    /// Amount of the ballot tokens minted for the pool, None in the configs created before it
+    /// was recorded
+    pub ballot_tokens_emission: Option<u64>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone)]
@@ -146,6 +146,7 @@ impl PoolConfig {
         )?;
         Ok(PoolConfig {
             data_point_source: bootstrap.data_point_source,
+            data_point_source_custom_script: None,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -153,13 +154,12 @@ impl PoolConfig {
             update_box_wrapper_inputs,
             token_ids,
             buyback_token_id: None,
+            oracle_tokens_emission: Some(bootstrap.tokens_to_mint.oracle_tokens.quantity),
+            ballot_tokens_emission: Some(bootstrap.tokens_to_mint.ballot_tokens.quantity),
         })
     }
 
-    fn load() -> Result<Self, anyhow::Error> {
-        let config_file_path = POOL_CONFIG_FILE_PATH
-            .get()
-            .ok_or_else(|| anyhow!("Pool config file path not set"))?;
+    pub fn load_from_file(config_file_path: &Path) -> Result<Self, anyhow::Error> {
         log::info!("Loading pool config from {}", config_file_path.display());
         let config_str = std::fs::read_to_string(config_file_path).context(format!(
             "failed to load pool config file from {}",
@@ -192,4 +192,37 @@ mod tests {
         let s = serde_yaml::to_string(&token_ids).unwrap();
         assert_eq!(token_ids, serde_yaml::from_str::<TokenIds>(&s).unwrap());
     }
+
+    #[test]
+    fn known_contract_indexes_are_filled_in() {
+        let pool_config =
+            PoolConfig::create(BootstrapConfig::default(), generate_token_ids()).unwrap();
+        let yaml = serde_yaml::to_string(&pool_config).unwrap();
+        let without_indexes: Vec<&str> = yaml
+            .lines()
+            .filter(|line| !line.trim_start().contains("_index:"))
+            .collect();
+        let loaded = PoolConfig::load_from_str(&without_indexes.join("\n")).unwrap();
+        assert_eq!(serde_yaml::to_string(&loaded).unwrap(), yaml);
+
+        // the refresh contract is not a known pool contract
+        let refresh_ergo_tree = base16::encode_lower(
+            &pool_config
+                .refresh_box_wrapper_inputs
+                .contract_inputs
+                .contract_parameters()
+                .ergo_tree_bytes(),
+        );
+        let pool_ergo_tree = base16::encode_lower(
+            &pool_config
+                .pool_box_wrapper_inputs
+                .contract_inputs
+                .contract_parameters()
+                .ergo_tree_bytes(),
+        );
+        let custom_pool_contract = without_indexes
+            .join("\n")
+            .replace(&pool_ergo_tree, &refresh_ergo_tree);
+        assert!(PoolConfig::load_from_str(&custom_pool_contract).is_err());
+    }
 }
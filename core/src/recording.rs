@@ -0,0 +1,381 @@
+//! Recording of everything the main loop iterations saw (`run --record`), for the incident
+//! analysis. The iterations are appended to a JSON lines file in the data folder and fed again
+//! through the pool state machine and the tx builders offline by the `replay` command (see
+//! [`crate::cli_commands::replay`]). The file is rotated once it reaches the size limit
+//! (`run --record-max-size-mb`), the previous iterations are kept in one rotated file.
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::datapoint_source::DataPointSource;
+use crate::datapoint_source::DataPointSourceError;
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_types::BlockHeight;
+use crate::oracle_types::Rate;
+use crate::pool_commands::PoolCommand;
+use crate::wallet::WalletDataError;
+use crate::wallet::WalletDataSource;
+
+/// Version of oracle-core writing the records
+pub const ORACLE_CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Size the recording is rotated at unless `run --record-max-size-mb` is given
+pub const DEFAULT_RECORDING_MAX_SIZE_MB: u64 = 100;
+
+/// The unspent boxes of all the pool tokens at a height
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoxSnapshot {
+    pub height: BlockHeight,
+    /// Boxes by the base16-encoded token ID
+    pub token_boxes: BTreeMap<String, Vec<ErgoBox>>,
+}
+
+impl BoxSnapshot {
+    /// The boxes with the token, none if the token was not looked up
+    pub fn boxes(&self, token_id: &str) -> Vec<ErgoBox> {
+        self.token_boxes.get(token_id).cloned().unwrap_or_default()
+    }
+}
+
+/// The datapoint the source returned, or its error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedDataPoint {
+    Rate(Rate),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IterationRecord {
+    /// Unix time in seconds
+    pub timestamp: u64,
+    pub oracle_core_version: String,
+    pub pool: String,
+    /// Where the recording oracle looked up the boxes, None in the recordings of the versions
+    /// before it was recorded
+    #[serde(default)]
+    pub box_source: Option<BoxSourceConfig>,
+    pub boxes: BoxSnapshot,
+    /// The wallet boxes the pool txs could spend (the boxes with the tokens of the other pools
+    /// are left out)
+    pub wallet_boxes: Vec<ErgoBox>,
+    /// Base58-encoded change address
+    pub change_address: String,
+    /// Only fetched when a datapoint is published
+    pub datapoint: Option<RecordedDataPoint>,
+    pub command: Option<PoolCommand>,
+    pub tx: Option<UnsignedTransaction>,
+    pub build_error: Option<String>,
+}
+
+impl IterationRecord {
+    pub fn new(
+        pool: &str,
+        box_source: BoxSourceConfig,
+        boxes: BoxSnapshot,
+        wallet_boxes: Vec<ErgoBox>,
+        change_address: &NetworkAddress,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            oracle_core_version: ORACLE_CORE_VERSION.to_string(),
+            pool: pool.to_string(),
+            box_source: Some(box_source),
+            boxes,
+            wallet_boxes,
+            change_address: change_address.to_base58(),
+            datapoint: None,
+            command: None,
+            tx: None,
+            build_error: None,
+        }
+    }
+
+    pub fn height(&self) -> BlockHeight {
+        self.boxes.height
+    }
+}
+
+/// The JSON lines file the iterations are recorded to
+#[derive(Debug, Clone)]
+pub struct MainLoopRecording {
+    path: PathBuf,
+    /// Size in bytes the file is rotated at, unlimited if None
+    max_size: Option<u64>,
+}
+
+impl MainLoopRecording {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            max_size: None,
+        }
+    }
+
+    /// Rotate the file once it reaches `max_size` bytes: it replaces the previously rotated file
+    /// (see [`Self::rotated_path`]), so the recording takes at most about twice `max_size`
+    pub fn with_max_size(self, max_size: u64) -> Self {
+        Self {
+            max_size: Some(max_size),
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file with the iterations recorded before the last rotation
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+
+    /// Appends the iteration to the file, rotates the file first if it is full
+    pub fn record(&self, record: &IterationRecord) -> std::io::Result<()> {
+        if let Some(max_size) = self.max_size {
+            match std::fs::metadata(&self.path) {
+                Ok(metadata) if metadata.len() >= max_size => {
+                    std::fs::rename(&self.path, self.rotated_path())?
+                }
+                Ok(_) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record).unwrap())
+    }
+
+    /// All the recorded iterations (the rotated file included), the oldest first
+    pub fn load(&self) -> std::io::Result<Vec<IterationRecord>> {
+        let mut records = load_records(&self.rotated_path())?;
+        records.extend(load_records(&self.path)?);
+        Ok(records)
+    }
+}
+
+fn load_records(path: &Path) -> std::io::Result<Vec<IterationRecord>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(Into::into))
+        .collect()
+}
+
+/// Passes the datapoints of the wrapped source through and keeps the last one for the record
+pub struct RecordingDataPointSource<'a> {
+    inner: &'a dyn DataPointSource,
+    last: Mutex<Option<RecordedDataPoint>>,
+}
+
+impl<'a> RecordingDataPointSource<'a> {
+    pub fn new(inner: &'a dyn DataPointSource) -> Self {
+        Self {
+            inner,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn last_datapoint(&self) -> Option<RecordedDataPoint> {
+        self.last.lock().unwrap().clone()
+    }
+}
+
+impl DataPointSource for RecordingDataPointSource<'_> {
+    fn get_datapoint(&self) -> Result<Rate, DataPointSourceError> {
+        let res = self.inner.get_datapoint();
+        *self.last.lock().unwrap() = Some(match &res {
+            Ok(rate) => RecordedDataPoint::Rate(*rate),
+            Err(e) => RecordedDataPoint::Error(e.to_string()),
+        });
+        res
+    }
+}
+
+/// Returns the recorded datapoint (or its error) again
+pub struct RecordedDataPointSource(pub Option<RecordedDataPoint>);
+
+impl DataPointSource for RecordedDataPointSource {
+    fn get_datapoint(&self) -> Result<Rate, DataPointSourceError> {
+        match &self.0 {
+            Some(RecordedDataPoint::Rate(rate)) => Ok(*rate),
+            Some(RecordedDataPoint::Error(e)) => Err(DataPointSourceError::Recorded(e.clone())),
+            // the recording oracle didn't ask for a datapoint
+            None => Err(DataPointSourceError::NoDataPoints),
+        }
+    }
+}
+
+/// The recorded wallet boxes and change address
+pub struct RecordedWallet {
+    unspent_boxes: Vec<ErgoBox>,
+    change_address: NetworkAddress,
+}
+
+impl RecordedWallet {
+    pub fn from_record(record: &IterationRecord) -> Result<Self, WalletDataError> {
+        Ok(Self {
+            unspent_boxes: record.wallet_boxes.clone(),
+            change_address: AddressEncoder::unchecked_parse_network_address_from_str(
+                &record.change_address,
+            )?,
+        })
+    }
+}
+
+impl WalletDataSource for RecordedWallet {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        Ok(self.unspent_boxes.clone())
+    }
+
+    fn get_change_address(&self) -> Result<NetworkAddress, WalletDataError> {
+        Ok(self.change_address.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    struct FailingSource;
+
+    impl DataPointSource for FailingSource {
+        fn get_datapoint(&self) -> Result<Rate, DataPointSourceError> {
+            Err(DataPointSourceError::NoDataPoints)
+        }
+    }
+
+    fn record(pool: &str, height: u32) -> IterationRecord {
+        let change_address = AddressEncoder::unchecked_parse_network_address_from_str(
+            "9iHyKxXs2ZNLMp9N9gbUT9V8gTbsV7HED1C1VhttMfBUMPDyF7r",
+        )
+        .unwrap();
+        let boxes = BoxSnapshot {
+            height: BlockHeight(height),
+            token_boxes: BTreeMap::from([("00".repeat(32), vec![force_any_val::<ErgoBox>()])]),
+        };
+        IterationRecord::new(
+            pool,
+            BoxSourceConfig::NodeScans,
+            boxes,
+            vec![force_any_val::<ErgoBox>()],
+            &change_address,
+        )
+    }
+
+    #[test]
+    fn test_recording_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-main-loop-recording-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let recording = MainLoopRecording::new(path.clone());
+        assert!(recording.load().unwrap().is_empty());
+        let mut first = record("default", 100);
+        first.command = Some(PoolCommand::PublishSubsequentDataPoint { republish: false });
+        first.datapoint = Some(RecordedDataPoint::Rate(Rate::from(42)));
+        let second = record("other", 101);
+        recording.record(&first).unwrap();
+        recording.record(&second).unwrap();
+        let loaded = recording.load().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].pool, "default");
+        assert_eq!(loaded[0].height(), BlockHeight(100));
+        assert_eq!(loaded[0].boxes, first.boxes);
+        assert_eq!(loaded[0].command, first.command);
+        assert_eq!(loaded[0].datapoint, first.datapoint);
+        assert_eq!(loaded[0].oracle_core_version, ORACLE_CORE_VERSION);
+        assert_eq!(loaded[0].box_source, Some(BoxSourceConfig::NodeScans));
+        assert_eq!(loaded[1].pool, "other");
+        assert_eq!(loaded[1].command, None);
+        let wallet = RecordedWallet::from_record(&loaded[1]).unwrap();
+        assert_eq!(
+            wallet.get_unspent_wallet_boxes().unwrap(),
+            second.wallet_boxes
+        );
+        assert_eq!(
+            wallet.get_change_address().unwrap().to_base58(),
+            second.change_address
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_recording_rotation() {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-main-loop-recording-rotation-{}.jsonl",
+            std::process::id()
+        ));
+        let mut iteration = record("default", 100);
+        let line_len = serde_json::to_string(&iteration).unwrap().len() as u64 + 1;
+        // rotated after every two records
+        let recording = MainLoopRecording::new(path.clone()).with_max_size(line_len + 1);
+        let _ = std::fs::remove_file(recording.rotated_path());
+        let _ = std::fs::remove_file(&path);
+        for height in 100..105 {
+            iteration.boxes.height = BlockHeight(height);
+            recording.record(&iteration).unwrap();
+        }
+        let heights: Vec<u32> = recording
+            .load()
+            .unwrap()
+            .iter()
+            .map(|record| record.height().0)
+            .collect();
+        // the first two records are gone with the second rotation
+        assert_eq!(heights, vec![102, 103, 104]);
+        assert_eq!(load_records(&path).unwrap().len(), 1);
+        std::fs::remove_file(recording.rotated_path()).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_datapoint_is_recorded_and_replayed() {
+        let failing = FailingSource;
+        let source = RecordingDataPointSource::new(&failing);
+        assert_eq!(source.last_datapoint(), None);
+        assert!(source.get_datapoint().is_err());
+        let recorded = source.last_datapoint();
+        assert_eq!(
+            recorded,
+            Some(RecordedDataPoint::Error(
+                DataPointSourceError::NoDataPoints.to_string()
+            ))
+        );
+        assert!(RecordedDataPointSource(recorded).get_datapoint().is_err());
+        assert_eq!(
+            RecordedDataPointSource(Some(RecordedDataPoint::Rate(Rate::from(42))))
+                .get_datapoint()
+                .unwrap(),
+            Rate::from(42)
+        );
+    }
+}
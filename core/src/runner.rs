@@ -0,0 +1,682 @@
+//! The oracle main loop. Starts the pools, the REST API and the metrics servers and runs the
+//! pool state machine on every new block until a termination signal is received. The config is
+//! reloaded before the next iteration when requested (see [`crate::config_reload`]).
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use crossbeam::channel::bounded;
+use crossbeam::channel::Receiver;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use log::error;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+use crate::action_report::PoolActionReport;
+use crate::actions::execute_action;
+use crate::actions::PoolAction;
+use crate::address_util::pks_to_network_addresses;
+use crate::api::start_rest_server;
+use crate::config_reload::listen_for_reload_signals;
+use crate::config_reload::reload_config;
+use crate::config_reload::ConfigFiles;
+use crate::config_reload::ConfigFilesWatcher;
+use crate::config_reload::ConfigReloadOutcome;
+use crate::config_reload::ConfigReloadReport;
+use crate::config_reload::ReloadRequest;
+use crate::config_reload::ReloadTrigger;
+use crate::context::OracleContext;
+use crate::logging::LogHandle;
+use crate::metrics::start_metrics_server;
+use crate::metrics::update_metrics;
+use crate::metrics::update_node_metrics;
+use crate::node_interface::node_api::NodeApi;
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_state::OraclePool;
+use crate::oracle_types::BlockHeight;
+use crate::pool_commands::build_action;
+use crate::pool_commands::publish_datapoint::PublishDatapointActionError;
+use crate::pool_commands::refresh::RefreshActionError;
+use crate::pool_commands::PoolCommand;
+use crate::pool_commands::PoolCommandError;
+use crate::recording::IterationRecord;
+use crate::recording::MainLoopRecording;
+use crate::recording::RecordedDataPoint;
+use crate::recording::RecordingDataPointSource;
+use crate::running_pool::check_box_source;
+use crate::running_pool::load_pool_configs;
+use crate::running_pool::RunningPool;
+use crate::scans::node_rescan_finished;
+use crate::scheduler::BlockScheduler;
+use crate::scheduler::ShutdownSignal;
+use crate::scheduler::TerminationSignals;
+use crate::scheduler::Wakeup;
+use crate::shadow_mode::posted_oracles;
+use crate::shadow_mode::PoolObservation;
+use crate::shadow_mode::ShadowReportEntry;
+use crate::shadow_mode::ShadowReportStorage;
+use crate::state::process;
+use crate::state::PoolState;
+use crate::wallet::token_guard::TokenGuardedWallet;
+use crate::wallet::OracleWallet;
+use crate::wallet::WalletDataSource;
+use crate::wallet_consolidation::build_consolidation_tx;
+use crate::wallet_consolidation::WalletConsolidationConfig;
+use crate::wallet_consolidation::WalletConsolidationError;
+use crate::wallet_funds::WalletFundsMonitor;
+use crate::wallet_funds::WalletFundsReport;
+
+/// How long to wait for the REST and metrics servers to stop on shutdown
+const SERVERS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the pool scans are checked on the nodes (they are also checked on start)
+const SCAN_VERIFICATION_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Wait for the previous consolidation tx to be mined before merging the wallet boxes again
+const MIN_BLOCKS_BETWEEN_CONSOLIDATIONS: u32 = 10;
+
+/// Run the pools from `config_files` until SIGTERM/SIGINT is received. The configs are reloaded
+/// on SIGHUP or when the config files change. With `recording` every main loop iteration is
+/// written to it (see [`crate::recording`]).
+pub fn run(
+    ctx: Arc<OracleContext>,
+    config_files: ConfigFiles,
+    log_handle: Option<LogHandle>,
+    read_only: bool,
+    shadow: bool,
+    recording: Option<MainLoopRecording>,
+    enable_rest_api: bool,
+) -> Result<(), anyhow::Error> {
+    let mut ctx = ctx;
+    check_box_source(&ctx.oracle_config, read_only, shadow)?;
+    let mut node_api = ctx.node_api();
+    let mut wallet = ctx.wallet()?;
+    let mut change_address = ctx
+        .change_address(&wallet)
+        .context("failed to get change address from the wallet")?;
+    let tokio_runtime = tokio::runtime::Runtime::new()?;
+    let (_, repost_receiver) = bounded::<bool>(1);
+
+    let mut running_pools: Vec<RunningPool> = load_pool_configs(&config_files.pools)?
+        .into_iter()
+        .map(|(name, pool_config)| RunningPool::start(&ctx, name, &pool_config, shadow))
+        .collect::<Result<_, _>>()?;
+    if shadow {
+        log::info!("Running in shadow mode, transactions will not be submitted");
+    }
+    if let Some(recording) = &recording {
+        log::info!(
+            "Recording the main loop iterations to {}",
+            recording.path().display()
+        );
+    }
+
+    // Set on SIGTERM/SIGINT, the main loop finishes the current iteration and exits. A second
+    // signal exits right away.
+    let termination_requested = ShutdownSignal::new();
+    let termination_requested_clone = termination_requested.clone();
+    tokio_runtime.spawn(async move {
+        let second_signal = async {
+            let mut signals = TerminationSignals::new()?;
+            signals.recv().await?;
+            log::info!(
+                "Termination signal received, shutting down after the current iteration \
+                 (send it again to force the shutdown)"
+            );
+            termination_requested_clone.trigger();
+            signals.recv().await
+        };
+        match second_signal.await {
+            Ok(()) => {
+                error!("Second termination signal received, forcing the shutdown");
+                std::process::exit(exitcode::SOFTWARE);
+            }
+            Err(e) => error!("Failed to listen for termination signals: {}", e),
+        }
+    });
+    let reload_request = ReloadRequest::new();
+    let reload_request_clone = reload_request.clone();
+    tokio_runtime.spawn(async move {
+        if let Err(e) = listen_for_reload_signals(reload_request_clone).await {
+            error!("Failed to listen for reload signals: {}", e);
+        }
+    });
+    let mut config_files_watcher = ConfigFilesWatcher::new(&config_files);
+    let config_reload_report = Arc::new(RwLock::new(None));
+    let wallet_funds = Arc::new(RwLock::new(WalletFundsMonitor::new(
+        ctx.oracle_config.wallet_low_balance_threshold(),
+    )));
+
+    let start_servers = |ctx: &Arc<OracleContext>, running_pools: &[RunningPool]| {
+        Servers::start(
+            &tokio_runtime,
+            ctx,
+            running_pools,
+            enable_rest_api,
+            &repost_receiver,
+            &config_reload_report,
+            &wallet_funds,
+        )
+    };
+    let mut servers = start_servers(&ctx, &running_pools);
+    let mut scheduler = BlockScheduler::new(
+        ctx.oracle_config.node_poll_interval(),
+        ctx.oracle_config.max_main_loop_interval(),
+    );
+    let mut last_consolidation_height: Option<BlockHeight> = None;
+    let mut last_scan_verification = Instant::now();
+    // set when the repaired scans are being rescanned, the pools are not run until it's finished
+    let mut scan_repair_rescan = false;
+    while !termination_requested.is_triggered() {
+        let files_changed = config_files_watcher.changed();
+        let reload_trigger = if reload_request.take() {
+            Some(ReloadTrigger::Signal)
+        } else if files_changed {
+            Some(ReloadTrigger::FileChange)
+        } else {
+            None
+        };
+        if let Some(trigger) = reload_trigger {
+            let res = reload_config(&ctx, &config_files, &running_pools, read_only, shadow);
+            let outcome = match res {
+                Ok(Some(reloaded)) => {
+                    log::info!("Config reloaded: {}", reloaded.changes.join(", "));
+                    ctx = reloaded.ctx;
+                    running_pools = reloaded.running_pools;
+                    change_address = reloaded.change_address;
+                    wallet = reloaded.wallet;
+                    node_api = ctx.node_api();
+                    if let Some(log_handle) = &log_handle {
+                        log_handle.set_config_log_level(ctx.oracle_config.log_level);
+                    }
+                    wallet_funds.write().unwrap().set_low_balance_threshold(
+                        ctx.oracle_config.wallet_low_balance_threshold(),
+                    );
+                    // the servers serve the pools they are started with
+                    servers.stop(&tokio_runtime);
+                    servers = start_servers(&ctx, &running_pools);
+                    scheduler = BlockScheduler::new(
+                        ctx.oracle_config.node_poll_interval(),
+                        ctx.oracle_config.max_main_loop_interval(),
+                    );
+                    ConfigReloadOutcome::Applied {
+                        changes: reloaded.changes,
+                    }
+                }
+                Ok(None) => {
+                    log::info!("Config reload: no changes");
+                    ConfigReloadOutcome::Unchanged
+                }
+                Err(e) => {
+                    error!("Config reload failed, keeping the current config: {:?}", e);
+                    ConfigReloadOutcome::Rejected {
+                        error: format!("{:?}", e),
+                    }
+                }
+            };
+            *config_reload_report.write().unwrap() =
+                Some(ConfigReloadReport::new(trigger, outcome));
+        }
+        if !scan_repair_rescan && last_scan_verification.elapsed() >= SCAN_VERIFICATION_INTERVAL {
+            for pool in &running_pools {
+                scan_repair_rescan |= pool.verify_node_scans(&ctx, &node_api);
+            }
+            last_scan_verification = Instant::now();
+        }
+        if ctx.oracle_config.box_source.requires_node() {
+            check_nodes(&ctx, &node_api, &running_pools);
+            if let Err(e) = check_wallet_funds(&node_api, &wallet, &wallet_funds) {
+                error!("Failed to check the wallet funds: {:?}", e);
+            }
+        }
+        if scan_repair_rescan {
+            match node_rescan_finished(&node_api) {
+                Ok(true) => {
+                    log::info!("Node {} finished the rescan", node_api.node_name());
+                    scan_repair_rescan = false;
+                }
+                Ok(false) => log::info!(
+                    "Node {} is rescanning for the repaired scans, skipping the iteration",
+                    node_api.node_name()
+                ),
+                Err(e) => error!("Failed to check the node rescan: {:?}", e),
+            }
+        }
+        if !scan_repair_rescan {
+            let wallet_funds_report = wallet_funds.read().unwrap().report().cloned();
+            let mut idle = true;
+            for pool in &running_pools {
+                // a pool tx must not spend the tokens of the other pools sharing the wallet
+                let wallet_boxes = running_pools
+                    .iter()
+                    .filter(|other| other.name != pool.name)
+                    .fold(TokenGuardedWallet::new(&wallet), |view, other| {
+                        view.protect_pool(&other.oracle_pool.pool_config().token_ids)
+                    });
+                let res = main_loop_iteration(
+                    &ctx,
+                    pool,
+                    read_only || shadow,
+                    &node_api,
+                    &wallet,
+                    &wallet_boxes,
+                    &change_address,
+                    wallet_funds_report.as_ref(),
+                    recording.as_ref(),
+                );
+                match res {
+                    Ok(submitted) => idle &= !submitted,
+                    Err(e) => {
+                        idle = false;
+                        error!("pool {}: error: {:?}", pool.name, e);
+                    }
+                }
+            }
+            if idle && !(read_only || shadow) {
+                if let Some(consolidation_config) = &ctx.oracle_config.wallet_consolidation {
+                    if let Err(e) = auto_consolidate_wallet(
+                        &ctx,
+                        &node_api,
+                        &wallet,
+                        consolidation_config,
+                        &mut last_consolidation_height,
+                    ) {
+                        error!("Failed to consolidate the wallet boxes: {:?}", e);
+                    }
+                }
+            }
+        }
+        match scheduler.wait_for_next_iteration(|| ctx.current_height(), &termination_requested) {
+            Wakeup::NewBlock(height) => log::debug!("New block at height {height}"),
+            Wakeup::Timer => log::debug!("No new block, running on timer"),
+            Wakeup::Shutdown => (),
+        }
+    }
+    servers.stop(&tokio_runtime);
+    log::info!("Oracle stopped");
+    Ok(())
+}
+
+/// The REST API and metrics servers
+struct Servers {
+    shutdown: ShutdownSignal,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Servers {
+    fn start(
+        tokio_runtime: &Runtime,
+        ctx: &Arc<OracleContext>,
+        running_pools: &[RunningPool],
+        enable_rest_api: bool,
+        repost_receiver: &Receiver<bool>,
+        config_reload_report: &Arc<RwLock<Option<ConfigReloadReport>>>,
+        wallet_funds: &Arc<RwLock<WalletFundsMonitor>>,
+    ) -> Self {
+        let shutdown = ShutdownSignal::new();
+        let mut handles = vec![];
+        // Start Oracle Core GET API Server
+        if enable_rest_api {
+            let ctx_clone = ctx.clone();
+            let pools_clone = running_pools.to_vec();
+            let repost_receiver = repost_receiver.clone();
+            let config_reload_report = config_reload_report.clone();
+            let wallet_funds = wallet_funds.clone();
+            let shutdown = shutdown.clone();
+            handles.push(tokio_runtime.spawn(async {
+                if let Err(e) = start_rest_server(
+                    ctx_clone,
+                    repost_receiver,
+                    pools_clone,
+                    config_reload_report,
+                    wallet_funds,
+                    shutdown,
+                )
+                .await
+                {
+                    error!("An error occurred while starting the REST server: {}", e);
+                    std::process::exit(exitcode::SOFTWARE);
+                }
+            }));
+        }
+        if let Some(metrics_port) = ctx.oracle_config.metrics_port {
+            let shutdown = shutdown.clone();
+            handles.push(tokio_runtime.spawn(async move {
+                if let Err(e) = start_metrics_server(metrics_port, shutdown).await {
+                    error!("An error occurred while starting the metrics server: {}", e);
+                    std::process::exit(exitcode::SOFTWARE);
+                }
+            }));
+        }
+        Self { shutdown, handles }
+    }
+
+    fn stop(self, tokio_runtime: &Runtime) {
+        self.shutdown.trigger();
+        tokio_runtime.block_on(async {
+            if tokio::time::timeout(
+                SERVERS_SHUTDOWN_TIMEOUT,
+                futures::future::join_all(self.handles),
+            )
+            .await
+            .is_err()
+            {
+                log::warn!("Servers did not stop in {:?}", SERVERS_SHUTDOWN_TIMEOUT);
+            }
+        });
+    }
+}
+
+/// Returns true if a pool tx was submitted. The tx inputs are selected from `wallet_boxes`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn main_loop_iteration(
+    ctx: &OracleContext,
+    pool: &RunningPool,
+    read_only: bool,
+    node_api: &NodeApi,
+    wallet: &OracleWallet,
+    wallet_boxes: &dyn WalletDataSource,
+    change_address: &NetworkAddress,
+    wallet_funds: Option<&WalletFundsReport>,
+    recording: Option<&MainLoopRecording>,
+) -> std::result::Result<bool, anyhow::Error> {
+    let oracle_pool = pool.oracle_pool.clone();
+    let shadow_report_storage = pool.shadow_report_storage.as_deref();
+    let requires_node = ctx.oracle_config.box_source.requires_node();
+    if requires_node {
+        wallet.check_ready()?;
+    }
+    let height = oracle_pool
+        .current_height()
+        .context("Failed to get the current height")?;
+    let pool_state = match oracle_pool.get_live_epoch_state() {
+        Ok(live_epoch_state) => PoolState::LiveEpoch(live_epoch_state),
+        Err(error) => {
+            log::error!("error getting live epoch state: {:?}", error);
+            PoolState::NeedsBootstrap
+        }
+    };
+    let epoch_length = oracle_pool
+        .pool_config()
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters()
+        .epoch_length();
+    let pool_box_epoch_id = match &pool_state {
+        PoolState::LiveEpoch(live_epoch_state) => Some(live_epoch_state.pool_box_epoch_id),
+        PoolState::NeedsBootstrap => None,
+    };
+    if let (Some(shadow_report_storage), Some(_)) = (shadow_report_storage, pool_box_epoch_id) {
+        resolve_shadow_reports(
+            &oracle_pool,
+            shadow_report_storage,
+            change_address.network(),
+        )?;
+    }
+    let mut submitted = false;
+    // without a node there are no wallet boxes to build the txs from, the pool is only watched
+    let cmd = if requires_node {
+        process(pool_state, epoch_length, height)
+    } else {
+        None
+    };
+    let datapoint_source = RecordingDataPointSource::new(pool.datapoint_source.as_ref());
+    let build_action_res = cmd.map(|cmd| {
+        log::debug!("Height {height}. Building action for command: {:?}", cmd);
+        build_action(
+            ctx,
+            cmd,
+            &oracle_pool,
+            wallet_boxes,
+            height,
+            change_address.address(),
+            &datapoint_source,
+        )
+    });
+    if let Some(recording) = recording {
+        record_iteration(
+            recording,
+            pool,
+            ctx.oracle_config.box_source,
+            height,
+            wallet_boxes,
+            change_address,
+            cmd,
+            &datapoint_source,
+            build_action_res.as_ref(),
+        );
+    }
+    if let Some(build_action_tuple_res) = build_action_res {
+        if let Some((action, report)) =
+            log_and_continue_if_non_fatal(change_address.network(), build_action_tuple_res)?
+        {
+            if let (Some(shadow_report_storage), Some(pool_box_epoch_id)) =
+                (shadow_report_storage, pool_box_epoch_id)
+            {
+                let entry = ShadowReportEntry::new(
+                    height,
+                    pool_box_epoch_id,
+                    action.tx().clone(),
+                    match datapoint_source.last_datapoint() {
+                        Some(RecordedDataPoint::Rate(rate)) => Some(rate),
+                        Some(RecordedDataPoint::Error(_)) | None => None,
+                    },
+                    report,
+                    posted_oracles(&oracle_pool)?,
+                    change_address.network(),
+                );
+                log::info!(
+                    "Shadow mode: would submit tx {} for epoch {} with datapoint {:?} and outputs {:?}: {:?}",
+                    String::from(entry.tx_id),
+                    pool_box_epoch_id.0,
+                    entry.datapoint,
+                    entry.output_box_ids,
+                    entry.action
+                );
+                shadow_report_storage.write().unwrap().record(entry);
+            } else if !read_only {
+                execute_action(
+                    ctx,
+                    &pool.name,
+                    action,
+                    node_api,
+                    wallet,
+                    oracle_pool.pool_config(),
+                )?;
+                // the boxes spent by the tx are still cached for this block
+                oracle_pool.invalidate_box_cache();
+                pool.action_report_storage.write().unwrap().add(report);
+                submitted = true;
+            }
+        };
+    }
+    update_metrics(
+        &pool.name,
+        oracle_pool,
+        change_address.network(),
+        wallet_funds,
+    )?;
+    Ok(submitted)
+}
+
+/// Appends what the iteration saw and built to the recording, the errors are only logged
+#[allow(clippy::too_many_arguments)]
+fn record_iteration(
+    recording: &MainLoopRecording,
+    pool: &RunningPool,
+    box_source: BoxSourceConfig,
+    height: BlockHeight,
+    wallet_boxes: &dyn WalletDataSource,
+    change_address: &NetworkAddress,
+    cmd: Option<PoolCommand>,
+    datapoint_source: &RecordingDataPointSource,
+    build_action_res: Option<&Result<(PoolAction, PoolActionReport), PoolCommandError>>,
+) {
+    let boxes = match pool.oracle_pool.box_snapshot(height) {
+        Ok(boxes) => boxes,
+        Err(e) => {
+            error!(
+                "pool {}: failed to record the iteration at height {}: {:?}",
+                pool.name, height.0, e
+            );
+            return;
+        }
+    };
+    // without a node there are no wallet boxes
+    let wallet_boxes = wallet_boxes.get_unspent_wallet_boxes().unwrap_or_else(|e| {
+        log::debug!("No wallet boxes in the recording: {:?}", e);
+        vec![]
+    });
+    let mut record =
+        IterationRecord::new(&pool.name, box_source, boxes, wallet_boxes, change_address);
+    record.command = cmd;
+    record.datapoint = datapoint_source.last_datapoint();
+    match build_action_res {
+        Some(Ok((action, _))) => record.tx = Some(action.tx().clone()),
+        Some(Err(e)) => record.build_error = Some(e.to_string()),
+        None => (),
+    }
+    if let Err(e) = recording.record(&record) {
+        error!("Failed to write to {}: {:?}", recording.path().display(), e);
+    }
+}
+
+/// Merges the small wallet boxes in a block where no pool tx was submitted
+fn auto_consolidate_wallet(
+    ctx: &OracleContext,
+    node_api: &NodeApi,
+    wallet: &OracleWallet,
+    config: &WalletConsolidationConfig,
+    last_consolidation_height: &mut Option<BlockHeight>,
+) -> std::result::Result<(), anyhow::Error> {
+    let height = BlockHeight(node_api.node().current_block_height()? as u32);
+    if let Some(last_height) = last_consolidation_height {
+        if height.0 < last_height.0 + MIN_BLOCKS_BETWEEN_CONSOLIDATIONS {
+            return Ok(());
+        }
+    }
+    let change_address = wallet.get_change_address()?;
+    let unsigned_tx = match build_consolidation_tx(
+        wallet.get_unspent_wallet_boxes()?,
+        config,
+        ctx.base_fee(),
+        change_address.address(),
+        height,
+    ) {
+        Ok(Some(unsigned_tx)) => unsigned_tx,
+        Ok(None) => return Ok(()),
+        Err(WalletConsolidationError::NotEnoughCoinsForFee { total, fee }) => {
+            log::debug!("Wallet consolidation skipped, {total} nanoERG in the small boxes don't cover the fee {fee} nanoERG");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    wallet.check_ready()?;
+    let inputs = unsigned_tx.inputs.len();
+    let tx_id = wallet.sign_and_submit_transaction(&unsigned_tx)?;
+    *last_consolidation_height = Some(height);
+    log::info!(
+        "Merged {} wallet boxes, tx id: {}",
+        inputs,
+        String::from(tx_id)
+    );
+    Ok(())
+}
+
+/// Registers the missing pool scans on the backup nodes and switches to the first healthy node
+fn check_nodes(ctx: &OracleContext, node_api: &NodeApi, running_pools: &[RunningPool]) {
+    for pool in running_pools {
+        pool.ensure_backup_node_scans(ctx, node_api);
+    }
+    // the pools looking up the boxes in the node extra index have no scans to check
+    let pool_names: Vec<String> = running_pools
+        .iter()
+        .filter(|p| p.node_scan_registry.is_some())
+        .map(|p| p.name.clone())
+        .collect();
+    let health = node_api.endpoints().check_and_select(&pool_names);
+    update_node_metrics(node_api.endpoints(), &health);
+}
+
+/// Samples the wallet balance and warns if it is low
+fn check_wallet_funds(
+    node_api: &NodeApi,
+    wallet: &OracleWallet,
+    wallet_funds: &RwLock<WalletFundsMonitor>,
+) -> std::result::Result<(), anyhow::Error> {
+    let height = BlockHeight(node_api.node().current_block_height()? as u32);
+    let mut wallet_funds = wallet_funds.write().unwrap();
+    let report = wallet_funds.update(wallet, height)?;
+    if report.low_balance {
+        log::warn!(
+            "Wallet balance {} nanoERG is below the low balance threshold {} nanoERG",
+            report.nano_ergs,
+            report.low_balance_threshold
+        );
+    }
+    Ok(())
+}
+
+fn resolve_shadow_reports(
+    oracle_pool: &OraclePool,
+    shadow_report_storage: &RwLock<ShadowReportStorage>,
+    network_prefix: NetworkPrefix,
+) -> std::result::Result<(), anyhow::Error> {
+    let observation = PoolObservation::observe(oracle_pool)?;
+    let max_deviation_percent = oracle_pool
+        .pool_config()
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters()
+        .max_deviation_percent();
+    let resolved = shadow_report_storage.write().unwrap().resolve(
+        &observation,
+        max_deviation_percent as u32,
+        network_prefix,
+    );
+    for entry in resolved {
+        log::info!(
+            "Shadow mode: {:?} (tx {}, datapoint {:?}) built at height {} for epoch {} resolved as {:?}",
+            entry.action,
+            String::from(entry.tx_id),
+            entry.datapoint,
+            entry.height,
+            entry.pool_box_epoch_id.0,
+            entry.outcome
+        );
+    }
+    Ok(())
+}
+
+fn log_and_continue_if_non_fatal(
+    network_prefix: NetworkPrefix,
+    res: Result<(PoolAction, PoolActionReport), PoolCommandError>,
+) -> Result<Option<(PoolAction, PoolActionReport)>, anyhow::Error> {
+    match res {
+        Ok(tuple) => Ok(Some(tuple)),
+        Err(PoolCommandError::RefreshActionError(RefreshActionError::FailedToReachConsensus {
+            expected,
+            found_public_keys,
+            found_num,
+        })) => {
+            let found_oracle_addresses: String =
+                pks_to_network_addresses(found_public_keys, network_prefix)
+                    .into_iter()
+                    .map(|net_addr| net_addr.to_base58())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+            log::error!("Refresh failed, not enough datapoints. The minimum number of datapoints within the deviation range: required minumum {expected}, found {found_num} from addresses {found_oracle_addresses},");
+            Ok(None)
+        }
+        Err(PoolCommandError::PublishDatapointActionError(
+            PublishDatapointActionError::DataPointSource(e),
+        )) => {
+            log::error!("Failed to get datapoint with error: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,486 @@
+//! Pools run by the `Run` command. Several pools can be run from one process, each with its own
+//! scans, datapoint source and reports, while sharing the node connection, the wallet and the
+//! REST API.
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use reqwest::Url;
+use thiserror::Error;
+
+use crate::action_report::ActionReportStorage;
+use crate::context::OracleContext;
+use crate::datapoint_source::RuntimeDataPointSource;
+use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_api::NodeApiError;
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_state::OraclePool;
+use crate::pool_config::PoolConfig;
+use crate::scans::NodeScanRegistry;
+use crate::scans::PoolTokenBoxes;
+use crate::shadow_mode::ShadowReportStorage;
+
+/// Name of the pool loaded from the pool config file given with `--pool-config-file`. Its scan
+/// IDs are kept in the default scans file.
+pub const DEFAULT_POOL_NAME: &str = "default";
+
+#[derive(Debug, Error)]
+pub enum PoolSpecError {
+    #[error("expected NAME=POOL_CONFIG_FILE, got {0}")]
+    Format(String),
+    #[error("invalid pool name {0}, only ASCII letters, digits, '-' and '_' are allowed")]
+    InvalidName(String),
+}
+
+/// A pool given on the command line as `NAME=POOL_CONFIG_FILE`
+#[derive(Debug, Clone)]
+pub struct PoolSpec {
+    pub name: String,
+    pub config_file_path: PathBuf,
+}
+
+impl FromStr for PoolSpec {
+    type Err = PoolSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s
+            .split_once('=')
+            .ok_or_else(|| PoolSpecError::Format(s.to_string()))?;
+        if path.is_empty() {
+            return Err(PoolSpecError::Format(s.to_string()));
+        }
+        // the name is used in file names, URL paths and metric labels
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(PoolSpecError::InvalidName(name.to_string()));
+        }
+        Ok(PoolSpec {
+            name: name.to_string(),
+            config_file_path: PathBuf::from(path),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RunningPool {
+    pub name: String,
+    pub oracle_pool: Arc<OraclePool>,
+    pub datapoint_source: Arc<RuntimeDataPointSource>,
+    pub action_report_storage: Arc<RwLock<ActionReportStorage>>,
+    pub shadow_report_storage: Option<Arc<RwLock<ShadowReportStorage>>>,
+    /// Scans registered on the primary node, None if the boxes are looked up in the node extra
+    /// index
+    pub node_scan_registry: Option<NodeScanRegistry>,
+    pub scans_file_path: PathBuf,
+}
+
+impl RunningPool {
+    /// Ensures the pool scans are registered in the node (or the node extra index is available)
+    /// and sets up the pool's datapoint source
+    pub fn start(
+        ctx: &OracleContext,
+        name: String,
+        pool_config: &PoolConfig,
+        shadow: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let scans_file_path = pool_scans_file_path(ctx, &name);
+        let pool = Self::start_with_scans_file(ctx, name, pool_config, shadow, scans_file_path)?;
+        pool.ensure_backup_node_scans(ctx, &ctx.node_api());
+        Ok(pool)
+    }
+
+    /// Like [`Self::start`], but keeps the scan IDs of the primary node in `scans_file_path` and
+    /// doesn't register the scans on the backup nodes (see [`Self::ensure_backup_node_scans`])
+    pub fn start_with_scans_file(
+        ctx: &OracleContext,
+        name: String,
+        pool_config: &PoolConfig,
+        shadow: bool,
+        scans_file_path: PathBuf,
+    ) -> Result<Self, anyhow::Error> {
+        log::info!("Starting pool {name}");
+        let datapoint_source = pool_datapoint_source(ctx, &name, pool_config)?;
+        let node_api = Arc::new(ctx.node_api());
+        let (node_scan_registry, token_boxes) = match ctx.oracle_config.box_source {
+            BoxSourceConfig::NodeScans => {
+                let node_scan_registry = NodeScanRegistry::ensure_node_registered_scans(
+                    &node_api.pinned_to(0),
+                    pool_config,
+                    &scans_file_path,
+                    ctx.oracle_config.scan_start_height,
+                    &ctx.scan_repair_log(),
+                )?;
+                let token_boxes = PoolTokenBoxes::from_scans(&node_scan_registry);
+                (Some(node_scan_registry), token_boxes)
+            }
+            BoxSourceConfig::NodeIndexer => {
+                check_node_indexer(&node_api)?;
+                (None, PoolTokenBoxes::from_indexer(pool_config))
+            }
+            BoxSourceConfig::Explorer => (
+                None,
+                PoolTokenBoxes::from_explorer(pool_config, Arc::new(ctx.explorer_api())),
+            ),
+        };
+        let oracle_pool = Arc::new(OraclePool::new(
+            node_api.clone(),
+            &token_boxes,
+            pool_config,
+            ctx.oracle_config.oracle_address_p2pk()?,
+        )?);
+        let pool = RunningPool {
+            name,
+            oracle_pool,
+            datapoint_source: Arc::new(datapoint_source),
+            action_report_storage: Arc::new(RwLock::new(ActionReportStorage::new())),
+            shadow_report_storage: if shadow {
+                Some(Arc::new(RwLock::new(ShadowReportStorage::new())))
+            } else {
+                None
+            },
+            node_scan_registry,
+            scans_file_path,
+        };
+        Ok(pool)
+    }
+
+    /// Registers the pool scans on the backup nodes which don't have them yet. A node which can't
+    /// be reached is retried on the next call.
+    pub fn ensure_backup_node_scans(&self, ctx: &OracleContext, node_api: &NodeApi) {
+        let node_scan_registry = match &self.node_scan_registry {
+            Some(node_scan_registry) => node_scan_registry,
+            None => return,
+        };
+        let nodes = node_api.endpoints();
+        for (index, endpoint) in nodes.endpoints().iter().enumerate().skip(1) {
+            if nodes.has_pool_scans(index, &self.name) {
+                continue;
+            }
+            let res = NodeScanRegistry::ensure_node_registered_scans_without_rescan_wait(
+                &node_api.pinned_to(index),
+                self.oracle_pool.pool_config(),
+                &backup_node_scans_file_path(&self.scans_file_path, &endpoint.url),
+                ctx.oracle_config.scan_start_height,
+                &ctx.scan_repair_log(),
+            );
+            match res {
+                Ok(node_registry) => {
+                    nodes.set_pool_scans(index, &self.name, node_scan_registry, &node_registry)
+                }
+                Err(e) => log::warn!(
+                    "pool {}: failed to register the scans on node {}: {:?}",
+                    self.name,
+                    endpoint.name(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Checks that the pool scans are still registered on the nodes and registers the missing
+    /// ones anew (see [`NodeScanRegistry::verify_and_repair`]). The box sources keep the scan IDs
+    /// the pool was started with, the new IDs are looked up through the node endpoints.
+    /// Returns true if a node is rescanning for the repaired scans.
+    pub fn verify_node_scans(&self, ctx: &OracleContext, node_api: &NodeApi) -> bool {
+        let node_scan_registry = match &self.node_scan_registry {
+            Some(node_scan_registry) => node_scan_registry,
+            None => return false,
+        };
+        let mut rescanning = false;
+        let nodes = node_api.endpoints();
+        for (index, endpoint) in nodes.endpoints().iter().enumerate() {
+            if !nodes.has_pool_scans(index, &self.name) {
+                // registered by ensure_backup_node_scans
+                continue;
+            }
+            let scans_file_path = if index == 0 {
+                self.scans_file_path.clone()
+            } else {
+                backup_node_scans_file_path(&self.scans_file_path, &endpoint.url)
+            };
+            let node_api = node_api.pinned_to(index);
+            let res = NodeScanRegistry::load_from_file(&scans_file_path).and_then(|registry| {
+                registry.verify_and_repair(
+                    &node_api,
+                    self.oracle_pool.pool_config(),
+                    &scans_file_path,
+                    ctx.oracle_config.scan_start_height,
+                    &ctx.scan_repair_log(),
+                )
+            });
+            match res {
+                Ok(Some(repaired)) => {
+                    nodes.set_pool_scans(index, &self.name, node_scan_registry, &repaired);
+                    rescanning = true;
+                }
+                Ok(None) => (),
+                Err(e) => log::warn!(
+                    "pool {}: failed to verify the scans on node {}: {:?}",
+                    self.name,
+                    endpoint.name(),
+                    e
+                ),
+            }
+        }
+        rescanning
+    }
+}
+
+/// Without a node (see [`BoxSourceConfig::Explorer`]) the pools can only be watched, no txs are
+/// built
+pub fn check_box_source(
+    oracle_config: &OracleConfig,
+    read_only: bool,
+    shadow: bool,
+) -> Result<(), anyhow::Error> {
+    if !oracle_config.box_source.requires_node() && (!read_only || shadow) {
+        return Err(anyhow!(
+            "box_source explorer doesn't use a node, only run --read-only is supported"
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if the extra index is disabled on one of the nodes (the pool boxes would not be found
+/// after a failover to it), warns if an index is behind its node. A backup node which can't be
+/// checked now is only warned about.
+pub(crate) fn check_node_indexer(node_api: &NodeApi) -> Result<(), anyhow::Error> {
+    for (index, endpoint) in node_api.endpoints().endpoints().iter().enumerate() {
+        let node_api = node_api.pinned_to(index);
+        let heights = node_api.indexed_height().and_then(|indexed_height| {
+            Ok((
+                indexed_height,
+                node_api.node().current_block_height()? as u32,
+            ))
+        });
+        match heights {
+            Ok((indexed_height, height)) if indexed_height < height => log::warn!(
+                "Extra index of node {} is behind the node (indexed {indexed_height}/{height} blocks), the pool boxes can be stale until it catches up",
+                endpoint.name()
+            ),
+            Ok(_) => (),
+            Err(e) if index > 0 && !matches!(e, NodeApiError::IndexerNotAvailable(_)) => {
+                log::warn!(
+                    "Failed to check the extra index of backup node {}: {:?}",
+                    endpoint.name(),
+                    e
+                )
+            }
+            Err(e) => {
+                return Err(e).context(format!(
+                    "box_source is node_indexer but the extra index of node {} is not available, set extraIndex = true in the node config",
+                    endpoint.name()
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Scan IDs file of the pool with the given name
+pub fn pool_scans_file_path(ctx: &OracleContext, name: &str) -> PathBuf {
+    if name == DEFAULT_POOL_NAME {
+        ctx.scans_file_path()
+    } else {
+        ctx.pool_scans_file_path(name)
+    }
+}
+
+/// Scan IDs file of a backup node, `scans_file_path` is the file of the primary node
+pub fn backup_node_scans_file_path(scans_file_path: &Path, node_url: &Url) -> PathBuf {
+    let stem = scans_file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    scans_file_path.with_file_name(format!(
+        "{}-node-{}-{}.json",
+        stem,
+        node_url.host_str().unwrap_or_default(),
+        node_url.port_or_known_default().unwrap_or_default()
+    ))
+}
+
+/// Deregisters the scans of the pool on the backup nodes and removes their scan IDs files (when
+/// the pool token ids change). A node which can't be reached keeps the stale scans.
+pub fn deregister_backup_node_scans(node_api: &NodeApi, scans_file_path: &Path) {
+    for (index, endpoint) in node_api.endpoints().endpoints().iter().enumerate().skip(1) {
+        let path = backup_node_scans_file_path(scans_file_path, &endpoint.url);
+        if !path.exists() {
+            continue;
+        }
+        let res = NodeScanRegistry::load_from_file(&path)
+            .and_then(|registry| Ok(registry.deregister_all_scans(&node_api.pinned_to(index))?));
+        if let Err(e) = res {
+            log::warn!(
+                "Failed to deregister the scans on node {}: {:?}",
+                endpoint.name(),
+                e
+            );
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Datapoint source of the pool with the given name. The custom script from the oracle config
+/// can only serve one pool, so only the default pool falls back to it, every other pool needs a
+/// datapoint source in its pool config.
+pub fn pool_datapoint_source(
+    ctx: &OracleContext,
+    name: &str,
+    pool_config: &PoolConfig,
+) -> Result<RuntimeDataPointSource, anyhow::Error> {
+    let custom_script = pool_config.data_point_source_custom_script.clone();
+    if name == DEFAULT_POOL_NAME {
+        return RuntimeDataPointSource::new(
+            pool_config.data_point_source,
+            custom_script.or_else(|| ctx.oracle_config.data_point_source_custom_script.clone()),
+        );
+    }
+    if pool_config.data_point_source.is_none() && custom_script.is_none() {
+        return Err(anyhow!(
+            "pool {name} has neither data_point_source nor data_point_source_custom_script in its pool config, only the {DEFAULT_POOL_NAME} pool can use data_point_source_custom_script from the oracle config"
+        ));
+    }
+    RuntimeDataPointSource::new(pool_config.data_point_source, custom_script)
+}
+
+/// Loads the pool configs of the pools given on the command line and checks that they don't
+/// track the same pool twice
+pub fn load_pool_configs(specs: &[PoolSpec]) -> Result<Vec<(String, PoolConfig)>, anyhow::Error> {
+    let mut pools: Vec<(String, PoolConfig)> = vec![];
+    for spec in specs {
+        let pool_config = PoolConfig::load_from_file(&spec.config_file_path)?;
+        if let Some((other_name, _)) = pools.iter().find(|(name, other_config)| {
+            *name == spec.name
+                || other_config.token_ids.pool_nft_token_id
+                    == pool_config.token_ids.pool_nft_token_id
+        }) {
+            return Err(anyhow::anyhow!(
+                "pool {} is a duplicate of pool {}",
+                spec.name,
+                other_name
+            ));
+        }
+        pools.push((spec.name.clone(), pool_config));
+    }
+    Ok(pools)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli_commands::bootstrap::BootstrapConfig;
+    use crate::oracle_config::OracleSecrets;
+    use crate::pool_commands::test_utils::generate_token_ids;
+    use crate::pool_config::PredefinedDataPointSource;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_pool_spec() {
+        let spec: PoolSpec = "erg-usd=/data/erg_usd/pool_config.yaml".parse().unwrap();
+        assert_eq!(spec.name, "erg-usd");
+        assert_eq!(
+            spec.config_file_path,
+            PathBuf::from("/data/erg_usd/pool_config.yaml")
+        );
+        assert!(matches!(
+            "pool_config.yaml".parse::<PoolSpec>(),
+            Err(PoolSpecError::Format(_))
+        ));
+        assert!(matches!(
+            "erg/usd=pool_config.yaml".parse::<PoolSpec>(),
+            Err(PoolSpecError::InvalidName(_))
+        ));
+        assert!(matches!(
+            "=pool_config.yaml".parse::<PoolSpec>(),
+            Err(PoolSpecError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn test_backup_node_scans_file_path() {
+        assert_eq!(
+            backup_node_scans_file_path(
+                Path::new("/data/scanIDs-erg-usd.json"),
+                &Url::parse("http://10.0.0.2:9053/").unwrap()
+            ),
+            PathBuf::from("/data/scanIDs-erg-usd-node-10.0.0.2-9053.json")
+        );
+        assert_eq!(
+            backup_node_scans_file_path(
+                Path::new("scanIDs.json"),
+                &Url::parse("https://node.example.com").unwrap()
+            ),
+            PathBuf::from("scanIDs-node-node.example.com-443.json")
+        );
+    }
+
+    #[test]
+    fn test_check_box_source() {
+        let node_scans = OracleConfig::default();
+        assert!(check_box_source(&node_scans, false, false).is_ok());
+        let explorer = OracleConfig {
+            box_source: BoxSourceConfig::Explorer,
+            ..OracleConfig::default()
+        };
+        assert!(check_box_source(&explorer, true, false).is_ok());
+        assert!(check_box_source(&explorer, false, false).is_err());
+        assert!(check_box_source(&explorer, true, true).is_err());
+    }
+
+    #[test]
+    fn test_pool_datapoint_source() {
+        let ctx = OracleContext::new(
+            OracleConfig {
+                data_point_source_custom_script: Some("./oracle_price.sh".to_string()),
+                ..OracleConfig::default()
+            },
+            OracleSecrets {
+                node_api_key: Some("hello".to_string()),
+                wallet_password: None,
+                wallet_mnemonic: None,
+                wallet_mnemonic_password: None,
+                wallet_secret_file_password: None,
+                backup_node_api_keys: vec![],
+            },
+            std::env::temp_dir(),
+        );
+        let without_source = PoolConfig {
+            data_point_source: None,
+            ..PoolConfig::create(BootstrapConfig::default(), generate_token_ids()).unwrap()
+        };
+        assert!(matches!(
+            pool_datapoint_source(&ctx, DEFAULT_POOL_NAME, &without_source),
+            Ok(RuntimeDataPointSource::ExternalScript(_))
+        ));
+        // a named pool doesn't inherit the custom script of the oracle config
+        assert!(pool_datapoint_source(&ctx, "erg-usd", &without_source).is_err());
+
+        let with_predefined = PoolConfig {
+            data_point_source: Some(PredefinedDataPointSource::NanoErgUsd),
+            ..without_source.clone()
+        };
+        assert!(matches!(
+            pool_datapoint_source(&ctx, "erg-usd", &with_predefined),
+            Ok(RuntimeDataPointSource::Predefined(_))
+        ));
+        let with_script = PoolConfig {
+            data_point_source_custom_script: Some("./erg_usd.sh".to_string()),
+            ..without_source
+        };
+        assert!(matches!(
+            pool_datapoint_source(&ctx, "erg-usd", &with_script),
+            Ok(RuntimeDataPointSource::ExternalScript(_))
+        ));
+    }
+}
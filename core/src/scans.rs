@@ -1,18 +1,24 @@
 use crate::contracts::pool::PoolContractError;
 use crate::contracts::refresh::RefreshContractError;
+use crate::explorer_api::ExplorerApiError;
 use crate::node_interface::node_api::{NodeApi, NodeApiError};
-use crate::oracle_config::{ORACLE_CONFIG, ORACLE_SECRETS};
 
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_node_interface::node_interface::NodeError;
 use ergo_node_interface::ScanId;
 use thiserror::Error;
 
+mod box_cache;
 mod generic_token_scan;
 mod registry;
+mod repair;
+mod token_boxes;
 
+pub use box_cache::*;
 pub use generic_token_scan::*;
 pub use registry::*;
+pub use repair::*;
+pub use token_boxes::*;
 
 /// Integer which is provided by the Ergo node to reference a given scan.
 pub type ScanID = String;
@@ -23,6 +29,8 @@ pub enum ScanError {
     NodeError(#[from] NodeError),
     #[error("node api error: {0}")]
     NodeApiError(#[from] NodeApiError),
+    #[error("explorer api error: {0}")]
+    ExplorerApiError(#[from] ExplorerApiError),
     #[error("no boxes found")]
     NoBoxesFound,
     #[error("failed to register scan")]
@@ -40,17 +48,12 @@ pub trait NodeScanId {
 }
 
 pub trait ScanGetBoxes: NodeScanId {
-    fn get_boxes(&self) -> Result<Vec<ErgoBox>, ScanError> {
-        let node_api = NodeApi::new(
-            ORACLE_SECRETS.node_api_key.clone(),
-            ORACLE_SECRETS.wallet_password.clone(),
-            &ORACLE_CONFIG.node_url,
-        );
-        let boxes = node_api.node.scan_boxes(self.scan_id())?;
+    fn get_boxes(&self, node_api: &NodeApi) -> Result<Vec<ErgoBox>, ScanError> {
+        let boxes = node_api.scan_boxes(self.scan_id())?;
         Ok(boxes)
     }
 
-    fn get_box(&self) -> Result<Option<ErgoBox>, ScanError> {
-        Ok(self.get_boxes()?.first().cloned())
+    fn get_box(&self, node_api: &NodeApi) -> Result<Option<ErgoBox>, ScanError> {
+        Ok(self.get_boxes(node_api)?.first().cloned())
     }
 }
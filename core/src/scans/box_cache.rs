@@ -0,0 +1,147 @@
+//! Snapshot of the pool boxes at the best block height. The box sources of a pool share it, so
+//! a main loop iteration, the metrics update and the REST API requests made in the same block see
+//! the same boxes and don't query the node again. A new snapshot is started when the pool observes
+//! a new best height (see [`crate::oracle_state::OraclePool::current_height`]).
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// None until the first height is observed, nothing is cached until then
+    height: Option<u32>,
+    boxes: HashMap<String, Vec<ErgoBox>>,
+}
+
+#[derive(Debug, Default)]
+pub struct BlockBoxCache {
+    snapshot: RwLock<Snapshot>,
+}
+
+impl BlockBoxCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn height(&self) -> Option<u32> {
+        self.snapshot.read().unwrap().height
+    }
+
+    /// Starts a new snapshot if the height differs from the current one
+    pub fn set_height(&self, height: u32) {
+        let mut snapshot = self.snapshot.write().unwrap();
+        if snapshot.height != Some(height) {
+            *snapshot = Snapshot {
+                height: Some(height),
+                boxes: HashMap::new(),
+            };
+        }
+    }
+
+    /// Whether the boxes of the key are cached at `height`
+    pub fn contains(&self, height: u32, key: &str) -> bool {
+        let snapshot = self.snapshot.read().unwrap();
+        snapshot.height == Some(height) && snapshot.boxes.contains_key(key)
+    }
+
+    /// Drops the cached boxes, e.g. after a tx spending them is submitted
+    pub fn invalidate(&self) {
+        self.snapshot.write().unwrap().boxes.clear();
+    }
+
+    /// Caches the boxes fetched at `height`, ignored if a new snapshot was started meanwhile
+    pub fn insert(&self, height: u32, key: String, boxes: Vec<ErgoBox>) {
+        let mut snapshot = self.snapshot.write().unwrap();
+        if snapshot.height == Some(height) {
+            snapshot.boxes.insert(key, boxes);
+        }
+    }
+
+    pub fn get_or_fetch<E>(
+        &self,
+        key: String,
+        fetch: impl FnOnce() -> Result<Vec<ErgoBox>, E>,
+    ) -> Result<Vec<ErgoBox>, E> {
+        let height = {
+            let snapshot = self.snapshot.read().unwrap();
+            if let Some(boxes) = snapshot.boxes.get(&key) {
+                return Ok(boxes.clone());
+            }
+            snapshot.height
+        };
+        let boxes = fetch()?;
+        if let Some(height) = height {
+            self.insert(height, key, boxes.clone());
+        }
+        Ok(boxes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+
+    #[test]
+    fn test_cached_per_height() {
+        let cache = BlockBoxCache::new();
+        let boxes = vec![force_any_val::<ErgoBox>()];
+        let fetches = Cell::new(0);
+        let fetch = || -> Result<Vec<ErgoBox>, ()> {
+            fetches.set(fetches.get() + 1);
+            Ok(boxes.clone())
+        };
+        // nothing is cached before a height is observed
+        cache.get_or_fetch("pool".to_string(), fetch).unwrap();
+        cache.get_or_fetch("pool".to_string(), fetch).unwrap();
+        assert_eq!(fetches.get(), 2);
+
+        cache.set_height(100);
+        assert_eq!(
+            cache.get_or_fetch("pool".to_string(), fetch).unwrap(),
+            boxes
+        );
+        assert_eq!(
+            cache.get_or_fetch("pool".to_string(), fetch).unwrap(),
+            boxes
+        );
+        assert_eq!(fetches.get(), 3);
+        cache.get_or_fetch("refresh".to_string(), fetch).unwrap();
+        assert_eq!(fetches.get(), 4);
+
+        // same height, the snapshot is kept
+        cache.set_height(100);
+        cache.get_or_fetch("pool".to_string(), fetch).unwrap();
+        assert_eq!(fetches.get(), 4);
+
+        cache.set_height(101);
+        cache.get_or_fetch("pool".to_string(), fetch).unwrap();
+        assert_eq!(fetches.get(), 5);
+
+        assert!(cache.contains(101, "pool"));
+        assert!(!cache.contains(100, "pool"));
+        assert!(!cache.contains(101, "refresh"));
+        cache.invalidate();
+        assert!(!cache.contains(101, "pool"));
+        cache.get_or_fetch("pool".to_string(), fetch).unwrap();
+        assert_eq!(fetches.get(), 6);
+    }
+
+    #[test]
+    fn test_stale_insert_is_ignored() {
+        let cache = BlockBoxCache::new();
+        cache.set_height(100);
+        cache.set_height(101);
+        cache.insert(100, "pool".to_string(), vec![force_any_val::<ErgoBox>()]);
+        let fetched = cache
+            .get_or_fetch("pool".to_string(), || -> Result<Vec<ErgoBox>, ()> {
+                Ok(vec![])
+            })
+            .unwrap();
+        assert!(fetched.is_empty());
+    }
+}
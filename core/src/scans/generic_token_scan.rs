@@ -1,5 +1,5 @@
+use crate::node_interface::node_api::NodeApi;
 use crate::spec_token::TokenIdKind;
-use crate::NodeApi;
 use derive_more::From;
 use derive_more::Into;
 use ergo_node_interface::ScanId;
@@ -7,6 +7,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
 
+use super::repair::tracked_asset_ids;
 use super::NodeScanId;
 use super::ScanError;
 use super::ScanGetBoxes;
@@ -47,6 +48,11 @@ impl<T: TokenIdKind + Clone> GenericTokenScan<T> {
             ]
           })
     }
+
+    /// Whether the tracking rule (as listed by the node) matches the boxes with the token
+    pub fn tracks_token(tracking_rule: &serde_json::Value, token_id: &T) -> bool {
+        tracked_asset_ids(tracking_rule) == tracked_asset_ids(&Self::tracking_rule(token_id))
+    }
 }
 
 impl<T: TokenIdKind + Clone> TryFrom<String> for GenericTokenScan<T> {
@@ -1,31 +1,29 @@
-use std::path::PathBuf;
+use std::path::Path;
 
 use crate::node_interface::node_api::NodeApi;
 use crate::node_interface::node_api::NodeApiError;
+use crate::node_interface::node_api::NodeScan;
 use crate::pool_config::PoolConfig;
 use crate::spec_token::BallotTokenId;
 use crate::spec_token::BuybackTokenId;
 use crate::spec_token::OracleTokenId;
 use crate::spec_token::PoolTokenId;
 use crate::spec_token::RefreshTokenId;
+use crate::spec_token::TokenIdKind;
 use crate::spec_token::UpdateTokenId;
 
-use crate::oracle_config::ORACLE_CONFIG;
 use ::serde::Deserialize;
 use ::serde::Serialize;
-use once_cell::sync;
+use ergo_node_interface::ScanId;
 use thiserror::Error;
 
 use super::generic_token_scan::GenericTokenScan;
+use super::repair::ScanRepair;
+use super::repair::ScanRepairLog;
+use super::repair::ScanRepairReason;
 use super::NodeScanId;
 use super::ScanError;
 
-pub static SCANS_DIR_PATH: sync::OnceCell<PathBuf> = sync::OnceCell::new();
-
-pub fn get_scans_file_path() -> PathBuf {
-    SCANS_DIR_PATH.get().unwrap().join("scanIDs.json")
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct NodeScanRegistry {
     #[serde(rename = "All Datapoints Scan")]
@@ -51,7 +49,7 @@ impl NodeScanRegistry {
         serde_json::to_string_pretty(&self).unwrap()
     }
 
-    fn save_to_json_file(&self, file_path: &PathBuf) -> Result<(), anyhow::Error> {
+    fn save_to_json_file(&self, file_path: &Path) -> Result<(), anyhow::Error> {
         let json_str = self.save_to_json_str();
         log::debug!("Saving scan IDs to {}", file_path.display());
         Ok(std::fs::write(file_path, json_str)
@@ -61,6 +59,8 @@ impl NodeScanRegistry {
     fn register_and_save_scans_inner(
         node_api: &NodeApi,
         pool_config: &PoolConfig,
+        scans_file_path: &Path,
+        scan_start_height: u32,
     ) -> std::result::Result<Self, anyhow::Error> {
         log::info!("Registering UTXO-Set Scans");
         let oracle_token_scan =
@@ -87,13 +87,12 @@ impl NodeScanRegistry {
             update_token_scan,
             buyback_token_scan,
         };
-        registry.save_to_json_file(&get_scans_file_path())?;
-        node_api.rescan_from_height(ORACLE_CONFIG.scan_start_height)?;
+        registry.save_to_json_file(scans_file_path)?;
+        node_api.rescan_from_height(scan_start_height)?;
         Ok(registry)
     }
 
-    pub fn load() -> Result<Self, anyhow::Error> {
-        let path = get_scans_file_path();
+    pub fn load_from_file(path: &Path) -> Result<Self, anyhow::Error> {
         log::info!("Loading scan IDs from {}", path.display());
         let json_str =
             std::fs::read_to_string(path).map_err(|e| NodeScanRegistryError::Io(e.to_string()))?;
@@ -101,14 +100,47 @@ impl NodeScanRegistry {
         Ok(registry)
     }
 
+    /// Loads the scans from the file and checks them on the node (registers the missing ones, see
+    /// [`Self::verify_and_repair`]) or registers all the scans if there is no file
     pub fn ensure_node_registered_scans(
         node_api: &NodeApi,
         pool_config: &PoolConfig,
+        scans_file_path: &Path,
+        scan_start_height: u32,
+        repair_log: &ScanRepairLog,
     ) -> std::result::Result<Self, anyhow::Error> {
-        let path = get_scans_file_path();
-        log::info!("Loading scan IDs from {}", path.display());
-        let registry = if let Ok(json_str) = std::fs::read_to_string(path) {
+        let registry = Self::ensure_node_registered_scans_without_rescan_wait(
+            node_api,
+            pool_config,
+            scans_file_path,
+            scan_start_height,
+            repair_log,
+        )?;
+        wait_for_node_rescan(node_api)?;
+        Ok(registry)
+    }
+
+    /// Like [`Self::ensure_node_registered_scans`], but returns while the node may still be
+    /// rescanning (used for the backup nodes, their rescan is checked by the node health check)
+    pub fn ensure_node_registered_scans_without_rescan_wait(
+        node_api: &NodeApi,
+        pool_config: &PoolConfig,
+        scans_file_path: &Path,
+        scan_start_height: u32,
+        repair_log: &ScanRepairLog,
+    ) -> std::result::Result<Self, anyhow::Error> {
+        log::info!("Loading scan IDs from {}", scans_file_path.display());
+        let registry = if let Ok(json_str) = std::fs::read_to_string(scans_file_path) {
             let loaded_registry = Self::load_from_json_str(&json_str)?;
+            let loaded_registry = loaded_registry
+                .verify_and_repair(
+                    node_api,
+                    pool_config,
+                    scans_file_path,
+                    scan_start_height,
+                    repair_log,
+                )?
+                .unwrap_or(loaded_registry);
             if let Some(pool_config_buyback_token_id) = pool_config.buyback_token_id.clone() {
                 log::info!("Buyback token is found in pool config, checking if scan is registered");
                 if loaded_registry.buyback_token_scan.is_some() {
@@ -117,12 +149,12 @@ impl NodeScanRegistry {
                 } else {
                     let buyback_token_scan =
                         GenericTokenScan::register(node_api, &pool_config_buyback_token_id)?;
-                    node_api.rescan_from_height(ORACLE_CONFIG.scan_start_height)?;
+                    node_api.rescan_from_height(scan_start_height)?;
                     let new_registry = Self {
                         buyback_token_scan: Some(buyback_token_scan),
                         ..loaded_registry
                     };
-                    new_registry.save_to_json_file(&get_scans_file_path())?;
+                    new_registry.save_to_json_file(scans_file_path)?;
                     new_registry
                 }
             } else {
@@ -135,7 +167,7 @@ impl NodeScanRegistry {
                         buyback_token_scan: None,
                         ..loaded_registry
                     };
-                    new_registry.save_to_json_file(&get_scans_file_path())?;
+                    new_registry.save_to_json_file(scans_file_path)?;
                     new_registry
                 } else {
                     loaded_registry
@@ -143,12 +175,129 @@ impl NodeScanRegistry {
             }
         } else {
             log::info!("Scans not found");
-            Self::register_and_save_scans_inner(node_api, pool_config)?
+            Self::register_and_save_scans_inner(
+                node_api,
+                pool_config,
+                scans_file_path,
+                scan_start_height,
+            )?
         };
-        wait_for_node_rescan(node_api)?;
         Ok(registry)
     }
 
+    /// Checks that every scan is registered on the node with the tracking rule of its token.
+    /// Registers the missing scans anew, saves the new scan IDs to the file, records the repairs
+    /// and triggers a rescan from `scan_start_height`. Returns None if all the scans are in place.
+    /// If a registration fails, the scans registered before it are still saved and recorded (so
+    /// they are not registered again on the next check) and the error is returned without a
+    /// rescan.
+    pub fn verify_and_repair(
+        &self,
+        node_api: &NodeApi,
+        pool_config: &PoolConfig,
+        scans_file_path: &Path,
+        scan_start_height: u32,
+        repair_log: &ScanRepairLog,
+    ) -> std::result::Result<Option<Self>, anyhow::Error> {
+        let node_scans = node_api.list_scans()?;
+        let mut repaired = self.clone();
+        let mut repairs = vec![];
+        let res = repaired.repair_scans(pool_config, &node_scans, node_api, &mut repairs);
+        if repairs.is_empty() {
+            res?;
+            log::debug!("All scans are registered on node {}", node_api.node_name());
+            return Ok(None);
+        }
+        repaired.save_to_json_file(scans_file_path)?;
+        for repair in &repairs {
+            if let Err(e) = repair_log.record(repair) {
+                log::error!("Failed to record the scan repair {:?}: {}", repair, e);
+            }
+        }
+        res?;
+        node_api.rescan_from_height(scan_start_height)?;
+        Ok(Some(repaired))
+    }
+
+    /// Replaces the scans missing on the node with newly registered ones (see [`verify_scan`]),
+    /// stops at the first failed registration
+    fn repair_scans(
+        &mut self,
+        pool_config: &PoolConfig,
+        node_scans: &[NodeScan],
+        node_api: &NodeApi,
+        repairs: &mut Vec<ScanRepair>,
+    ) -> Result<(), ScanError> {
+        let token_ids = &pool_config.token_ids;
+        self.oracle_token_scan = verify_scan(
+            self.oracle_token_scan,
+            &token_ids.oracle_token_id,
+            "All Datapoints Scan",
+            node_scans,
+            node_api,
+            repairs,
+        )?;
+        self.pool_token_scan = verify_scan(
+            self.pool_token_scan,
+            &token_ids.pool_nft_token_id,
+            "Pool Box Scan",
+            node_scans,
+            node_api,
+            repairs,
+        )?;
+        self.ballot_token_scan = verify_scan(
+            self.ballot_token_scan,
+            &token_ids.ballot_token_id,
+            "Ballot Box Scan",
+            node_scans,
+            node_api,
+            repairs,
+        )?;
+        self.refresh_token_scan = verify_scan(
+            self.refresh_token_scan,
+            &token_ids.refresh_nft_token_id,
+            "Refresh Box Scan",
+            node_scans,
+            node_api,
+            repairs,
+        )?;
+        self.update_token_scan = verify_scan(
+            self.update_token_scan,
+            &token_ids.update_nft_token_id,
+            "Update Box Scan",
+            node_scans,
+            node_api,
+            repairs,
+        )?;
+        // a scan added or removed in the pool config is handled by ensure_node_registered_scans
+        if let (Some(scan), Some(buyback_token_id)) =
+            (self.buyback_token_scan, &pool_config.buyback_token_id)
+        {
+            self.buyback_token_scan = Some(verify_scan(
+                scan,
+                buyback_token_id,
+                "Buyback Box Scan",
+                node_scans,
+                node_api,
+                repairs,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Scan IDs in a fixed order, so the scans of the same pool on different nodes can be paired
+    pub fn scan_ids(&self) -> Vec<ScanId> {
+        let mut scan_ids = vec![
+            self.oracle_token_scan.scan_id(),
+            self.pool_token_scan.scan_id(),
+            self.ballot_token_scan.scan_id(),
+            self.refresh_token_scan.scan_id(),
+            self.update_token_scan.scan_id(),
+        ];
+        scan_ids.extend(self.buyback_token_scan.map(|scan| scan.scan_id()));
+        scan_ids
+    }
+
     pub fn deregister_all_scans(self, node_api: &NodeApi) -> Result<(), NodeApiError> {
         node_api.deregister_scan(self.oracle_token_scan.scan_id())?;
         node_api.deregister_scan(self.pool_token_scan.scan_id())?;
@@ -162,16 +311,59 @@ impl NodeScanRegistry {
     }
 }
 
+/// The scan if it is registered on the node with the tracking rule of the token, otherwise a newly
+/// registered scan
+fn verify_scan<T: TokenIdKind + Clone>(
+    scan: GenericTokenScan<T>,
+    token_id: &T,
+    scan_name: &str,
+    node_scans: &[NodeScan],
+    node_api: &NodeApi,
+    repairs: &mut Vec<ScanRepair>,
+) -> Result<GenericTokenScan<T>, ScanError> {
+    let reason = match node_scans
+        .iter()
+        .find(|node_scan| ScanId::from(node_scan.scan_id) == scan.scan_id())
+    {
+        Some(node_scan) if GenericTokenScan::tracks_token(&node_scan.tracking_rule, token_id) => {
+            return Ok(scan)
+        }
+        Some(_) => ScanRepairReason::DifferentTrackingRule,
+        None => ScanRepairReason::Missing,
+    };
+    log::warn!(
+        "{} {} is not registered on node {} ({:?}), registering it again",
+        scan_name,
+        scan.scan_id(),
+        node_api.node_name(),
+        reason
+    );
+    let new_scan = GenericTokenScan::register(node_api, token_id)?;
+    repairs.push(ScanRepair::new(
+        node_api.node_name(),
+        scan_name,
+        scan.scan_id(),
+        new_scan.scan_id(),
+        reason,
+    ));
+    Ok(new_scan)
+}
+
+/// Whether the node wallet has scanned up to the current block height
+pub fn node_rescan_finished(node_api: &NodeApi) -> Result<bool, NodeApiError> {
+    let wallet_height = node_api.node().wallet_status()?.height;
+    let block_height = node_api.node().current_block_height()?;
+    Ok(wallet_height == block_height)
+}
+
 pub fn wait_for_node_rescan(node_api: &NodeApi) -> Result<(), NodeApiError> {
-    let wallet_height = node_api.node.wallet_status()?.height;
-    let block_height = node_api.node.current_block_height()?;
-    if wallet_height == block_height {
+    if node_rescan_finished(node_api)? {
         log::debug!("No wallet scan is running");
         return Ok(());
     }
     Ok(loop {
-        let wallet_height = node_api.node.wallet_status()?.height;
-        let block_height = node_api.node.current_block_height()?;
+        let wallet_height = node_api.node().wallet_status()?.height;
+        let block_height = node_api.node().current_block_height()?;
         println!("Scanned {}/{} blocks", wallet_height, block_height);
         if wallet_height == block_height {
             log::info!("Wallet Scan Complete!");
@@ -197,7 +389,6 @@ pub enum NodeScanRegistryError {
 mod tests {
     use super::*;
     use crate::scans::NodeScanId;
-    use ergo_node_interface::ScanId;
     use expect_test::expect;
     use pretty_assertions::assert_eq;
 
@@ -0,0 +1,158 @@
+//! Record of the scans found missing on a node (e.g. after its database was wiped or the scans
+//! were deregistered by hand) and registered anew. The repairs are appended to a JSON lines file
+//! in the data folder.
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use ergo_node_interface::ScanId;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanRepairReason {
+    /// No scan with the ID is registered on the node
+    Missing,
+    /// The scan with the ID tracks another token, the ID was reused by the node
+    DifferentTrackingRule,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanRepair {
+    /// Unix time in seconds
+    pub timestamp: u64,
+    pub node: String,
+    pub scan_name: String,
+    pub old_scan_id: String,
+    pub new_scan_id: String,
+    pub reason: ScanRepairReason,
+}
+
+impl ScanRepair {
+    pub fn new(
+        node: &str,
+        scan_name: &str,
+        old_scan_id: ScanId,
+        new_scan_id: ScanId,
+        reason: ScanRepairReason,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            node: node.to_string(),
+            scan_name: scan_name.to_string(),
+            old_scan_id: old_scan_id.to_string(),
+            new_scan_id: new_scan_id.to_string(),
+            reason,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanRepairLog {
+    path: PathBuf,
+}
+
+impl ScanRepairLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Appends the repair to the file
+    pub fn record(&self, repair: &ScanRepair) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(repair).unwrap())
+    }
+
+    /// All the recorded repairs, the oldest first
+    pub fn load(&self) -> std::io::Result<Vec<ScanRepair>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Into::into))
+            .collect()
+    }
+}
+
+/// Asset IDs the tracking rule matches on, in the order of appearance
+pub fn tracked_asset_ids(tracking_rule: &serde_json::Value) -> Vec<&serde_json::Value> {
+    match tracking_rule {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .flat_map(|(key, value)| {
+                if key == "assetId" {
+                    vec![value]
+                } else {
+                    tracked_asset_ids(value)
+                }
+            })
+            .collect(),
+        serde_json::Value::Array(values) => values.iter().flat_map(tracked_asset_ids).collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_tracked_asset_ids() {
+        let rule = json!({
+            "predicate": "and",
+            "args": [
+                { "predicate": "containsAsset", "assetId": "aa" },
+                { "predicate": "equals", "value": "bb" },
+            ]
+        });
+        assert_eq!(tracked_asset_ids(&rule), vec![&json!("aa")]);
+        assert!(tracked_asset_ids(&json!({ "predicate": "equals", "value": "bb" })).is_empty());
+    }
+
+    #[test]
+    fn test_repair_log_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-scan-repairs-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = ScanRepairLog::new(path.clone());
+        assert_eq!(log.load().unwrap(), vec![]);
+        let repairs = vec![
+            ScanRepair::new(
+                "http://127.0.0.1:9053/",
+                "Pool Box Scan",
+                ScanId::from(10),
+                ScanId::from(20),
+                ScanRepairReason::Missing,
+            ),
+            ScanRepair::new(
+                "http://127.0.0.1:9053/",
+                "Refresh Box Scan",
+                ScanId::from(11),
+                ScanId::from(21),
+                ScanRepairReason::DifferentTrackingRule,
+            ),
+        ];
+        for repair in &repairs {
+            log.record(repair).unwrap();
+        }
+        assert_eq!(log.load().unwrap(), repairs);
+        std::fs::remove_file(path).unwrap();
+    }
+}
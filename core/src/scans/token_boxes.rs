@@ -0,0 +1,213 @@
+//! Lookup of the unspent boxes holding the pool tokens, through the scans registered on the node,
+//! the node extra index or the Explorer API (see [`crate::oracle_config::BoxSourceConfig`]), or
+//! in a main loop recording for the replay (see [`crate::recording`]).
+use std::sync::Arc;
+
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+
+use crate::explorer_api::ExplorerApi;
+use crate::node_interface::async_node::AsyncNodeClient;
+use crate::node_interface::node_api::NodeApi;
+use crate::pool_config::PoolConfig;
+use crate::recording::BoxSnapshot;
+use crate::spec_token::BallotTokenId;
+use crate::spec_token::BuybackTokenId;
+use crate::spec_token::OracleTokenId;
+use crate::spec_token::PoolTokenId;
+use crate::spec_token::RefreshTokenId;
+use crate::spec_token::TokenIdKind;
+use crate::spec_token::UpdateTokenId;
+
+use super::BlockBoxCache;
+use super::GenericTokenScan;
+use super::NodeScanId;
+use super::NodeScanRegistry;
+use super::ScanError;
+use super::ScanGetBoxes;
+
+#[derive(Debug, Clone)]
+pub enum TokenBoxes<T: TokenIdKind + Clone> {
+    /// Boxes tracked by the scan registered on the node
+    Scan(GenericTokenScan<T>),
+    /// Boxes with the token from the node extra index
+    Indexer(T),
+    /// Boxes with the token from the Explorer API, the node is not used
+    Explorer(Arc<ExplorerApi>, T),
+    /// Boxes with the token in a recorded snapshot, neither the node nor the Explorer API is used
+    Recorded(Arc<BoxSnapshot>, T),
+}
+
+impl<T: TokenIdKind + Clone> TokenBoxes<T> {
+    /// The boxes from the snapshot of the current block, fetched if not there yet
+    pub fn get_boxes(
+        &self,
+        node_api: &NodeApi,
+        cache: &BlockBoxCache,
+    ) -> Result<Vec<ErgoBox>, ScanError> {
+        cache.get_or_fetch(self.cache_key(), || self.fetch_boxes(node_api))
+    }
+
+    pub fn get_box(
+        &self,
+        node_api: &NodeApi,
+        cache: &BlockBoxCache,
+    ) -> Result<Option<ErgoBox>, ScanError> {
+        Ok(self.get_boxes(node_api, cache)?.first().cloned())
+    }
+
+    /// Puts the boxes in the snapshot of the block at `height`. The Explorer API and recording
+    /// lookups are left to [`Self::get_boxes`].
+    pub async fn prefetch(
+        &self,
+        client: &AsyncNodeClient,
+        cache: &BlockBoxCache,
+        height: u32,
+    ) -> Result<(), ScanError> {
+        if cache.contains(height, &self.cache_key()) {
+            return Ok(());
+        }
+        let boxes = match self {
+            TokenBoxes::Scan(scan) => client.scan_boxes(scan.scan_id()).await?,
+            TokenBoxes::Indexer(token_id) => {
+                client
+                    .get_unspent_boxes_by_token_id(token_id.token_id())
+                    .await?
+            }
+            TokenBoxes::Explorer(..) | TokenBoxes::Recorded(..) => return Ok(()),
+        };
+        cache.insert(height, self.cache_key(), boxes);
+        Ok(())
+    }
+
+    /// Lookups of the same token (or scan) share the cached boxes
+    fn cache_key(&self) -> String {
+        match self {
+            TokenBoxes::Scan(scan) => format!("scan-{}", scan.scan_id()),
+            TokenBoxes::Indexer(token_id)
+            | TokenBoxes::Explorer(_, token_id)
+            | TokenBoxes::Recorded(_, token_id) => {
+                format!("token-{}", String::from(token_id.token_id()))
+            }
+        }
+    }
+
+    fn fetch_boxes(&self, node_api: &NodeApi) -> Result<Vec<ErgoBox>, ScanError> {
+        match self {
+            TokenBoxes::Scan(scan) => scan.get_boxes(node_api),
+            TokenBoxes::Indexer(token_id) => {
+                Ok(node_api.get_unspent_boxes_by_token_id(token_id.token_id())?)
+            }
+            TokenBoxes::Explorer(explorer_api, token_id) => {
+                Ok(explorer_api.get_unspent_boxes_by_token_id(token_id.token_id())?)
+            }
+            TokenBoxes::Recorded(snapshot, token_id) => {
+                Ok(snapshot.boxes(&String::from(token_id.token_id())))
+            }
+        }
+    }
+
+    /// Height of the chain the boxes are looked up in
+    pub fn current_height(&self, node_api: &NodeApi) -> Result<u32, ScanError> {
+        match self {
+            TokenBoxes::Scan(_) | TokenBoxes::Indexer(_) => {
+                Ok(node_api.node().current_block_height()? as u32)
+            }
+            TokenBoxes::Explorer(explorer_api, _) => Ok(explorer_api.get_current_height()?),
+            TokenBoxes::Recorded(snapshot, _) => Ok(snapshot.height.0),
+        }
+    }
+}
+
+/// Lookups of all the pool tokens the box sources of [`crate::oracle_state::OraclePool`] use
+#[derive(Debug, Clone)]
+pub struct PoolTokenBoxes {
+    pub oracle_token: TokenBoxes<OracleTokenId>,
+    pub pool_token: TokenBoxes<PoolTokenId>,
+    pub ballot_token: TokenBoxes<BallotTokenId>,
+    pub refresh_token: TokenBoxes<RefreshTokenId>,
+    pub update_token: TokenBoxes<UpdateTokenId>,
+    pub buyback_token: Option<TokenBoxes<BuybackTokenId>>,
+}
+
+impl PoolTokenBoxes {
+    pub fn from_scans(node_scan_registry: &NodeScanRegistry) -> Self {
+        Self {
+            oracle_token: TokenBoxes::Scan(node_scan_registry.oracle_token_scan.clone()),
+            pool_token: TokenBoxes::Scan(node_scan_registry.pool_token_scan.clone()),
+            ballot_token: TokenBoxes::Scan(node_scan_registry.ballot_token_scan.clone()),
+            refresh_token: TokenBoxes::Scan(node_scan_registry.refresh_token_scan.clone()),
+            update_token: TokenBoxes::Scan(node_scan_registry.update_token_scan.clone()),
+            buyback_token: node_scan_registry
+                .buyback_token_scan
+                .clone()
+                .map(TokenBoxes::Scan),
+        }
+    }
+
+    pub fn from_indexer(pool_config: &PoolConfig) -> Self {
+        let token_ids = &pool_config.token_ids;
+        Self {
+            oracle_token: TokenBoxes::Indexer(token_ids.oracle_token_id.clone()),
+            pool_token: TokenBoxes::Indexer(token_ids.pool_nft_token_id.clone()),
+            ballot_token: TokenBoxes::Indexer(token_ids.ballot_token_id.clone()),
+            refresh_token: TokenBoxes::Indexer(token_ids.refresh_nft_token_id.clone()),
+            update_token: TokenBoxes::Indexer(token_ids.update_nft_token_id.clone()),
+            buyback_token: pool_config
+                .buyback_token_id
+                .clone()
+                .map(TokenBoxes::Indexer),
+        }
+    }
+
+    pub fn from_explorer(pool_config: &PoolConfig, explorer_api: Arc<ExplorerApi>) -> Self {
+        let token_ids = &pool_config.token_ids;
+        Self {
+            oracle_token: TokenBoxes::Explorer(
+                explorer_api.clone(),
+                token_ids.oracle_token_id.clone(),
+            ),
+            pool_token: TokenBoxes::Explorer(
+                explorer_api.clone(),
+                token_ids.pool_nft_token_id.clone(),
+            ),
+            ballot_token: TokenBoxes::Explorer(
+                explorer_api.clone(),
+                token_ids.ballot_token_id.clone(),
+            ),
+            refresh_token: TokenBoxes::Explorer(
+                explorer_api.clone(),
+                token_ids.refresh_nft_token_id.clone(),
+            ),
+            update_token: TokenBoxes::Explorer(
+                explorer_api.clone(),
+                token_ids.update_nft_token_id.clone(),
+            ),
+            buyback_token: pool_config
+                .buyback_token_id
+                .clone()
+                .map(|token_id| TokenBoxes::Explorer(explorer_api.clone(), token_id)),
+        }
+    }
+
+    /// Lookups in the boxes recorded by a main loop iteration
+    pub fn from_recording(pool_config: &PoolConfig, snapshot: Arc<BoxSnapshot>) -> Self {
+        let token_ids = &pool_config.token_ids;
+        Self {
+            oracle_token: TokenBoxes::Recorded(snapshot.clone(), token_ids.oracle_token_id.clone()),
+            pool_token: TokenBoxes::Recorded(snapshot.clone(), token_ids.pool_nft_token_id.clone()),
+            ballot_token: TokenBoxes::Recorded(snapshot.clone(), token_ids.ballot_token_id.clone()),
+            refresh_token: TokenBoxes::Recorded(
+                snapshot.clone(),
+                token_ids.refresh_nft_token_id.clone(),
+            ),
+            update_token: TokenBoxes::Recorded(
+                snapshot.clone(),
+                token_ids.update_nft_token_id.clone(),
+            ),
+            buyback_token: pool_config
+                .buyback_token_id
+                .clone()
+                .map(|token_id| TokenBoxes::Recorded(snapshot.clone(), token_id)),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Scheduling of the main loop iterations and graceful shutdown.
+//! The main loop is woken up when the node reports a new best block height (polled every
+//! `poll_interval`), or when `max_interval` passed since the last iteration without a new block.
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::sync::Notify;
+
+pub const DEFAULT_NODE_POLL_INTERVAL_SECS: u64 = 5;
+pub const DEFAULT_MAX_MAIN_LOOP_INTERVAL_SECS: u64 = 60;
+
+/// A flag shared between the main loop, the signal handler and the servers. Can be waited on
+/// from both blocking and async code.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    inner: Arc<ShutdownSignalInner>,
+}
+
+#[derive(Debug, Default)]
+struct ShutdownSignalInner {
+    triggered: Mutex<bool>,
+    condvar: Condvar,
+    notify: Notify,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        *self.inner.triggered.lock().unwrap() = true;
+        self.inner.condvar.notify_all();
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.inner.triggered.lock().unwrap()
+    }
+
+    /// Blocks the current thread for `timeout` or until the signal is triggered.
+    /// Returns true if the signal is triggered.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.inner.triggered.lock().unwrap();
+        let (guard, _) = self
+            .inner
+            .condvar
+            .wait_timeout_while(guard, timeout, |triggered| !*triggered)
+            .unwrap();
+        *guard
+    }
+
+    /// Resolves when the signal is triggered
+    pub async fn triggered(self) {
+        loop {
+            // created before the check so that a trigger in between is not missed
+            let notified = self.inner.notify.notified();
+            if self.is_triggered() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Listens for SIGINT (Ctrl-C) and, on Unix, SIGTERM. Keeps listening after the first signal,
+/// so that a repeated one can force the shutdown.
+#[derive(Debug)]
+pub struct TerminationSignals {
+    #[cfg(unix)]
+    sigterm: tokio::signal::unix::Signal,
+}
+
+impl TerminationSignals {
+    /// Must be called within a tokio runtime
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            sigterm: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?,
+        })
+    }
+
+    /// Resolves on the next termination signal
+    pub async fn recv(&mut self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                res = tokio::signal::ctrl_c() => res?,
+                _ = self.sigterm.recv() => (),
+            }
+        }
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wakeup {
+    NewBlock(u32),
+    Timer,
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub struct BlockScheduler {
+    poll_interval: Duration,
+    max_interval: Duration,
+    last_height: Option<u32>,
+}
+
+impl BlockScheduler {
+    pub fn new(poll_interval: Duration, max_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            max_interval,
+            last_height: None,
+        }
+    }
+
+    /// Blocks until a new best block height is reported by `get_height`, `max_interval` elapses
+    /// or `shutdown` is triggered. Failures to get the height are logged and retried on the next
+    /// poll.
+    pub fn wait_for_next_iteration<F, E>(
+        &mut self,
+        mut get_height: F,
+        shutdown: &ShutdownSignal,
+    ) -> Wakeup
+    where
+        F: FnMut() -> Result<u32, E>,
+        E: std::fmt::Debug,
+    {
+        let deadline = Instant::now() + self.max_interval;
+        if self.last_height.is_none() {
+            // don't treat the height of the first iteration as a new block
+            self.last_height = get_height().ok();
+        }
+        loop {
+            let timeout = self
+                .poll_interval
+                .min(deadline.saturating_duration_since(Instant::now()));
+            if shutdown.wait_timeout(timeout) {
+                return Wakeup::Shutdown;
+            }
+            match get_height() {
+                Ok(height) if self.last_height != Some(height) => {
+                    self.last_height = Some(height);
+                    return Wakeup::NewBlock(height);
+                }
+                Ok(_) => (),
+                Err(e) => log::warn!("Failed to get the current height: {:?}", e),
+            }
+            if Instant::now() >= deadline {
+                return Wakeup::Timer;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_wakes_on_new_block() {
+        let mut scheduler = BlockScheduler::new(Duration::from_millis(1), Duration::from_secs(60));
+        let shutdown = ShutdownSignal::new();
+        let calls = Cell::new(0u32);
+        let get_height = || -> Result<u32, ()> {
+            calls.set(calls.get() + 1);
+            Ok(if calls.get() < 5 { 100 } else { 101 })
+        };
+        assert_eq!(
+            scheduler.wait_for_next_iteration(get_height, &shutdown),
+            Wakeup::NewBlock(101)
+        );
+    }
+
+    #[test]
+    fn test_timer_fallback() {
+        let mut scheduler =
+            BlockScheduler::new(Duration::from_millis(1), Duration::from_millis(20));
+        let shutdown = ShutdownSignal::new();
+        assert_eq!(
+            scheduler.wait_for_next_iteration(|| Ok::<u32, ()>(100), &shutdown),
+            Wakeup::Timer
+        );
+        assert_eq!(
+            scheduler.wait_for_next_iteration(|| Err::<u32, _>("node is down"), &shutdown),
+            Wakeup::Timer
+        );
+    }
+
+    #[test]
+    fn test_shutdown_interrupts_wait() {
+        let mut scheduler = BlockScheduler::new(Duration::from_secs(60), Duration::from_secs(60));
+        let shutdown = ShutdownSignal::new();
+        let shutdown_clone = shutdown.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            shutdown_clone.trigger();
+        });
+        assert_eq!(
+            scheduler.wait_for_next_iteration(|| Ok::<u32, ()>(100), &shutdown),
+            Wakeup::Shutdown
+        );
+        assert!(shutdown.is_triggered());
+    }
+}
@@ -0,0 +1,428 @@
+//! Sources of the oracle secrets (node API keys, wallet passwords and mnemonic). Each secret is
+//! taken from the first source that has it:
+//! 1. systemd credential named as the environment variable (`LoadCredential=ORACLE_NODE_API_KEY:...`),
+//!    read from `$CREDENTIALS_DIRECTORY`;
+//! 2. the secrets file (`secrets_file` in the oracle config), a YAML file readable only by its
+//!    owner, or the same file encrypted with `encrypt-secrets-file` (the password is
+//!    ORACLE_SECRETS_FILE_PASSWORD, which is looked up in the systemd credentials and the
+//!    environment only);
+//! 3. the environment variable.
+use std::path::Path;
+use std::path::PathBuf;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::wallet::secret_storage::EncryptedSecret;
+use crate::wallet::secret_storage::SecretStorageError;
+
+/// Set by systemd for the services with `LoadCredential=`/`SetCredential=`
+const CREDENTIALS_DIRECTORY_ENV_VAR: &str = "CREDENTIALS_DIRECTORY";
+
+/// PBKDF2 iterations of the encrypted secrets file, the same as the node uses for its wallet
+const ENCRYPTION_ITERATIONS: u32 = 128_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secret {
+    NodeApiKey,
+    NodeWalletPassword,
+    WalletMnemonic,
+    WalletMnemonicPassword,
+    WalletSecretFilePassword,
+    /// Password of the encrypted secrets file, can't be in the secrets file itself
+    SecretsFilePassword,
+    /// API key of the n-th (starting from 1) node in `backup_nodes`
+    BackupNodeApiKey(usize),
+}
+
+impl Secret {
+    /// Name of the environment variable and the systemd credential
+    pub fn env_var(&self) -> String {
+        match self {
+            Secret::NodeApiKey => "ORACLE_NODE_API_KEY".to_string(),
+            Secret::NodeWalletPassword => "ORACLE_NODE_WALLET_PASSWORD".to_string(),
+            Secret::WalletMnemonic => "ORACLE_WALLET_MNEMONIC".to_string(),
+            Secret::WalletMnemonicPassword => "ORACLE_WALLET_MNEMONIC_PASSWORD".to_string(),
+            Secret::WalletSecretFilePassword => "ORACLE_WALLET_SECRET_FILE_PASSWORD".to_string(),
+            Secret::SecretsFilePassword => "ORACLE_SECRETS_FILE_PASSWORD".to_string(),
+            Secret::BackupNodeApiKey(n) => format!("ORACLE_BACKUP_NODE_API_KEY_{n}"),
+        }
+    }
+}
+
+/// The secrets file, the keys are the environment variable names without `ORACLE_` in lower case,
+/// except for the API keys of the backup nodes which are listed in `backup_node_api_keys`. No
+/// `Debug` to keep the secrets out of the logs.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecretsFile {
+    pub node_api_key: Option<String>,
+    pub node_wallet_password: Option<String>,
+    pub wallet_mnemonic: Option<String>,
+    pub wallet_mnemonic_password: Option<String>,
+    pub wallet_secret_file_password: Option<String>,
+    /// In the order of `backup_nodes`
+    pub backup_node_api_keys: Option<Vec<String>>,
+}
+
+impl SecretsFile {
+    fn get(&self, secret: Secret) -> Option<&String> {
+        match secret {
+            Secret::NodeApiKey => self.node_api_key.as_ref(),
+            Secret::NodeWalletPassword => self.node_wallet_password.as_ref(),
+            Secret::WalletMnemonic => self.wallet_mnemonic.as_ref(),
+            Secret::WalletMnemonicPassword => self.wallet_mnemonic_password.as_ref(),
+            Secret::WalletSecretFilePassword => self.wallet_secret_file_password.as_ref(),
+            Secret::SecretsFilePassword => None,
+            Secret::BackupNodeApiKey(n) => self
+                .backup_node_api_keys
+                .as_ref()
+                .and_then(|keys| keys.get(n.checked_sub(1)?)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SecretsError {
+    #[error("{} is not set (systemd credential, secrets file or environment variable)", .0.env_var())]
+    Missing(Secret),
+    #[error("{} is empty", .0.env_var())]
+    Empty(Secret),
+    #[error("failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error(
+        "secrets file {path} is accessible by other users (mode {mode:o}), run `chmod 600 {path}`"
+    )]
+    InsecurePermissions { path: String, mode: u32 },
+    #[error("failed to parse secrets file {0}: {1}")]
+    Parse(String, serde_yaml::Error),
+    #[error("secrets file {0} is encrypted, but ORACLE_SECRETS_FILE_PASSWORD is not set")]
+    NoPassword(String),
+    #[error("failed to decrypt secrets file {0}: {1}")]
+    Decryption(String, SecretStorageError),
+    #[error("failed to encrypt secrets file: {0}")]
+    Encryption(SecretStorageError),
+    #[error("secrets file {0} is not valid UTF-8")]
+    Utf8(String),
+}
+
+/// Looks up the secrets in the precedence order, see the module docs
+pub struct SecretSources {
+    credentials_dir: Option<PathBuf>,
+    file: SecretsFile,
+    env: fn(&str) -> Option<String>,
+}
+
+impl SecretSources {
+    /// Reads (and decrypts if encrypted) the secrets file
+    pub fn load(secrets_file: Option<&Path>) -> Result<Self, SecretsError> {
+        let mut sources = SecretSources {
+            credentials_dir: std::env::var_os(CREDENTIALS_DIRECTORY_ENV_VAR).map(PathBuf::from),
+            file: SecretsFile::default(),
+            env: |name| std::env::var(name).ok(),
+        };
+        if let Some(path) = secrets_file {
+            sources.file = sources.read_secrets_file(path)?;
+        }
+        Ok(sources)
+    }
+
+    pub fn get(&self, secret: Secret) -> Result<Option<String>, SecretsError> {
+        if let Some(dir) = &self.credentials_dir {
+            let path = dir.join(secret.env_var());
+            match std::fs::read_to_string(&path) {
+                Ok(value) => return Ok(Some(strip_trailing_newline(value))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(SecretsError::Io(path.display().to_string(), e)),
+            }
+        }
+        if let Some(value) = self.file.get(secret) {
+            return Ok(Some(value.clone()));
+        }
+        Ok((self.env)(&secret.env_var()))
+    }
+
+    /// Like [`Self::get`], but the secret must be set and not empty
+    pub fn get_required(&self, secret: Secret) -> Result<String, SecretsError> {
+        match self.get(secret)? {
+            Some(value) if value.is_empty() => Err(SecretsError::Empty(secret)),
+            Some(value) => Ok(value),
+            None => Err(SecretsError::Missing(secret)),
+        }
+    }
+
+    fn read_secrets_file(&self, path: &Path) -> Result<SecretsFile, SecretsError> {
+        check_permissions(path)?;
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| SecretsError::Io(path.display().to_string(), e))?;
+        let plain = match EncryptedSecret::load_from_str(&content) {
+            Ok(encrypted) => {
+                let password = self
+                    .get(Secret::SecretsFilePassword)?
+                    .ok_or_else(|| SecretsError::NoPassword(path.display().to_string()))?;
+                let bytes = encrypted
+                    .decrypt(&password)
+                    .map_err(|e| SecretsError::Decryption(path.display().to_string(), e))?;
+                String::from_utf8(bytes)
+                    .map_err(|_| SecretsError::Utf8(path.display().to_string()))?
+            }
+            Err(_) => content,
+        };
+        serde_yaml::from_str(&plain).map_err(|e| SecretsError::Parse(path.display().to_string(), e))
+    }
+}
+
+/// Encrypts the secrets file with ORACLE_SECRETS_FILE_PASSWORD
+pub fn encrypt_secrets_file(
+    secrets_file: &Path,
+    encrypted_file: &Path,
+    password: &str,
+) -> Result<(), SecretsError> {
+    encrypt_secrets_file_with_iterations(
+        secrets_file,
+        encrypted_file,
+        password,
+        ENCRYPTION_ITERATIONS,
+    )
+}
+
+fn encrypt_secrets_file_with_iterations(
+    secrets_file: &Path,
+    encrypted_file: &Path,
+    password: &str,
+    iterations: u32,
+) -> Result<(), SecretsError> {
+    let plain = std::fs::read_to_string(secrets_file)
+        .map_err(|e| SecretsError::Io(secrets_file.display().to_string(), e))?;
+    // fail early on a typo rather than on the oracle start
+    serde_yaml::from_str::<SecretsFile>(&plain)
+        .map_err(|e| SecretsError::Parse(secrets_file.display().to_string(), e))?;
+    let mut salt = [0u8; 32];
+    let mut iv = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut iv);
+    let encrypted = EncryptedSecret::encrypt(plain.as_bytes(), password, &salt, iv, iterations)
+        .map_err(SecretsError::Encryption)?;
+    write_owner_only(
+        encrypted_file,
+        &serde_json::to_string_pretty(&encrypted).unwrap(),
+    )
+    .map_err(|e| SecretsError::Io(encrypted_file.display().to_string(), e))
+}
+
+fn strip_trailing_newline(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), SecretsError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)
+        .map_err(|e| SecretsError::Io(path.display().to_string(), e))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(SecretsError::InsecurePermissions {
+            path: path.display().to_string(),
+            mode: mode & 0o777,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), SecretsError> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_owner_only(path: &Path, content: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_owner_only(path: &Path, content: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "oracle-core-secrets-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_env(name: &str) -> Option<String> {
+        match name {
+            "ORACLE_NODE_API_KEY" => Some("env key".to_string()),
+            "ORACLE_NODE_WALLET_PASSWORD" => Some("env password".to_string()),
+            "ORACLE_SECRETS_FILE_PASSWORD" => Some("file password".to_string()),
+            "ORACLE_BACKUP_NODE_API_KEY_2" => Some("env backup key".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let credentials_dir = test_dir("precedence");
+        std::fs::write(
+            credentials_dir.join("ORACLE_NODE_API_KEY"),
+            "credential key\n",
+        )
+        .unwrap();
+        let sources = SecretSources {
+            credentials_dir: Some(credentials_dir),
+            file: SecretsFile {
+                node_api_key: Some("file key".to_string()),
+                wallet_mnemonic: Some("file mnemonic".to_string()),
+                ..Default::default()
+            },
+            env: test_env,
+        };
+        assert_eq!(
+            sources.get(Secret::NodeApiKey).unwrap(),
+            Some("credential key".to_string())
+        );
+        assert_eq!(
+            sources.get(Secret::WalletMnemonic).unwrap(),
+            Some("file mnemonic".to_string())
+        );
+        assert_eq!(
+            sources.get(Secret::NodeWalletPassword).unwrap(),
+            Some("env password".to_string())
+        );
+        assert_eq!(sources.get(Secret::WalletMnemonicPassword).unwrap(), None);
+        assert!(matches!(
+            sources.get_required(Secret::WalletMnemonicPassword),
+            Err(SecretsError::Missing(Secret::WalletMnemonicPassword))
+        ));
+    }
+
+    #[test]
+    fn test_backup_node_api_keys() {
+        let sources = SecretSources {
+            credentials_dir: None,
+            file: SecretsFile {
+                backup_node_api_keys: Some(vec!["file backup key".to_string()]),
+                ..Default::default()
+            },
+            env: test_env,
+        };
+        assert_eq!(
+            Secret::BackupNodeApiKey(2).env_var(),
+            "ORACLE_BACKUP_NODE_API_KEY_2"
+        );
+        assert_eq!(
+            sources.get(Secret::BackupNodeApiKey(1)).unwrap(),
+            Some("file backup key".to_string())
+        );
+        assert_eq!(
+            sources.get(Secret::BackupNodeApiKey(2)).unwrap(),
+            Some("env backup key".to_string())
+        );
+        assert!(matches!(
+            sources.get_required(Secret::BackupNodeApiKey(3)),
+            Err(SecretsError::Missing(Secret::BackupNodeApiKey(3)))
+        ));
+    }
+
+    #[test]
+    fn test_empty_secret() {
+        let sources = SecretSources {
+            credentials_dir: None,
+            file: SecretsFile {
+                node_api_key: Some("".to_string()),
+                ..Default::default()
+            },
+            env: test_env,
+        };
+        assert!(matches!(
+            sources.get_required(Secret::NodeApiKey),
+            Err(SecretsError::Empty(Secret::NodeApiKey))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secrets_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = test_dir("permissions");
+        let path = dir.join("secrets.yaml");
+        std::fs::write(&path, "node_api_key: file key\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let sources = SecretSources {
+            credentials_dir: None,
+            file: SecretsFile::default(),
+            env: test_env,
+        };
+        assert!(matches!(
+            sources.read_secrets_file(&path),
+            Err(SecretsError::InsecurePermissions { mode: 0o644, .. })
+        ));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(
+            sources.read_secrets_file(&path).unwrap().node_api_key,
+            Some("file key".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encrypted_secrets_file() {
+        let dir = test_dir("encrypted");
+        let plain_path = dir.join("secrets.yaml");
+        let encrypted_path = dir.join("secrets.enc");
+        std::fs::write(
+            &plain_path,
+            "node_api_key: file key\nwallet_mnemonic: file mnemonic\n",
+        )
+        .unwrap();
+        encrypt_secrets_file_with_iterations(&plain_path, &encrypted_path, "file password", 1000)
+            .unwrap();
+        let sources = SecretSources {
+            credentials_dir: None,
+            file: SecretsFile::default(),
+            env: test_env,
+        };
+        let file = sources.read_secrets_file(&encrypted_path).unwrap();
+        assert_eq!(file.node_api_key, Some("file key".to_string()));
+        assert_eq!(file.wallet_mnemonic, Some("file mnemonic".to_string()));
+
+        let sources = SecretSources {
+            env: |_| Some("wrong password".to_string()),
+            ..sources
+        };
+        assert!(matches!(
+            sources.read_secrets_file(&encrypted_path),
+            Err(SecretsError::Decryption(..))
+        ));
+    }
+}
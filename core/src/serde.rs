@@ -26,6 +26,11 @@ use crate::{
             RefreshContractParameters, RefreshContractParametersError,
             RefreshContractParametersInputs,
         },
+        registry::{
+            identify_bytes, ContractKind, KnownContract, BALLOT_TOKEN, BUFFER_LENGTH, EPOCH_LENGTH,
+            MAX_DEVIATION_PERCENT, MIN_DATA_POINTS, MIN_STORAGE_RENT, MIN_VOTES, ORACLE_TOKEN_ID,
+            POOL_NFT, REFRESH_NFT, UPDATE_NFT,
+        },
         update::{UpdateContractParameters, UpdateContractParametersError},
     },
     oracle_types::{EpochLength, MinDatapoints},
@@ -36,6 +41,8 @@ use crate::{
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub(crate) struct PoolConfigSerde {
     data_point_source: Option<PredefinedDataPointSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_point_source_custom_script: Option<String>,
     oracle_contract_parameters: OracleContractParametersSerde,
     pool_contract_parameters: PoolContractParametersSerde,
     refresh_contract_parameters: RefreshContractParametersSerde,
@@ -43,6 +50,10 @@ pub(crate) struct PoolConfigSerde {
     ballot_contract_parameters: BallotContractParametersSerde,
     token_ids: TokenIds,
     buyback_token_id: Option<BuybackTokenId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oracle_tokens_emission: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ballot_tokens_emission: Option<u64>,
 }
 
 #[derive(Debug, Error)]
@@ -65,6 +76,52 @@ pub enum SerdeConversionError {
     UpdateContractParameters(#[from] UpdateContractParametersError),
     #[error("BoxValueError: {0}")]
     BoxValueError(#[from] BoxValueError),
+    #[error(
+        "{kind} contract: {constant}_index is not set and the contract is not a known version, \
+         see print-contract-hashes"
+    )]
+    UnknownConstantIndex {
+        kind: ContractKind,
+        constant: &'static str,
+    },
+}
+
+/// Decoded contract ErgoTree bytes and the known version of the contract, to fill in the
+/// constant indexes left out of the config
+struct ContractLayout {
+    kind: ContractKind,
+    ergo_tree_bytes: Vec<u8>,
+    known: Option<&'static KnownContract>,
+}
+
+impl ContractLayout {
+    fn new(kind: ContractKind, ergo_tree_bytes: &str) -> Result<Self, SerdeConversionError> {
+        let ergo_tree_bytes = base16::decode(ergo_tree_bytes)?;
+        let known = identify_bytes(kind, &ergo_tree_bytes);
+        Ok(Self {
+            kind,
+            ergo_tree_bytes,
+            known,
+        })
+    }
+
+    /// The index from the config, or the index of the known contract version
+    fn index(
+        &self,
+        constant: &'static str,
+        index: Option<usize>,
+    ) -> Result<usize, SerdeConversionError> {
+        match index {
+            Some(index) => Ok(index),
+            None => self
+                .known
+                .and_then(|known| known.constant_index(constant))
+                .ok_or(SerdeConversionError::UnknownConstantIndex {
+                    kind: self.kind,
+                    constant,
+                }),
+        }
+    }
 }
 
 impl From<PoolConfig> for PoolConfigSerde {
@@ -108,7 +165,10 @@ impl From<PoolConfig> for PoolConfigSerde {
             update_contract_parameters,
             token_ids: c.token_ids,
             data_point_source: c.data_point_source,
+            data_point_source_custom_script: c.data_point_source_custom_script,
             buyback_token_id: c.buyback_token_id,
+            oracle_tokens_emission: c.oracle_tokens_emission,
+            ballot_tokens_emission: c.ballot_tokens_emission,
         }
     }
 }
@@ -116,10 +176,17 @@ impl From<PoolConfig> for PoolConfigSerde {
 impl TryFrom<PoolConfigSerde> for PoolConfig {
     type Error = SerdeConversionError;
     fn try_from(c: PoolConfigSerde) -> Result<Self, Self::Error> {
+        let oracle = ContractLayout::new(
+            ContractKind::Oracle,
+            &c.oracle_contract_parameters.ergo_tree_bytes,
+        )?;
         let oracle_contract_parameters = OracleContractParameters::checked_load(
-            base16::decode(c.oracle_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.oracle_contract_parameters.pool_nft_index,
-            c.oracle_contract_parameters.min_storage_rent_index,
+            oracle.ergo_tree_bytes.clone(),
+            oracle.index(POOL_NFT, c.oracle_contract_parameters.pool_nft_index)?,
+            oracle.index(
+                MIN_STORAGE_RENT,
+                c.oracle_contract_parameters.min_storage_rent_index,
+            )?,
             c.oracle_contract_parameters.min_storage_rent.try_into()?,
         )?;
 
@@ -131,44 +198,46 @@ impl TryFrom<PoolConfigSerde> for PoolConfig {
         )
         .map_err(PoolConfigError::from)?;
 
+        let pool = ContractLayout::new(
+            ContractKind::Pool,
+            &c.pool_contract_parameters.ergo_tree_bytes,
+        )?;
         let pool_contract_parameters = PoolContractParameters::checked_load(
-            base16::decode(c.pool_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.pool_contract_parameters.refresh_nft_index,
-            c.pool_contract_parameters.update_nft_index,
+            pool.ergo_tree_bytes.clone(),
+            pool.index(REFRESH_NFT, c.pool_contract_parameters.refresh_nft_index)?,
+            pool.index(UPDATE_NFT, c.pool_contract_parameters.update_nft_index)?,
         )?;
 
         let refresh_contract_parameters =
-            RefreshContractParameters::checked_load(RefreshContractParametersInputs {
-                ergo_tree_bytes: base16::decode(
-                    c.refresh_contract_parameters.ergo_tree_bytes.as_str(),
-                )?,
-                pool_nft_index: c.refresh_contract_parameters.pool_nft_index,
-                oracle_token_id_index: c.refresh_contract_parameters.oracle_token_id_index,
-                min_data_points_index: c.refresh_contract_parameters.min_data_points_index,
-                min_data_points: c.refresh_contract_parameters.min_data_points,
-                buffer_length_index: c.refresh_contract_parameters.buffer_length_index,
-                buffer_length: c.refresh_contract_parameters.buffer_length,
-                max_deviation_percent_index: c
-                    .refresh_contract_parameters
-                    .max_deviation_percent_index,
-                max_deviation_percent: c.refresh_contract_parameters.max_deviation_percent,
-                epoch_length_index: c.refresh_contract_parameters.epoch_length_index,
-                epoch_length: c.refresh_contract_parameters.epoch_length,
-            })?;
+            RefreshContractParameters::checked_load(c.refresh_contract_parameters.inputs()?)?;
 
+        let update = ContractLayout::new(
+            ContractKind::Update,
+            &c.update_contract_parameters.ergo_tree_bytes,
+        )?;
         let update_contract_parameters = UpdateContractParameters::checked_load(
-            base16::decode(c.update_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.update_contract_parameters.pool_nft_index,
-            c.update_contract_parameters.ballot_token_index,
-            c.update_contract_parameters.min_votes_index,
+            update.ergo_tree_bytes.clone(),
+            update.index(POOL_NFT, c.update_contract_parameters.pool_nft_index)?,
+            update.index(
+                BALLOT_TOKEN,
+                c.update_contract_parameters.ballot_token_index,
+            )?,
+            update.index(MIN_VOTES, c.update_contract_parameters.min_votes_index)?,
             c.update_contract_parameters.min_votes,
         )?;
 
+        let ballot = ContractLayout::new(
+            ContractKind::Ballot,
+            &c.ballot_contract_parameters.ergo_tree_bytes,
+        )?;
         let ballot_contract_parameters = BallotContractParameters::checked_load(
-            base16::decode(c.ballot_contract_parameters.ergo_tree_bytes.as_str())?,
+            ballot.ergo_tree_bytes.clone(),
             c.ballot_contract_parameters.min_storage_rent.try_into()?,
-            c.ballot_contract_parameters.min_storage_rent_index,
-            c.ballot_contract_parameters.update_nft_index,
+            ballot.index(
+                MIN_STORAGE_RENT,
+                c.ballot_contract_parameters.min_storage_rent_index,
+            )?,
+            ballot.index(UPDATE_NFT, c.ballot_contract_parameters.update_nft_index)?,
         )?;
 
         let refresh_box_wrapper_inputs = RefreshBoxWrapperInputs::checked_load(
@@ -205,6 +274,7 @@ impl TryFrom<PoolConfigSerde> for PoolConfig {
 
         Ok(PoolConfig {
             data_point_source: c.data_point_source,
+            data_point_source_custom_script: c.data_point_source_custom_script,
             oracle_box_wrapper_inputs,
             pool_box_wrapper_inputs,
             refresh_box_wrapper_inputs,
@@ -212,6 +282,8 @@ impl TryFrom<PoolConfigSerde> for PoolConfig {
             ballot_box_wrapper_inputs,
             token_ids: c.token_ids,
             buyback_token_id: c.buyback_token_id,
+            oracle_tokens_emission: c.oracle_tokens_emission,
+            ballot_tokens_emission: c.ballot_tokens_emission,
         })
     }
 }
@@ -252,46 +324,55 @@ impl TryFrom<BootstrapConfigSerde> for BootstrapConfig {
     type Error = SerdeConversionError;
 
     fn try_from(c: BootstrapConfigSerde) -> Result<Self, Self::Error> {
+        let pool = ContractLayout::new(
+            ContractKind::Pool,
+            &c.pool_contract_parameters.ergo_tree_bytes,
+        )?;
         let pool_contract_parameters = PoolContractParameters::checked_load(
-            base16::decode(c.pool_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.pool_contract_parameters.refresh_nft_index,
-            c.pool_contract_parameters.update_nft_index,
+            pool.ergo_tree_bytes.clone(),
+            pool.index(REFRESH_NFT, c.pool_contract_parameters.refresh_nft_index)?,
+            pool.index(UPDATE_NFT, c.pool_contract_parameters.update_nft_index)?,
         )?;
         let refresh_contract_parameters =
-            RefreshContractParameters::build_with(RefreshContractParametersInputs {
-                ergo_tree_bytes: base16::decode(
-                    c.refresh_contract_parameters.ergo_tree_bytes.as_str(),
-                )?,
-                pool_nft_index: c.refresh_contract_parameters.pool_nft_index,
-                oracle_token_id_index: c.refresh_contract_parameters.oracle_token_id_index,
-                min_data_points_index: c.refresh_contract_parameters.min_data_points_index,
-                min_data_points: c.refresh_contract_parameters.min_data_points,
-                buffer_length_index: c.refresh_contract_parameters.buffer_length_index,
-                buffer_length: c.refresh_contract_parameters.buffer_length,
-                max_deviation_percent_index: c
-                    .refresh_contract_parameters
-                    .max_deviation_percent_index,
-                max_deviation_percent: c.refresh_contract_parameters.max_deviation_percent,
-                epoch_length_index: c.refresh_contract_parameters.epoch_length_index,
-                epoch_length: c.refresh_contract_parameters.epoch_length,
-            })?;
+            RefreshContractParameters::build_with(c.refresh_contract_parameters.inputs()?)?;
+        let update = ContractLayout::new(
+            ContractKind::Update,
+            &c.update_contract_parameters.ergo_tree_bytes,
+        )?;
         let update_contract_parameters = UpdateContractParameters::build_with(
-            base16::decode(c.update_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.update_contract_parameters.pool_nft_index,
-            c.update_contract_parameters.ballot_token_index,
-            c.update_contract_parameters.min_votes_index,
+            update.ergo_tree_bytes.clone(),
+            update.index(POOL_NFT, c.update_contract_parameters.pool_nft_index)?,
+            update.index(
+                BALLOT_TOKEN,
+                c.update_contract_parameters.ballot_token_index,
+            )?,
+            update.index(MIN_VOTES, c.update_contract_parameters.min_votes_index)?,
             c.update_contract_parameters.min_votes,
         )?;
+        let ballot = ContractLayout::new(
+            ContractKind::Ballot,
+            &c.ballot_contract_parameters.ergo_tree_bytes,
+        )?;
         let ballot_contract_parameters = BallotContractParameters::build_with(
-            base16::decode(c.ballot_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.ballot_contract_parameters.min_storage_rent_index,
+            ballot.ergo_tree_bytes.clone(),
+            ballot.index(
+                MIN_STORAGE_RENT,
+                c.ballot_contract_parameters.min_storage_rent_index,
+            )?,
             c.ballot_contract_parameters.min_storage_rent.try_into()?,
-            c.ballot_contract_parameters.update_nft_index,
+            ballot.index(UPDATE_NFT, c.ballot_contract_parameters.update_nft_index)?,
+        )?;
+        let oracle = ContractLayout::new(
+            ContractKind::Oracle,
+            &c.oracle_contract_parameters.ergo_tree_bytes,
         )?;
         let oracle_contract_parameters = OracleContractParameters::build_with(
-            base16::decode(c.oracle_contract_parameters.ergo_tree_bytes.as_str())?,
-            c.oracle_contract_parameters.pool_nft_index,
-            c.oracle_contract_parameters.min_storage_rent_index,
+            oracle.ergo_tree_bytes.clone(),
+            oracle.index(POOL_NFT, c.oracle_contract_parameters.pool_nft_index)?,
+            oracle.index(
+                MIN_STORAGE_RENT,
+                c.oracle_contract_parameters.min_storage_rent_index,
+            )?,
             c.oracle_contract_parameters.min_storage_rent.try_into()?,
         )?;
 
@@ -310,8 +391,8 @@ impl TryFrom<BootstrapConfigSerde> for BootstrapConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OracleContractParametersSerde {
     ergo_tree_bytes: String,
-    pool_nft_index: usize,
-    min_storage_rent_index: usize,
+    pool_nft_index: Option<usize>,
+    min_storage_rent_index: Option<usize>,
     min_storage_rent: u64,
 }
 
@@ -319,8 +400,8 @@ impl From<OracleContractParameters> for OracleContractParametersSerde {
     fn from(p: OracleContractParameters) -> Self {
         OracleContractParametersSerde {
             ergo_tree_bytes: base16::encode_lower(p.ergo_tree_bytes().as_slice()),
-            pool_nft_index: p.pool_nft_index,
-            min_storage_rent_index: p.min_storage_rent_index,
+            pool_nft_index: Some(p.pool_nft_index),
+            min_storage_rent_index: Some(p.min_storage_rent_index),
             min_storage_rent: *p.min_storage_rent.as_u64(),
         }
     }
@@ -329,16 +410,16 @@ impl From<OracleContractParameters> for OracleContractParametersSerde {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct PoolContractParametersSerde {
     ergo_tree_bytes: String,
-    refresh_nft_index: usize,
-    update_nft_index: usize,
+    refresh_nft_index: Option<usize>,
+    update_nft_index: Option<usize>,
 }
 
 impl From<PoolContractParameters> for PoolContractParametersSerde {
     fn from(p: PoolContractParameters) -> Self {
         PoolContractParametersSerde {
             ergo_tree_bytes: base16::encode_lower(p.ergo_tree_bytes().as_slice()),
-            refresh_nft_index: p.refresh_nft_index(),
-            update_nft_index: p.update_nft_index(),
+            refresh_nft_index: Some(p.refresh_nft_index()),
+            update_nft_index: Some(p.update_nft_index()),
         }
     }
 }
@@ -346,15 +427,15 @@ impl From<PoolContractParameters> for PoolContractParametersSerde {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct RefreshContractParametersSerde {
     ergo_tree_bytes: String,
-    pool_nft_index: usize,
-    oracle_token_id_index: usize,
-    min_data_points_index: usize,
+    pool_nft_index: Option<usize>,
+    oracle_token_id_index: Option<usize>,
+    min_data_points_index: Option<usize>,
     min_data_points: MinDatapoints,
-    buffer_length_index: usize,
+    buffer_length_index: Option<usize>,
     buffer_length: i32,
-    max_deviation_percent_index: usize,
+    max_deviation_percent_index: Option<usize>,
     max_deviation_percent: i32,
-    epoch_length_index: usize,
+    epoch_length_index: Option<usize>,
     epoch_length: EpochLength,
 }
 
@@ -362,35 +443,55 @@ impl From<RefreshContractParameters> for RefreshContractParametersSerde {
     fn from(p: RefreshContractParameters) -> Self {
         RefreshContractParametersSerde {
             ergo_tree_bytes: base16::encode_lower(p.ergo_tree_bytes().as_slice()),
-            pool_nft_index: p.pool_nft_index(),
-            oracle_token_id_index: p.oracle_token_id_index(),
-            min_data_points_index: p.min_data_points_index(),
+            pool_nft_index: Some(p.pool_nft_index()),
+            oracle_token_id_index: Some(p.oracle_token_id_index()),
+            min_data_points_index: Some(p.min_data_points_index()),
             min_data_points: p.min_data_points(),
-            buffer_length_index: p.buffer_length_index(),
+            buffer_length_index: Some(p.buffer_length_index()),
             buffer_length: p.buffer_length(),
-            max_deviation_percent_index: p.max_deviation_percent_index(),
+            max_deviation_percent_index: Some(p.max_deviation_percent_index()),
             max_deviation_percent: p.max_deviation_percent(),
-            epoch_length_index: p.epoch_length_index(),
+            epoch_length_index: Some(p.epoch_length_index()),
             epoch_length: p.epoch_length(),
         }
     }
 }
 
+impl RefreshContractParametersSerde {
+    fn inputs(&self) -> Result<RefreshContractParametersInputs, SerdeConversionError> {
+        let refresh = ContractLayout::new(ContractKind::Refresh, &self.ergo_tree_bytes)?;
+        Ok(RefreshContractParametersInputs {
+            pool_nft_index: refresh.index(POOL_NFT, self.pool_nft_index)?,
+            oracle_token_id_index: refresh.index(ORACLE_TOKEN_ID, self.oracle_token_id_index)?,
+            min_data_points_index: refresh.index(MIN_DATA_POINTS, self.min_data_points_index)?,
+            min_data_points: self.min_data_points,
+            buffer_length_index: refresh.index(BUFFER_LENGTH, self.buffer_length_index)?,
+            buffer_length: self.buffer_length,
+            max_deviation_percent_index: refresh
+                .index(MAX_DEVIATION_PERCENT, self.max_deviation_percent_index)?,
+            max_deviation_percent: self.max_deviation_percent,
+            epoch_length_index: refresh.index(EPOCH_LENGTH, self.epoch_length_index)?,
+            epoch_length: self.epoch_length,
+            ergo_tree_bytes: refresh.ergo_tree_bytes,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct BallotContractParametersSerde {
     ergo_tree_bytes: String,
-    min_storage_rent_index: usize,
+    min_storage_rent_index: Option<usize>,
     min_storage_rent: u64,
-    update_nft_index: usize,
+    update_nft_index: Option<usize>,
 }
 
 impl From<BallotContractParameters> for BallotContractParametersSerde {
     fn from(c: BallotContractParameters) -> Self {
         BallotContractParametersSerde {
             ergo_tree_bytes: base16::encode_lower(c.ergo_tree_bytes().as_slice()),
-            min_storage_rent_index: c.min_storage_rent_index(),
+            min_storage_rent_index: Some(c.min_storage_rent_index()),
             min_storage_rent: *c.min_storage_rent().as_u64(),
-            update_nft_index: c.update_nft_index(),
+            update_nft_index: Some(c.update_nft_index()),
         }
     }
 }
@@ -399,9 +500,9 @@ impl From<BallotContractParameters> for BallotContractParametersSerde {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct UpdateContractParametersSerde {
     ergo_tree_bytes: String,
-    pool_nft_index: usize,
-    ballot_token_index: usize,
-    min_votes_index: usize,
+    pool_nft_index: Option<usize>,
+    ballot_token_index: Option<usize>,
+    min_votes_index: Option<usize>,
     min_votes: u64,
 }
 
@@ -409,9 +510,9 @@ impl From<UpdateContractParameters> for UpdateContractParametersSerde {
     fn from(p: UpdateContractParameters) -> Self {
         UpdateContractParametersSerde {
             ergo_tree_bytes: base16::encode_lower(p.ergo_tree_bytes().as_slice()),
-            pool_nft_index: p.pool_nft_index(),
-            ballot_token_index: p.ballot_token_index(),
-            min_votes_index: p.min_votes_index(),
+            pool_nft_index: Some(p.pool_nft_index()),
+            ballot_token_index: Some(p.ballot_token_index()),
+            min_votes_index: Some(p.min_votes_index()),
             min_votes: p.min_votes(),
         }
     }
@@ -431,10 +532,11 @@ impl TryFrom<UpdateBootstrapConfigSerde> for UpdateBootstrapConfig {
         config_serde: UpdateBootstrapConfigSerde,
     ) -> Result<UpdateBootstrapConfig, Self::Error> {
         let pool_contract_parameters = if let Some(c) = config_serde.pool_contract_parameters {
+            let pool = ContractLayout::new(ContractKind::Pool, &c.ergo_tree_bytes)?;
             Some(PoolContractParameters::checked_load(
-                base16::decode(c.ergo_tree_bytes.as_str())?,
-                c.refresh_nft_index,
-                c.update_nft_index,
+                pool.ergo_tree_bytes.clone(),
+                pool.index(REFRESH_NFT, c.refresh_nft_index)?,
+                pool.index(UPDATE_NFT, c.update_nft_index)?,
             )?)
         } else {
             None
@@ -442,31 +544,18 @@ impl TryFrom<UpdateBootstrapConfigSerde> for UpdateBootstrapConfig {
 
         let refresh_contract_parameters = if let Some(c) = config_serde.refresh_contract_parameters
         {
-            Some(RefreshContractParameters::build_with(
-                RefreshContractParametersInputs {
-                    ergo_tree_bytes: base16::decode(c.ergo_tree_bytes.as_str())?,
-                    pool_nft_index: c.pool_nft_index,
-                    oracle_token_id_index: c.oracle_token_id_index,
-                    min_data_points_index: c.min_data_points_index,
-                    min_data_points: c.min_data_points,
-                    buffer_length_index: c.buffer_length_index,
-                    buffer_length: c.buffer_length,
-                    max_deviation_percent_index: c.max_deviation_percent_index,
-                    max_deviation_percent: c.max_deviation_percent,
-                    epoch_length_index: c.epoch_length_index,
-                    epoch_length: c.epoch_length,
-                },
-            )?)
+            Some(RefreshContractParameters::build_with(c.inputs()?)?)
         } else {
             None
         };
 
         let update_contract_parameters = if let Some(c) = config_serde.update_contract_parameters {
+            let update = ContractLayout::new(ContractKind::Update, &c.ergo_tree_bytes)?;
             Some(UpdateContractParameters::build_with(
-                base16::decode(c.ergo_tree_bytes.as_str())?,
-                c.pool_nft_index,
-                c.ballot_token_index,
-                c.min_votes_index,
+                update.ergo_tree_bytes.clone(),
+                update.index(POOL_NFT, c.pool_nft_index)?,
+                update.index(BALLOT_TOKEN, c.ballot_token_index)?,
+                update.index(MIN_VOTES, c.min_votes_index)?,
                 c.min_votes,
             )?)
         } else {
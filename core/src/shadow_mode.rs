@@ -0,0 +1,492 @@
+//! Shadow (simulation) mode. The oracle builds its actions as usual but never submits them.
+//! Every action that would have been submitted is kept along with the built transaction, and once
+//! the next pool box appears on-chain the entry is resolved against what actually happened: who
+//! refreshed the pool, with which rate, and whether our datapoint would have been included.
+use std::collections::VecDeque;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::TxId;
+use ergo_lib::ergo_chain_types::EcPoint;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use serde::Serialize;
+
+use crate::action_report::PoolActionReport;
+use crate::address_util::pks_to_network_addresses;
+use crate::box_kind::PoolBox;
+use crate::oracle_state::DataSourceError;
+use crate::oracle_state::OraclePool;
+use crate::oracle_types::BlockHeight;
+use crate::oracle_types::EpochCounter;
+use crate::oracle_types::Rate;
+use crate::pool_commands::refresh::deviation_check;
+
+/// Maximum number of entries kept in the storage, older entries are dropped first
+const MAX_ENTRIES: usize = 100;
+
+/// The action we would have performed
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum ShadowAction {
+    Refresh {
+        rate: Rate,
+        oracles_collected: Vec<NetworkAddress>,
+    },
+    PublishDatapoint {
+        datapoint: Rate,
+    },
+}
+
+/// What happened on-chain in the epoch the action was built for
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status")]
+pub enum ShadowOutcome {
+    /// The action was built, the pool box for the next epoch is not on-chain yet
+    Pending,
+    /// The pool was refreshed by someone
+    Refreshed {
+        pool_box_epoch_id: EpochCounter,
+        pool_box_height: BlockHeight,
+        rate: Rate,
+        refreshed_by: Option<NetworkAddress>,
+        oracles_collected: Vec<NetworkAddress>,
+        /// Only set for the datapoint publishing. Our datapoint is considered included if the
+        /// datapoints collected by the refresh along with ours pass the refresh contract check
+        /// (the max and the min datapoints are within the max deviation range).
+        datapoint_would_be_included: Option<bool>,
+    },
+    /// More than one epoch passed before we could observe the pool box of the next epoch
+    Unresolved,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowReportEntry {
+    pub height: BlockHeight,
+    pub pool_box_epoch_id: EpochCounter,
+    pub tx_id: TxId,
+    pub tx: UnsignedTransaction,
+    /// Ids of the boxes the tx would have created
+    pub output_box_ids: Vec<BoxId>,
+    /// Datapoint fetched from the datapoint source in the iteration the action was built, None if
+    /// the action didn't need one
+    pub datapoint: Option<Rate>,
+    pub action: ShadowAction,
+    pub outcome: ShadowOutcome,
+    /// The oracle boxes posted at the time the action was built, the inputs of the refresh that
+    /// resolves the entry
+    #[serde(skip)]
+    posted_oracles: Vec<PostedOracle>,
+}
+
+impl ShadowReportEntry {
+    pub fn new(
+        height: BlockHeight,
+        pool_box_epoch_id: EpochCounter,
+        tx: UnsignedTransaction,
+        datapoint: Option<Rate>,
+        report: PoolActionReport,
+        posted_oracles: Vec<PostedOracle>,
+        network_prefix: NetworkPrefix,
+    ) -> Self {
+        let action = match report {
+            PoolActionReport::Refresh(report) => ShadowAction::Refresh {
+                rate: report.rate,
+                oracles_collected: pks_to_network_addresses(
+                    report.oracle_boxes_collected,
+                    network_prefix,
+                ),
+            },
+            PoolActionReport::PublishDatapoint(report) => ShadowAction::PublishDatapoint {
+                datapoint: report.posted_datapoint,
+            },
+        };
+        let tx_id = tx.id();
+        let output_box_ids = tx
+            .output_candidates
+            .iter()
+            .enumerate()
+            // the outputs of a built tx are valid boxes
+            .filter_map(|(index, candidate)| {
+                ErgoBox::from_box_candidate(candidate, tx_id, index as u16).ok()
+            })
+            .map(|b| b.box_id())
+            .collect();
+        Self {
+            height,
+            pool_box_epoch_id,
+            tx_id,
+            tx,
+            output_box_ids,
+            datapoint,
+            action,
+            outcome: ShadowOutcome::Pending,
+            posted_oracles,
+        }
+    }
+
+    fn is_same_kind(&self, other: &ShadowReportEntry) -> bool {
+        matches!(
+            (&self.action, &other.action),
+            (ShadowAction::Refresh { .. }, ShadowAction::Refresh { .. })
+                | (
+                    ShadowAction::PublishDatapoint { .. },
+                    ShadowAction::PublishDatapoint { .. }
+                )
+        )
+    }
+}
+
+/// A posted oracle box
+#[derive(Debug, Clone)]
+pub struct PostedOracle {
+    pub public_key: EcPoint,
+    pub datapoint: Rate,
+    pub reward_amount: u64,
+}
+
+/// The pool box and the oracle boxes collected by the refresh that created it
+#[derive(Debug, Clone)]
+pub struct PoolObservation {
+    pub pool_box_epoch_id: EpochCounter,
+    pub pool_box_height: BlockHeight,
+    pub rate: Rate,
+    /// The public keys and the reward token amounts of the oracle boxes created by the refresh
+    /// tx (the outputs of the tx that created the pool box)
+    pub collected_reward_amounts: Vec<(EcPoint, u64)>,
+}
+
+impl PoolObservation {
+    pub fn observe(oracle_pool: &OraclePool) -> Result<Self, DataSourceError> {
+        let pool_box = oracle_pool.get_pool_box_source().get_pool_box()?;
+        let refresh_tx_id = pool_box.get_box().transaction_id;
+        let collected_reward_amounts = oracle_pool
+            .get_collected_datapoint_boxes_source()
+            .get_collected_datapoint_boxes()?
+            .into_iter()
+            .filter(|b| b.get_box().transaction_id == refresh_tx_id)
+            .map(|b| (b.public_key(), b.reward_token().amount.as_u64()))
+            .collect();
+        Ok(Self {
+            pool_box_epoch_id: pool_box.epoch_counter(),
+            pool_box_height: BlockHeight(pool_box.get_box().creation_height),
+            rate: pool_box.rate(),
+            collected_reward_amounts,
+        })
+    }
+}
+
+/// The currently posted oracle boxes
+pub fn posted_oracles(oracle_pool: &OraclePool) -> Result<Vec<PostedOracle>, DataSourceError> {
+    Ok(oracle_pool
+        .get_posted_datapoint_boxes_source()
+        .get_posted_datapoint_boxes()?
+        .into_iter()
+        .map(|b| PostedOracle {
+            public_key: b.public_key(),
+            datapoint: b.rate(),
+            reward_amount: b.reward_token().amount.as_u64(),
+        })
+        .collect())
+}
+
+#[derive(Debug)]
+pub struct ShadowReportStorage {
+    entries: VecDeque<ShadowReportEntry>,
+}
+
+impl ShadowReportStorage {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Adds the entry. A pending entry of the same kind built for the same epoch is replaced,
+    /// since the action is rebuilt on every main loop iteration until the epoch changes.
+    pub fn record(&mut self, entry: ShadowReportEntry) {
+        self.entries.retain(|e| {
+            !(matches!(e.outcome, ShadowOutcome::Pending)
+                && e.pool_box_epoch_id == entry.pool_box_epoch_id
+                && e.is_same_kind(&entry))
+        });
+        self.entries.push_back(entry);
+        while self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Resolves pending entries built for the epoch preceding the observed pool box. Returns the
+    /// entries resolved by this call.
+    pub fn resolve(
+        &mut self,
+        observation: &PoolObservation,
+        max_deviation_percent: u32,
+        network_prefix: NetworkPrefix,
+    ) -> Vec<ShadowReportEntry> {
+        let mut resolved = vec![];
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| matches!(e.outcome, ShadowOutcome::Pending))
+        {
+            let epoch = entry.pool_box_epoch_id.0;
+            if observation.pool_box_epoch_id.0 == epoch + 1 {
+                entry.outcome =
+                    refreshed_outcome(entry, observation, max_deviation_percent, network_prefix);
+                resolved.push(entry.clone());
+            } else if observation.pool_box_epoch_id.0 > epoch + 1 {
+                entry.outcome = ShadowOutcome::Unresolved;
+                resolved.push(entry.clone());
+            }
+        }
+        resolved
+    }
+
+    pub fn entries(&self) -> Vec<ShadowReportEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+fn refreshed_outcome(
+    entry: &ShadowReportEntry,
+    observation: &PoolObservation,
+    max_deviation_percent: u32,
+    network_prefix: NetworkPrefix,
+) -> ShadowOutcome {
+    let to_address =
+        |pk: &EcPoint| NetworkAddress::new(network_prefix, &Address::P2Pk(pk.clone().into()));
+    // the posted oracle box spent by the refresh tx for each of its oracle outputs
+    let collected: Vec<(&EcPoint, u64, Option<&PostedOracle>)> = observation
+        .collected_reward_amounts
+        .iter()
+        .map(|(pk, amount)| {
+            let posted = entry.posted_oracles.iter().find(|p| &p.public_key == pk);
+            (pk, *amount, posted)
+        })
+        .collect();
+    // the refresh contract pays one reward token to every collected oracle and one more for each
+    // collected box to the oracle that built the refresh tx
+    let collector_reward = 1 + collected.len() as u64;
+    let refreshed_by = collected
+        .iter()
+        .find(|(_, amount, posted)| {
+            matches!(posted, Some(posted) if *amount == posted.reward_amount + collector_reward)
+        })
+        .map(|(pk, _, _)| to_address(pk));
+    let datapoint_would_be_included = match entry.action {
+        ShadowAction::PublishDatapoint { datapoint } => {
+            let mut datapoints: Vec<Rate> = collected
+                .iter()
+                .filter_map(|(_, _, posted)| posted.map(|p| p.datapoint))
+                .collect();
+            // the datapoints of the refresh inputs posted after the action was built are not
+            // known, the pool rate is their average
+            if datapoints.len() < collected.len() {
+                datapoints.push(observation.rate);
+            }
+            datapoints.push(datapoint);
+            Some(deviation_check(max_deviation_percent, datapoints))
+        }
+        ShadowAction::Refresh { .. } => None,
+    };
+    ShadowOutcome::Refreshed {
+        pool_box_epoch_id: observation.pool_box_epoch_id,
+        pool_box_height: observation.pool_box_height,
+        rate: observation.rate,
+        refreshed_by,
+        oracles_collected: observation
+            .collected_reward_amounts
+            .iter()
+            .map(|(pk, _)| to_address(pk))
+            .collect(),
+        datapoint_would_be_included,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::chain::transaction::UnsignedInput;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
+    use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::action_report::PublishDatapointActionReport;
+    use crate::action_report::RefreshActionReport;
+
+    fn make_tx() -> UnsignedTransaction {
+        let input_box = force_any_val::<ErgoBox>();
+        UnsignedTransaction::new_from_vec(
+            vec![UnsignedInput::new(
+                input_box.box_id(),
+                ContextExtension::empty(),
+            )],
+            vec![],
+            vec![ErgoBoxCandidate::from(input_box)],
+        )
+        .unwrap()
+    }
+
+    fn make_entry(
+        epoch: u32,
+        report: PoolActionReport,
+        posted: Vec<PostedOracle>,
+    ) -> ShadowReportEntry {
+        ShadowReportEntry::new(
+            BlockHeight(100),
+            EpochCounter(epoch),
+            make_tx(),
+            Some(2000.into()),
+            report,
+            posted,
+            NetworkPrefix::Mainnet,
+        )
+    }
+
+    #[test]
+    fn test_record_replaces_pending_entry_for_the_same_epoch() {
+        let mut storage = ShadowReportStorage::new();
+        let report = |rate: i64| {
+            PoolActionReport::from(PublishDatapointActionReport {
+                posted_datapoint: rate.into(),
+            })
+        };
+        storage.record(make_entry(1, report(100), vec![]));
+        storage.record(make_entry(1, report(101), vec![]));
+        storage.record(make_entry(2, report(102), vec![]));
+        let entries = storage.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].datapoint, Some(2000.into()));
+        let output = ErgoBox::from_box_candidate(
+            entries[0].tx.output_candidates.first(),
+            entries[0].tx_id,
+            0,
+        )
+        .unwrap();
+        assert_eq!(entries[0].output_box_ids, vec![output.box_id()]);
+        assert!(matches!(
+            entries[0].action,
+            ShadowAction::PublishDatapoint { datapoint } if datapoint == 101
+        ));
+    }
+
+    #[test]
+    fn test_resolve_refresh_and_publish_datapoint() {
+        let refresher_pk = force_any_val::<EcPoint>();
+        let other_pk = force_any_val::<EcPoint>();
+        let posted_oracle = |pk: &EcPoint, datapoint: i64| PostedOracle {
+            public_key: pk.clone(),
+            datapoint: datapoint.into(),
+            reward_amount: 10,
+        };
+        let posted = vec![
+            posted_oracle(&refresher_pk, 1000),
+            posted_oracle(&other_pk, 1020),
+        ];
+        let mut storage = ShadowReportStorage::new();
+        storage.record(make_entry(
+            5,
+            RefreshActionReport {
+                oracle_boxes_collected: vec![refresher_pk.clone(), other_pk.clone()],
+                rate: 1000.into(),
+            }
+            .into(),
+            posted.clone(),
+        ));
+        storage.record(make_entry(
+            5,
+            PublishDatapointActionReport {
+                posted_datapoint: 2000.into(),
+            }
+            .into(),
+            posted.clone(),
+        ));
+        let not_yet = PoolObservation {
+            pool_box_epoch_id: EpochCounter(5),
+            pool_box_height: BlockHeight(90),
+            rate: 990.into(),
+            collected_reward_amounts: vec![],
+        };
+        assert!(storage
+            .resolve(&not_yet, 5, NetworkPrefix::Mainnet)
+            .is_empty());
+        let observation = PoolObservation {
+            pool_box_epoch_id: EpochCounter(6),
+            pool_box_height: BlockHeight(120),
+            rate: 1010.into(),
+            // the refresher gets 1 + 2 reward tokens for collecting the 2 boxes
+            collected_reward_amounts: vec![(refresher_pk.clone(), 13), (other_pk, 11)],
+        };
+        let resolved = storage.resolve(&observation, 5, NetworkPrefix::Mainnet);
+        assert_eq!(resolved.len(), 2);
+        let refresher_address =
+            NetworkAddress::new(NetworkPrefix::Mainnet, &Address::P2Pk(refresher_pk.into()));
+        match &resolved[0].outcome {
+            ShadowOutcome::Refreshed {
+                refreshed_by,
+                oracles_collected,
+                datapoint_would_be_included,
+                ..
+            } => {
+                assert_eq!(refreshed_by.as_ref(), Some(&refresher_address));
+                assert_eq!(oracles_collected.len(), 2);
+                assert_eq!(*datapoint_would_be_included, None);
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        assert!(matches!(
+            resolved[1].outcome,
+            ShadowOutcome::Refreshed {
+                datapoint_would_be_included: Some(false),
+                ..
+            }
+        ));
+
+        // our datapoint is checked against the collected datapoints (1000 and 1020), not just the
+        // pool rate: 1055 is within 5% of the pool rate but the spread 1000..1055 is over 5%
+        let included = |datapoint: i64| {
+            let mut storage = ShadowReportStorage::new();
+            storage.record(make_entry(
+                5,
+                PublishDatapointActionReport {
+                    posted_datapoint: datapoint.into(),
+                }
+                .into(),
+                posted.clone(),
+            ));
+            match &storage.resolve(&observation, 5, NetworkPrefix::Mainnet)[0].outcome {
+                ShadowOutcome::Refreshed {
+                    datapoint_would_be_included,
+                    ..
+                } => datapoint_would_be_included.unwrap(),
+                outcome => panic!("unexpected outcome {:?}", outcome),
+            }
+        };
+        assert!(included(1030));
+        assert!(!included(1055));
+    }
+
+    #[test]
+    fn test_resolve_skipped_epoch() {
+        let mut storage = ShadowReportStorage::new();
+        storage.record(make_entry(
+            5,
+            PublishDatapointActionReport {
+                posted_datapoint: 2000.into(),
+            }
+            .into(),
+            vec![],
+        ));
+        let observation = PoolObservation {
+            pool_box_epoch_id: EpochCounter(7),
+            pool_box_height: BlockHeight(150),
+            rate: 2000.into(),
+            collected_reward_amounts: vec![],
+        };
+        let resolved = storage.resolve(&observation, 5, NetworkPrefix::Mainnet);
+        assert!(matches!(resolved[0].outcome, ShadowOutcome::Unresolved));
+    }
+}
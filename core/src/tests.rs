@@ -1 +1,3 @@
 mod bootstrap_and_run;
+mod node_sim;
+mod oracle_sim;
@@ -10,7 +10,9 @@ use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInp
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
 use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
 use ergo_lib::wallet::Wallet;
+use ergo_node_interface::node_interface::NodeError;
 use sigma_test_util::force_any_val;
 
 use crate::cli_commands::bootstrap::perform_bootstrap_chained_transaction;
@@ -18,7 +20,6 @@ use crate::cli_commands::bootstrap::BootstrapConfig;
 use crate::cli_commands::bootstrap::BootstrapInput;
 use crate::node_interface;
 use crate::node_interface::SubmitTransaction;
-use crate::oracle_config::BASE_FEE;
 use crate::oracle_types::BlockHeight;
 use crate::pool_commands::test_utils::init_log_tests;
 use crate::pool_commands::test_utils::LocalTxSigner;
@@ -33,14 +34,18 @@ impl<'a> SubmitTransaction for ChainSubmitTx<'a> {
     fn submit_transaction(&self, tx: &Transaction) -> node_interface::Result<TxId> {
         self.chain
             .borrow_mut()
-            .add_block(Block::new(vec![tx.clone()]));
+            .add_block(Block::new(vec![tx.clone()]))
+            .map_err(|e| NodeError::BadRequest(e.to_string()))?;
         Ok(tx.id())
     }
 }
 
-fn bootstrap(wallet: &Wallet, net_address: &NetworkAddress, chain: &mut ChainSim) -> PoolConfig {
-    let ctx = force_any_val::<ErgoStateContext>();
-
+pub(super) fn bootstrap(
+    wallet: &Wallet,
+    net_address: &NetworkAddress,
+    chain: &mut ChainSim,
+    ctx: &ErgoStateContext,
+) -> PoolConfig {
     let unspent_boxes = chain.get_unspent_boxes(&net_address.address().script().unwrap());
 
     let bootstrap_config = BootstrapConfig::default();
@@ -56,10 +61,10 @@ fn bootstrap(wallet: &Wallet, net_address: &NetworkAddress, chain: &mut ChainSim
             unspent_boxes: unspent_boxes.clone(),
             change_address: net_address.clone(),
         },
-        tx_signer: &mut LocalTxSigner { ctx: &ctx, wallet },
+        tx_signer: &mut LocalTxSigner { ctx, wallet },
         submit_tx: &mut submit_tx_mock,
-        tx_fee: *BASE_FEE,
-        erg_value_per_box: *BASE_FEE,
+        tx_fee: SUGGESTED_TX_FEE(),
+        erg_value_per_box: SUGGESTED_TX_FEE(),
         change_address: net_address.address(),
         height,
     })
@@ -70,7 +75,7 @@ fn bootstrap(wallet: &Wallet, net_address: &NetworkAddress, chain: &mut ChainSim
 #[test]
 fn test_bootstrap_and_run() {
     init_log_tests();
-    let mut chain = ChainSim::new();
+    let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
     let secret = force_any_val::<DlogProverInput>();
     let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
     let net_address = NetworkAddress::new(
@@ -82,6 +87,7 @@ fn test_bootstrap_and_run() {
         100_000_000_u64.try_into().unwrap(),
         None,
     );
-    let _oracle_config = bootstrap(&wallet, &net_address, &mut chain);
+    let ctx = chain.state_context().unwrap();
+    let _oracle_config = bootstrap(&wallet, &net_address, &mut chain, &ctx);
     assert_eq!(chain.height, 8);
 }
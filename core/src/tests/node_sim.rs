@@ -0,0 +1,442 @@
+use std::convert::TryInto;
+
+use ergo_chain_sim::Block;
+use ergo_chain_sim::ChainSim;
+use ergo_chain_sim::NodeSim;
+use ergo_chain_sim::NodeSimServer;
+use ergo_chain_sim::DEFAULT_API_KEY;
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+use ergo_node_interface::ScanId;
+use reqwest::Url;
+use sigma_test_util::force_any_val;
+
+use crate::box_kind::PoolBox;
+use crate::cli_commands::bootstrap::perform_bootstrap_chained_transaction;
+use crate::cli_commands::bootstrap::BootstrapConfig;
+use crate::cli_commands::bootstrap::BootstrapInput;
+use crate::context::OracleContext;
+use crate::contracts::refresh::RefreshContractParameters;
+use crate::contracts::refresh::RefreshContractParametersInputs;
+use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_api::NodeApiError;
+use crate::node_interface::node_endpoints::NodeHealth;
+use crate::oracle_config::BackupNodeConfig;
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_config::OracleSecrets;
+use crate::oracle_types::BlockHeight;
+use crate::oracle_types::EpochLength;
+use crate::oracle_types::MinDatapoints;
+use crate::oracle_types::Rate;
+use crate::pool_commands::test_utils::generate_token_ids;
+use crate::pool_commands::test_utils::init_log_tests;
+use crate::pool_config::PoolConfig;
+use crate::runner::main_loop_iteration;
+use crate::running_pool::check_node_indexer;
+use crate::running_pool::RunningPool;
+use crate::running_pool::DEFAULT_POOL_NAME;
+use crate::scans::GenericTokenScan;
+use crate::scans::NodeScanRegistry;
+use crate::scans::ScanGetBoxes;
+use crate::scans::ScanRepairLog;
+use crate::spec_token::TokenIdKind;
+use crate::wallet::WalletDataSource;
+
+#[test]
+fn test_bootstrap_against_node_sim() {
+    init_log_tests();
+    let secret = force_any_val::<DlogProverInput>();
+    let net_address = NetworkAddress::new(
+        NetworkPrefix::Mainnet,
+        &Address::P2Pk(secret.public_image()),
+    );
+    let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+    chain.generate_unspent_box(
+        net_address.address().script().unwrap(),
+        100_000_000_u64.try_into().unwrap(),
+        None,
+    );
+    let height = BlockHeight(chain.height + 1);
+    let server = NodeSimServer::start(NodeSim::new(
+        chain,
+        vec![secret.into()],
+        NetworkPrefix::Mainnet,
+    ))
+    .unwrap();
+    let node_api = NodeApi::new(
+        DEFAULT_API_KEY.to_string(),
+        None,
+        &Url::parse(&server.url()).unwrap(),
+    );
+    assert!(node_api.node().wallet_status().unwrap().unlocked);
+    assert_eq!(node_api.get_change_address().unwrap(), net_address);
+
+    let (pool_config, tx_ids) = perform_bootstrap_chained_transaction(BootstrapInput {
+        oracle_address: net_address.clone(),
+        config: BootstrapConfig::default(),
+        wallet: &node_api,
+        tx_signer: node_api.node(),
+        submit_tx: node_api.node(),
+        tx_fee: SUGGESTED_TX_FEE(),
+        erg_value_per_box: SUGGESTED_TX_FEE(),
+        change_address: net_address.address(),
+        height,
+    })
+    .unwrap();
+    // every submitted tx goes into a new block
+    assert_eq!(server.node().chain.height, tx_ids.len() as u32);
+    assert_eq!(
+        node_api.node().current_block_height().unwrap(),
+        tx_ids.len() as u64
+    );
+
+    let pool_nft_token_id = pool_config.token_ids.pool_nft_token_id;
+    let pool_token_scan = GenericTokenScan::register(&node_api, &pool_nft_token_id).unwrap();
+    let pool_boxes = pool_token_scan.get_boxes(&node_api).unwrap();
+    assert_eq!(pool_boxes.len(), 1);
+    assert_eq!(
+        pool_boxes[0].tokens.as_ref().unwrap().first().token_id,
+        pool_nft_token_id.token_id()
+    );
+    assert_eq!(node_api.list_scans().unwrap().len(), 1);
+    assert!(!node_api.get_unspent_wallet_boxes().unwrap().is_empty());
+
+    let chain_height = server.node().chain.height;
+    let last_headers = node_api.get_last_headers().unwrap();
+    assert_eq!(last_headers.first().unwrap().height, chain_height);
+    let mut wallet_box_ids: Vec<String> = node_api
+        .get_unspent_wallet_boxes()
+        .unwrap()
+        .iter()
+        .map(|b| String::from(b.box_id()))
+        .collect();
+    let mut address_box_ids: Vec<String> = node_api
+        .get_unspent_boxes_by_address(&net_address)
+        .unwrap()
+        .iter()
+        .map(|b| String::from(b.box_id()))
+        .collect();
+    wallet_box_ids.sort();
+    address_box_ids.sort();
+    assert_eq!(address_box_ids, wallet_box_ids);
+
+    let pool_box_id = pool_boxes[0].box_id();
+    assert_eq!(
+        node_api.get_box_with_pool(pool_box_id).unwrap().box_id(),
+        pool_box_id
+    );
+    assert!(matches!(
+        node_api.get_box_with_pool(force_any_val::<BoxId>()),
+        Err(NodeApiError::BoxNotFound(_))
+    ));
+}
+
+/// The scans registered before a failed registration are saved and recorded, the next check
+/// registers the rest and rescans
+#[test]
+fn test_partial_scan_repair() {
+    init_log_tests();
+    let server = NodeSimServer::start(NodeSim::new(
+        ChainSim::new(),
+        vec![],
+        NetworkPrefix::Mainnet,
+    ))
+    .unwrap();
+    server.node().max_scans = Some(2);
+    let node_api = NodeApi::new(
+        DEFAULT_API_KEY.to_string(),
+        None,
+        &Url::parse(&server.url()).unwrap(),
+    );
+    let pool_config = PoolConfig::create(BootstrapConfig::default(), generate_token_ids()).unwrap();
+    // none of the scans is registered on the node
+    let registry = NodeScanRegistry {
+        oracle_token_scan: GenericTokenScan::new(ScanId::from(1)),
+        pool_token_scan: GenericTokenScan::new(ScanId::from(2)),
+        ballot_token_scan: GenericTokenScan::new(ScanId::from(3)),
+        refresh_token_scan: GenericTokenScan::new(ScanId::from(4)),
+        update_token_scan: GenericTokenScan::new(ScanId::from(5)),
+        buyback_token_scan: None,
+    };
+    let dir = std::env::temp_dir().join(format!("oracle-core-scan-repair-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scans_file_path = dir.join("scanIDs.json");
+    let repair_log = ScanRepairLog::new(dir.join("scan_repairs.log"));
+
+    assert!(registry
+        .verify_and_repair(&node_api, &pool_config, &scans_file_path, 0, &repair_log)
+        .is_err());
+    let saved = NodeScanRegistry::load_from_file(&scans_file_path).unwrap();
+    assert_ne!(saved.oracle_token_scan, registry.oracle_token_scan);
+    assert_ne!(saved.pool_token_scan, registry.pool_token_scan);
+    assert_eq!(saved.ballot_token_scan, registry.ballot_token_scan);
+    assert_eq!(repair_log.load().unwrap().len(), 2);
+    assert_eq!(server.node().rescan_from_height(), None);
+
+    server.node().max_scans = None;
+    let repaired = saved
+        .verify_and_repair(&node_api, &pool_config, &scans_file_path, 0, &repair_log)
+        .unwrap()
+        .unwrap();
+    assert_eq!(repaired.oracle_token_scan, saved.oracle_token_scan);
+    assert_eq!(repaired.pool_token_scan, saved.pool_token_scan);
+    assert_ne!(repaired.ballot_token_scan, registry.ballot_token_scan);
+    assert_eq!(
+        NodeScanRegistry::load_from_file(&scans_file_path).unwrap(),
+        repaired
+    );
+    assert_eq!(repair_log.load().unwrap().len(), 5);
+    assert_eq!(server.node().rescan_from_height(), Some(0));
+    assert_eq!(node_api.list_scans().unwrap().len(), 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The extra index is checked on the backup nodes too, a node without it would serve no pool
+/// boxes after a failover
+#[test]
+fn test_node_indexer_is_checked_on_every_node() {
+    init_log_tests();
+    let start_node = || {
+        NodeSimServer::start(NodeSim::new(
+            ChainSim::new(),
+            vec![],
+            NetworkPrefix::Mainnet,
+        ))
+        .unwrap()
+    };
+    let primary = start_node();
+    let backup = start_node();
+    backup.node().extra_index = false;
+    let ctx = OracleContext::new(
+        OracleConfig {
+            node_url: Some(Url::parse(&primary.url()).unwrap()),
+            backup_nodes: vec![BackupNodeConfig {
+                url: Url::parse(&backup.url()).unwrap(),
+            }],
+            box_source: BoxSourceConfig::NodeIndexer,
+            ..OracleConfig::default()
+        },
+        OracleSecrets {
+            node_api_key: Some(DEFAULT_API_KEY.to_string()),
+            wallet_password: None,
+            wallet_mnemonic: None,
+            wallet_mnemonic_password: None,
+            wallet_secret_file_password: None,
+            backup_node_api_keys: vec![DEFAULT_API_KEY.to_string()],
+        },
+        std::env::temp_dir(),
+    );
+    let error = check_node_indexer(&ctx.node_api()).unwrap_err();
+    assert!(format!("{:?}", error).contains(&backup.url()), "{error:?}");
+
+    backup.node().extra_index = true;
+    check_node_indexer(&ctx.node_api()).unwrap();
+}
+
+#[test]
+fn test_reads_fail_over_from_stopped_primary() {
+    init_log_tests();
+    let start_node = || {
+        let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+        while chain.height < 20 {
+            chain.add_block(Block::new(vec![])).unwrap();
+        }
+        NodeSimServer::start(NodeSim::new(chain, vec![], NetworkPrefix::Mainnet)).unwrap()
+    };
+    let primary = start_node();
+    let backup = start_node();
+    let primary_url = Url::parse(&primary.url()).unwrap();
+    drop(primary);
+    let ctx = OracleContext::new(
+        OracleConfig {
+            node_url: Some(primary_url),
+            backup_nodes: vec![BackupNodeConfig {
+                url: Url::parse(&backup.url()).unwrap(),
+            }],
+            box_source: BoxSourceConfig::NodeIndexer,
+            ..OracleConfig::default()
+        },
+        OracleSecrets {
+            node_api_key: Some(DEFAULT_API_KEY.to_string()),
+            wallet_password: None,
+            wallet_mnemonic: None,
+            wallet_mnemonic_password: None,
+            wallet_secret_file_password: None,
+            backup_node_api_keys: vec![DEFAULT_API_KEY.to_string()],
+        },
+        std::env::temp_dir(),
+    );
+    let node_api = ctx.node_api();
+    assert_eq!(node_api.endpoints().active_index(), 0);
+    // the pinned client doesn't fail over
+    assert!(node_api
+        .pinned_to(0)
+        .get_last_headers()
+        .unwrap_err()
+        .is_node_failure());
+
+    // the read is retried on the backup node, which the requests go to from now on
+    let headers = node_api.get_last_headers().unwrap();
+    assert_eq!(headers.first().unwrap().height, 20);
+    assert_eq!(node_api.endpoints().active_index(), 1);
+    assert!(matches!(
+        node_api.endpoints().health()[0],
+        NodeHealth::Unreachable(_)
+    ));
+    assert_eq!(node_api.indexed_height().unwrap(), 20);
+}
+
+/// Refresh contract of a pool run by a single oracle with short epochs
+#[cfg(unix)]
+fn single_oracle_refresh_contract_parameters(
+    epoch_length: EpochLength,
+) -> RefreshContractParameters {
+    let parameters = RefreshContractParameters::default();
+    RefreshContractParameters::build_with(RefreshContractParametersInputs {
+        ergo_tree_bytes: parameters.ergo_tree_bytes(),
+        pool_nft_index: parameters.pool_nft_index(),
+        oracle_token_id_index: parameters.oracle_token_id_index(),
+        min_data_points_index: parameters.min_data_points_index(),
+        min_data_points: MinDatapoints(1),
+        buffer_length_index: parameters.buffer_length_index(),
+        buffer_length: parameters.buffer_length(),
+        max_deviation_percent_index: parameters.max_deviation_percent_index(),
+        max_deviation_percent: parameters.max_deviation_percent(),
+        epoch_length_index: parameters.epoch_length_index(),
+        epoch_length,
+    })
+    .unwrap()
+}
+
+/// Runs the main loop iterations of an oracle holding its key in the node wallet, a block is
+/// mined after each iteration. The datapoint comes from a script (see
+/// `data_point_source_custom_script`).
+#[cfg(unix)]
+#[test]
+fn test_main_loop_against_node_sim() {
+    use std::os::unix::fs::PermissionsExt;
+
+    const RATE: i64 = 123_456_789;
+    init_log_tests();
+    let epoch_length = EpochLength(5);
+    let secret = force_any_val::<DlogProverInput>();
+    let oracle_public_key = *secret.public_image().h;
+    let net_address = NetworkAddress::new(
+        NetworkPrefix::Mainnet,
+        &Address::P2Pk(secret.public_image()),
+    );
+    let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+    while chain.height < 100 {
+        chain.add_block(Block::new(vec![])).unwrap();
+    }
+    chain.generate_unspent_box(
+        net_address.address().script().unwrap(),
+        100_000_000_000_u64.try_into().unwrap(),
+        None,
+    );
+    let height = BlockHeight(chain.height + 1);
+    let server = NodeSimServer::start(NodeSim::new(
+        chain,
+        vec![secret.into()],
+        NetworkPrefix::Mainnet,
+    ))
+    .unwrap();
+    let node_url = Url::parse(&server.url()).unwrap();
+    let bootstrap_node_api = NodeApi::new(DEFAULT_API_KEY.to_string(), None, &node_url);
+    let (mut pool_config, _) = perform_bootstrap_chained_transaction(BootstrapInput {
+        oracle_address: net_address.clone(),
+        config: BootstrapConfig {
+            refresh_contract_parameters: single_oracle_refresh_contract_parameters(epoch_length),
+            ..BootstrapConfig::default()
+        },
+        wallet: &bootstrap_node_api,
+        tx_signer: bootstrap_node_api.node(),
+        submit_tx: bootstrap_node_api.node(),
+        tx_fee: SUGGESTED_TX_FEE(),
+        erg_value_per_box: SUGGESTED_TX_FEE(),
+        change_address: net_address.address(),
+        height,
+    })
+    .unwrap();
+
+    let data_dir =
+        std::env::temp_dir().join(format!("oracle-core-main-loop-{}", std::process::id()));
+    std::fs::create_dir_all(&data_dir).unwrap();
+    let script_path = data_dir.join("datapoint.sh");
+    // the output is parsed as is, without a trailing newline
+    std::fs::write(&script_path, format!("#!/bin/sh\nprintf {RATE}\n")).unwrap();
+    std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    pool_config.data_point_source_custom_script = Some(script_path.display().to_string());
+    let ctx = OracleContext::new(
+        OracleConfig {
+            node_url: Some(node_url),
+            oracle_address: net_address.clone(),
+            box_source: BoxSourceConfig::NodeIndexer,
+            ..OracleConfig::default()
+        },
+        OracleSecrets {
+            node_api_key: Some(DEFAULT_API_KEY.to_string()),
+            wallet_password: None,
+            wallet_mnemonic: None,
+            wallet_mnemonic_password: None,
+            wallet_secret_file_password: None,
+            backup_node_api_keys: vec![],
+        },
+        data_dir.clone(),
+    );
+    let pool =
+        RunningPool::start(&ctx, DEFAULT_POOL_NAME.to_string(), &pool_config, false).unwrap();
+    let node_api = ctx.node_api();
+    let wallet = ctx.wallet().unwrap();
+    let change_address = ctx.change_address(&wallet).unwrap();
+    let oracle_pool = pool.oracle_pool.clone();
+    oracle_pool.current_height().unwrap();
+    let bootstrapped_pool_box = oracle_pool.get_pool_box_source().get_pool_box().unwrap();
+    assert_ne!(bootstrapped_pool_box.rate(), Rate::from(RATE));
+
+    let mut submitted_txs = 0;
+    for _ in 0..3 * (epoch_length.0 as u32 + 1) {
+        let submitted = main_loop_iteration(
+            &ctx,
+            &pool,
+            false,
+            &node_api,
+            &wallet,
+            &wallet,
+            &change_address,
+            None,
+            None,
+        )
+        .unwrap();
+        if submitted {
+            submitted_txs += 1;
+        }
+        server.node().chain.mine_block().unwrap();
+    }
+    assert!(submitted_txs >= 2);
+
+    // the datapoint of the oracle was collected by its refresh
+    let refresh_report = pool
+        .action_report_storage
+        .read()
+        .unwrap()
+        .get_last_refresh_report()
+        .cloned()
+        .unwrap();
+    assert_eq!(refresh_report.rate, Rate::from(RATE));
+    assert_eq!(
+        refresh_report.oracle_boxes_collected,
+        vec![oracle_public_key]
+    );
+    oracle_pool.current_height().unwrap();
+    let pool_box = oracle_pool.get_pool_box_source().get_pool_box().unwrap();
+    assert_eq!(pool_box.rate(), Rate::from(RATE));
+    assert!(pool_box.epoch_counter().0 > bootstrapped_pool_box.epoch_counter().0);
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
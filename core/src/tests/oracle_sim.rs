@@ -0,0 +1,808 @@
+//! Runs a pool bootstrapped in `ChainSim` with a number of simulated oracles over many blocks.
+//! Each oracle has its own key and datapoint source and goes through `state::process` and
+//! `build_action` on every block, like the main loop does.
+
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use ergo_chain_sim::Block;
+use ergo_chain_sim::ChainSim;
+use ergo_chain_sim::ChainSimError;
+use ergo_chain_sim::NodeSim;
+use ergo_chain_sim::NodeSimServer;
+use ergo_chain_sim::TxValidationError;
+use ergo_chain_sim::DEFAULT_API_KEY;
+use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::ergotree_ir::chain::token::Token;
+use ergo_lib::wallet::box_selector::BoxSelector;
+use ergo_lib::wallet::box_selector::SimpleBoxSelector;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::tx_builder::TxBuilder;
+use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+use ergo_lib::wallet::Wallet;
+use reqwest::Url;
+use sigma_test_util::force_any_val;
+
+use crate::box_kind::OracleBox;
+use crate::box_kind::PoolBox;
+use crate::box_kind::RefreshBoxWrapperInputs;
+use crate::cli_commands::replay::replay_iteration;
+use crate::cli_commands::verify_pool::verify_pool;
+use crate::cli_commands::verify_pool::verify_pool_boxes;
+use crate::context::OracleContext;
+use crate::contracts::refresh::RefreshContractParameters;
+use crate::contracts::refresh::RefreshContractParametersInputs;
+use crate::datapoint_source::DataPointSource;
+use crate::datapoint_source::DataPointSourceError;
+use crate::node_interface::node_api::NodeApi;
+use crate::oracle_config::BoxSourceConfig;
+use crate::oracle_config::OracleConfig;
+use crate::oracle_config::OracleSecrets;
+use crate::oracle_state::OraclePool;
+use crate::oracle_types::EpochCounter;
+use crate::oracle_types::EpochLength;
+use crate::oracle_types::Rate;
+use crate::pool_commands::build_action;
+use crate::pool_commands::test_utils::find_input_boxes;
+use crate::pool_commands::test_utils::init_log_tests;
+use crate::pool_commands::test_utils::WalletDataMock;
+use crate::pool_commands::PoolCommand;
+use crate::pool_config::PoolConfig;
+use crate::recording::IterationRecord;
+use crate::recording::RecordingDataPointSource;
+use crate::scans::PoolTokenBoxes;
+use crate::spec_token::RewardTokenId;
+use crate::spec_token::TokenIdKind;
+use crate::state::process;
+use crate::state::PoolState;
+
+use super::bootstrap_and_run::bootstrap;
+
+/// Height the pool is bootstrapped after, the epoch start heights are computed from it
+const START_HEIGHT: u32 = 100;
+const NANO_ERGS_PER_ORACLE: u64 = 1_000_000_000;
+const BASE_RATE: i64 = 100_000_000;
+
+/// Posts the given rate, fails if there is none
+struct FixedRate(Option<Rate>);
+
+impl DataPointSource for FixedRate {
+    fn get_datapoint(&self) -> Result<Rate, DataPointSourceError> {
+        self.0.ok_or(DataPointSourceError::NoDataPoints)
+    }
+}
+
+/// How a simulated oracle takes part in the pool
+struct SimOracleConfig {
+    datapoint_source: Box<dyn DataPointSource>,
+    /// An offline oracle never acts
+    online: bool,
+    /// Acts only when the pool box is at least this many blocks old
+    delay: u32,
+}
+
+impl SimOracleConfig {
+    /// Posts the base rate off by the given per mille
+    fn honest(per_mille_off: i64) -> Self {
+        SimOracleConfig {
+            datapoint_source: Box::new(FixedRate(Some(
+                (BASE_RATE * (1000 + per_mille_off) / 1000).into(),
+            ))),
+            online: true,
+            delay: 0,
+        }
+    }
+
+    fn offline() -> Self {
+        SimOracleConfig {
+            online: false,
+            ..SimOracleConfig::honest(0)
+        }
+    }
+
+    fn failing_source() -> Self {
+        SimOracleConfig {
+            datapoint_source: Box::new(FixedRate(None)),
+            ..SimOracleConfig::honest(0)
+        }
+    }
+
+    fn late(delay: u32) -> Self {
+        SimOracleConfig {
+            delay,
+            ..SimOracleConfig::honest(0)
+        }
+    }
+}
+
+struct SimOracle {
+    address: NetworkAddress,
+    wallet: Wallet,
+    ctx: OracleContext,
+    pool: OraclePool,
+    config: SimOracleConfig,
+}
+
+impl SimOracle {
+    /// The signed tx for the action the oracle takes at the current height if any
+    fn act(
+        &self,
+        server: &NodeSimServer,
+        epoch_length: EpochLength,
+        state_context: &ErgoStateContext,
+    ) -> Result<Option<Transaction>, String> {
+        if !self.config.online {
+            return Ok(None);
+        }
+        let height = self.pool.current_height().unwrap();
+        let live_epoch = self.pool.get_live_epoch_state().unwrap();
+        if height.0 < live_epoch.latest_pool_box_height.0 + self.config.delay {
+            return Ok(None);
+        }
+        match process(PoolState::LiveEpoch(live_epoch), epoch_length, height) {
+            Some(cmd) => self.sign_action(server, cmd, state_context).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Build and sign the tx for the command at the current height
+    fn sign_action(
+        &self,
+        server: &NodeSimServer,
+        cmd: PoolCommand,
+        state_context: &ErgoStateContext,
+    ) -> Result<Transaction, String> {
+        let height = self.pool.current_height().unwrap();
+        let wallet = WalletDataMock {
+            unspent_boxes: server
+                .node()
+                .chain
+                .get_unspent_boxes(&self.address.address().script().unwrap()),
+            change_address: self.address.clone(),
+        };
+        let (action, _) = build_action(
+            &self.ctx,
+            cmd,
+            &self.pool,
+            &wallet,
+            height,
+            self.address.address(),
+            self.config.datapoint_source.as_ref(),
+        )
+        .map_err(|e| e.to_string())?;
+        let unsigned_tx = action.tx().clone();
+        let input_boxes = find_input_boxes(
+            unsigned_tx.clone(),
+            server.node().chain.unspent_boxes().to_vec(),
+        );
+        let tx = self
+            .wallet
+            .sign_transaction(
+                TransactionContext::new(unsigned_tx, input_boxes, vec![]).unwrap(),
+                state_context,
+                None,
+            )
+            .unwrap();
+        Ok(tx)
+    }
+
+    /// What the main loop would record for the oracle at the current height
+    fn record_iteration(
+        &self,
+        server: &NodeSimServer,
+        epoch_length: EpochLength,
+    ) -> IterationRecord {
+        let height = self.pool.current_height().unwrap();
+        let wallet = WalletDataMock {
+            unspent_boxes: server
+                .node()
+                .chain
+                .get_unspent_boxes(&self.address.address().script().unwrap()),
+            change_address: self.address.clone(),
+        };
+        let mut record = IterationRecord::new(
+            "default",
+            self.ctx.oracle_config.box_source,
+            self.pool.box_snapshot(height).unwrap(),
+            wallet.unspent_boxes.clone(),
+            &self.address,
+        );
+        let live_epoch = self.pool.get_live_epoch_state().unwrap();
+        record.command = process(PoolState::LiveEpoch(live_epoch), epoch_length, height);
+        if let Some(cmd) = record.command {
+            let datapoint_source =
+                RecordingDataPointSource::new(self.config.datapoint_source.as_ref());
+            match build_action(
+                &self.ctx,
+                cmd,
+                &self.pool,
+                &wallet,
+                height,
+                self.address.address(),
+                &datapoint_source,
+            ) {
+                Ok((action, _)) => record.tx = Some(action.tx().clone()),
+                Err(e) => record.build_error = Some(e.to_string()),
+            }
+            record.datapoint = datapoint_source.last_datapoint();
+        }
+        record
+    }
+
+    /// Reward tokens in the oracle box, `None` if the oracle has not posted any datapoint yet
+    fn reward_tokens(&self) -> Option<u64> {
+        self.pool.current_height().unwrap();
+        self.pool
+            .get_local_datapoint_box_source()
+            .get_local_oracle_datapoint_box()
+            .unwrap()
+            .map(|oracle_box| *oracle_box.reward_token().amount.as_u64())
+    }
+}
+
+/// A pool box seen on the chain
+#[derive(Debug, Clone)]
+struct SimPoolBox {
+    creation_height: u32,
+    epoch_counter: EpochCounter,
+    rate: Rate,
+    reward_tokens: u64,
+}
+
+struct PoolSim {
+    server: NodeSimServer,
+    epoch_length: EpochLength,
+    oracles: Vec<SimOracle>,
+    /// The bootstrapped pool box followed by the refreshed ones
+    pool_boxes: Vec<SimPoolBox>,
+    /// Oracle index with the error of the actions that could not be built
+    build_errors: Vec<(usize, String)>,
+    /// Txs the chain rejected as invalid
+    failed_txs: Vec<ChainSimError>,
+    /// Txs spending the boxes already spent by a mempool tx (e.g. concurrent refreshes), the
+    /// node drops them as a double spending attempt
+    conflicting_txs: usize,
+}
+
+impl PoolSim {
+    /// Bootstrap the pool and send each oracle an oracle token, a reward token and the ERGs for
+    /// the tx fees
+    fn bootstrap(configs: Vec<SimOracleConfig>) -> PoolSim {
+        init_log_tests();
+        let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+        while chain.height < START_HEIGHT {
+            chain.add_block(Block::new(vec![])).unwrap();
+        }
+        let secret = force_any_val::<DlogProverInput>();
+        let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+        let net_address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &Address::P2Pk(secret.public_image()),
+        );
+        chain.generate_unspent_box(
+            net_address.address().script().unwrap(),
+            100_000_000_000_u64.try_into().unwrap(),
+            None,
+        );
+        let state_context = chain.state_context().unwrap();
+        let pool_config = bootstrap(&wallet, &net_address, &mut chain, &state_context);
+
+        let oracle_secrets: Vec<DlogProverInput> = configs
+            .iter()
+            .map(|_| force_any_val::<DlogProverInput>())
+            .collect();
+        let oracle_addresses: Vec<NetworkAddress> = oracle_secrets
+            .iter()
+            .map(|secret| {
+                NetworkAddress::new(
+                    NetworkPrefix::Mainnet,
+                    &Address::P2Pk(secret.public_image()),
+                )
+            })
+            .collect();
+        let state_context = chain.state_context().unwrap();
+        let funding_tx = fund_oracles(
+            &chain,
+            &wallet,
+            &net_address,
+            &pool_config,
+            &oracle_addresses,
+            &state_context,
+        );
+        chain.add_block(Block::new(vec![funding_tx])).unwrap();
+
+        let server = NodeSimServer::start(
+            NodeSim::new(chain, vec![secret.into()], NetworkPrefix::Mainnet).with_manual_mining(),
+        )
+        .unwrap();
+        let node_api = Arc::new(NodeApi::new(
+            DEFAULT_API_KEY.to_string(),
+            None,
+            &Url::parse(&server.url()).unwrap(),
+        ));
+        let token_boxes = PoolTokenBoxes::from_indexer(&pool_config);
+        let oracles = configs
+            .into_iter()
+            .zip(oracle_secrets)
+            .zip(oracle_addresses)
+            .map(|((config, secret), address)| {
+                let pool = OraclePool::new(
+                    node_api.clone(),
+                    &token_boxes,
+                    &pool_config,
+                    secret.public_image(),
+                )
+                .unwrap();
+                let ctx = OracleContext::new(
+                    OracleConfig {
+                        oracle_address: address.clone(),
+                        ..OracleConfig::default()
+                    },
+                    OracleSecrets {
+                        node_api_key: Some(DEFAULT_API_KEY.to_string()),
+                        wallet_password: None,
+                        wallet_mnemonic: None,
+                        wallet_mnemonic_password: None,
+                        wallet_secret_file_password: None,
+                        backup_node_api_keys: vec![],
+                    },
+                    std::env::temp_dir(),
+                );
+                SimOracle {
+                    address,
+                    wallet: Wallet::from_secrets(vec![secret.into()]),
+                    ctx,
+                    pool,
+                    config,
+                }
+            })
+            .collect();
+        let epoch_length = pool_config
+            .refresh_box_wrapper_inputs
+            .contract_inputs
+            .contract_parameters()
+            .epoch_length();
+        let mut sim = PoolSim {
+            server,
+            epoch_length,
+            oracles,
+            pool_boxes: vec![],
+            build_errors: vec![],
+            failed_txs: vec![],
+            conflicting_txs: 0,
+        };
+        sim.record_pool_box();
+        sim
+    }
+
+    /// Let every oracle act on each block, a different oracle goes first in each block so they
+    /// take turns in collecting the datapoints
+    fn run(&mut self, blocks: u32) {
+        for _ in 0..blocks {
+            let (height, state_context) = {
+                let node = self.server.node();
+                (node.chain.height, node.chain.state_context().unwrap())
+            };
+            let oracle_count = self.oracles.len();
+            let mut txs = vec![];
+            for i in 0..oracle_count {
+                let oracle_index = (height as usize + i) % oracle_count;
+                match self.oracles[oracle_index].act(
+                    &self.server,
+                    self.epoch_length,
+                    &state_context,
+                ) {
+                    Ok(Some(tx)) => txs.push(tx),
+                    Ok(None) => (),
+                    Err(e) => {
+                        log::info!("Height {height}. Oracle {oracle_index}: {e}");
+                        self.build_errors.push((oracle_index, e));
+                    }
+                }
+            }
+            for tx in txs {
+                self.submit(tx);
+            }
+            self.server.node().chain.mine_block().unwrap();
+            self.record_pool_box();
+        }
+    }
+
+    fn submit(&mut self, tx: Transaction) {
+        match self.server.node().chain.submit_tx(tx) {
+            Ok(()) => (),
+            Err(ChainSimError::MempoolConflict(..)) => self.conflicting_txs += 1,
+            Err(e @ (ChainSimError::InvalidTx(..) | ChainSimError::RollbackAboveHeight(..))) => {
+                log::error!("{e}");
+                self.failed_txs.push(e);
+            }
+        }
+    }
+
+    fn record_pool_box(&mut self) {
+        let pool = &self.oracles[0].pool;
+        pool.current_height().unwrap();
+        let pool_box = pool.get_pool_box_source().get_pool_box().unwrap();
+        let creation_height = pool_box.get_box().creation_height;
+        if self.pool_boxes.last().map(|b| b.creation_height) != Some(creation_height) {
+            self.pool_boxes.push(SimPoolBox {
+                creation_height,
+                epoch_counter: pool_box.epoch_counter(),
+                rate: pool_box.rate(),
+                reward_tokens: *pool_box.reward_token().amount.as_u64(),
+            });
+        }
+    }
+
+    fn refresh_count(&self) -> usize {
+        self.pool_boxes.len() - 1
+    }
+
+    fn rewards(&self) -> Vec<Option<u64>> {
+        self.oracles.iter().map(|o| o.reward_tokens()).collect()
+    }
+
+    /// The pool box is refreshed in every epoch up to the current height
+    fn assert_refreshed_every_epoch(&self) {
+        let max_gap = self.epoch_length.0 as u32 + 1;
+        for (previous, next) in self.pool_boxes.iter().zip(self.pool_boxes.iter().skip(1)) {
+            assert!(
+                next.creation_height - previous.creation_height <= max_gap,
+                "no refresh between heights {} and {}",
+                previous.creation_height,
+                next.creation_height
+            );
+            assert_eq!(next.epoch_counter.0, previous.epoch_counter.0 + 1);
+        }
+        let height = self.server.node().chain.height;
+        let last_refresh_height = self.pool_boxes.last().unwrap().creation_height;
+        assert!(
+            height - last_refresh_height <= max_gap,
+            "no refresh since height {last_refresh_height}, current height {height}"
+        );
+    }
+
+    /// The reward tokens taken from the pool box are in the oracle boxes, each oracle got one for
+    /// its first datapoint box from the bootstrap wallet
+    fn assert_rewards_conserved(&self) {
+        let distributed: u64 = self
+            .rewards()
+            .into_iter()
+            .flatten()
+            .map(|reward_tokens| reward_tokens - 1)
+            .sum();
+        let first_pool_box = self.pool_boxes.first().unwrap();
+        let last_pool_box = self.pool_boxes.last().unwrap();
+        assert_eq!(
+            first_pool_box.reward_tokens - last_pool_box.reward_tokens,
+            distributed
+        );
+    }
+
+    fn assert_no_failed_txs(&self) {
+        assert!(self.failed_txs.is_empty(), "{:?}", self.failed_txs);
+    }
+
+    fn assert_no_build_errors(&self, oracle_index: usize) {
+        let errors: Vec<&String> = self
+            .build_errors
+            .iter()
+            .filter(|(i, _)| *i == oracle_index)
+            .map(|(_, e)| e)
+            .collect();
+        assert!(errors.is_empty(), "oracle {oracle_index}: {errors:?}");
+    }
+
+    fn epoch_blocks(&self, epochs: u32) -> u32 {
+        epochs * (self.epoch_length.0 as u32 + 1)
+    }
+}
+
+/// The tx sending each oracle an oracle token and a reward token (for the first datapoint box)
+/// with ERGs for the tx fees
+fn fund_oracles(
+    chain: &ChainSim,
+    wallet: &Wallet,
+    net_address: &NetworkAddress,
+    pool_config: &PoolConfig,
+    oracle_addresses: &[NetworkAddress],
+    state_context: &ErgoStateContext,
+) -> Transaction {
+    let height = chain.height;
+    let oracle_count = oracle_addresses.len() as u64;
+    let oracle_token = Token {
+        token_id: pool_config.token_ids.oracle_token_id.token_id(),
+        amount: 1u64.try_into().unwrap(),
+    };
+    let reward_token = Token {
+        token_id: pool_config.token_ids.reward_token_id.token_id(),
+        amount: 1u64.try_into().unwrap(),
+    };
+    let output_candidates = oracle_addresses
+        .iter()
+        .map(|address| {
+            let mut builder = ErgoBoxCandidateBuilder::new(
+                NANO_ERGS_PER_ORACLE.try_into().unwrap(),
+                address.address().script().unwrap(),
+                height,
+            );
+            builder.add_token(oracle_token.clone());
+            builder.add_token(reward_token.clone());
+            builder.build().unwrap()
+        })
+        .collect();
+    let target_balance: BoxValue = (NANO_ERGS_PER_ORACLE * oracle_count
+        + *SUGGESTED_TX_FEE().as_u64())
+    .try_into()
+    .unwrap();
+    let box_selection = SimpleBoxSelector::new()
+        .select(
+            chain.get_unspent_boxes(&net_address.address().script().unwrap()),
+            target_balance,
+            &[
+                Token {
+                    amount: oracle_count.try_into().unwrap(),
+                    ..oracle_token
+                },
+                Token {
+                    amount: oracle_count.try_into().unwrap(),
+                    ..reward_token
+                },
+            ],
+        )
+        .unwrap();
+    let input_boxes = box_selection.boxes.as_vec().clone();
+    let unsigned_tx = TxBuilder::new(
+        box_selection,
+        output_candidates,
+        height,
+        SUGGESTED_TX_FEE(),
+        net_address.address(),
+    )
+    .build()
+    .unwrap();
+    wallet
+        .sign_transaction(
+            TransactionContext::new(unsigned_tx, input_boxes, vec![]).unwrap(),
+            state_context,
+            None,
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_honest_oracles() {
+    let mut sim = PoolSim::bootstrap((0..4).map(SimOracleConfig::honest).collect());
+    sim.run(sim.epoch_blocks(4));
+    sim.assert_no_failed_txs();
+    sim.assert_refreshed_every_epoch();
+    assert!(sim.refresh_count() >= 3);
+    sim.assert_rewards_conserved();
+    let refresh_count = sim.refresh_count() as u64;
+    for (i, reward_tokens) in sim.rewards().into_iter().enumerate() {
+        sim.assert_no_build_errors(i);
+        // one per refresh on top of the one from the first datapoint box
+        assert!(reward_tokens.unwrap() > refresh_count);
+    }
+    // the collector gets a reward for each collected datapoint as well
+    let rewards: Vec<u64> = sim.rewards().into_iter().flatten().collect();
+    assert!(rewards.iter().max() > rewards.iter().min());
+}
+
+#[test]
+fn test_offline_oracle_and_failing_datapoint_source() {
+    let mut sim = PoolSim::bootstrap(vec![
+        SimOracleConfig::honest(0),
+        SimOracleConfig::offline(),
+        SimOracleConfig::honest(5),
+        SimOracleConfig::failing_source(),
+        SimOracleConfig::honest(10),
+        SimOracleConfig::honest(15),
+    ]);
+    sim.run(sim.epoch_blocks(3));
+    sim.assert_no_failed_txs();
+    sim.assert_refreshed_every_epoch();
+    sim.assert_rewards_conserved();
+    let rewards = sim.rewards();
+    assert_eq!(rewards[1], None);
+    assert_eq!(rewards[3], None);
+    assert!(sim.build_errors.iter().any(|(i, _)| *i == 3));
+    for i in [0, 2, 4, 5] {
+        sim.assert_no_build_errors(i);
+        assert!(rewards[i].unwrap() > sim.refresh_count() as u64);
+    }
+}
+
+#[test]
+fn test_outlier_is_not_rewarded() {
+    let mut sim = PoolSim::bootstrap(vec![
+        SimOracleConfig::honest(0),
+        SimOracleConfig::honest(5),
+        SimOracleConfig::honest(200),
+        SimOracleConfig::honest(10),
+        SimOracleConfig::honest(15),
+    ]);
+    sim.run(sim.epoch_blocks(3));
+    sim.assert_no_failed_txs();
+    sim.assert_refreshed_every_epoch();
+    sim.assert_rewards_conserved();
+    let rewards = sim.rewards();
+    // only the reward token of its first datapoint box
+    assert_eq!(rewards[2], Some(1));
+    for i in [0, 1, 3, 4] {
+        assert!(rewards[i].unwrap() > sim.refresh_count() as u64);
+    }
+    // the rate is the average of the honest datapoints
+    let honest_rates = BASE_RATE..=BASE_RATE * 1015 / 1000;
+    for pool_box in sim.pool_boxes.iter().skip(1) {
+        assert!(honest_rates.contains(&i64::from(pool_box.rate)));
+    }
+}
+
+#[test]
+fn test_late_publisher_is_collected() {
+    let mut sim = PoolSim::bootstrap(vec![
+        SimOracleConfig::honest(0),
+        SimOracleConfig::honest(5),
+        SimOracleConfig::honest(10),
+        // posts right before the epoch ends
+        SimOracleConfig::late(25),
+    ]);
+    sim.run(sim.epoch_blocks(3));
+    sim.assert_no_failed_txs();
+    sim.assert_refreshed_every_epoch();
+    sim.assert_rewards_conserved();
+    for (i, reward_tokens) in sim.rewards().into_iter().enumerate() {
+        sim.assert_no_build_errors(i);
+        assert!(reward_tokens.unwrap() > sim.refresh_count() as u64);
+    }
+}
+
+#[test]
+fn test_no_refresh_without_enough_oracles() {
+    let mut sim = PoolSim::bootstrap(vec![
+        SimOracleConfig::honest(0),
+        SimOracleConfig::honest(5),
+        SimOracleConfig::offline(),
+        SimOracleConfig::honest(10),
+    ]);
+    sim.run(sim.epoch_blocks(2));
+    sim.assert_no_failed_txs();
+    assert_eq!(sim.refresh_count(), 0);
+    assert_eq!(sim.rewards(), vec![Some(1), Some(1), None, Some(1)]);
+    // the refresh is attempted and fails on the number of datapoints
+    assert!(!sim.build_errors.is_empty());
+}
+
+#[test]
+fn test_refresh_before_epoch_end_is_rejected() {
+    let mut sim = PoolSim::bootstrap((0..4).map(SimOracleConfig::honest).collect());
+    // the first datapoints are posted in the first block
+    sim.run(2);
+    assert_eq!(sim.refresh_count(), 0);
+    let state_context = sim.server.node().chain.state_context().unwrap();
+    let tx = sim.oracles[0]
+        .sign_action(&sim.server, PoolCommand::Refresh, &state_context)
+        .unwrap();
+    // the refresh contract checks the pool box is older than the epoch length
+    let error = sim.server.node().chain.submit_tx(tx).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            ChainSimError::InvalidTx(
+                _,
+                TxValidationError::ScriptFalse(_) | TxValidationError::ScriptError(..)
+            )
+        ),
+        "{error}"
+    );
+}
+
+#[test]
+fn test_recorded_iterations_replay_to_the_same_decisions() {
+    let mut sim = PoolSim::bootstrap((0..4).map(SimOracleConfig::honest).collect());
+    let mut records = vec![];
+    for _ in 0..sim.epoch_blocks(2) {
+        records.push(sim.oracles[0].record_iteration(&sim.server, sim.epoch_length));
+        sim.run(1);
+    }
+    assert!(records.iter().any(|record| record.tx.is_some()));
+    assert!(records.iter().any(|record| record.command.is_none()));
+    let oracle = &sim.oracles[0];
+    for record in &records {
+        let replayed = replay_iteration(&oracle.ctx, oracle.pool.pool_config(), record).unwrap();
+        assert!(replayed.is_same_decision(), "{replayed:?}");
+        assert_eq!(replayed.height, record.height());
+    }
+
+    // an oracle watching the pool through the explorer builds no txs, whatever the box source
+    // of the replaying oracle is
+    let mut watching = records
+        .iter()
+        .find(|record| record.command.is_some())
+        .unwrap()
+        .clone();
+    watching.box_source = Some(BoxSourceConfig::Explorer);
+    let replayed = replay_iteration(&oracle.ctx, oracle.pool.pool_config(), &watching).unwrap();
+    assert_eq!(replayed.command, None);
+}
+
+#[test]
+fn test_verify_pool() {
+    let mut sim = PoolSim::bootstrap((0..4).map(SimOracleConfig::honest).collect());
+    sim.run(2);
+    let pool = &sim.oracles[0].pool;
+    let report = verify_pool(pool).unwrap();
+    assert!(report.passed(), "{report}");
+    assert!(report
+        .checks
+        .iter()
+        .any(|check| check.name.starts_with("oracle boxes (4 in contracts")));
+    assert!(report
+        .checks
+        .iter()
+        .any(|check| check.name == "oracle tokens: total 15 minted"));
+
+    let snapshot = pool.box_snapshot(pool.current_height().unwrap()).unwrap();
+    let mut pool_config = pool.pool_config().clone();
+    let token_ids = pool_config.token_ids.clone();
+    let parameters = pool_config
+        .refresh_box_wrapper_inputs
+        .contract_inputs
+        .contract_parameters()
+        .clone();
+    let other_parameters = RefreshContractParameters::build_with(RefreshContractParametersInputs {
+        ergo_tree_bytes: parameters.ergo_tree_bytes(),
+        pool_nft_index: parameters.pool_nft_index(),
+        oracle_token_id_index: parameters.oracle_token_id_index(),
+        min_data_points_index: parameters.min_data_points_index(),
+        min_data_points: parameters.min_data_points(),
+        buffer_length_index: parameters.buffer_length_index(),
+        buffer_length: parameters.buffer_length(),
+        max_deviation_percent_index: parameters.max_deviation_percent_index(),
+        max_deviation_percent: parameters.max_deviation_percent(),
+        epoch_length_index: parameters.epoch_length_index(),
+        epoch_length: EpochLength(parameters.epoch_length().0 * 2),
+    })
+    .unwrap();
+    pool_config.refresh_box_wrapper_inputs = RefreshBoxWrapperInputs::build_with(
+        other_parameters,
+        token_ids.oracle_token_id.clone(),
+        token_ids.pool_nft_token_id.clone(),
+        token_ids.refresh_nft_token_id.clone(),
+    )
+    .unwrap();
+    pool_config.token_ids.reward_token_id =
+        RewardTokenId::from_token_id_unchecked(token_ids.ballot_token_id.token_id());
+    pool_config.oracle_tokens_emission = Some(16);
+    pool_config.ballot_tokens_emission = None;
+    let report = verify_pool_boxes(&pool_config, &snapshot);
+    assert!(!report.passed());
+    let failed: Vec<&str> = report
+        .failed_checks()
+        .map(|check| check.name.as_str())
+        .collect();
+    assert!(failed.contains(&"refresh box: contract"), "{report}");
+    assert!(failed.contains(&"pool box: tokens"), "{report}");
+    assert!(
+        failed.contains(&"oracle tokens: total 16 minted"),
+        "{report}"
+    );
+    assert!(!failed
+        .iter()
+        .any(|name| name.starts_with("ballot tokens: total")));
+    assert!(failed
+        .iter()
+        .any(|name| name.starts_with("oracle boxes") && name.ends_with("tokens")));
+    // the NFT constants point at the right tokens
+    assert!(!failed
+        .iter()
+        .any(|name| name.ends_with("token id constants")));
+}
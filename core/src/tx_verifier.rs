@@ -0,0 +1,327 @@
+//! Local evaluation of the input scripts of a transaction before it is submitted to the node.
+//! The node only returns an opaque error for a rejected transaction, so we reduce every input's
+//! guarding script ourselves and report which contract rejected the transaction.
+use std::fmt::Display;
+use std::rc::Rc;
+
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::ergotree_interpreter::eval::context::Context;
+use ergo_lib::ergotree_interpreter::eval::env::Env;
+use ergo_lib::ergotree_interpreter::eval::reduce_to_crypto;
+use ergo_lib::ergotree_interpreter::eval::EvalError;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTreeError;
+use ergo_lib::ergotree_ir::mir::bin_op::BinOpKind;
+use ergo_lib::ergotree_ir::mir::bin_op::LogicalOp;
+use ergo_lib::ergotree_ir::mir::block::BlockValue;
+use ergo_lib::ergotree_ir::mir::bool_to_sigma::BoolToSigmaProp;
+use ergo_lib::ergotree_ir::mir::collection::Collection;
+use ergo_lib::ergotree_ir::mir::expr::Expr;
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::SigmaBoolean;
+use ergo_lib::ergotree_ir::types::stype::SType;
+use ergo_lib::wallet::signing::make_context;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::signing::TxSigningError;
+use thiserror::Error;
+
+use crate::pool_config::TokenIds;
+use crate::spec_token::BuybackTokenId;
+use crate::spec_token::TokenIdKind;
+
+/// The kind of contract guarding a transaction input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputContract {
+    Pool,
+    Refresh,
+    Oracle,
+    Ballot,
+    Update,
+    Buyback,
+    Wallet,
+    Unknown,
+}
+
+impl Display for InputContract {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            InputContract::Pool => "pool contract",
+            InputContract::Refresh => "refresh contract",
+            InputContract::Oracle => "oracle contract",
+            InputContract::Ballot => "ballot contract",
+            InputContract::Update => "update contract",
+            InputContract::Buyback => "buyback contract",
+            InputContract::Wallet => "wallet",
+            InputContract::Unknown => "unknown contract",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TxVerifierError {
+    #[error(
+        "{contract}: script reduced to false (input #{input_index}), failed condition: {failed_condition}"
+    )]
+    ReducedToFalse {
+        contract: InputContract,
+        input_index: usize,
+        /// The first of the AND-ed conditions of the script which is false, the whole script if
+        /// it can't be split
+        failed_condition: String,
+    },
+    #[error("{contract}: script evaluation failed (input #{input_index}): {error}")]
+    Eval {
+        contract: InputContract,
+        input_index: usize,
+        error: EvalError,
+    },
+    #[error("{contract}: cannot parse ErgoTree (input #{input_index}): {error:?}")]
+    ErgoTree {
+        contract: InputContract,
+        input_index: usize,
+        error: ErgoTreeError,
+    },
+    #[error("input box {0} is not found")]
+    InputBoxNotFound(usize),
+    #[error("tx context error: {0}")]
+    TxContext(#[from] TxSigningError),
+}
+
+/// Identifies the contract of an input box by the pool tokens it holds. Boxes guarded by a P2PK
+/// script are reported as wallet boxes regardless of their tokens.
+pub fn identify_input_contract(
+    ergo_box: &ErgoBox,
+    token_ids: &TokenIds,
+    buyback_token_id: Option<&BuybackTokenId>,
+) -> InputContract {
+    if let Ok(Address::P2Pk(_)) = Address::recreate_from_ergo_tree(&ergo_box.ergo_tree) {
+        return InputContract::Wallet;
+    }
+    let has_token = |token_id: TokenId| {
+        ergo_box
+            .tokens
+            .as_ref()
+            .map(|tokens| tokens.iter().any(|t| t.token_id == token_id))
+            .unwrap_or(false)
+    };
+    if has_token(token_ids.pool_nft_token_id.token_id()) {
+        InputContract::Pool
+    } else if has_token(token_ids.refresh_nft_token_id.token_id()) {
+        InputContract::Refresh
+    } else if has_token(token_ids.update_nft_token_id.token_id()) {
+        InputContract::Update
+    } else if has_token(token_ids.oracle_token_id.token_id()) {
+        InputContract::Oracle
+    } else if has_token(token_ids.ballot_token_id.token_id()) {
+        InputContract::Ballot
+    } else if buyback_token_id
+        .map(|id| has_token(id.token_id()))
+        .unwrap_or(false)
+    {
+        InputContract::Buyback
+    } else {
+        InputContract::Unknown
+    }
+}
+
+/// Reduces the guarding script of every input of `unsigned_tx` using the given input and data
+/// input boxes (with context extensions taken from the tx). Scripts that reduce to a sigma
+/// proposition (e.g. a signature requirement) are accepted, only a `false` result or an
+/// evaluation error is reported.
+pub fn verify_tx_input_scripts(
+    unsigned_tx: &UnsignedTransaction,
+    input_boxes: Vec<ErgoBox>,
+    data_boxes: Vec<ErgoBox>,
+    state_context: &ErgoStateContext,
+    token_ids: &TokenIds,
+    buyback_token_id: Option<&BuybackTokenId>,
+) -> Result<(), TxVerifierError> {
+    let tx_context = TransactionContext::new(unsigned_tx.clone(), input_boxes.clone(), data_boxes)?;
+    for (input_index, input) in unsigned_tx.inputs.iter().enumerate() {
+        let input_box = input_boxes
+            .iter()
+            .find(|b| b.box_id() == input.box_id)
+            .ok_or(TxVerifierError::InputBoxNotFound(input_index))?;
+        let contract = identify_input_contract(input_box, token_ids, buyback_token_id);
+        let expr =
+            input_box
+                .ergo_tree
+                .proposition()
+                .map_err(|error| TxVerifierError::ErgoTree {
+                    contract,
+                    input_index,
+                    error,
+                })?;
+        let ctx = Rc::new(make_context(state_context, &tx_context, input_index)?);
+        let reduced = reduce_to_crypto(&expr, &Env::empty(), ctx.clone()).map_err(|error| {
+            TxVerifierError::Eval {
+                contract,
+                input_index,
+                error,
+            }
+        })?;
+        if let SigmaBoolean::TrivialProp(false) = reduced.sigma_prop {
+            let failed_condition = find_failed_condition(&expr, ctx).unwrap_or(&expr);
+            return Err(TxVerifierError::ReducedToFalse {
+                contract,
+                input_index,
+                failed_condition: format!("{:?}", failed_condition),
+            });
+        }
+        log::trace!("Input #{input_index} ({contract}) passed local script evaluation");
+    }
+    Ok(())
+}
+
+/// The first condition of a script reduced to false which is false on its own. The script is split
+/// into the conditions AND-ed at its top level (`sigmaProp(a && b)`, `allOf(Coll(a, b))`,
+/// `a && b` as a sigma proposition), every condition is reduced with the val defs of the script.
+#[allow(clippy::wildcard_enum_match_arm)]
+fn find_failed_condition(expr: &Expr, ctx: Rc<Context>) -> Option<&Expr> {
+    let (val_defs, result) = match expr {
+        Expr::BlockValue(block) => (block.expr.items.clone(), &*block.expr.result),
+        _ => (vec![], expr),
+    };
+    let mut conditions = vec![];
+    collect_conditions(result, &mut conditions);
+    conditions.into_iter().find(|condition| {
+        let condition_prop = Expr::BlockValue(
+            BlockValue {
+                items: val_defs.clone(),
+                result: Box::new(Expr::BoolToSigmaProp(BoolToSigmaProp {
+                    input: Box::new((*condition).clone()),
+                })),
+            }
+            .into(),
+        );
+        matches!(
+            reduce_to_crypto(&condition_prop, &Env::empty(), ctx.clone()),
+            Ok(reduced) if reduced.sigma_prop == SigmaBoolean::TrivialProp(false)
+        )
+    })
+}
+
+/// The boolean conditions AND-ed at the top level of a sigma proposition (or a boolean expression)
+#[allow(clippy::wildcard_enum_match_arm)]
+fn collect_conditions<'a>(expr: &'a Expr, conditions: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BoolToSigmaProp(bool_to_sigma) => {
+            collect_conditions(&bool_to_sigma.input, conditions)
+        }
+        Expr::SigmaAnd(sigma_and) => sigma_and
+            .items
+            .iter()
+            .for_each(|item| collect_conditions(item, conditions)),
+        Expr::BinOp(bin_op) if bin_op.expr.kind == BinOpKind::Logical(LogicalOp::And) => {
+            collect_conditions(&bin_op.expr.left, conditions);
+            collect_conditions(&bin_op.expr.right, conditions);
+        }
+        Expr::And(and) => match &*and.expr.input {
+            Expr::Collection(Collection::Exprs { items, .. }) => items
+                .iter()
+                .for_each(|item| collect_conditions(item, conditions)),
+            _ => conditions.push(expr),
+        },
+        // a sigma proposition which is not AND-ed (e.g. a signature) is not split further
+        _ => {
+            if expr.tpe() == SType::SBoolean {
+                conditions.push(expr)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use ergo_lib::chain::transaction::UnsignedInput;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
+    use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
+    use ergo_lib::ergotree_ir::chain::token::Token;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::box_kind::PoolBox;
+    use crate::contracts::pool::PoolContractParameters;
+    use crate::oracle_types::BlockHeight;
+    use crate::oracle_types::EpochCounter;
+    use crate::pool_commands::test_utils::generate_token_ids;
+    use crate::pool_commands::test_utils::make_pool_box;
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+
+    /// Spend the pool box along with a wallet box holding the given token as the second input
+    fn verify_pool_box_spending(
+        second_input_token: impl Fn(&TokenIds) -> TokenId,
+    ) -> Result<(), TxVerifierError> {
+        let ctx = force_any_val::<ErgoStateContext>();
+        let height = BlockHeight(ctx.pre_header.height);
+        let token_ids = generate_token_ids();
+        let pool_box = make_pool_box(
+            200,
+            EpochCounter(1),
+            SUGGESTED_TX_FEE(),
+            height - 32,
+            &PoolContractParameters::default(),
+            &token_ids,
+        )
+        .get_box()
+        .clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let wallet_box = make_wallet_unspent_box(
+            secret.public_image(),
+            SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
+            Some(
+                vec![Token {
+                    token_id: second_input_token(&token_ids),
+                    amount: 1u64.try_into().unwrap(),
+                }]
+                .try_into()
+                .unwrap(),
+            ),
+        );
+        let input_boxes = vec![pool_box, wallet_box];
+        let unsigned_tx = UnsignedTransaction::new_from_vec(
+            input_boxes
+                .iter()
+                .map(|b| UnsignedInput::new(b.box_id(), ContextExtension::empty()))
+                .collect(),
+            vec![],
+            input_boxes
+                .iter()
+                .cloned()
+                .map(ErgoBoxCandidate::from)
+                .collect(),
+        )
+        .unwrap();
+        verify_tx_input_scripts(&unsigned_tx, input_boxes, vec![], &ctx, &token_ids, None)
+    }
+
+    #[test]
+    fn test_pool_box_spent_with_refresh_nft() {
+        verify_pool_box_spending(|token_ids| token_ids.refresh_nft_token_id.token_id()).unwrap();
+    }
+
+    #[test]
+    fn test_pool_box_spent_without_refresh_nft() {
+        let res = verify_pool_box_spending(|_| force_any_val::<TokenId>());
+        assert!(matches!(
+            res,
+            Err(TxVerifierError::ReducedToFalse {
+                contract: InputContract::Pool,
+                input_index: 0,
+                ..
+            })
+        ));
+        let message = res.unwrap_err().to_string();
+        assert!(message.starts_with("pool contract: script reduced to false"));
+        // the pool box is spent with the refresh NFT or the update NFT
+        assert!(message.contains("failed condition: BinOp"));
+        assert!(message.contains("Logical(Or)"));
+    }
+}
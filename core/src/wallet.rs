@@ -1,10 +1,30 @@
+use anyhow::anyhow;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::chain::transaction::TxId;
+use ergo_lib::chain::transaction::TxIoVec;
 use ergo_lib::ergotree_ir::chain::address::AddressEncoderError;
 use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_node_interface::node_interface::NodeError;
 use thiserror::Error;
 
+use crate::node_interface::node_api::NodeApi;
 use crate::node_interface::node_api::NodeApiError;
+use crate::node_interface::SignTransaction;
+use crate::node_interface::SignTransactionWithInputs;
+use crate::node_interface::SubmitTransaction;
+
+use self::external_signer::ExternalSigner;
+use self::external_signer::ExternalSignerError;
+use self::local_wallet::LocalWallet;
+use self::local_wallet::LocalWalletError;
+
+pub mod external_signer;
+pub mod local_wallet;
+pub mod secret_storage;
+pub mod sign_policy;
+pub mod token_guard;
 
 #[derive(Debug, Error)]
 pub enum WalletDataError {
@@ -23,3 +43,149 @@ pub trait WalletDataSource {
     fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError>;
     fn get_change_address(&self) -> Result<NetworkAddress, WalletDataError>;
 }
+
+/// The wallet the oracle spends its boxes from and signs its transactions with
+#[derive(Debug)]
+pub enum OracleWallet {
+    /// The node wallet, has to be unlocked
+    Node(NodeApi),
+    /// Keys are kept by the oracle, see [`LocalWallet`]
+    Local(LocalWallet),
+    /// Keys are kept by an external signer, see [`ExternalSigner`]
+    External(ExternalSigner),
+}
+
+impl OracleWallet {
+    /// Checks that the wallet is able to sign transactions
+    pub fn check_ready(&self) -> Result<(), anyhow::Error> {
+        match self {
+            OracleWallet::Node(node_api) => {
+                if !node_api.node().wallet_status()?.unlocked {
+                    return Err(anyhow!("Wallet is locked!"));
+                }
+                Ok(())
+            }
+            OracleWallet::Local(_) | OracleWallet::External(_) => Ok(()),
+        }
+    }
+
+    /// Sign an `UnsignedTransaction` and then submit it to the mempool.
+    pub fn sign_and_submit_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<TxId, NodeApiError> {
+        match self {
+            OracleWallet::Node(node_api) => node_api.sign_and_submit_transaction(unsigned_tx),
+            OracleWallet::Local(local_wallet) => {
+                let signed_tx = local_wallet
+                    .sign_transaction(unsigned_tx)
+                    .map_err(|e| NodeApiError::Signing(e.to_string()))?;
+                local_wallet.submit_transaction(&signed_tx)
+            }
+            OracleWallet::External(external_signer) => {
+                let signed_tx = external_signer
+                    .sign_transaction(unsigned_tx)
+                    .map_err(|e| NodeApiError::Signing(e.to_string()))?;
+                external_signer.submit_transaction(&signed_tx)
+            }
+        }
+    }
+}
+
+impl WalletDataSource for OracleWallet {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        match self {
+            OracleWallet::Node(node_api) => node_api.get_unspent_wallet_boxes(),
+            OracleWallet::Local(local_wallet) => Ok(local_wallet.get_unspent_boxes()?),
+            OracleWallet::External(external_signer) => Ok(external_signer.get_unspent_boxes()?),
+        }
+    }
+
+    fn get_change_address(&self) -> Result<NetworkAddress, WalletDataError> {
+        match self {
+            OracleWallet::Node(node_api) => WalletDataSource::get_change_address(node_api),
+            OracleWallet::Local(local_wallet) => Ok(local_wallet.address().clone()),
+            OracleWallet::External(external_signer) => Ok(external_signer.address().clone()),
+        }
+    }
+}
+
+fn local_wallet_node_error(e: LocalWalletError) -> NodeError {
+    match e {
+        LocalWalletError::NodeApi(NodeApiError::NodeInterfaceError(node_error)) => node_error,
+        e => NodeError::Other(e.to_string()),
+    }
+}
+
+fn external_signer_node_error(e: ExternalSignerError) -> NodeError {
+    match e {
+        ExternalSignerError::NodeApi(NodeApiError::NodeInterfaceError(node_error)) => node_error,
+        e => NodeError::Other(e.to_string()),
+    }
+}
+
+impl SignTransaction for OracleWallet {
+    fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> crate::node_interface::Result<Transaction> {
+        match self {
+            OracleWallet::Node(node_api) => {
+                SignTransaction::sign_transaction(node_api.node(), unsigned_tx)
+            }
+            OracleWallet::Local(local_wallet) => local_wallet
+                .sign_transaction(unsigned_tx)
+                .map_err(local_wallet_node_error),
+            OracleWallet::External(external_signer) => external_signer
+                .sign_transaction(unsigned_tx)
+                .map_err(external_signer_node_error),
+        }
+    }
+}
+
+impl SignTransactionWithInputs for OracleWallet {
+    fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        inputs: TxIoVec<ErgoBox>,
+        data_boxes: Option<TxIoVec<ErgoBox>>,
+    ) -> crate::node_interface::Result<Transaction> {
+        match self {
+            OracleWallet::Node(node_api) => {
+                node_api
+                    .node()
+                    .sign_transaction_with_inputs(unsigned_tx, inputs, data_boxes)
+            }
+            OracleWallet::Local(local_wallet) => local_wallet
+                .sign_transaction_with_inputs(
+                    unsigned_tx,
+                    inputs.as_vec().clone(),
+                    data_boxes.map(|bs| bs.as_vec().clone()).unwrap_or_default(),
+                )
+                .map_err(local_wallet_node_error),
+            OracleWallet::External(external_signer) => external_signer
+                .sign_transaction_with_inputs(
+                    unsigned_tx,
+                    inputs.as_vec().clone(),
+                    data_boxes.map(|bs| bs.as_vec().clone()).unwrap_or_default(),
+                )
+                .map_err(external_signer_node_error),
+        }
+    }
+}
+
+impl SubmitTransaction for OracleWallet {
+    fn submit_transaction(&self, tx: &Transaction) -> crate::node_interface::Result<TxId> {
+        match self {
+            OracleWallet::Node(node_api) => {
+                SubmitTransaction::submit_transaction(node_api.node(), tx)
+            }
+            OracleWallet::Local(local_wallet) => local_wallet
+                .submit_transaction(tx)
+                .map_err(|e| local_wallet_node_error(e.into())),
+            OracleWallet::External(external_signer) => external_signer
+                .submit_transaction(tx)
+                .map_err(|e| external_signer_node_error(e.into())),
+        }
+    }
+}
@@ -0,0 +1,409 @@
+//! Signing by an external signer holding the oracle key (e.g. on a separate host). The signer gets
+//! the unsigned tx with its input boxes and the last block headers, checks the tx against its
+//! [`SignPolicy`] and returns the signed tx. The oracle checks that the signed tx is the requested
+//! one before submitting it.
+//!
+//! The signer listens either on HTTP (`POST <url>/sign` with a JSON body) or on a Unix socket
+//! (one JSON request per line, one JSON response per line).
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::chain::transaction::TxId;
+use ergo_lib::ergo_chain_types::Header;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::Wallet;
+use reqwest::Url;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::node_interface::node_api::state_context_from_headers;
+use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_api::NodeApiError;
+
+use super::sign_policy::SignPolicy;
+
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignRequest {
+    pub tx: UnsignedTransaction,
+    pub inputs: Vec<ErgoBox>,
+    pub data_inputs: Vec<ErgoBox>,
+    /// The last 10 block headers (the newest first) for the signing context
+    pub headers: Vec<Header>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignResponse {
+    Signed { tx: Transaction },
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Error)]
+pub enum ExternalSignerError {
+    #[error("invalid signer url {0}, expected http(s)://<host>:<port> or unix:<socket path>")]
+    InvalidUrl(String),
+    #[error("unix sockets are not supported on this platform")]
+    UnixSocketsNotSupported,
+    #[error("signer io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("signer http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid signer message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("signer rejected the tx: {0}")]
+    Rejected(String),
+    #[error("signer returned tx {signed} instead of the requested tx {requested}")]
+    TxMismatch { requested: String, signed: String },
+    #[error("node api error: {0}")]
+    NodeApi(#[from] NodeApiError),
+}
+
+/// Where the external signer listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerEndpoint {
+    Http(Url),
+    Unix(PathBuf),
+}
+
+impl FromStr for SignerEndpoint {
+    type Err = ExternalSignerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(SignerEndpoint::Unix(PathBuf::from(path)));
+        }
+        match Url::parse(s) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                Ok(SignerEndpoint::Http(url))
+            }
+            _ => Err(ExternalSignerError::InvalidUrl(s.to_string())),
+        }
+    }
+}
+
+impl SignerEndpoint {
+    pub fn send(&self, request: &SignRequest) -> Result<SignResponse, ExternalSignerError> {
+        match self {
+            SignerEndpoint::Http(url) => {
+                let sign_url = format!("{}/sign", url.as_str().trim_end_matches('/'));
+                let response = reqwest::blocking::Client::builder()
+                    .timeout(SIGNER_TIMEOUT)
+                    .build()?
+                    .post(sign_url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_string(request)?)
+                    .send()?
+                    .error_for_status()?;
+                Ok(serde_json::from_str(&response.text()?)?)
+            }
+            SignerEndpoint::Unix(path) => send_over_unix_socket(path, request),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send_over_unix_socket(
+    path: &std::path::Path,
+    request: &SignRequest,
+) -> Result<SignResponse, ExternalSignerError> {
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+#[cfg(not(unix))]
+fn send_over_unix_socket(
+    _path: &std::path::Path,
+    _request: &SignRequest,
+) -> Result<SignResponse, ExternalSignerError> {
+    Err(ExternalSignerError::UnixSocketsNotSupported)
+}
+
+/// Wallet of the oracle address with the key held by the external signer. The node is used to look
+/// up the unspent boxes of the oracle address and to submit transactions.
+#[derive(Debug)]
+pub struct ExternalSigner {
+    endpoint: SignerEndpoint,
+    address: NetworkAddress,
+    node_api: NodeApi,
+}
+
+impl ExternalSigner {
+    pub fn new(endpoint: SignerEndpoint, address: NetworkAddress, node_api: NodeApi) -> Self {
+        Self {
+            endpoint,
+            address,
+            node_api,
+        }
+    }
+
+    pub fn address(&self) -> &NetworkAddress {
+        &self.address
+    }
+
+    pub fn get_unspent_boxes(&self) -> Result<Vec<ErgoBox>, NodeApiError> {
+        self.node_api.get_unspent_boxes_by_address(&self.address)
+    }
+
+    pub fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        input_boxes: Vec<ErgoBox>,
+        data_boxes: Vec<ErgoBox>,
+    ) -> Result<Transaction, ExternalSignerError> {
+        let request = SignRequest {
+            tx: unsigned_tx.clone(),
+            inputs: input_boxes,
+            data_inputs: data_boxes,
+            headers: self.node_api.get_last_headers()?,
+        };
+        match self.endpoint.send(&request)? {
+            SignResponse::Signed { tx } => {
+                check_signed_tx(unsigned_tx, &tx)?;
+                Ok(tx)
+            }
+            SignResponse::Rejected { reason } => Err(ExternalSignerError::Rejected(reason)),
+        }
+    }
+
+    /// Signs the tx looking up its input and data input boxes in the node (UTXO set and mempool)
+    pub fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<Transaction, ExternalSignerError> {
+        let (input_boxes, data_boxes) = self.node_api.get_tx_input_boxes(unsigned_tx)?;
+        self.sign_transaction_with_inputs(unsigned_tx, input_boxes, data_boxes)
+    }
+
+    pub fn submit_transaction(&self, tx: &Transaction) -> Result<TxId, NodeApiError> {
+        log::trace!(
+            "Submitting signed transaction: {}",
+            serde_json::to_string_pretty(&tx).unwrap()
+        );
+        Ok(self.node_api.node().submit_transaction(tx)?)
+    }
+}
+
+/// Checks that the signed tx has the same inputs, data inputs and outputs as the requested one (the
+/// tx id does not depend on the proofs)
+pub fn check_signed_tx(
+    unsigned_tx: &UnsignedTransaction,
+    signed_tx: &Transaction,
+) -> Result<(), ExternalSignerError> {
+    let requested = unsigned_tx.id();
+    let signed = signed_tx.id();
+    if requested != signed {
+        return Err(ExternalSignerError::TxMismatch {
+            requested: String::from(requested),
+            signed: String::from(signed),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the input and data input boxes of the request are the ones the tx spends and reads,
+/// in the same order. The policy judges the tx by these boxes, so a made-up box (e.g. an oracle
+/// box owned by another key) must not get to it.
+fn check_request_boxes(request: &SignRequest) -> Result<(), String> {
+    let tx_inputs: Vec<BoxId> = request.tx.inputs.iter().map(|input| input.box_id).collect();
+    let tx_data_inputs: Vec<BoxId> = request
+        .tx
+        .data_inputs
+        .iter()
+        .flat_map(|data_inputs| data_inputs.iter().map(|data_input| data_input.box_id))
+        .collect();
+    check_box_ids("input", &request.inputs, &tx_inputs)?;
+    check_box_ids("data input", &request.data_inputs, &tx_data_inputs)
+}
+
+fn check_box_ids(kind: &str, boxes: &[ErgoBox], tx_box_ids: &[BoxId]) -> Result<(), String> {
+    let box_ids: Vec<BoxId> = boxes.iter().map(|b| b.box_id()).collect();
+    if box_ids == tx_box_ids {
+        return Ok(());
+    }
+    let ids = |box_ids: &[BoxId]| {
+        box_ids
+            .iter()
+            .map(|box_id| String::from(*box_id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    Err(format!(
+        "the {kind} boxes [{}] of the request are not the {kind}s [{}] of the tx",
+        ids(&box_ids),
+        ids(tx_box_ids)
+    ))
+}
+
+/// Handles the request on the signer side
+pub fn sign_request(
+    wallet: &Wallet,
+    policy: &dyn SignPolicy,
+    request: SignRequest,
+) -> SignResponse {
+    if let Err(reason) = check_request_boxes(&request).and_then(|_| policy.check(&request)) {
+        return SignResponse::Rejected { reason };
+    }
+    let state_context = match state_context_from_headers(request.headers) {
+        Ok(state_context) => state_context,
+        Err(e) => {
+            return SignResponse::Rejected {
+                reason: e.to_string(),
+            }
+        }
+    };
+    let signed_tx = TransactionContext::new(request.tx, request.inputs, request.data_inputs)
+        .map_err(|e| e.to_string())
+        .and_then(|tx_context| {
+            wallet
+                .sign_transaction(tx_context, &state_context, None)
+                .map_err(|e| e.to_string())
+        });
+    match signed_tx {
+        Ok(tx) => SignResponse::Signed { tx },
+        Err(reason) => SignResponse::Rejected { reason },
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::convert::TryInto;
+
+    use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+    use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::ergotree_ir::chain::token::Token;
+    use ergo_lib::ergotree_ir::chain::token::TokenId;
+    use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+    use ergo_lib::ergotree_ir::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::wallet::box_selector::BoxSelector;
+    use ergo_lib::wallet::box_selector::SimpleBoxSelector;
+    use ergo_lib::wallet::tx_builder::TxBuilder;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+    use sigma_test_util::force_any_val;
+
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+
+    use super::*;
+
+    pub(crate) fn p2pk_ergo_tree(secret: &DlogProverInput) -> ErgoTree {
+        Address::P2Pk(secret.public_image()).script().unwrap()
+    }
+
+    /// Request to sign a tx spending a box of the `secret` key (with the given token) to
+    /// `output_ergo_tree` (the `secret` key if not set)
+    pub(crate) fn make_request(
+        secret: &DlogProverInput,
+        input_token_id: Option<TokenId>,
+        output_ergo_tree: Option<ErgoTree>,
+    ) -> SignRequest {
+        let value = SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap();
+        let tokens = input_token_id
+            .map(|token_id| vec![Token::from((token_id, 1u64.try_into().unwrap()))])
+            .map(|tokens| tokens.try_into().unwrap());
+        let input = make_wallet_unspent_box(secret.public_image(), value, tokens);
+        let output_value = SUGGESTED_TX_FEE().checked_mul_u32(10).unwrap();
+        let output = ErgoBoxCandidateBuilder::new(
+            output_value,
+            output_ergo_tree.unwrap_or_else(|| p2pk_ergo_tree(secret)),
+            1,
+        )
+        .build()
+        .unwrap();
+        let target_balance = output_value.checked_add(&SUGGESTED_TX_FEE()).unwrap();
+        let box_selection = SimpleBoxSelector::new()
+            .select(vec![input.clone()], target_balance, &[])
+            .unwrap();
+        let tx = TxBuilder::new(
+            box_selection,
+            vec![output],
+            1,
+            SUGGESTED_TX_FEE(),
+            Address::P2Pk(secret.public_image()),
+        )
+        .build()
+        .unwrap();
+        SignRequest {
+            tx,
+            inputs: vec![input],
+            data_inputs: vec![],
+            headers: vec![],
+        }
+    }
+
+    fn sign(secret: &DlogProverInput, request: &SignRequest) -> Transaction {
+        let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+        let tx_context = TransactionContext::new(
+            request.tx.clone(),
+            request.inputs.clone(),
+            request.data_inputs.clone(),
+        )
+        .unwrap();
+        wallet
+            .sign_transaction(tx_context, &force_any_val::<ErgoStateContext>(), None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_check_signed_tx() {
+        let secret = force_any_val::<DlogProverInput>();
+        let request = make_request(&secret, None, None);
+        let signed_tx = sign(&secret, &request);
+        assert!(check_signed_tx(&request.tx, &signed_tx).is_ok());
+        let other_request = make_request(&secret, None, None);
+        assert!(matches!(
+            check_signed_tx(&other_request.tx, &signed_tx),
+            Err(ExternalSignerError::TxMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_messages_roundtrip() {
+        let secret = force_any_val::<DlogProverInput>();
+        let request = make_request(&secret, None, None);
+        let json = serde_json::to_string(&request).unwrap();
+        let parsed: SignRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.tx.id(), request.tx.id());
+        let response = SignResponse::Signed {
+            tx: sign(&secret, &request),
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""status":"signed""#));
+        let parsed: SignResponse = serde_json::from_str(&json).unwrap();
+        assert!(matches!(parsed, SignResponse::Signed { tx } if tx.id() == request.tx.id()));
+    }
+
+    #[test]
+    fn test_signer_endpoint_parsing() {
+        assert_eq!(
+            "unix:/run/oracle-signer.sock"
+                .parse::<SignerEndpoint>()
+                .unwrap(),
+            SignerEndpoint::Unix(PathBuf::from("/run/oracle-signer.sock"))
+        );
+        assert!(matches!(
+            "http://127.0.0.1:9011".parse::<SignerEndpoint>().unwrap(),
+            SignerEndpoint::Http(_)
+        ));
+        assert!("ftp://127.0.0.1".parse::<SignerEndpoint>().is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Wallet with the keys kept by the oracle. Transactions are signed locally, the node is used only
+//! to look up the unspent boxes of the oracle address and to submit transactions, so the node
+//! wallet is not needed (e.g. when running against a shared or public node).
+use std::convert::TryInto;
+use std::path::Path;
+
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::chain::transaction::TxId;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::wallet::derivation_path::ChildIndexHardened;
+use ergo_lib::wallet::derivation_path::ChildIndexNormal;
+use ergo_lib::wallet::derivation_path::DerivationPath;
+use ergo_lib::wallet::ext_secret_key::ExtSecretKey;
+use ergo_lib::wallet::mnemonic::Mnemonic;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::Wallet;
+use thiserror::Error;
+
+use crate::node_interface::node_api::NodeApi;
+use crate::node_interface::node_api::NodeApiError;
+
+use super::secret_storage::EncryptedSecret;
+use super::secret_storage::SecretStorageError;
+
+#[derive(Debug, Error)]
+pub enum LocalWalletError {
+    #[error("failed to read secret file {0}: {1}")]
+    SecretFileIo(String, std::io::Error),
+    #[error("secret storage error: {0}")]
+    SecretStorage(#[from] SecretStorageError),
+    #[error("expected 64 bytes seed, got {0}")]
+    InvalidSeedLength(usize),
+    #[error("key derivation error: {0}")]
+    KeyDerivation(String),
+    #[error("node api error: {0}")]
+    NodeApi(#[from] NodeApiError),
+    #[error("signing error: {0}")]
+    Signing(String),
+}
+
+pub struct LocalWallet {
+    wallet: Wallet,
+    address: NetworkAddress,
+    node_api: NodeApi,
+}
+
+impl std::fmt::Debug for LocalWallet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't print the keys
+        f.debug_struct("LocalWallet")
+            .field("address", &self.address.to_base58())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalWallet {
+    pub fn new(secret: SecretKey, network_prefix: NetworkPrefix, node_api: NodeApi) -> Self {
+        let address = NetworkAddress::new(network_prefix, &secret.get_address_from_public_image());
+        Self {
+            wallet: Wallet::from_secrets(vec![secret]),
+            address,
+            node_api,
+        }
+    }
+
+    /// Uses the first key of the wallet (EIP-3 path m/44'/429'/0'/0/0), the same as the node
+    /// wallet restored from this mnemonic
+    pub fn from_mnemonic(
+        mnemonic: &str,
+        mnemonic_password: &str,
+        network_prefix: NetworkPrefix,
+        node_api: NodeApi,
+    ) -> Result<Self, LocalWalletError> {
+        Ok(Self::new(
+            secret_key_from_mnemonic(mnemonic, mnemonic_password)?,
+            network_prefix,
+            node_api,
+        ))
+    }
+
+    /// Loads the seed from the encrypted secret file of the node wallet (in the node's `keystore`
+    /// folder)
+    pub fn from_secret_file(
+        path: &Path,
+        password: &str,
+        network_prefix: NetworkPrefix,
+        node_api: NodeApi,
+    ) -> Result<Self, LocalWalletError> {
+        Ok(Self::new(
+            secret_key_from_file(path, password)?,
+            network_prefix,
+            node_api,
+        ))
+    }
+
+    pub fn address(&self) -> &NetworkAddress {
+        &self.address
+    }
+
+    pub fn get_unspent_boxes(&self) -> Result<Vec<ErgoBox>, NodeApiError> {
+        self.node_api.get_unspent_boxes_by_address(&self.address)
+    }
+
+    /// Signs the tx with the given input and data input boxes
+    pub fn sign_transaction_with_inputs(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+        input_boxes: Vec<ErgoBox>,
+        data_boxes: Vec<ErgoBox>,
+    ) -> Result<Transaction, LocalWalletError> {
+        let state_context = self.node_api.get_state_context()?;
+        let tx_context = TransactionContext::new(unsigned_tx.clone(), input_boxes, data_boxes)
+            .map_err(|e| LocalWalletError::Signing(e.to_string()))?;
+        self.wallet
+            .sign_transaction(tx_context, &state_context, None)
+            .map_err(|e| LocalWalletError::Signing(e.to_string()))
+    }
+
+    /// Signs the tx looking up its input and data input boxes in the node (UTXO set and mempool)
+    pub fn sign_transaction(
+        &self,
+        unsigned_tx: &UnsignedTransaction,
+    ) -> Result<Transaction, LocalWalletError> {
+        let (input_boxes, data_boxes) = self.node_api.get_tx_input_boxes(unsigned_tx)?;
+        self.sign_transaction_with_inputs(unsigned_tx, input_boxes, data_boxes)
+    }
+
+    pub fn submit_transaction(&self, tx: &Transaction) -> Result<TxId, NodeApiError> {
+        log::trace!(
+            "Submitting signed transaction: {}",
+            serde_json::to_string_pretty(&tx).unwrap()
+        );
+        Ok(self.node_api.node().submit_transaction(tx)?)
+    }
+}
+
+/// The first key of the wallet (EIP-3 path m/44'/429'/0'/0/0) restored from the mnemonic
+pub fn secret_key_from_mnemonic(
+    mnemonic: &str,
+    mnemonic_password: &str,
+) -> Result<SecretKey, LocalWalletError> {
+    first_eip3_secret_key(Mnemonic::to_seed(mnemonic, mnemonic_password))
+}
+
+/// The first key of the wallet (EIP-3 path m/44'/429'/0'/0/0) from the encrypted secret file of
+/// the node wallet
+pub fn secret_key_from_file(path: &Path, password: &str) -> Result<SecretKey, LocalWalletError> {
+    let json_str = std::fs::read_to_string(path)
+        .map_err(|e| LocalWalletError::SecretFileIo(path.display().to_string(), e))?;
+    let seed = EncryptedSecret::load_from_str(&json_str)?.decrypt(password)?;
+    let seed_len = seed.len();
+    let seed: [u8; 64] = seed
+        .try_into()
+        .map_err(|_| LocalWalletError::InvalidSeedLength(seed_len))?;
+    first_eip3_secret_key(seed)
+}
+
+fn first_eip3_secret_key(seed: [u8; 64]) -> Result<SecretKey, LocalWalletError> {
+    let master_key = ExtSecretKey::derive_master(seed)
+        .map_err(|e| LocalWalletError::KeyDerivation(e.to_string()))?;
+    let path = DerivationPath::new(
+        ChildIndexHardened::from_31_bit(0)
+            .map_err(|e| LocalWalletError::KeyDerivation(e.to_string()))?,
+        vec![ChildIndexNormal::normal(0)
+            .map_err(|e| LocalWalletError::KeyDerivation(e.to_string()))?],
+    );
+    Ok(master_key
+        .derive(path)
+        .map_err(|e| LocalWalletError::KeyDerivation(e.to_string()))?
+        .secret_key())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MNEMONIC: &str = "race relax argue hair sorry riot there spirit ready fetch food hedgehog hybrid mobile pretty";
+
+    fn first_address(mnemonic_password: &str) -> String {
+        let seed = Mnemonic::to_seed(MNEMONIC, mnemonic_password);
+        let secret = first_eip3_secret_key(seed).unwrap();
+        NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &secret.get_address_from_public_image(),
+        )
+        .to_base58()
+    }
+
+    #[test]
+    fn test_key_derivation() {
+        assert_eq!(
+            first_address(""),
+            "9eYMpbGgBf42bCcnB2nG3wQdqPzpCCw5eB1YaWUUen9uCaW3wwm"
+        );
+        assert_ne!(first_address(""), first_address("mnemonic password"));
+    }
+}
@@ -0,0 +1,214 @@
+//! Encrypted secret file in the format of the Ergo node wallet (the JSON files in the node's
+//! `keystore` folder). The file holds the wallet seed encrypted with AES-GCM, the key is derived
+//! from the password with PBKDF2.
+use aes_gcm::aead::consts::U16;
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::KeyInit;
+use aes_gcm::aes::Aes256;
+use aes_gcm::AesGcm;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Sha256;
+use thiserror::Error;
+
+/// AES-GCM with the 16 bytes IV used by the node
+type NodeAesGcm = AesGcm<Aes256, U16>;
+
+const SUPPORTED_PRF: &str = "HmacSHA256";
+const IV_LENGTH: usize = 16;
+const KEY_LENGTH_BITS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedSecret {
+    /// Base16-encoded encrypted seed (without the authentication tag)
+    pub cipher_text: String,
+    pub salt: String,
+    pub iv: String,
+    pub auth_tag: String,
+    pub cipher_params: CipherParams,
+    /// Set by the node for wallets created before the fix of the BIP-32 key derivation
+    #[serde(default)]
+    pub use_pre1627_key_derivation: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CipherParams {
+    pub prf: String,
+    /// PBKDF2 iterations
+    pub c: u32,
+    /// Length of the derived key in bits
+    pub dk_len: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum SecretStorageError {
+    #[error("failed to parse secret file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid base16 in secret file: {0}")]
+    Base16(#[from] base16::DecodeError),
+    #[error("unsupported cipher params: {0}")]
+    UnsupportedCipherParams(String),
+    #[error("the wallet uses the pre-1627 key derivation, which is not supported")]
+    Pre1627KeyDerivation,
+    #[error("failed to encrypt the secret")]
+    Encryption,
+    #[error("failed to decrypt the secret (wrong password?)")]
+    Decryption,
+}
+
+impl EncryptedSecret {
+    pub fn load_from_str(json_str: &str) -> Result<Self, SecretStorageError> {
+        Ok(serde_json::from_str(json_str)?)
+    }
+
+    /// Encrypts the seed. `salt` and `iv` must be random.
+    pub fn encrypt(
+        seed: &[u8],
+        password: &str,
+        salt: &[u8],
+        iv: [u8; IV_LENGTH],
+        iterations: u32,
+    ) -> Result<Self, SecretStorageError> {
+        let cipher_params = CipherParams {
+            prf: SUPPORTED_PRF.to_string(),
+            c: iterations,
+            dk_len: KEY_LENGTH_BITS,
+        };
+        let cipher = cipher(password, salt, &cipher_params)?;
+        let mut encrypted = cipher
+            .encrypt(GenericArray::from_slice(&iv), seed)
+            .map_err(|_| SecretStorageError::Encryption)?;
+        // the tag is appended to the cipher text, the node keeps it separately
+        let auth_tag = encrypted.split_off(encrypted.len() - 16);
+        Ok(Self {
+            cipher_text: base16::encode_lower(&encrypted),
+            salt: base16::encode_lower(salt),
+            iv: base16::encode_lower(&iv),
+            auth_tag: base16::encode_lower(&auth_tag),
+            cipher_params,
+            use_pre1627_key_derivation: false,
+        })
+    }
+
+    /// Decrypts the wallet seed
+    pub fn decrypt(&self, password: &str) -> Result<Vec<u8>, SecretStorageError> {
+        if self.use_pre1627_key_derivation {
+            return Err(SecretStorageError::Pre1627KeyDerivation);
+        }
+        let iv = base16::decode(&self.iv)?;
+        if iv.len() != IV_LENGTH {
+            return Err(SecretStorageError::UnsupportedCipherParams(format!(
+                "IV length {}",
+                iv.len()
+            )));
+        }
+        let salt = base16::decode(&self.salt)?;
+        let mut payload = base16::decode(&self.cipher_text)?;
+        payload.extend(base16::decode(&self.auth_tag)?);
+        cipher(password, &salt, &self.cipher_params)?
+            .decrypt(GenericArray::from_slice(&iv), payload.as_slice())
+            .map_err(|_| SecretStorageError::Decryption)
+    }
+}
+
+fn cipher(
+    password: &str,
+    salt: &[u8],
+    cipher_params: &CipherParams,
+) -> Result<NodeAesGcm, SecretStorageError> {
+    if cipher_params.prf != SUPPORTED_PRF || cipher_params.dk_len != KEY_LENGTH_BITS {
+        return Err(SecretStorageError::UnsupportedCipherParams(format!(
+            "{:?}",
+            cipher_params
+        )));
+    }
+    let mut key = [0u8; KEY_LENGTH_BITS / 8];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, cipher_params.c, &mut key);
+    Ok(NodeAesGcm::new(GenericArray::from_slice(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+    use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+    use ergo_lib::wallet::mnemonic::Mnemonic;
+
+    use super::*;
+    use crate::wallet::local_wallet::secret_key_from_file;
+
+    const MNEMONIC: &str = "race relax argue hair sorry riot there spirit ready fetch food hedgehog hybrid mobile pretty";
+
+    /// Secret file of the node wallet restored from `MNEMONIC` (without a mnemonic password),
+    /// encrypted with "wallet password" and the default cipher params of the node
+    const NODE_SECRET_FILE: &str = r#"{
+        "cipherText": "965e8cdc96d966f27095831292de748a8a8c2bc5f4b1993d6226d297d5cf5c3f99f8564b2e1d192d31f8a214339e4bcb41fa1a33927e50816745fc60fc57a04f",
+        "salt": "f67eb2c8d1617f9268304073c1d002de274eb94042d3a6fc3454c6b09d0841ce",
+        "iv": "63715a28e2de840f8b14ecfea4fee229",
+        "authTag": "ce3dd92a4e95fa2ad4561ccff1641294",
+        "cipherParams": {
+            "prf": "HmacSHA256",
+            "c": 128000,
+            "dkLen": 256
+        },
+        "usePre1627KeyDerivation": false
+    }"#;
+
+    /// First address (m/44'/429'/0'/0/0) of the wallet restored from `MNEMONIC`
+    const NODE_WALLET_ADDRESS: &str = "9eYMpbGgBf42bCcnB2nG3wQdqPzpCCw5eB1YaWUUen9uCaW3wwm";
+
+    #[test]
+    fn test_node_secret_file() {
+        let secret = EncryptedSecret::load_from_str(NODE_SECRET_FILE).unwrap();
+        assert_eq!(
+            secret.decrypt("wallet password").unwrap(),
+            Mnemonic::to_seed(MNEMONIC, "").to_vec()
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "oracle-core-secret-file-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&path, NODE_SECRET_FILE).unwrap();
+        let secret_key = secret_key_from_file(&path, "wallet password");
+        std::fs::remove_file(&path).unwrap();
+        let address = NetworkAddress::new(
+            NetworkPrefix::Mainnet,
+            &secret_key.unwrap().get_address_from_public_image(),
+        );
+        assert_eq!(address.to_base58(), NODE_WALLET_ADDRESS);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let seed = [7u8; 64];
+        let secret = EncryptedSecret::encrypt(&seed, "pass", &[1u8; 32], [2u8; 16], 10).unwrap();
+        let json_str = serde_json::to_string(&secret).unwrap();
+        assert!(json_str.contains("cipherText"));
+        let loaded = EncryptedSecret::load_from_str(&json_str).unwrap();
+        assert_eq!(loaded.decrypt("pass").unwrap(), seed.to_vec());
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let secret =
+            EncryptedSecret::encrypt(&[7u8; 64], "pass", &[1u8; 32], [2u8; 16], 10).unwrap();
+        assert!(matches!(
+            secret.decrypt("wrong"),
+            Err(SecretStorageError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn test_pre1627_key_derivation_is_rejected() {
+        let mut secret =
+            EncryptedSecret::encrypt(&[7u8; 64], "pass", &[1u8; 32], [2u8; 16], 10).unwrap();
+        secret.use_pre1627_key_derivation = true;
+        assert!(matches!(
+            secret.decrypt("pass"),
+            Err(SecretStorageError::Pre1627KeyDerivation)
+        ));
+    }
+}
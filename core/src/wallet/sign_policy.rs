@@ -0,0 +1,740 @@
+//! Policy of the external signer: which transactions it signs with the oracle key
+use ergo_lib::ergo_chain_types::blake2b256_hash;
+use ergo_lib::ergo_chain_types::Digest32;
+use ergo_lib::ergo_chain_types::EcPoint;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisterId;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::ergotree_ir::mir::constant::TryExtractInto;
+use ergo_lib::ergotree_ir::serialization::SigmaParsingError;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::wallet::miner_fee::MINERS_FEE_ADDRESS;
+use thiserror::Error;
+
+use crate::contracts::ballot::BallotContract;
+use crate::contracts::ballot::BallotContractError;
+use crate::contracts::oracle::OracleContract;
+use crate::contracts::oracle::OracleContractError;
+use crate::contracts::pool::PoolContract;
+use crate::contracts::pool::PoolContractError;
+use crate::contracts::refresh::RefreshContract;
+use crate::contracts::refresh::RefreshContractError;
+use crate::contracts::update::UpdateContract;
+use crate::contracts::update::UpdateContractError;
+use crate::pool_config::PoolConfig;
+use crate::pool_config::TokenIds;
+use crate::spec_token::TokenIdKind;
+
+use super::external_signer::SignRequest;
+
+/// Decides whether the signer signs the requested tx
+pub trait SignPolicy {
+    /// Returns the reason of the rejection if the tx must not be signed
+    fn check(&self, request: &SignRequest) -> Result<(), String>;
+}
+
+/// Kind of a pool tx, determined by the pool boxes it spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PoolTxKind {
+    /// Spends the refresh box
+    Refresh,
+    /// Spends the update box
+    UpdatePool,
+    /// Spends a ballot box
+    Vote,
+    /// Spends a box with the oracle token and keeps the token and the reward tokens in the oracle
+    /// box (publish datapoint)
+    Datapoint,
+    /// Spends the oracle box and hands the oracle token over to another key (transfer-oracle-token)
+    TransferOracleToken,
+    /// Spends the oracle box and sends the reward tokens to an address (extract-reward-tokens)
+    ExtractRewardTokens,
+    /// Spends none of the pool boxes (e.g. bootstrap, prepare-update)
+    Other,
+}
+
+impl PoolTxKind {
+    /// The kind by the spent boxes only, the oracle box txs are all [`PoolTxKind::Datapoint`]
+    pub fn of_inputs(inputs: &[ErgoBox], token_ids: &TokenIds) -> Self {
+        let spends = |token_id: TokenId| inputs.iter().any(|b| holds_token(b, &token_id));
+        if spends(token_ids.refresh_nft_token_id.token_id()) {
+            PoolTxKind::Refresh
+        } else if spends(token_ids.update_nft_token_id.token_id()) {
+            PoolTxKind::UpdatePool
+        } else if spends(token_ids.ballot_token_id.token_id()) {
+            PoolTxKind::Vote
+        } else if spends(token_ids.oracle_token_id.token_id()) {
+            PoolTxKind::Datapoint
+        } else {
+            PoolTxKind::Other
+        }
+    }
+}
+
+fn holds_token(ergo_box: &ErgoBox, token_id: &TokenId) -> bool {
+    ergo_box
+        .tokens
+        .as_ref()
+        .map(|tokens| tokens.iter().any(|t| &t.token_id == token_id))
+        .unwrap_or(false)
+}
+
+/// The public key in R4 (the owner of the oracle and ballot boxes)
+fn r4_public_key(ergo_box: &ErgoBox) -> Option<EcPoint> {
+    ergo_box
+        .get_register(NonMandatoryRegisterId::R4.into())
+        .and_then(|c| c.try_extract_into::<EcPoint>().ok())
+}
+
+#[derive(Debug, Error)]
+pub enum PoolTxPolicyError {
+    #[error("sigma parsing error: {0}")]
+    SigmaParsing(#[from] SigmaParsingError),
+    #[error("{0}")]
+    PoolContract(#[from] PoolContractError),
+    #[error("{0}")]
+    RefreshContract(#[from] RefreshContractError),
+    #[error("{0}")]
+    UpdateContract(#[from] UpdateContractError),
+    #[error("{0}")]
+    OracleContract(#[from] OracleContractError),
+    #[error("{0}")]
+    BallotContract(#[from] BallotContractError),
+    #[error("the signer address and the oracle token target must be P2PK addresses")]
+    NotP2PK,
+}
+
+/// Signs the txs of the allowed kinds of one pool. Every output holding a pool token (the pool,
+/// refresh and update NFTs, oracle, reward and ballot tokens) must be guarded by the pool contract
+/// for the token, and the oracle and ballot boxes must stay with the keys owning them. The oracle
+/// token is handed over to another key, and the reward tokens are sent to a P2PK address, only in
+/// the allowed transfer-oracle-token/extract-reward-tokens txs and only to the configured targets.
+/// Outputs without pool tokens may only be guarded by the signer key (change) or be the one miner
+/// fee output of at most `max_fee`, so the oracle funds never leave the signer key.
+#[derive(Debug, Clone)]
+pub struct PoolTxPolicy {
+    token_ids: TokenIds,
+    signer_ergo_tree: ErgoTree,
+    signer_public_key: EcPoint,
+    miner_fee_ergo_tree: ErgoTree,
+    max_fee: BoxValue,
+    pool_ergo_tree: ErgoTree,
+    refresh_ergo_tree: ErgoTree,
+    update_ergo_tree: ErgoTree,
+    oracle_ergo_tree: ErgoTree,
+    ballot_ergo_tree: ErgoTree,
+    allowed_kinds: Vec<PoolTxKind>,
+    oracle_token_target: Option<EcPoint>,
+    reward_tokens_target: Option<ErgoTree>,
+}
+
+impl PoolTxPolicy {
+    pub fn new(
+        pool_config: &PoolConfig,
+        signer_address: &Address,
+        max_fee: BoxValue,
+        allowed_kinds: Vec<PoolTxKind>,
+        oracle_token_target: Option<&Address>,
+        reward_tokens_target: Option<&Address>,
+    ) -> Result<Self, PoolTxPolicyError> {
+        let public_key = |address: &Address| match address {
+            Address::P2Pk(pk) => Ok(*pk.h.clone()),
+            _ => Err(PoolTxPolicyError::NotP2PK),
+        };
+        Ok(Self {
+            token_ids: pool_config.token_ids.clone(),
+            signer_ergo_tree: signer_address.script()?,
+            signer_public_key: public_key(signer_address)?,
+            miner_fee_ergo_tree: MINERS_FEE_ADDRESS.script()?,
+            max_fee,
+            pool_ergo_tree: PoolContract::checked_load(
+                &pool_config.pool_box_wrapper_inputs.contract_inputs,
+            )?
+            .ergo_tree(),
+            refresh_ergo_tree: RefreshContract::checked_load(
+                &pool_config.refresh_box_wrapper_inputs.contract_inputs,
+            )?
+            .ergo_tree(),
+            update_ergo_tree: UpdateContract::checked_load(
+                &pool_config.update_box_wrapper_inputs.contract_inputs,
+            )?
+            .ergo_tree(),
+            oracle_ergo_tree: OracleContract::checked_load(
+                &pool_config.oracle_box_wrapper_inputs.contract_inputs,
+            )?
+            .ergo_tree(),
+            ballot_ergo_tree: BallotContract::checked_load(
+                &pool_config.ballot_box_wrapper_inputs.contract_inputs,
+            )?
+            .ergo_tree(),
+            allowed_kinds,
+            oracle_token_target: oracle_token_target.map(public_key).transpose()?,
+            reward_tokens_target: reward_tokens_target
+                .map(|address| address.script())
+                .transpose()?,
+        })
+    }
+
+    fn pool_token_ids(&self) -> [TokenId; 6] {
+        [
+            self.token_ids.pool_nft_token_id.token_id(),
+            self.token_ids.refresh_nft_token_id.token_id(),
+            self.token_ids.update_nft_token_id.token_id(),
+            self.token_ids.oracle_token_id.token_id(),
+            self.token_ids.reward_token_id.token_id(),
+            self.token_ids.ballot_token_id.token_id(),
+        ]
+    }
+
+    /// The owners of the spent boxes with the token (and the signer), the outputs with the token
+    /// must stay with them
+    fn owners(&self, inputs: &[ErgoBox], token_id: &TokenId) -> Vec<EcPoint> {
+        let mut owners: Vec<EcPoint> = inputs
+            .iter()
+            .filter(|b| holds_token(b, token_id))
+            .filter_map(r4_public_key)
+            .collect();
+        owners.push(self.signer_public_key.clone());
+        owners
+    }
+
+    /// The hashes of the new pool box contract the spent ballots voted for
+    fn voted_pool_box_hashes(&self, inputs: &[ErgoBox]) -> Vec<Digest32> {
+        let ballot_token_id = self.token_ids.ballot_token_id.token_id();
+        inputs
+            .iter()
+            .filter(|b| holds_token(b, &ballot_token_id))
+            .filter_map(|b| {
+                b.get_register(NonMandatoryRegisterId::R6.into())
+                    .and_then(|c| c.try_extract_into::<Digest32>().ok())
+            })
+            .collect()
+    }
+
+    /// The kind of the tx, the oracle box txs are told apart by where the tokens go
+    fn kind(&self, request: &SignRequest, outputs: &[ErgoBox]) -> PoolTxKind {
+        let kind = PoolTxKind::of_inputs(&request.inputs, &self.token_ids);
+        if kind != PoolTxKind::Datapoint {
+            return kind;
+        }
+        let oracle_token_id = self.token_ids.oracle_token_id.token_id();
+        let reward_token_id = self.token_ids.reward_token_id.token_id();
+        let owners = self.owners(&request.inputs, &oracle_token_id);
+        let hands_over_oracle_token = outputs.iter().any(|b| {
+            holds_token(b, &oracle_token_id)
+                && !r4_public_key(b)
+                    .map(|pk| owners.contains(&pk))
+                    .unwrap_or(false)
+        });
+        let sends_reward_tokens = outputs.iter().any(|b| {
+            holds_token(b, &reward_token_id)
+                && b.ergo_tree != self.oracle_ergo_tree
+                && b.ergo_tree != self.pool_ergo_tree
+        });
+        if hands_over_oracle_token {
+            PoolTxKind::TransferOracleToken
+        } else if sends_reward_tokens {
+            PoolTxKind::ExtractRewardTokens
+        } else {
+            PoolTxKind::Datapoint
+        }
+    }
+
+    /// Checks where the output with the pool token goes
+    fn check_pool_token_output(
+        &self,
+        kind: PoolTxKind,
+        inputs: &[ErgoBox],
+        output: &ErgoBox,
+        token_id: &TokenId,
+    ) -> Result<(), String> {
+        let is_new_pool_box = || {
+            kind == PoolTxKind::UpdatePool
+                && output
+                    .ergo_tree
+                    .sigma_serialize_bytes()
+                    .map(|bytes| {
+                        self.voted_pool_box_hashes(inputs)
+                            .contains(&blake2b256_hash(&bytes))
+                    })
+                    .unwrap_or(false)
+        };
+        let stays_with_owner = || {
+            r4_public_key(output)
+                .map(|pk| self.owners(inputs, token_id).contains(&pk))
+                .unwrap_or(false)
+        };
+        let token_ids = &self.token_ids;
+        let allowed = if *token_id == token_ids.pool_nft_token_id.token_id() {
+            output.ergo_tree == self.pool_ergo_tree || is_new_pool_box()
+        } else if *token_id == token_ids.refresh_nft_token_id.token_id() {
+            output.ergo_tree == self.refresh_ergo_tree
+        } else if *token_id == token_ids.update_nft_token_id.token_id() {
+            output.ergo_tree == self.update_ergo_tree
+        } else if *token_id == token_ids.oracle_token_id.token_id() {
+            let handed_over_to_target = kind == PoolTxKind::TransferOracleToken
+                && r4_public_key(output).is_some()
+                && r4_public_key(output) == self.oracle_token_target;
+            output.ergo_tree == self.oracle_ergo_tree
+                && (stays_with_owner() || handed_over_to_target)
+        } else if *token_id == token_ids.reward_token_id.token_id() {
+            let sent_to_target = kind == PoolTxKind::ExtractRewardTokens
+                && self.reward_tokens_target.as_ref() == Some(&output.ergo_tree);
+            output.ergo_tree == self.oracle_ergo_tree
+                || output.ergo_tree == self.pool_ergo_tree
+                || is_new_pool_box()
+                || sent_to_target
+        } else if *token_id == token_ids.ballot_token_id.token_id() {
+            output.ergo_tree == self.ballot_ergo_tree && stays_with_owner()
+        } else {
+            true
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!(
+                "token {} goes to an unexpected contract or key",
+                String::from(*token_id)
+            ))
+        }
+    }
+}
+
+impl SignPolicy for PoolTxPolicy {
+    fn check(&self, request: &SignRequest) -> Result<(), String> {
+        let outputs = request
+            .tx
+            .output_candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                ErgoBox::from_box_candidate(candidate, request.tx.id(), index as u16)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid output: {e}"))?;
+        let kind = self.kind(request, &outputs);
+        if !self.allowed_kinds.contains(&kind) {
+            return Err(format!("{:?} txs are not allowed", kind));
+        }
+        let pool_token_ids = self.pool_token_ids();
+        let mut has_fee_output = false;
+        for (index, output) in outputs.iter().enumerate() {
+            let output_pool_token_ids: Vec<TokenId> = output
+                .tokens
+                .as_ref()
+                .map(|tokens| {
+                    tokens
+                        .iter()
+                        .map(|t| t.token_id)
+                        .filter(|token_id| pool_token_ids.contains(token_id))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if output_pool_token_ids.is_empty() {
+                if output.ergo_tree == self.miner_fee_ergo_tree {
+                    if has_fee_output {
+                        return Err(format!("output {index} is a second miner fee output"));
+                    }
+                    if output.value.as_u64() > self.max_fee.as_u64() {
+                        return Err(format!(
+                            "output {index} pays a miner fee of {} nanoERG, more than the max fee {}",
+                            output.value.as_u64(),
+                            self.max_fee.as_u64()
+                        ));
+                    }
+                    has_fee_output = true;
+                } else if output.ergo_tree != self.signer_ergo_tree {
+                    return Err(format!(
+                        "output {index} without pool tokens is not guarded by the signer key"
+                    ));
+                }
+                continue;
+            }
+            for token_id in &output_pool_token_ids {
+                self.check_pool_token_output(kind, &request.inputs, output, token_id)
+                    .map_err(|reason| format!("output {index}: {reason}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Signs everything, for the CLI commands run against a signer without a pool policy
+#[derive(Debug, Clone, Copy)]
+pub struct AllowAll;
+
+impl SignPolicy for AllowAll {
+    fn check(&self, _request: &SignRequest) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+    use ergo_lib::chain::transaction::TxId;
+    use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
+    use ergo_lib::ergotree_ir::chain::token::Token;
+    use ergo_lib::ergotree_ir::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::wallet::box_selector::BoxSelector;
+    use ergo_lib::wallet::box_selector::SimpleBoxSelector;
+    use ergo_lib::wallet::tx_builder::TxBuilder;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+    use ergo_lib::wallet::Wallet;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::cli_commands::bootstrap::BootstrapConfig;
+    use crate::pool_commands::test_utils::generate_token_ids;
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+    use crate::wallet::external_signer::sign_request;
+    use crate::wallet::external_signer::tests::make_request;
+    use crate::wallet::external_signer::tests::p2pk_ergo_tree;
+    use crate::wallet::external_signer::SignResponse;
+
+    fn address(secret: &DlogProverInput) -> Address {
+        Address::P2Pk(secret.public_image())
+    }
+
+    fn token(token_id: TokenId, amount: u64) -> Token {
+        Token::from((token_id, amount.try_into().unwrap()))
+    }
+
+    /// Output with the tokens guarded by `ergo_tree`, with the public key of `owner` in R4
+    fn token_output(
+        ergo_tree: ErgoTree,
+        owner: Option<&DlogProverInput>,
+        tokens: Vec<Token>,
+    ) -> ErgoBoxCandidate {
+        let mut builder = ErgoBoxCandidateBuilder::new(
+            SUGGESTED_TX_FEE().checked_mul_u32(10).unwrap(),
+            ergo_tree,
+            1,
+        );
+        if let Some(owner) = owner {
+            builder
+                .set_register_value(NonMandatoryRegisterId::R4, (*owner.public_image().h).into());
+        }
+        for token in tokens {
+            builder.add_token(token);
+        }
+        builder.build().unwrap()
+    }
+
+    /// Request to sign a tx spending a box of the `secret` key with the tokens to the outputs
+    fn make_token_request(
+        secret: &DlogProverInput,
+        input_tokens: Vec<Token>,
+        outputs: Vec<ErgoBoxCandidate>,
+    ) -> SignRequest {
+        make_token_request_with_fee(secret, input_tokens, outputs, SUGGESTED_TX_FEE())
+    }
+
+    fn make_token_request_with_fee(
+        secret: &DlogProverInput,
+        input_tokens: Vec<Token>,
+        outputs: Vec<ErgoBoxCandidate>,
+        fee: BoxValue,
+    ) -> SignRequest {
+        let input = make_wallet_unspent_box(
+            secret.public_image(),
+            SUGGESTED_TX_FEE().checked_mul_u32(100).unwrap(),
+            Some(input_tokens.clone().try_into().unwrap()),
+        );
+        let target_balance = outputs.iter().fold(fee, |balance, output| {
+            balance.checked_add(&output.value).unwrap()
+        });
+        let box_selection = SimpleBoxSelector::new()
+            .select(vec![input.clone()], target_balance, &input_tokens)
+            .unwrap();
+        let tx = TxBuilder::new(box_selection, outputs, 1, fee, address(secret))
+            .build()
+            .unwrap();
+        SignRequest {
+            tx,
+            inputs: vec![input],
+            data_inputs: vec![],
+            headers: vec![],
+        }
+    }
+
+    fn pool_config() -> PoolConfig {
+        PoolConfig::create(BootstrapConfig::default(), generate_token_ids()).unwrap()
+    }
+
+    fn oracle_ergo_tree(pool_config: &PoolConfig) -> ErgoTree {
+        OracleContract::checked_load(&pool_config.oracle_box_wrapper_inputs.contract_inputs)
+            .unwrap()
+            .ergo_tree()
+    }
+
+    /// The oracle token and the reward tokens
+    fn oracle_box_tokens(token_ids: &TokenIds, reward_tokens: u64) -> Vec<Token> {
+        vec![
+            token(token_ids.oracle_token_id.token_id(), 1),
+            token(token_ids.reward_token_id.token_id(), reward_tokens),
+        ]
+    }
+
+    #[test]
+    fn test_pool_tx_kind() {
+        let token_ids = generate_token_ids();
+        let secret = force_any_val::<DlogProverInput>();
+        let request = make_request(&secret, Some(token_ids.oracle_token_id.token_id()), None);
+        assert_eq!(
+            PoolTxKind::of_inputs(&request.inputs, &token_ids),
+            PoolTxKind::Datapoint
+        );
+        let request = make_request(&secret, None, None);
+        assert_eq!(
+            PoolTxKind::of_inputs(&request.inputs, &token_ids),
+            PoolTxKind::Other
+        );
+    }
+
+    #[test]
+    fn test_pool_tx_policy() {
+        let pool_config = pool_config();
+        let token_ids = pool_config.token_ids.clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![PoolTxKind::Datapoint],
+            None,
+            None,
+        )
+        .unwrap();
+        // publishes a datapoint in the oracle box of the signer
+        let request = make_token_request(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![token_output(
+                oracle_ergo_tree(&pool_config),
+                Some(&secret),
+                oracle_box_tokens(&token_ids, 5),
+            )],
+        );
+        assert_eq!(policy.check(&request), Ok(()));
+        // not allowed kind
+        let request = make_request(&secret, None, None);
+        assert!(policy.check(&request).is_err());
+        // sends the funds to a foreign address
+        let foreign_ergo_tree = p2pk_ergo_tree(&force_any_val::<DlogProverInput>());
+        let request = make_request(
+            &secret,
+            Some(token_ids.oracle_token_id.token_id()),
+            Some(foreign_ergo_tree),
+        );
+        assert!(policy.check(&request).is_err());
+    }
+
+    #[test]
+    fn test_oracle_token_to_foreign_key_is_rejected() {
+        let pool_config = pool_config();
+        let token_ids = pool_config.token_ids.clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let foreign = force_any_val::<DlogProverInput>();
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![PoolTxKind::Datapoint],
+            None,
+            None,
+        )
+        .unwrap();
+        // publish-shaped tx sending the oracle token to a foreign P2PK address
+        let request = make_token_request(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![token_output(
+                p2pk_ergo_tree(&foreign),
+                None,
+                oracle_box_tokens(&token_ids, 5),
+            )],
+        );
+        assert!(policy.check(&request).is_err());
+        // oracle box owned by a foreign key
+        let request = make_token_request(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![token_output(
+                oracle_ergo_tree(&pool_config),
+                Some(&foreign),
+                oracle_box_tokens(&token_ids, 5),
+            )],
+        );
+        assert!(policy.check(&request).is_err());
+    }
+
+    #[test]
+    fn test_transfer_and_extract_only_to_targets() {
+        let pool_config = pool_config();
+        let token_ids = pool_config.token_ids.clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let target = force_any_val::<DlogProverInput>();
+        let foreign = force_any_val::<DlogProverInput>();
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![
+                PoolTxKind::Datapoint,
+                PoolTxKind::TransferOracleToken,
+                PoolTxKind::ExtractRewardTokens,
+            ],
+            Some(&address(&target)),
+            Some(&address(&target)),
+        )
+        .unwrap();
+        let transfer_to = |owner: &DlogProverInput| {
+            make_token_request(
+                &secret,
+                oracle_box_tokens(&token_ids, 1),
+                vec![token_output(
+                    oracle_ergo_tree(&pool_config),
+                    Some(owner),
+                    oracle_box_tokens(&token_ids, 1),
+                )],
+            )
+        };
+        assert_eq!(policy.check(&transfer_to(&target)), Ok(()));
+        assert!(policy.check(&transfer_to(&foreign)).is_err());
+
+        let extract_to = |destination: &DlogProverInput| {
+            make_token_request(
+                &secret,
+                oracle_box_tokens(&token_ids, 5),
+                vec![
+                    token_output(
+                        oracle_ergo_tree(&pool_config),
+                        Some(&secret),
+                        oracle_box_tokens(&token_ids, 1),
+                    ),
+                    token_output(
+                        p2pk_ergo_tree(destination),
+                        None,
+                        vec![token(token_ids.reward_token_id.token_id(), 4)],
+                    ),
+                ],
+            )
+        };
+        assert_eq!(policy.check(&extract_to(&target)), Ok(()));
+        assert!(policy.check(&extract_to(&foreign)).is_err());
+
+        // the transfer and the extraction must be allowed explicitly
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![PoolTxKind::Datapoint],
+            Some(&address(&target)),
+            Some(&address(&target)),
+        )
+        .unwrap();
+        assert!(policy.check(&transfer_to(&target)).is_err());
+        assert!(policy.check(&extract_to(&target)).is_err());
+    }
+
+    #[test]
+    fn test_miner_fee_is_capped() {
+        let pool_config = pool_config();
+        let token_ids = pool_config.token_ids.clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![PoolTxKind::Datapoint],
+            None,
+            None,
+        )
+        .unwrap();
+        let oracle_box = || {
+            token_output(
+                oracle_ergo_tree(&pool_config),
+                Some(&secret),
+                oracle_box_tokens(&token_ids, 5),
+            )
+        };
+        let request = make_token_request_with_fee(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![oracle_box()],
+            SUGGESTED_TX_FEE().checked_mul_u32(50).unwrap(),
+        );
+        assert!(policy.check(&request).is_err());
+        // the funds sent to the miners in a second fee output
+        let second_fee_output = ErgoBoxCandidateBuilder::new(
+            SUGGESTED_TX_FEE(),
+            MINERS_FEE_ADDRESS.script().unwrap(),
+            1,
+        )
+        .build()
+        .unwrap();
+        let request = make_token_request(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![oracle_box(), second_fee_output],
+        );
+        assert!(policy.check(&request).is_err());
+    }
+
+    #[test]
+    fn test_forged_inputs_are_rejected() {
+        let pool_config = pool_config();
+        let token_ids = pool_config.token_ids.clone();
+        let secret = force_any_val::<DlogProverInput>();
+        let attacker = force_any_val::<DlogProverInput>();
+        let policy = PoolTxPolicy::new(
+            &pool_config,
+            &address(&secret),
+            SUGGESTED_TX_FEE(),
+            vec![PoolTxKind::Datapoint],
+            None,
+            None,
+        )
+        .unwrap();
+        let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+        // hands the oracle token of the signer over to the attacker
+        let mut request = make_token_request(
+            &secret,
+            oracle_box_tokens(&token_ids, 5),
+            vec![token_output(
+                oracle_ergo_tree(&pool_config),
+                Some(&attacker),
+                oracle_box_tokens(&token_ids, 5),
+            )],
+        );
+        assert!(policy.check(&request).is_err());
+        // a made-up oracle box of the attacker among the inputs makes the attacker an owner
+        let forged_box = ErgoBox::from_box_candidate(
+            &token_output(
+                oracle_ergo_tree(&pool_config),
+                Some(&attacker),
+                oracle_box_tokens(&token_ids, 1),
+            ),
+            force_any_val::<TxId>(),
+            0,
+        )
+        .unwrap();
+        request.inputs.push(forged_box.clone());
+        assert!(matches!(
+            sign_request(&wallet, &policy, request.clone()),
+            SignResponse::Rejected { reason } if reason.contains("input boxes")
+        ));
+        // or replaces the spent box
+        request.inputs = vec![forged_box];
+        assert!(matches!(
+            sign_request(&wallet, &policy, request),
+            SignResponse::Rejected { reason } if reason.contains("input boxes")
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+//! Keeps the pool tokens held by the wallet (spare oracle tokens, a ballot token, reward tokens)
+//! out of the box selection. Otherwise a box holding them can be picked to pay the tx fee and the
+//! tokens end up in the change box or, worse, in an output of a tx that moves tokens.
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+
+use crate::pool_config::TokenIds;
+use crate::spec_token::TokenIdKind;
+
+use super::WalletDataError;
+use super::WalletDataSource;
+
+/// Wallet view without the boxes holding the protected tokens. The txs which move the pool tokens
+/// from the wallet (e.g. the first datapoint, the first vote) explicitly allow them.
+pub struct TokenGuardedWallet<'a> {
+    wallet: &'a dyn WalletDataSource,
+    protected: Vec<TokenId>,
+    allowed: Vec<TokenId>,
+}
+
+impl<'a> TokenGuardedWallet<'a> {
+    /// No tokens are protected until [`Self::protect_pool`] is called
+    pub fn new(wallet: &'a dyn WalletDataSource) -> Self {
+        Self {
+            wallet,
+            protected: vec![],
+            allowed: vec![],
+        }
+    }
+
+    /// Hides the boxes holding any of the pool tokens
+    pub fn protect_pool(mut self, token_ids: &TokenIds) -> Self {
+        self.protected.extend([
+            token_ids.pool_nft_token_id.token_id(),
+            token_ids.refresh_nft_token_id.token_id(),
+            token_ids.update_nft_token_id.token_id(),
+            token_ids.oracle_token_id.token_id(),
+            token_ids.reward_token_id.token_id(),
+            token_ids.ballot_token_id.token_id(),
+        ]);
+        self
+    }
+
+    /// Lets the boxes holding the token be selected
+    pub fn allow(mut self, token_id: TokenId) -> Self {
+        self.allowed.push(token_id);
+        self
+    }
+
+    fn is_protected(&self, ergo_box: &ErgoBox) -> bool {
+        ergo_box
+            .tokens
+            .as_ref()
+            .map(|tokens| {
+                tokens.iter().any(|t| {
+                    self.protected.contains(&t.token_id) && !self.allowed.contains(&t.token_id)
+                })
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl<'a> WalletDataSource for TokenGuardedWallet<'a> {
+    fn get_unspent_wallet_boxes(&self) -> Result<Vec<ErgoBox>, WalletDataError> {
+        let boxes = self.wallet.get_unspent_wallet_boxes()?;
+        let (protected, unprotected): (Vec<ErgoBox>, Vec<ErgoBox>) =
+            boxes.into_iter().partition(|b| self.is_protected(b));
+        if !protected.is_empty() {
+            log::debug!(
+                "{} wallet boxes holding pool tokens are excluded from the box selection",
+                protected.len()
+            );
+        }
+        Ok(unprotected)
+    }
+
+    fn get_change_address(&self) -> Result<NetworkAddress, WalletDataError> {
+        self.wallet.get_change_address()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::convert::TryInto;
+
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
+    use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+    use ergo_lib::ergotree_ir::chain::token::Token;
+    use ergo_lib::ergotree_ir::chain::token::TokenAmount;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::pool_commands::test_utils::generate_token_ids;
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+    use crate::pool_commands::test_utils::WalletDataMock;
+
+    #[test]
+    fn test_boxes_with_pool_tokens_are_hidden() {
+        let token_ids = generate_token_ids();
+        let secret = force_any_val::<DlogProverInput>();
+        let value = BoxValue::try_from(1_000_000_000u64).unwrap();
+        let box_with = |token_id: TokenId| {
+            make_wallet_unspent_box(
+                secret.public_image(),
+                value,
+                Some(
+                    vec![Token {
+                        token_id,
+                        amount: TokenAmount::try_from(1u64).unwrap(),
+                    }]
+                    .try_into()
+                    .unwrap(),
+                ),
+            )
+        };
+        let plain_box = make_wallet_unspent_box(secret.public_image(), value, None);
+        let foreign_token_box = box_with(force_any_val::<TokenId>());
+        let ballot_token_box = box_with(token_ids.ballot_token_id.token_id());
+        let reward_token_box = box_with(token_ids.reward_token_id.token_id());
+        let wallet_mock = WalletDataMock {
+            unspent_boxes: vec![
+                plain_box.clone(),
+                foreign_token_box.clone(),
+                ballot_token_box.clone(),
+                reward_token_box.clone(),
+            ],
+            change_address: AddressEncoder::unchecked_parse_network_address_from_str(
+                "9iHyKxXs2ZNLMp9N9gbUT9V8gTbsV7HED1C1VhttMfBUMPDyF7r",
+            )
+            .unwrap(),
+        };
+
+        let unguarded = TokenGuardedWallet::new(&wallet_mock);
+        assert_eq!(unguarded.get_unspent_wallet_boxes().unwrap().len(), 4);
+
+        let guarded = TokenGuardedWallet::new(&wallet_mock).protect_pool(&token_ids);
+        assert_eq!(
+            guarded.get_unspent_wallet_boxes().unwrap(),
+            vec![plain_box.clone(), foreign_token_box.clone()]
+        );
+
+        let ballot_allowed = TokenGuardedWallet::new(&wallet_mock)
+            .protect_pool(&token_ids)
+            .allow(token_ids.ballot_token_id.token_id());
+        assert_eq!(
+            ballot_allowed.get_unspent_wallet_boxes().unwrap(),
+            vec![plain_box, foreign_token_box, ballot_token_box]
+        );
+    }
+}
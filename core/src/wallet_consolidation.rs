@@ -0,0 +1,248 @@
+//! Consolidation of the oracle wallet UTXOs. Every refresh and datapoint tx leaves a change box in
+//! the wallet, so over time the wallet accumulates many small boxes which make the box selection
+//! slower and the txs bigger. The small boxes are merged into one box paying to the change
+//! address. Boxes holding tokens (oracle, ballot, reward or any other tokens) are never spent.
+use std::convert::TryFrom;
+use std::convert::TryInto;
+
+use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilder;
+use ergo_lib::chain::ergo_box::box_builder::ErgoBoxCandidateBuilderError;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValueError;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::serialization::SigmaParsingError;
+use ergo_lib::wallet::box_selector::BoxSelection;
+use ergo_lib::wallet::tx_builder::TxBuilder;
+use ergo_lib::wallet::tx_builder::TxBuilderError;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::oracle_types::BlockHeight;
+
+/// Consolidate if the wallet has at least this many small boxes
+pub const DEFAULT_CONSOLIDATION_MIN_BOXES: usize = 20;
+/// 0.1 ERG
+pub const DEFAULT_CONSOLIDATION_MAX_BOX_VALUE_NANO_ERGS: u64 = 100_000_000;
+/// Keeps the consolidation tx well below the max tx size
+pub const DEFAULT_CONSOLIDATION_MAX_INPUTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WalletConsolidationConfig {
+    /// Don't consolidate if the wallet has less small boxes
+    pub min_boxes: usize,
+    /// Boxes with more coins (in nanoERG) are not consolidated
+    pub max_box_value_nano_ergs: u64,
+    /// Max number of boxes merged in one tx
+    pub max_inputs: usize,
+}
+
+impl Default for WalletConsolidationConfig {
+    fn default() -> Self {
+        Self {
+            min_boxes: DEFAULT_CONSOLIDATION_MIN_BOXES,
+            max_box_value_nano_ergs: DEFAULT_CONSOLIDATION_MAX_BOX_VALUE_NANO_ERGS,
+            max_inputs: DEFAULT_CONSOLIDATION_MAX_INPUTS,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WalletConsolidationError {
+    #[error("consolidated boxes hold {total} nanoERG, not enough to pay the fee {fee} nanoERG")]
+    NotEnoughCoinsForFee { total: u64, fee: u64 },
+    #[error("box value error: {0}")]
+    BoxValue(#[from] BoxValueError),
+    #[error("box builder error: {0}")]
+    ErgoBoxCandidateBuilder(#[from] ErgoBoxCandidateBuilderError),
+    #[error("Sigma parsing error: {0}")]
+    SigmaParse(#[from] SigmaParsingError),
+    #[error("tx builder error: {0}")]
+    TxBuilder(#[from] TxBuilderError),
+}
+
+/// The wallet boxes to merge, the smallest first. Empty if there are less than `min_boxes` of
+/// them (not worth a tx).
+pub fn select_boxes_to_consolidate(
+    unspent_boxes: Vec<ErgoBox>,
+    config: &WalletConsolidationConfig,
+) -> Vec<ErgoBox> {
+    let mut small_boxes: Vec<ErgoBox> = unspent_boxes
+        .into_iter()
+        .filter(|b| b.tokens.is_none())
+        .filter(|b| *b.value.as_u64() <= config.max_box_value_nano_ergs)
+        .collect();
+    if small_boxes.len() < config.min_boxes.max(2) {
+        return vec![];
+    }
+    small_boxes.sort_by_key(|b| *b.value.as_u64());
+    small_boxes.truncate(config.max_inputs);
+    small_boxes
+}
+
+/// Builds the tx merging the boxes to consolidate into one box to `change_address`. Returns None
+/// if there is nothing to consolidate.
+pub fn build_consolidation_tx(
+    unspent_boxes: Vec<ErgoBox>,
+    config: &WalletConsolidationConfig,
+    tx_fee: BoxValue,
+    change_address: Address,
+    height: BlockHeight,
+) -> Result<Option<UnsignedTransaction>, WalletConsolidationError> {
+    let boxes = select_boxes_to_consolidate(unspent_boxes, config);
+    if boxes.len() < 2 {
+        return Ok(None);
+    }
+    let total: u64 = boxes.iter().map(|b| *b.value.as_u64()).sum();
+    let fee = *tx_fee.as_u64();
+    let out_value = total
+        .checked_sub(fee)
+        .filter(|v| *v >= *BoxValue::SAFE_USER_MIN().as_u64())
+        .ok_or(WalletConsolidationError::NotEnoughCoinsForFee { total, fee })?;
+    let out_box = ErgoBoxCandidateBuilder::new(
+        BoxValue::try_from(out_value)?,
+        change_address.script()?,
+        height.0,
+    )
+    .build()?;
+    let box_selection = BoxSelection {
+        // at least 2 and at most max_inputs boxes
+        boxes: boxes.try_into().unwrap(),
+        change_boxes: vec![],
+    };
+    let tx_builder = TxBuilder::new(
+        box_selection,
+        vec![out_box],
+        height.0,
+        tx_fee,
+        change_address,
+    );
+    Ok(Some(tx_builder.build()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+    use ergo_lib::ergotree_ir::chain::token::Token;
+    use ergo_lib::ergotree_ir::chain::token::TokenAmount;
+    use ergo_lib::wallet::signing::TransactionContext;
+    use ergo_lib::wallet::tx_builder::SUGGESTED_TX_FEE;
+    use ergo_lib::wallet::Wallet;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::pool_commands::test_utils::find_input_boxes;
+    use crate::pool_commands::test_utils::generate_token_ids;
+    use crate::pool_commands::test_utils::make_wallet_unspent_box;
+    use crate::spec_token::TokenIdKind;
+
+    fn make_boxes(secret: &DlogProverInput, values: &[u64]) -> Vec<ErgoBox> {
+        values
+            .iter()
+            .map(|v| {
+                make_wallet_unspent_box(
+                    secret.public_image(),
+                    BoxValue::try_from(*v).unwrap(),
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_boxes_to_consolidate() {
+        let secret = force_any_val::<DlogProverInput>();
+        let token_ids = generate_token_ids();
+        let config = WalletConsolidationConfig {
+            min_boxes: 4,
+            max_box_value_nano_ergs: 10_000_000,
+            max_inputs: 3,
+        };
+        let mut boxes = make_boxes(&secret, &[5_000_000, 1_000_000, 20_000_000, 2_000_000]);
+        // not enough small boxes
+        assert!(select_boxes_to_consolidate(boxes.clone(), &config).is_empty());
+
+        boxes.push(make_wallet_unspent_box(
+            secret.public_image(),
+            BoxValue::try_from(1_000_000u64).unwrap(),
+            Some(
+                vec![Token {
+                    token_id: token_ids.oracle_token_id.token_id(),
+                    amount: TokenAmount::try_from(1u64).unwrap(),
+                }]
+                .try_into()
+                .unwrap(),
+            ),
+        ));
+        // the box with the oracle token is not counted
+        assert!(select_boxes_to_consolidate(boxes.clone(), &config).is_empty());
+
+        boxes.extend(make_boxes(&secret, &[3_000_000, 4_000_000]));
+        let selected: Vec<u64> = select_boxes_to_consolidate(boxes, &config)
+            .iter()
+            .map(|b| *b.value.as_u64())
+            .collect();
+        assert_eq!(selected, vec![1_000_000, 2_000_000, 3_000_000]);
+    }
+
+    #[test]
+    fn test_build_consolidation_tx() {
+        let ctx = force_any_val::<ErgoStateContext>();
+        let height = BlockHeight(ctx.pre_header.height);
+        let secret = force_any_val::<DlogProverInput>();
+        let wallet = Wallet::from_secrets(vec![secret.clone().into()]);
+        let change_address = Address::P2Pk(secret.public_image());
+        let boxes = make_boxes(
+            &secret,
+            &[10_000_000, 20_000_000, 30_000_000, 2_000_000_000],
+        );
+        let config = WalletConsolidationConfig {
+            min_boxes: 3,
+            ..Default::default()
+        };
+        let tx = build_consolidation_tx(
+            boxes.clone(),
+            &config,
+            SUGGESTED_TX_FEE(),
+            change_address,
+            height,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(tx.inputs.len(), 3);
+        assert_eq!(tx.output_candidates.len(), 2);
+        assert_eq!(
+            *tx.output_candidates.first().value.as_u64(),
+            60_000_000 - *SUGGESTED_TX_FEE().as_u64()
+        );
+
+        let tx_context =
+            TransactionContext::new(tx.clone(), find_input_boxes(tx, boxes), vec![]).unwrap();
+        assert!(wallet.sign_transaction(tx_context, &ctx, None).is_ok());
+    }
+
+    #[test]
+    fn test_consolidation_not_worth_the_fee() {
+        let secret = force_any_val::<DlogProverInput>();
+        let boxes = make_boxes(&secret, &[100_000, 100_000, 100_000]);
+        let config = WalletConsolidationConfig {
+            min_boxes: 3,
+            ..Default::default()
+        };
+        let res = build_consolidation_tx(
+            boxes,
+            &config,
+            SUGGESTED_TX_FEE(),
+            Address::P2Pk(secret.public_image()),
+            BlockHeight(100),
+        );
+        assert!(matches!(
+            res,
+            Err(WalletConsolidationError::NotEnoughCoinsForFee { .. })
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! Monitoring of the oracle wallet funds. The balance is sampled on every main loop iteration and
+//! the spend rate is estimated from the balance decreases (tx fees and box values), so the
+//! operator is warned before the wallet runs out of ERG.
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::oracle_types::BlockHeight;
+use crate::oracle_types::EpochLength;
+use crate::wallet::WalletDataError;
+use crate::wallet::WalletDataSource;
+
+/// 1 ERG
+pub const DEFAULT_WALLET_LOW_BALANCE_NANO_ERGS: u64 = 1_000_000_000;
+
+/// The spend rate is estimated over this many last blocks (about a day)
+const SPEND_WINDOW_BLOCKS: u32 = 720;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WalletFundsReport {
+    pub height: BlockHeight,
+    pub nano_ergs: u64,
+    pub box_count: usize,
+    pub low_balance_threshold: u64,
+    pub low_balance: bool,
+    /// Average balance decrease per block, None until the balance is observed at two heights
+    pub spend_per_block: Option<f64>,
+    /// Blocks until the wallet runs out at the current spend rate, None if no spend is observed
+    pub runway_blocks: Option<u64>,
+}
+
+impl WalletFundsReport {
+    pub fn spend_per_epoch(&self, epoch_length: EpochLength) -> Option<u64> {
+        self.spend_per_block
+            .map(|spend| (spend * epoch_length.0 as f64).round() as u64)
+    }
+
+    pub fn runway_epochs(&self, epoch_length: EpochLength) -> Option<u64> {
+        self.runway_blocks
+            .map(|blocks| blocks / epoch_length.0.max(1) as u64)
+    }
+
+    /// The funds as seen by a pool with the given epoch length
+    pub fn pool_funds(&self, epoch_length: EpochLength) -> PoolWalletFunds {
+        PoolWalletFunds {
+            nano_ergs: self.nano_ergs,
+            low_balance_threshold: self.low_balance_threshold,
+            low_balance: self.low_balance,
+            spend_per_epoch: self.spend_per_epoch(epoch_length),
+            runway_epochs: self.runway_epochs(epoch_length),
+        }
+    }
+}
+
+/// Wallet funds in the epochs of a pool, served by /oracleHealth
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolWalletFunds {
+    pub nano_ergs: u64,
+    pub low_balance_threshold: u64,
+    pub low_balance: bool,
+    pub spend_per_epoch: Option<u64>,
+    pub runway_epochs: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct WalletFundsMonitor {
+    /// (height, balance) samples of the last SPEND_WINDOW_BLOCKS blocks, the oldest first
+    samples: VecDeque<(BlockHeight, u64)>,
+    low_balance_threshold: u64,
+    report: Option<WalletFundsReport>,
+}
+
+impl WalletFundsMonitor {
+    pub fn new(low_balance_threshold: u64) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            low_balance_threshold,
+            report: None,
+        }
+    }
+
+    pub fn set_low_balance_threshold(&mut self, low_balance_threshold: u64) {
+        self.low_balance_threshold = low_balance_threshold;
+    }
+
+    pub fn report(&self) -> Option<&WalletFundsReport> {
+        self.report.as_ref()
+    }
+
+    /// Samples the unspent boxes of the wallet
+    pub fn update(
+        &mut self,
+        wallet: &dyn WalletDataSource,
+        height: BlockHeight,
+    ) -> Result<&WalletFundsReport, WalletDataError> {
+        let boxes = wallet.get_unspent_wallet_boxes()?;
+        let nano_ergs = boxes.iter().map(|b| *b.value.as_u64()).sum();
+        Ok(self.record(height, nano_ergs, boxes.len()))
+    }
+
+    fn record(
+        &mut self,
+        height: BlockHeight,
+        nano_ergs: u64,
+        box_count: usize,
+    ) -> &WalletFundsReport {
+        match self.samples.back_mut() {
+            Some(last) if last.0 == height => last.1 = nano_ergs,
+            _ => self.samples.push_back((height, nano_ergs)),
+        }
+        while self
+            .samples
+            .front()
+            .map(|(h, _)| h.0 + SPEND_WINDOW_BLOCKS < height.0)
+            .unwrap_or(false)
+        {
+            self.samples.pop_front();
+        }
+        let spend_per_block = self.spend_per_block();
+        let runway_blocks = spend_per_block
+            .filter(|spend| *spend > 0.0)
+            .map(|spend| (nano_ergs as f64 / spend) as u64);
+        self.report.insert(WalletFundsReport {
+            height,
+            nano_ergs,
+            box_count,
+            low_balance_threshold: self.low_balance_threshold,
+            low_balance: nano_ergs < self.low_balance_threshold,
+            spend_per_block,
+            runway_blocks,
+        })
+    }
+
+    /// Sum of the balance decreases over the observed blocks, top-ups are ignored
+    fn spend_per_block(&self) -> Option<f64> {
+        let (first_height, _) = self.samples.front()?;
+        let (last_height, _) = self.samples.back()?;
+        let blocks = last_height
+            .0
+            .checked_sub(first_height.0)
+            .filter(|b| *b > 0)?;
+        let spent: u64 = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|((_, before), (_, after))| before.saturating_sub(*after))
+            .sum();
+        Some(spent as f64 / blocks as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_estimate_before_two_heights() {
+        let mut monitor = WalletFundsMonitor::new(100);
+        let report = monitor.record(BlockHeight(10), 1000, 1);
+        assert_eq!(report.spend_per_block, None);
+        assert_eq!(report.runway_blocks, None);
+        assert!(!report.low_balance);
+        let report = monitor.record(BlockHeight(10), 900, 1);
+        assert_eq!(report.spend_per_block, None);
+    }
+
+    #[test]
+    fn test_runway_estimate_ignores_top_ups() {
+        let mut monitor = WalletFundsMonitor::new(100);
+        monitor.record(BlockHeight(10), 1000, 1);
+        monitor.record(BlockHeight(15), 900, 1);
+        // top-up
+        monitor.record(BlockHeight(20), 2000, 2);
+        let report = monitor.record(BlockHeight(30), 1800, 2).clone();
+        // 300 spent over 20 blocks
+        assert_eq!(report.spend_per_block, Some(15.0));
+        assert_eq!(report.runway_blocks, Some(120));
+        assert_eq!(report.spend_per_epoch(EpochLength(30)), Some(450));
+        assert_eq!(report.runway_epochs(EpochLength(30)), Some(4));
+    }
+
+    #[test]
+    fn test_low_balance() {
+        let mut monitor = WalletFundsMonitor::new(1000);
+        assert!(monitor.record(BlockHeight(10), 999, 1).low_balance);
+        monitor.set_low_balance_threshold(500);
+        assert!(!monitor.record(BlockHeight(11), 999, 1).low_balance);
+    }
+
+    #[test]
+    fn test_old_samples_are_dropped() {
+        let mut monitor = WalletFundsMonitor::new(0);
+        monitor.record(BlockHeight(10), 10_000, 1);
+        monitor.record(BlockHeight(11), 1000, 1);
+        let report = monitor.record(BlockHeight(11 + SPEND_WINDOW_BLOCKS), 1000, 1);
+        assert_eq!(report.spend_per_block, Some(0.0));
+        assert_eq!(report.runway_blocks, None);
+    }
+}
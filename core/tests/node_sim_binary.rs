@@ -0,0 +1,176 @@
+//! Runs the built `oracle-core` binary (`bootstrap` and `run`) against a simulated node, the
+//! simulated node serves the Explorer transaction endpoint the bootstrap waits on as well.
+#![cfg(unix)]
+
+use std::convert::TryInto;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Child;
+use std::process::Command;
+use std::time::Duration;
+use std::time::Instant;
+
+use ergo_chain_sim::Block;
+use ergo_chain_sim::ChainSim;
+use ergo_chain_sim::NodeSim;
+use ergo_chain_sim::NodeSimServer;
+use ergo_chain_sim::DEFAULT_API_KEY;
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::ergotree_interpreter::sigma_protocol::private_input::DlogProverInput;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisterId;
+use oracle_core::cli_commands::bootstrap::BootstrapConfig;
+use oracle_core::contracts::refresh::RefreshContractParameters;
+use oracle_core::contracts::refresh::RefreshContractParametersInputs;
+use oracle_core::oracle_config::BoxSourceConfig;
+use oracle_core::oracle_config::OracleConfig;
+use oracle_core::oracle_types::EpochLength;
+use oracle_core::oracle_types::MinDatapoints;
+use oracle_core::pool_config::PoolConfig;
+use oracle_core::serde::BootstrapConfigSerde;
+use oracle_core::spec_token::TokenIdKind;
+use reqwest::Url;
+use sigma_test_util::force_any_val;
+
+const RATE: i64 = 123_456_789;
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Kills the `oracle-core run` process when the test is over (or has failed)
+struct RunningOracle(Child);
+
+impl Drop for RunningOracle {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn oracle_core(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_oracle-core"));
+    command
+        .current_dir(dir)
+        .env("ORACLE_NODE_API_KEY", DEFAULT_API_KEY)
+        .arg("--oracle-config-file")
+        .arg(dir.join("oracle_config.yaml"))
+        .arg("--pool-config-file")
+        .arg(dir.join("pool_config.yaml"))
+        .arg("-d")
+        .arg(dir);
+    command
+}
+
+/// The refresh contract of a pool with a single oracle
+fn single_oracle_refresh_contract_parameters(
+    epoch_length: EpochLength,
+) -> RefreshContractParameters {
+    let parameters = RefreshContractParameters::default();
+    RefreshContractParameters::build_with(RefreshContractParametersInputs {
+        ergo_tree_bytes: parameters.ergo_tree_bytes(),
+        pool_nft_index: parameters.pool_nft_index(),
+        oracle_token_id_index: parameters.oracle_token_id_index(),
+        min_data_points_index: parameters.min_data_points_index(),
+        min_data_points: MinDatapoints(1),
+        buffer_length_index: parameters.buffer_length_index(),
+        buffer_length: parameters.buffer_length(),
+        max_deviation_percent_index: parameters.max_deviation_percent_index(),
+        max_deviation_percent: parameters.max_deviation_percent(),
+        epoch_length_index: parameters.epoch_length_index(),
+        epoch_length,
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_bootstrap_and_run_binary_against_node_sim() {
+    let secret = force_any_val::<DlogProverInput>();
+    let net_address = NetworkAddress::new(
+        NetworkPrefix::Mainnet,
+        &Address::P2Pk(secret.public_image()),
+    );
+    let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+    while chain.height < 100 {
+        chain.add_block(Block::new(vec![])).unwrap();
+    }
+    chain.generate_unspent_box(
+        net_address.address().script().unwrap(),
+        100_000_000_000_u64.try_into().unwrap(),
+        None,
+    );
+    let server = NodeSimServer::start(NodeSim::new(
+        chain,
+        vec![secret.into()],
+        NetworkPrefix::Mainnet,
+    ))
+    .unwrap();
+    let node_url = Url::parse(&server.url()).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("oracle-core-binary-{}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    let script_path = dir.join("datapoint.sh");
+    // the output is parsed as is, without a trailing newline
+    std::fs::write(&script_path, format!("#!/bin/sh\nprintf {RATE}\n")).unwrap();
+    std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let oracle_config = OracleConfig {
+        node_url: Some(node_url.clone()),
+        explorer_url: Some(node_url),
+        oracle_address: net_address,
+        box_source: BoxSourceConfig::NodeIndexer,
+        data_point_source_custom_script: Some(script_path.display().to_string()),
+        node_poll_interval_secs: Some(1),
+        max_main_loop_interval_secs: Some(2),
+        ..OracleConfig::default()
+    };
+    std::fs::write(
+        dir.join("oracle_config.yaml"),
+        serde_yaml::to_string(&oracle_config).unwrap(),
+    )
+    .unwrap();
+    let bootstrap_config = BootstrapConfig {
+        data_point_source: None,
+        refresh_contract_parameters: single_oracle_refresh_contract_parameters(EpochLength(5)),
+        ..BootstrapConfig::default()
+    };
+    std::fs::write(
+        dir.join("bootstrap.yaml"),
+        serde_yaml::to_string(&BootstrapConfigSerde::from(bootstrap_config)).unwrap(),
+    )
+    .unwrap();
+
+    let status = oracle_core(&dir)
+        .arg("bootstrap")
+        .arg("bootstrap.yaml")
+        .status()
+        .unwrap();
+    assert!(status.success(), "bootstrap failed: {status}");
+    let pool_config = PoolConfig::load_from_file(&dir.join("pool_config.yaml")).unwrap();
+    let pool_nft_token_id = pool_config.token_ids.pool_nft_token_id.token_id();
+    let pool_box_rate = || {
+        server
+            .node()
+            .chain
+            .get_unspent_boxes_by_token_id(&pool_nft_token_id)
+            .first()
+            .and_then(|pool_box| pool_box.get_register(NonMandatoryRegisterId::R4.into()))
+            .and_then(|rate| rate.try_extract_into::<i64>().ok())
+    };
+    assert_ne!(pool_box_rate(), None);
+    assert_ne!(pool_box_rate(), Some(RATE));
+
+    let oracle = RunningOracle(oracle_core(&dir).arg("run").spawn().unwrap());
+    let start = Instant::now();
+    while pool_box_rate() != Some(RATE) {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "the pool box was not refreshed with the datapoint in {TIMEOUT:?}"
+        );
+        std::thread::sleep(Duration::from_millis(500));
+        server.node().chain.mine_block().unwrap();
+    }
+    drop(oracle);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
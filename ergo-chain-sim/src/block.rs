@@ -1,6 +1,7 @@
 use ergo_lib::chain::transaction::Transaction;
 
 /// Block with transactions
+#[derive(Clone)]
 pub struct Block {
     pub(crate) txs: Vec<Transaction>,
 }
@@ -10,4 +11,9 @@ impl Block {
     pub fn new(txs: Vec<Transaction>) -> Block {
         Block { txs }
     }
+
+    /// The transactions of the block
+    pub fn txs(&self) -> &[Transaction] {
+        &self.txs
+    }
 }
@@ -1,26 +1,73 @@
+use std::collections::HashMap;
+
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
 use ergo_lib::chain::transaction::Transaction;
 use ergo_lib::chain::transaction::TxId;
+use ergo_lib::ergo_chain_types::BlockId;
+use ergo_lib::ergo_chain_types::Digest32;
+use ergo_lib::ergo_chain_types::Header;
+use ergo_lib::ergo_chain_types::PreHeader;
 use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
 use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
 use ergo_lib::ergotree_ir::chain::ergo_box::BoxTokens;
 use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
 use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisters;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
 use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use thiserror::Error;
 
+use crate::validate_tx;
 use crate::Block;
+use crate::TxValidationError;
+
+/// Target time between the blocks of the Ergo mainnet, in milliseconds
+pub const DEFAULT_BLOCK_INTERVAL_MS: u64 = 120_000;
+
+/// Timestamp of the genesis block (height 0) of the simulated chain, in milliseconds
+pub const GENESIS_TIMESTAMP_MS: u64 = 1_561_978_800_000;
+
+/// Reason a block, a transaction or a rollback is rejected
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ChainSimError {
+    /// A transaction of the block (with the given ID) is invalid
+    #[error("invalid tx {0}: {1}")]
+    InvalidTx(String, TxValidationError),
+    /// The transaction spends a box already spent by another transaction in the mempool
+    #[error("tx {0} spends box {1} already spent by tx {2} in the mempool")]
+    MempoolConflict(String, String, String),
+    /// The height to roll back to is above the current height
+    #[error("cannot roll back to height {0} above the current height {1}")]
+    RollbackAboveHeight(u32, u32),
+}
 
 /// Ergo blockchain(UTXO) simulation
+#[derive(Clone)]
 pub struct ChainSim {
     blocks: Vec<Block>,
-    all_boxes: Vec<ErgoBox>,
     unspent_boxes: Vec<ErgoBox>,
+    /// The unspent boxes at each height (before the block at the height + 1), for the rollbacks
+    unspent_boxes_history: Vec<Vec<ErgoBox>>,
+    /// Indexes in `unspent_boxes` of the boxes holding the token (by the token ID)
+    token_index: HashMap<String, Vec<usize>>,
+    /// Transactions waiting to be put into a block, in the order they were submitted
+    mempool: Vec<Transaction>,
+    /// Template for the state context of each block, the input scripts are verified against it
+    state_context_template: Option<ErgoStateContext>,
+    /// Verify the input scripts, unless disabled with [`ChainSim::without_script_verification`]
+    verify_scripts: bool,
+    /// Headers of the blocks, the block at height 1 first (only with a state context template)
+    headers: Vec<Header>,
+    /// Number of headers made so far, to tell apart the IDs of the blocks of different branches
+    headers_made: u64,
+    block_interval_ms: u64,
 
     /// Current height
     pub height: u32,
 }
 
 impl ChainSim {
-    fn get_unspent_box(&self, box_id: &BoxId) -> Option<ErgoBox> {
+    /// Returns the unspent box with the given id
+    pub fn get_unspent_box(&self, box_id: &BoxId) -> Option<ErgoBox> {
         for b in &self.unspent_boxes {
             if b.box_id() == *box_id {
                 return Some(b.clone());
@@ -29,60 +76,260 @@ impl ChainSim {
         None
     }
 
-    fn get_box(&self, box_id: &BoxId) -> Option<ErgoBox> {
-        for b in &self.all_boxes {
-            if b.box_id() == *box_id {
-                return Some(b.clone());
-            }
-        }
-        None
-    }
-
-    fn update_utxo(&mut self, tx: Transaction) {
-        let boxes_to_spend = tx.inputs.mapped(|i| {
-            self.get_unspent_box(&i.box_id)
-                .ok_or(format!(
-                    "cannot find {:?} in unspent boxes: {:?}",
-                    &i.box_id, &self.unspent_boxes
-                ))
-                .unwrap()
-        });
-        let _data_input_boxes = tx
-            .data_inputs
-            .map(|data_inputs| data_inputs.mapped(|i| self.get_box(&i.box_id)));
-        // TODO: verify tx signatures
-        // TODO: verify tx using all the checks from https://github.com/ergoplatform/ergo/blob/1935c95560a30b19cdb52c1a291e8a389ba63c97/src/main/scala/org/ergoplatform/modifiers/mempool/ErgoTransaction.scala#L80-L384
-        //
-        dbg!("removing spent boxes from UTXO: {:?}", &boxes_to_spend);
-        self.unspent_boxes = self
-            .unspent_boxes
-            .clone()
-            .into_iter()
-            .filter(|b| !boxes_to_spend.as_vec().contains(b))
-            .collect();
-        dbg!("adding tx outputs to UTXO: {:?}", &tx.outputs);
-        self.unspent_boxes.append(tx.outputs.to_vec().as_mut());
-        self.all_boxes.append(tx.outputs.to_vec().as_mut());
-    }
-
-    /// Create a new chain simulation
+    /// Create a new chain simulation. The input scripts of the transactions are verified, so a
+    /// chain accepting transactions needs a state context (see [`ChainSim::with_state_context`])
+    /// unless the verification is disabled with [`ChainSim::without_script_verification`].
     pub fn new() -> ChainSim {
         ChainSim {
             blocks: Vec::new(),
-            all_boxes: Vec::new(),
             unspent_boxes: Vec::new(),
+            unspent_boxes_history: Vec::new(),
+            token_index: HashMap::new(),
+            mempool: Vec::new(),
+            state_context_template: None,
+            verify_scripts: true,
+            headers: Vec::new(),
+            headers_made: 0,
+            block_interval_ms: DEFAULT_BLOCK_INTERVAL_MS,
             height: 0,
         }
     }
 
-    /// Add a new block to the chain (head/latest)
-    pub fn add_block(&mut self, block: Block) {
-        block.txs.iter().for_each(|tx| {
-            // dbg!(&tx);
-            self.update_utxo(tx.clone());
-        });
+    /// Verify the input scripts of the transactions against the state context of the block they
+    /// go into (see [`ChainSim::state_context`]). The given state context is the template for the
+    /// headers and the pre-header, their heights, IDs, parent IDs and timestamps come from the
+    /// chain.
+    pub fn with_state_context(mut self, template: ErgoStateContext) -> ChainSim {
+        self.state_context_template = Some(template);
+        self.headers.clear();
+        while (self.headers.len() as u32) < self.height {
+            self.push_header();
+        }
+        self
+    }
+
+    /// Accept the transactions without verifying their input scripts, e.g. unsigned transactions
+    /// in the tests of the UTXO set bookkeeping. The other checks still apply.
+    pub fn without_script_verification(mut self) -> ChainSim {
+        self.verify_scripts = false;
+        self
+    }
+
+    /// Time between the blocks, in milliseconds ([`DEFAULT_BLOCK_INTERVAL_MS`] by default)
+    pub fn with_block_interval(mut self, block_interval_ms: u64) -> ChainSim {
+        self.block_interval_ms = block_interval_ms;
+        self
+    }
+
+    /// Timestamp of the block at the given height, in milliseconds
+    pub fn block_timestamp(&self, height: u32) -> u64 {
+        GENESIS_TIMESTAMP_MS + height as u64 * self.block_interval_ms
+    }
+
+    /// Headers of the blocks, the block at height 1 first. Empty unless the chain is created
+    /// with [`ChainSim::with_state_context`].
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    /// State context of the next block (at `height + 1`), the one the transactions submitted now
+    /// are verified against: the last 10 headers (the newest first, the genesis header repeated
+    /// on a chain shorter than 10 blocks) and the pre-header of the next block.
+    /// `None` unless the chain is created with [`ChainSim::with_state_context`].
+    pub fn state_context(&self) -> Option<ErgoStateContext> {
+        let template = self.state_context_template.as_ref()?;
+        let headers: [Header; 10] =
+            std::array::from_fn(|i| self.header_at(template, self.height.saturating_sub(i as u32)));
+        let last_header = &headers[0];
+        let pre_header = PreHeader {
+            version: last_header.version,
+            parent_id: last_header.id.clone(),
+            timestamp: self.block_timestamp(self.height + 1),
+            n_bits: last_header.n_bits,
+            height: self.height + 1,
+            miner_pk: template.pre_header.miner_pk.clone(),
+            votes: template.pre_header.votes.clone(),
+        };
+        Some(ErgoStateContext::new(pre_header, headers))
+    }
+
+    /// Header of the block at the given height, the genesis header is made from the template
+    fn header_at(&self, template: &ErgoStateContext, height: u32) -> Header {
+        match height.checked_sub(1) {
+            Some(index) => self.headers[index as usize].clone(),
+            None => make_header(
+                &template.headers[0],
+                0,
+                BlockId(Digest32::zero()),
+                self.block_timestamp(0),
+                0,
+            ),
+        }
+    }
+
+    /// Adds the header of the block at the height `headers.len() + 1`
+    fn push_header(&mut self) {
+        if let Some(template) = &self.state_context_template {
+            let height = self.headers.len() as u32 + 1;
+            let parent_id = self.header_at(template, height - 1).id;
+            let header = make_header(
+                &template.headers[0],
+                height,
+                parent_id,
+                self.block_timestamp(height),
+                self.headers_made + 1,
+            );
+            self.headers.push(header);
+            self.headers_made += 1;
+        }
+    }
+
+    fn set_unspent_boxes(&mut self, unspent_boxes: Vec<ErgoBox>) {
+        let mut token_index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, b) in unspent_boxes.iter().enumerate() {
+            for token in b.tokens.iter().flat_map(|tokens| tokens.iter()) {
+                token_index
+                    .entry(String::from(token.token_id))
+                    .or_default()
+                    .push(index);
+            }
+        }
+        self.unspent_boxes = unspent_boxes;
+        self.token_index = token_index;
+    }
+
+    /// Add a new block to the chain (head/latest). The block is rejected if any of its
+    /// transactions is invalid, a transaction can spend the outputs of the preceding ones. The
+    /// mempool transactions spending the same boxes are dropped.
+    pub fn add_block(&mut self, block: Block) -> Result<(), ChainSimError> {
+        let unspent_boxes = apply_txs(
+            self.unspent_boxes.clone(),
+            &block.txs,
+            self.state_context().as_ref(),
+            self.verify_scripts,
+        )?;
+        let previous_unspent_boxes = std::mem::take(&mut self.unspent_boxes);
+        self.unspent_boxes_history.push(previous_unspent_boxes);
+        self.set_unspent_boxes(unspent_boxes);
         self.blocks.push(block);
+        self.push_header();
         self.height += 1;
+        self.revalidate_mempool();
+        Ok(())
+    }
+
+    /// Put the transaction into the mempool. It can spend the outputs of the transactions
+    /// already there, but not the boxes they spend.
+    pub fn submit_tx(&mut self, tx: Transaction) -> Result<(), ChainSimError> {
+        for input in tx.inputs.iter() {
+            if let Some(conflicting_tx) = self
+                .mempool
+                .iter()
+                .find(|mempool_tx| mempool_tx.inputs.iter().any(|i| i.box_id == input.box_id))
+            {
+                return Err(ChainSimError::MempoolConflict(
+                    String::from(tx.id()),
+                    String::from(input.box_id),
+                    String::from(conflicting_tx.id()),
+                ));
+            }
+        }
+        validate_tx(
+            &tx,
+            &self.unspent_boxes_with_pool(),
+            self.state_context().as_ref(),
+            self.verify_scripts,
+        )
+        .map_err(|e| ChainSimError::InvalidTx(String::from(tx.id()), e))?;
+        self.mempool.push(tx);
+        Ok(())
+    }
+
+    /// Transactions waiting to be put into a block, in the order they were submitted
+    pub fn mempool(&self) -> &[Transaction] {
+        &self.mempool
+    }
+
+    /// Put all the mempool transactions into a new block
+    pub fn mine_block(&mut self) -> Result<(), ChainSimError> {
+        let txs = std::mem::take(&mut self.mempool);
+        self.add_block(Block::new(txs))
+    }
+
+    /// Drops the mempool transactions that are no longer valid (e.g. their inputs were spent by
+    /// a block)
+    fn revalidate_mempool(&mut self) {
+        let state_context = self.state_context();
+        let mut unspent_boxes = self.unspent_boxes.clone();
+        let mut mempool = Vec::new();
+        for tx in std::mem::take(&mut self.mempool) {
+            if let Ok(next_unspent_boxes) = apply_txs(
+                unspent_boxes.clone(),
+                std::slice::from_ref(&tx),
+                state_context.as_ref(),
+                self.verify_scripts,
+            ) {
+                unspent_boxes = next_unspent_boxes;
+                mempool.push(tx);
+            }
+        }
+        self.mempool = mempool;
+    }
+
+    /// Unspent boxes with the mempool transactions applied
+    pub fn unspent_boxes_with_pool(&self) -> Vec<ErgoBox> {
+        let mut unspent_boxes = self.unspent_boxes.clone();
+        for tx in &self.mempool {
+            spend(&mut unspent_boxes, tx);
+        }
+        unspent_boxes
+    }
+
+    /// Removes the blocks above the height, their transactions go back to the mempool (before the
+    /// ones already there). Returns the removed blocks. The boxes generated after the height are
+    /// removed as well.
+    pub fn rollback_to(&mut self, height: u32) -> Result<Vec<Block>, ChainSimError> {
+        if height > self.height {
+            return Err(ChainSimError::RollbackAboveHeight(height, self.height));
+        }
+        if height == self.height {
+            return Ok(Vec::new());
+        }
+        let unspent_boxes = self.unspent_boxes_history[height as usize].clone();
+        self.unspent_boxes_history.truncate(height as usize);
+        self.set_unspent_boxes(unspent_boxes);
+        let removed_blocks = self.blocks.split_off(height as usize);
+        self.headers.truncate(height as usize);
+        self.height = height;
+        let mut mempool: Vec<Transaction> = removed_blocks
+            .iter()
+            .flat_map(|block| block.txs.iter().cloned())
+            .collect();
+        mempool.append(&mut self.mempool);
+        self.mempool = mempool;
+        self.revalidate_mempool();
+        Ok(removed_blocks)
+    }
+
+    /// Switches to an alternative branch forking at the given height, the chain is left as is if
+    /// any of the blocks is invalid. Returns the blocks of the abandoned branch.
+    pub fn reorg(
+        &mut self,
+        fork_height: u32,
+        blocks: Vec<Block>,
+    ) -> Result<Vec<Block>, ChainSimError> {
+        let mut branch = self.clone();
+        let abandoned_blocks = branch.rollback_to(fork_height)?;
+        for block in blocks {
+            branch.add_block(block)?;
+        }
+        *self = branch;
+        Ok(abandoned_blocks)
+    }
+
+    /// The blocks of the chain, the block at height 1 first
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
     }
 
     /// Generates an unspent box guarded by a given ErgoTree holding a given assests
@@ -102,8 +349,14 @@ impl ChainSim {
             0,
         )
         .unwrap();
-        self.unspent_boxes.push(b.clone());
-        self.all_boxes.push(b);
+        let mut unspent_boxes = std::mem::take(&mut self.unspent_boxes);
+        unspent_boxes.push(b);
+        self.set_unspent_boxes(unspent_boxes);
+    }
+
+    /// Returns all unspent boxes
+    pub fn unspent_boxes(&self) -> &[ErgoBox] {
+        &self.unspent_boxes
     }
 
     /// Returns unspent boxes guarder by the given ErgoTree
@@ -114,6 +367,19 @@ impl ChainSim {
             .cloned()
             .collect()
     }
+
+    /// Returns unspent boxes holding the given token
+    pub fn get_unspent_boxes_by_token_id(&self, token_id: &TokenId) -> Vec<ErgoBox> {
+        self.token_index
+            .get(&String::from(*token_id))
+            .map(|indexes| {
+                indexes
+                    .iter()
+                    .map(|index| self.unspent_boxes[*index].clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
 impl Default for ChainSim {
@@ -121,3 +387,199 @@ impl Default for ChainSim {
         Self::new()
     }
 }
+
+/// Header at the given height made from the template, the ID is unique for the height and the
+/// sequence number
+fn make_header(
+    template: &Header,
+    height: u32,
+    parent_id: BlockId,
+    timestamp: u64,
+    sequence_number: u64,
+) -> Header {
+    let mut id = [0u8; 32];
+    id[..4].copy_from_slice(&height.to_be_bytes());
+    id[4..12].copy_from_slice(&sequence_number.to_be_bytes());
+    let mut header = template.clone();
+    header.id = BlockId(Digest32::from(id));
+    header.parent_id = parent_id;
+    header.height = height;
+    header.timestamp = timestamp;
+    header
+}
+
+/// Removes the boxes spent by the transaction and adds its outputs
+fn spend(unspent_boxes: &mut Vec<ErgoBox>, tx: &Transaction) {
+    unspent_boxes.retain(|b| !tx.inputs.iter().any(|input| input.box_id == b.box_id()));
+    unspent_boxes.extend(tx.outputs.iter().cloned());
+}
+
+/// Validates and applies the transactions in order
+fn apply_txs(
+    mut unspent_boxes: Vec<ErgoBox>,
+    txs: &[Transaction],
+    state_context: Option<&ErgoStateContext>,
+    verify_scripts: bool,
+) -> Result<Vec<ErgoBox>, ChainSimError> {
+    for tx in txs {
+        validate_tx(tx, &unspent_boxes, state_context, verify_scripts)
+            .map_err(|e| ChainSimError::InvalidTx(String::from(tx.id()), e))?;
+        spend(&mut unspent_boxes, tx);
+    }
+    Ok(unspent_boxes)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::test_util::chain_with_box;
+    use crate::test_util::output;
+    use crate::test_util::spending_tx;
+    use crate::test_util::token;
+
+    #[test]
+    fn test_mempool_chained_txs_and_conflicts() {
+        let (mut chain, input) = chain_with_box(2_000_000, vec![]);
+        let tx = spending_tx(&[input.clone()], &[], vec![output(2_000_000, vec![])]);
+        chain.submit_tx(tx.clone()).unwrap();
+        // spends the output of the tx in the mempool
+        let chained_tx = spending_tx(
+            &[tx.outputs.first().clone()],
+            &[],
+            vec![output(2_000_000, vec![])],
+        );
+        chain.submit_tx(chained_tx.clone()).unwrap();
+        let double_spend = spending_tx(&[input.clone()], &[], vec![output(2_000_000, vec![])]);
+        assert_eq!(
+            chain.submit_tx(double_spend.clone()).unwrap_err(),
+            ChainSimError::MempoolConflict(
+                String::from(double_spend.id()),
+                String::from(input.box_id()),
+                String::from(tx.id())
+            )
+        );
+        assert_eq!(chain.mempool().len(), 2);
+
+        chain.mine_block().unwrap();
+        assert_eq!(chain.height, 1);
+        assert_eq!(chain.blocks()[0].txs().len(), 2);
+        assert!(chain.mempool().is_empty());
+        assert_eq!(chain.unspent_boxes(), &[chained_tx.outputs.first().clone()]);
+    }
+
+    #[test]
+    fn test_block_drops_conflicting_mempool_txs() {
+        let (mut chain, input) = chain_with_box(2_000_000, vec![]);
+        let mempool_tx = spending_tx(&[input.clone()], &[], vec![output(2_000_000, vec![])]);
+        chain.submit_tx(mempool_tx).unwrap();
+        let block_tx = spending_tx(&[input], &[], vec![output(2_000_000, vec![])]);
+        chain.add_block(Block::new(vec![block_tx])).unwrap();
+        assert!(chain.mempool().is_empty());
+    }
+
+    #[test]
+    fn test_rollback_and_reorg() {
+        let (mut chain, input) = chain_with_box(2_000_000, vec![]);
+        let token_id: TokenId = input.box_id().into();
+        let mint_tx = spending_tx(
+            &[input.clone()],
+            &[],
+            vec![output(2_000_000, vec![token(token_id, 1)])],
+        );
+        chain.add_block(Block::new(vec![mint_tx.clone()])).unwrap();
+        let token_box = mint_tx.outputs.first().clone();
+        assert_eq!(
+            chain.get_unspent_boxes_by_token_id(&token_id),
+            vec![token_box.clone()]
+        );
+        let transfer_tx = spending_tx(
+            &[token_box.clone()],
+            &[],
+            vec![output(2_000_000, vec![token(token_id, 1)])],
+        );
+        chain
+            .add_block(Block::new(vec![transfer_tx.clone()]))
+            .unwrap();
+        assert_eq!(
+            chain.get_unspent_boxes_by_token_id(&token_id),
+            vec![transfer_tx.outputs.first().clone()]
+        );
+
+        assert_eq!(
+            chain.rollback_to(3).unwrap_err(),
+            ChainSimError::RollbackAboveHeight(3, 2)
+        );
+        let removed_blocks = chain.rollback_to(1).unwrap();
+        assert_eq!(removed_blocks.len(), 1);
+        assert_eq!(chain.height, 1);
+        assert_eq!(
+            chain.get_unspent_boxes_by_token_id(&token_id),
+            vec![token_box.clone()]
+        );
+        // the tx of the removed block is back in the mempool
+        assert_eq!(chain.mempool(), &[transfer_tx.clone()]);
+
+        // an alternative branch burning the token instead, the transfer tx conflicts with it
+        let burn_tx = spending_tx(&[token_box.clone()], &[], vec![output(2_000_000, vec![])]);
+        let invalid_tx = spending_tx(&[input], &[], vec![output(2_000_000, vec![])]);
+        assert!(chain
+            .reorg(
+                0,
+                vec![
+                    Block::new(vec![mint_tx.clone()]),
+                    Block::new(vec![invalid_tx])
+                ]
+            )
+            .is_err());
+        assert_eq!(chain.height, 1);
+        let abandoned_blocks = chain
+            .reorg(
+                0,
+                vec![Block::new(vec![mint_tx]), Block::new(vec![burn_tx])],
+            )
+            .unwrap();
+        assert_eq!(abandoned_blocks.len(), 1);
+        assert_eq!(chain.height, 2);
+        assert!(chain.get_unspent_boxes_by_token_id(&token_id).is_empty());
+        assert!(chain.mempool().is_empty());
+    }
+
+    #[test]
+    fn test_state_context_follows_the_chain() {
+        let mut chain = ChainSim::new()
+            .with_state_context(force_any_val::<ErgoStateContext>())
+            .with_block_interval(60_000);
+        // the genesis header stands for the missing ones
+        let state_context = chain.state_context().unwrap();
+        assert_eq!(state_context.pre_header.height, 1);
+        assert!(state_context.headers.iter().all(|h| h.height == 0));
+
+        for _ in 0..12 {
+            chain.add_block(Block::new(vec![])).unwrap();
+        }
+        let state_context = chain.state_context().unwrap();
+        assert_eq!(state_context.pre_header.height, 13);
+        assert_eq!(
+            state_context.pre_header.timestamp,
+            GENESIS_TIMESTAMP_MS + 13 * 60_000
+        );
+        assert_eq!(state_context.pre_header.parent_id, chain.headers()[11].id);
+        let heights: Vec<u32> = state_context.headers.iter().map(|h| h.height).collect();
+        assert_eq!(heights, (3..=12).rev().collect::<Vec<u32>>());
+        for pair in state_context.headers.windows(2) {
+            assert_eq!(pair[0].parent_id, pair[1].id);
+            assert_eq!(pair[0].timestamp - pair[1].timestamp, 60_000);
+        }
+
+        // the block replacing the last one gets a new ID
+        let replaced_block_id = chain.headers()[11].id.clone();
+        chain.rollback_to(11).unwrap();
+        assert_eq!(chain.headers().len(), 11);
+        chain.add_block(Block::new(vec![])).unwrap();
+        assert_eq!(chain.headers()[11].height, 12);
+        assert_ne!(chain.headers()[11].id, replaced_block_id);
+    }
+}
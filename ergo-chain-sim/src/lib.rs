@@ -23,3 +23,15 @@ pub use chain::*;
 
 mod block;
 pub use block::*;
+
+mod validation;
+pub(crate) use validation::validate_tx;
+
+#[cfg(test)]
+mod test_util;
+pub use validation::TxValidationError;
+
+#[cfg(feature = "node-server")]
+mod node_server;
+#[cfg(feature = "node-server")]
+pub use node_server::*;
@@ -0,0 +1,785 @@
+//! HTTP server implementing the node REST API endpoints used by oracle-core, backed by
+//! [`ChainSim`]. Lets the oracle-core binary (and its node clients) run against a simulated chain
+//! without a live Ergo node.
+//!
+//! Simplifications compared to the node:
+//! - a submitted transaction is put into a new block right away, unless the node is created
+//!   with [`NodeSim::with_manual_mining`] (then the blocks are mined with [`ChainSim::mine_block`]);
+//! - the scans match boxes on `containsAsset` (combined with `and`, `or`, `not`), other predicates
+//!   match nothing, and are applied to the whole UTXO set so no rescan is needed;
+//! - the wallet is synced to the chain height at all times.
+//!
+//! The confirmed transactions are also served on the Explorer API endpoint
+//! (`/api/v1/transactions/{id}`, in the node format), so the node URL can be set as the
+//! `explorer_url` for the commands waiting for the transactions to be confirmed (e.g. bootstrap).
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread::JoinHandle;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use ergo_lib::chain::transaction::unsigned::UnsignedTransaction;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergo_chain_types::Digest32;
+use ergo_lib::ergotree_ir::chain::address::AddressEncoder;
+use ergo_lib::ergotree_ir::chain::address::NetworkAddress;
+use ergo_lib::ergotree_ir::chain::address::NetworkPrefix;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::wallet::secret_key::SecretKey;
+use ergo_lib::wallet::signing::TransactionContext;
+use ergo_lib::wallet::Wallet;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::ChainSim;
+
+/// API key of the simulated node unless set with [`NodeSim::with_api_key`]
+pub const DEFAULT_API_KEY: &str = "hello";
+
+/// A scan registered on the simulated node
+#[derive(Debug, Clone)]
+struct Scan {
+    scan_id: u32,
+    scan_name: String,
+    tracking_rule: Value,
+}
+
+/// State of the simulated node: the chain, the wallet and the registered scans
+pub struct NodeSim {
+    /// The simulated chain, blocks can be added directly as well
+    pub chain: ChainSim,
+    wallet: Wallet,
+    wallet_addresses: Vec<NetworkAddress>,
+    wallet_pass: Option<String>,
+    wallet_unlocked: bool,
+    api_key: String,
+    scans: Vec<Scan>,
+    next_scan_id: u32,
+    /// Reject the scan registrations once this many scans are registered
+    pub max_scans: Option<usize>,
+    /// Serve the extra index endpoints (`extraIndex = true` in the node config)
+    pub extra_index: bool,
+    rescan_from_height: Option<u32>,
+    /// Mine a block with every submitted transaction
+    auto_mine: bool,
+}
+
+impl NodeSim {
+    /// Create a simulated node with a wallet holding the given secrets, the first one is used
+    /// for the change address. The transactions are signed with the state context of the next
+    /// block of the chain (see [`ChainSim::with_state_context`]).
+    pub fn new(chain: ChainSim, secrets: Vec<SecretKey>, network_prefix: NetworkPrefix) -> NodeSim {
+        let wallet_addresses = secrets
+            .iter()
+            .map(|secret| {
+                NetworkAddress::new(network_prefix, &secret.get_address_from_public_image())
+            })
+            .collect();
+        NodeSim {
+            chain,
+            wallet: Wallet::from_secrets(secrets),
+            wallet_addresses,
+            wallet_pass: None,
+            wallet_unlocked: true,
+            api_key: DEFAULT_API_KEY.to_string(),
+            scans: Vec::new(),
+            next_scan_id: 10,
+            max_scans: None,
+            extra_index: true,
+            rescan_from_height: None,
+            auto_mine: true,
+        }
+    }
+
+    /// Require the given API key instead of [`DEFAULT_API_KEY`]
+    pub fn with_api_key(mut self, api_key: &str) -> NodeSim {
+        self.api_key = api_key.to_string();
+        self
+    }
+
+    /// Start with the wallet locked, it is unlocked with the given password
+    pub fn with_wallet_pass(mut self, wallet_pass: &str) -> NodeSim {
+        self.wallet_pass = Some(wallet_pass.to_string());
+        self.wallet_unlocked = false;
+        self
+    }
+
+    /// Keep the submitted transactions in the mempool until a block is mined
+    pub fn with_manual_mining(mut self) -> NodeSim {
+        self.auto_mine = false;
+        self
+    }
+
+    /// Height the last wallet rescan was requested from
+    pub fn rescan_from_height(&self) -> Option<u32> {
+        self.rescan_from_height
+    }
+
+    /// Unspent boxes matched by the tracking rule of the scan
+    pub fn scan_boxes(&self, scan_id: u32) -> Option<Vec<ErgoBox>> {
+        let scan = self.scans.iter().find(|scan| scan.scan_id == scan_id)?;
+        Some(
+            self.chain
+                .unspent_boxes()
+                .iter()
+                .filter(|b| tracking_rule_matches(&scan.tracking_rule, b))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Unspent boxes guarded by the wallet addresses
+    pub fn wallet_boxes(&self) -> Vec<ErgoBox> {
+        let wallet_trees: Vec<ErgoTree> = self
+            .wallet_addresses
+            .iter()
+            .filter_map(|address| address.address().script().ok())
+            .collect();
+        self.chain
+            .unspent_boxes()
+            .iter()
+            .filter(|b| wallet_trees.contains(&b.ergo_tree))
+            .cloned()
+            .collect()
+    }
+
+    fn check_api_key(&self, headers: &HeaderMap) -> Result<(), NodeSimError> {
+        match headers.get("api_key") {
+            Some(api_key) if api_key.as_bytes() == self.api_key.as_bytes() => Ok(()),
+            _ => Err(NodeSimError::new(
+                StatusCode::FORBIDDEN,
+                "Api key is not correct",
+            )),
+        }
+    }
+
+    /// Without the extra index the node doesn't have its endpoints
+    fn check_extra_index(&self) -> Result<(), NodeSimError> {
+        if self.extra_index {
+            Ok(())
+        } else {
+            Err(NodeSimError::new(
+                StatusCode::NOT_FOUND,
+                "The requested resource could not be found.",
+            ))
+        }
+    }
+
+    fn submit(&mut self, tx: Transaction) -> Result<String, NodeSimError> {
+        let tx_id = String::from(tx.id());
+        self.chain
+            .submit_tx(tx)
+            .map_err(|e| NodeSimError::bad_request(e.to_string()))?;
+        if self.auto_mine {
+            self.chain
+                .mine_block()
+                .map_err(|e| NodeSimError::bad_request(e.to_string()))?;
+        }
+        Ok(tx_id)
+    }
+}
+
+/// Whether the box matches the node scan tracking rule
+fn tracking_rule_matches(tracking_rule: &Value, ergo_box: &ErgoBox) -> bool {
+    let args = || {
+        tracking_rule["args"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+    };
+    match tracking_rule["predicate"].as_str() {
+        Some("containsAsset") => ergo_box.tokens.as_ref().map_or(false, |tokens| {
+            tokens.iter().any(|token| {
+                Some(String::from(token.token_id).as_str()) == tracking_rule["assetId"].as_str()
+            })
+        }),
+        Some("and") => args()
+            .iter()
+            .all(|rule| tracking_rule_matches(rule, ergo_box)),
+        Some("or") => args()
+            .iter()
+            .any(|rule| tracking_rule_matches(rule, ergo_box)),
+        Some("not") => !tracking_rule_matches(&tracking_rule["args"], ergo_box),
+        _ => false,
+    }
+}
+
+/// Error response in the format of the node
+#[derive(Debug)]
+struct NodeSimError {
+    status: StatusCode,
+    detail: String,
+}
+
+impl NodeSimError {
+    fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        NodeSimError {
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    fn bad_request(detail: impl Into<String>) -> Self {
+        NodeSimError::new(StatusCode::BAD_REQUEST, detail)
+    }
+}
+
+impl IntoResponse for NodeSimError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": self.status.as_u16(),
+            "reason": self.status.canonical_reason().unwrap_or_default(),
+            "detail": self.detail,
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+type SharedNodeSim = Arc<Mutex<NodeSim>>;
+
+type NodeSimResult = Result<Json<Value>, NodeSimError>;
+
+/// An unspent box as listed by the wallet and scan endpoints
+fn wallet_box_json(ergo_box: &ErgoBox, height: u32, scans: Vec<u32>) -> Value {
+    json!({
+        "box": ergo_box,
+        "confirmationsNum": height.saturating_sub(ergo_box.creation_height),
+        "inclusionHeight": ergo_box.creation_height,
+        "onchain": true,
+        "spent": false,
+        "scans": scans,
+    })
+}
+
+async fn info(State(node): State<SharedNodeSim>) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    Ok(Json(json!({
+        "name": "ergo-chain-sim",
+        "fullHeight": node.chain.height,
+        "headersHeight": node.chain.height,
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterScanRequest {
+    scan_name: String,
+    tracking_rule: Value,
+}
+
+async fn register_scan(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Json(req): Json<RegisterScanRequest>,
+) -> NodeSimResult {
+    let mut node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    if node
+        .max_scans
+        .map_or(false, |max_scans| node.scans.len() >= max_scans)
+    {
+        return Err(NodeSimError::bad_request("too many scans registered"));
+    }
+    let scan_id = node.next_scan_id;
+    node.next_scan_id += 1;
+    node.scans.push(Scan {
+        scan_id,
+        scan_name: req.scan_name,
+        tracking_rule: req.tracking_rule,
+    });
+    Ok(Json(json!({ "scanId": scan_id })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeregisterScanRequest {
+    scan_id: u32,
+}
+
+async fn deregister_scan(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Json(req): Json<DeregisterScanRequest>,
+) -> NodeSimResult {
+    let mut node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    if !node.scans.iter().any(|scan| scan.scan_id == req.scan_id) {
+        return Err(NodeSimError::bad_request(format!(
+            "scan {} is not registered",
+            req.scan_id
+        )));
+    }
+    node.scans.retain(|scan| scan.scan_id != req.scan_id);
+    Ok(Json(json!({ "scanId": req.scan_id })))
+}
+
+async fn list_scans(State(node): State<SharedNodeSim>, headers: HeaderMap) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    let scans: Vec<Value> = node
+        .scans
+        .iter()
+        .map(|scan| {
+            json!({
+                "scanId": scan.scan_id,
+                "scanName": scan.scan_name,
+                "trackingRule": scan.tracking_rule,
+                "walletInteraction": "off",
+                "removeOffchain": true,
+            })
+        })
+        .collect();
+    Ok(Json(json!(scans)))
+}
+
+async fn scan_unspent_boxes(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Path(scan_id): Path<u32>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    let boxes = node
+        .scan_boxes(scan_id)
+        .ok_or_else(|| NodeSimError::bad_request(format!("scan {} is not registered", scan_id)))?;
+    let boxes: Vec<Value> = boxes
+        .iter()
+        .map(|b| wallet_box_json(b, node.chain.height, vec![scan_id]))
+        .collect();
+    Ok(Json(json!(boxes)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RescanRequest {
+    from_height: u32,
+}
+
+async fn rescan(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Json(req): Json<RescanRequest>,
+) -> NodeSimResult {
+    let mut node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    node.rescan_from_height = Some(req.from_height);
+    Ok(Json(json!({})))
+}
+
+async fn wallet_status(State(node): State<SharedNodeSim>, headers: HeaderMap) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    Ok(Json(json!({
+        "isInitialized": true,
+        "isUnlocked": node.wallet_unlocked,
+        "changeAddress": node.wallet_addresses.first().map(|address| address.to_base58()).unwrap_or_default(),
+        "walletHeight": node.chain.height,
+        "error": "",
+    })))
+}
+
+#[derive(Deserialize)]
+struct UnlockRequest {
+    pass: String,
+}
+
+async fn wallet_unlock(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Json(req): Json<UnlockRequest>,
+) -> NodeSimResult {
+    let mut node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    match &node.wallet_pass {
+        Some(wallet_pass) if *wallet_pass != req.pass => {
+            Err(NodeSimError::bad_request("wrong wallet password"))
+        }
+        _ => {
+            node.wallet_unlocked = true;
+            Ok(Json(json!({})))
+        }
+    }
+}
+
+async fn wallet_addresses(State(node): State<SharedNodeSim>, headers: HeaderMap) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    let addresses: Vec<String> = node
+        .wallet_addresses
+        .iter()
+        .map(|address| address.to_base58())
+        .collect();
+    Ok(Json(json!(addresses)))
+}
+
+async fn wallet_unspent_boxes(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    let boxes: Vec<Value> = node
+        .wallet_boxes()
+        .iter()
+        .map(|b| wallet_box_json(b, node.chain.height, vec![]))
+        .collect();
+    Ok(Json(json!(boxes)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignRequest {
+    tx: UnsignedTransaction,
+    /// Serialized input boxes, looked up in the UTXO set if not given
+    #[serde(default)]
+    inputs_raw: Option<Vec<String>>,
+    #[serde(default)]
+    data_inputs_raw: Option<Vec<String>>,
+}
+
+fn parse_raw_boxes(raw_boxes: &[String]) -> Result<Vec<ErgoBox>, NodeSimError> {
+    raw_boxes
+        .iter()
+        .map(|raw_box| {
+            let bytes = base16::decode(raw_box)
+                .map_err(|e| NodeSimError::bad_request(format!("invalid box bytes: {}", e)))?;
+            ErgoBox::sigma_parse_bytes(&bytes)
+                .map_err(|e| NodeSimError::bad_request(format!("invalid box: {}", e)))
+        })
+        .collect()
+}
+
+/// The boxes from the UTXO set or the outputs of the mempool transactions
+fn find_unspent_boxes(node: &NodeSim, box_ids: &[BoxId]) -> Result<Vec<ErgoBox>, NodeSimError> {
+    let unspent_boxes = node.chain.unspent_boxes_with_pool();
+    box_ids
+        .iter()
+        .map(|box_id| {
+            unspent_boxes
+                .iter()
+                .find(|b| b.box_id() == *box_id)
+                .cloned()
+                .ok_or_else(|| {
+                    NodeSimError::bad_request(format!(
+                        "box {} is not in the UTXO set",
+                        String::from(*box_id)
+                    ))
+                })
+        })
+        .collect()
+}
+
+async fn sign_transaction(
+    State(node): State<SharedNodeSim>,
+    headers: HeaderMap,
+    Json(req): Json<SignRequest>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_api_key(&headers)?;
+    if !node.wallet_unlocked {
+        return Err(NodeSimError::bad_request("wallet is locked"));
+    }
+    let input_boxes = match &req.inputs_raw {
+        Some(inputs_raw) => parse_raw_boxes(inputs_raw)?,
+        None => {
+            let box_ids: Vec<BoxId> = req.tx.inputs.iter().map(|input| input.box_id).collect();
+            find_unspent_boxes(&node, &box_ids)?
+        }
+    };
+    let data_boxes = match (&req.data_inputs_raw, &req.tx.data_inputs) {
+        (Some(data_inputs_raw), _) => parse_raw_boxes(data_inputs_raw)?,
+        (None, Some(data_inputs)) => {
+            let box_ids: Vec<BoxId> = data_inputs.iter().map(|input| input.box_id).collect();
+            find_unspent_boxes(&node, &box_ids)?
+        }
+        (None, None) => vec![],
+    };
+    let tx_context = TransactionContext::new(req.tx, input_boxes, data_boxes)
+        .map_err(|e| NodeSimError::bad_request(e.to_string()))?;
+    let state_context = node
+        .chain
+        .state_context()
+        .ok_or_else(|| NodeSimError::bad_request("the simulated chain has no state context"))?;
+    let tx = node
+        .wallet
+        .sign_transaction(tx_context, &state_context, None)
+        .map_err(|e| NodeSimError::bad_request(e.to_string()))?;
+    Ok(Json(json!(tx)))
+}
+
+async fn submit_transaction(
+    State(node): State<SharedNodeSim>,
+    Json(tx): Json<Transaction>,
+) -> NodeSimResult {
+    let tx_id = node.lock().unwrap().submit(tx)?;
+    Ok(Json(json!(tx_id)))
+}
+
+/// Mempool transactions with an input or an output guarded by the tree
+async fn unconfirmed_transactions_by_ergo_tree(
+    State(node): State<SharedNodeSim>,
+    Json(ergo_tree_hex): Json<String>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    let has_tree =
+        |b: &ErgoBox| b.ergo_tree.to_base16_bytes().ok().as_ref() == Some(&ergo_tree_hex);
+    // the boxes the mempool transactions can spend
+    let spendable_boxes: Vec<ErgoBox> = node
+        .chain
+        .unspent_boxes()
+        .iter()
+        .cloned()
+        .chain(
+            node.chain
+                .mempool()
+                .iter()
+                .flat_map(|tx| tx.outputs.to_vec()),
+        )
+        .collect();
+    let txs: Vec<&Transaction> = node
+        .chain
+        .mempool()
+        .iter()
+        .filter(|tx| {
+            tx.outputs.iter().any(has_tree)
+                || tx.inputs.iter().any(|input| {
+                    spendable_boxes
+                        .iter()
+                        .any(|b| b.box_id() == input.box_id && has_tree(b))
+                })
+        })
+        .collect();
+    Ok(Json(json!(txs)))
+}
+
+#[derive(Deserialize)]
+struct Paging {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    5
+}
+
+/// Unspent boxes holding the token, as in the node extra index
+async fn unspent_boxes_by_token_id(
+    State(node): State<SharedNodeSim>,
+    Path(token_id): Path<String>,
+    Query(paging): Query<Paging>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_extra_index()?;
+    let token_id: TokenId = Digest32::try_from(token_id.clone())
+        .map_err(|_| NodeSimError::bad_request(format!("invalid token id {}", token_id)))?
+        .into();
+    let boxes: Vec<ErgoBox> = node
+        .chain
+        .get_unspent_boxes_by_token_id(&token_id)
+        .into_iter()
+        .skip(paging.offset)
+        .take(paging.limit)
+        .collect();
+    Ok(Json(json!(boxes)))
+}
+
+/// Unspent boxes guarded by the address, as in the node extra index
+async fn unspent_boxes_by_address(
+    State(node): State<SharedNodeSim>,
+    Query(paging): Query<Paging>,
+    Json(address): Json<String>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_extra_index()?;
+    let ergo_tree = AddressEncoder::unchecked_parse_network_address_from_str(&address)
+        .ok()
+        .and_then(|address| address.address().script().ok())
+        .ok_or_else(|| NodeSimError::bad_request(format!("invalid address {}", address)))?;
+    let boxes: Vec<ErgoBox> = node
+        .chain
+        .get_unspent_boxes(&ergo_tree)
+        .into_iter()
+        .skip(paging.offset)
+        .take(paging.limit)
+        .collect();
+    Ok(Json(json!(boxes)))
+}
+
+/// The last headers of the chain, the oldest first
+async fn last_headers(
+    State(node): State<SharedNodeSim>,
+    Path(count): Path<usize>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    let headers = node.chain.headers();
+    Ok(Json(json!(headers[headers.len().saturating_sub(count)..])))
+}
+
+async fn indexed_height(State(node): State<SharedNodeSim>) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    node.check_extra_index()?;
+    Ok(Json(json!({
+        "indexedHeight": node.chain.height,
+        "fullHeight": node.chain.height,
+    })))
+}
+
+async fn box_with_pool_by_id(
+    State(node): State<SharedNodeSim>,
+    Path(box_id): Path<String>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    let box_id: BoxId = Digest32::try_from(box_id.clone())
+        .map_err(|_| NodeSimError::bad_request(format!("invalid box id {}", box_id)))?
+        .into();
+    match node
+        .chain
+        .unspent_boxes_with_pool()
+        .into_iter()
+        .find(|b| b.box_id() == box_id)
+    {
+        Some(ergo_box) => Ok(Json(json!(ergo_box))),
+        None => Err(NodeSimError::new(
+            StatusCode::NOT_FOUND,
+            format!("box {} not found", String::from(box_id)),
+        )),
+    }
+}
+
+/// A confirmed transaction, on the Explorer API endpoint
+async fn explorer_transaction(
+    State(node): State<SharedNodeSim>,
+    Path(tx_id): Path<String>,
+) -> NodeSimResult {
+    let node = node.lock().unwrap();
+    match node
+        .chain
+        .blocks()
+        .iter()
+        .flat_map(|block| block.txs())
+        .find(|tx| String::from(tx.id()) == tx_id)
+    {
+        Some(tx) => Ok(Json(json!(tx))),
+        None => Err(NodeSimError::new(
+            StatusCode::NOT_FOUND,
+            format!("transaction {} not found", tx_id),
+        )),
+    }
+}
+
+/// Router serving the node endpoints from the given simulated node
+pub fn node_router(node: Arc<Mutex<NodeSim>>) -> Router {
+    Router::new()
+        .route("/info", get(info))
+        .route("/scan/register", post(register_scan))
+        .route("/scan/deregister", post(deregister_scan))
+        .route("/scan/listAll", get(list_scans))
+        .route("/scan/unspentBoxes/:scan_id", get(scan_unspent_boxes))
+        .route("/wallet/rescan", post(rescan))
+        .route("/wallet/status", get(wallet_status))
+        .route("/wallet/unlock", post(wallet_unlock))
+        .route("/wallet/addresses", get(wallet_addresses))
+        .route("/wallet/boxes/unspent", get(wallet_unspent_boxes))
+        .route("/wallet/transaction/sign", post(sign_transaction))
+        .route("/transactions", post(submit_transaction))
+        .route(
+            "/transactions/unconfirmed/byErgoTree",
+            post(unconfirmed_transactions_by_ergo_tree),
+        )
+        .route("/utxo/withPool/byId/:box_id", get(box_with_pool_by_id))
+        .route(
+            "/blockchain/box/unspent/byTokenId/:token_id",
+            get(unspent_boxes_by_token_id),
+        )
+        .route(
+            "/blockchain/box/unspent/byAddress",
+            post(unspent_boxes_by_address),
+        )
+        .route("/blockchain/indexedHeight", get(indexed_height))
+        .route("/blocks/lastHeaders/:count", get(last_headers))
+        .route("/api/v1/transactions/:tx_id", get(explorer_transaction))
+        .with_state(node)
+}
+
+/// Simulated node served on a local port from a background thread, stopped on drop
+pub struct NodeSimServer {
+    addr: SocketAddr,
+    node: Arc<Mutex<NodeSim>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NodeSimServer {
+    /// Serve the simulated node on a free local port
+    pub fn start(node: NodeSim) -> std::io::Result<NodeSimServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let node = Arc::new(Mutex::new(node));
+        let router = node_router(node.clone());
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()?;
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            runtime.block_on(async move {
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(router.into_make_service())
+                    .with_graceful_shutdown(async {
+                        shutdown_rx.await.ok();
+                    })
+                    .await
+                    .unwrap();
+            })
+        });
+        Ok(NodeSimServer {
+            addr,
+            node,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        })
+    }
+
+    /// URL of the node to put in the oracle config (`node_url`)
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// The simulated node, e.g. to add blocks to its chain
+    pub fn node(&self) -> MutexGuard<'_, NodeSim> {
+        self.node.lock().unwrap()
+    }
+}
+
+impl Drop for NodeSimServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
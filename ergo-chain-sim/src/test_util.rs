@@ -0,0 +1,80 @@
+//! Helpers building unsigned transactions, for the chains without script verification (see
+//! [`ChainSim::without_script_verification`])
+use std::convert::TryInto;
+
+use ergo_lib::chain::transaction::input::prover_result::ProverResult;
+use ergo_lib::chain::transaction::DataInput;
+use ergo_lib::chain::transaction::Input;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
+use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ProofBytes;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBoxCandidate;
+use ergo_lib::ergotree_ir::chain::ergo_box::NonMandatoryRegisters;
+use ergo_lib::ergotree_ir::chain::token::Token;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+use ergo_lib::wallet::secret_key::SecretKey;
+
+use crate::ChainSim;
+
+pub(crate) fn wallet_tree() -> ErgoTree {
+    SecretKey::random_dlog()
+        .get_address_from_public_image()
+        .script()
+        .unwrap()
+}
+
+pub(crate) fn output(value: u64, tokens: Vec<Token>) -> ErgoBoxCandidate {
+    ErgoBoxCandidate {
+        value: value.try_into().unwrap(),
+        ergo_tree: wallet_tree(),
+        tokens: tokens.try_into().ok(),
+        additional_registers: NonMandatoryRegisters::empty(),
+        creation_height: 0,
+    }
+}
+
+/// Unsigned spending of the boxes
+pub(crate) fn spending_tx(
+    inputs: &[ErgoBox],
+    data_inputs: &[ErgoBox],
+    outputs: Vec<ErgoBoxCandidate>,
+) -> Transaction {
+    Transaction::new_from_vec(
+        inputs
+            .iter()
+            .map(|b| {
+                Input::new(
+                    b.box_id(),
+                    ProverResult {
+                        proof: ProofBytes::Empty,
+                        extension: ContextExtension::empty(),
+                    },
+                )
+            })
+            .collect(),
+        data_inputs
+            .iter()
+            .map(|b| DataInput { box_id: b.box_id() })
+            .collect(),
+        outputs,
+    )
+    .unwrap()
+}
+
+pub(crate) fn chain_with_box(value: u64, tokens: Vec<Token>) -> (ChainSim, ErgoBox) {
+    let mut chain = ChainSim::new().without_script_verification();
+    let value: BoxValue = value.try_into().unwrap();
+    chain.generate_unspent_box(wallet_tree(), value, tokens.try_into().ok());
+    let unspent_box = chain.unspent_boxes()[0].clone();
+    (chain, unspent_box)
+}
+
+pub(crate) fn token(token_id: TokenId, amount: u64) -> Token {
+    Token {
+        token_id,
+        amount: amount.try_into().unwrap(),
+    }
+}
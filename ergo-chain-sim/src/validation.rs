@@ -0,0 +1,312 @@
+//! Checks a transaction has to pass to be included in a block, a subset of the node's stateful
+//! transaction validation (https://github.com/ergoplatform/ergo/blob/1935c95560a30b19cdb52c1a291e8a389ba63c97/src/main/scala/org/ergoplatform/modifiers/mempool/ErgoTransaction.scala#L80-L384)
+use std::collections::HashMap;
+
+use ergo_lib::chain::ergo_state_context::ErgoStateContext;
+use ergo_lib::chain::transaction::verify_tx_input_proof;
+use ergo_lib::chain::transaction::Transaction;
+use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::wallet::signing::TransactionContext;
+use thiserror::Error;
+
+/// Reason a transaction is rejected
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TxValidationError {
+    /// The input box is not in the UTXO set (unknown or already spent)
+    #[error("input box {0} is not in the UTXO set")]
+    InputNotFound(String),
+    /// The box is spent by more than one input
+    #[error("input box {0} is spent twice")]
+    DuplicateInput(String),
+    /// The data input box is not in the UTXO set
+    #[error("data input box {0} is not in the UTXO set")]
+    DataInputNotFound(String),
+    /// The nanoERGs in the inputs and the outputs differ
+    #[error("the inputs hold {0} nanoERG while the outputs hold {1} nanoERG")]
+    ErgNotConserved(u64, u64),
+    /// The outputs hold more of a token than the inputs, only the token with the ID of the first
+    /// input box can be minted
+    #[error("the inputs hold {1} of token {0} while the outputs hold {2}")]
+    TokenNotConserved(String, u64, u64),
+    /// The inputs do not match the transaction
+    #[error("invalid transaction context: {0}")]
+    TxContext(String),
+    /// The input script failed to evaluate or its proof failed to verify
+    #[error("input #{0}: script verification failed: {1}")]
+    ScriptError(usize, String),
+    /// The input script with its proof evaluated to false
+    #[error("input #{0}: script evaluated to false")]
+    ScriptFalse(usize),
+    /// The input scripts are to be verified but the chain has no state context (see
+    /// `ChainSim::with_state_context` and `ChainSim::without_script_verification`)
+    #[error("no state context to verify the input scripts against")]
+    NoStateContext,
+}
+
+/// Checks the transaction against the UTXO set. With `verify_scripts` the input scripts are
+/// verified against the state context, the transaction is rejected if there is none.
+pub(crate) fn validate_tx(
+    tx: &Transaction,
+    utxo: &[ErgoBox],
+    state_context: Option<&ErgoStateContext>,
+    verify_scripts: bool,
+) -> Result<(), TxValidationError> {
+    let find_box = |box_id: &BoxId| utxo.iter().find(|b| b.box_id() == *box_id).cloned();
+    let mut input_boxes: Vec<ErgoBox> = Vec::new();
+    for input in tx.inputs.iter() {
+        if input_boxes.iter().any(|b| b.box_id() == input.box_id) {
+            return Err(TxValidationError::DuplicateInput(String::from(
+                input.box_id,
+            )));
+        }
+        let input_box = find_box(&input.box_id)
+            .ok_or_else(|| TxValidationError::InputNotFound(String::from(input.box_id)))?;
+        input_boxes.push(input_box);
+    }
+    let data_boxes = tx
+        .data_inputs
+        .iter()
+        .flat_map(|data_inputs| data_inputs.iter())
+        .map(|data_input| {
+            find_box(&data_input.box_id).ok_or_else(|| {
+                TxValidationError::DataInputNotFound(String::from(data_input.box_id))
+            })
+        })
+        .collect::<Result<Vec<ErgoBox>, TxValidationError>>()?;
+    let outputs = tx.outputs.to_vec();
+    check_erg_conservation(&input_boxes, &outputs)?;
+    check_token_conservation(&input_boxes, &outputs)?;
+    if verify_scripts {
+        let state_context = state_context.ok_or(TxValidationError::NoStateContext)?;
+        verify_input_scripts(tx, input_boxes, data_boxes, state_context)?;
+    }
+    Ok(())
+}
+
+fn check_erg_conservation(
+    input_boxes: &[ErgoBox],
+    outputs: &[ErgoBox],
+) -> Result<(), TxValidationError> {
+    let inputs_value: u64 = input_boxes.iter().map(|b| *b.value.as_u64()).sum();
+    let outputs_value: u64 = outputs.iter().map(|b| *b.value.as_u64()).sum();
+    if inputs_value != outputs_value {
+        return Err(TxValidationError::ErgNotConserved(
+            inputs_value,
+            outputs_value,
+        ));
+    }
+    Ok(())
+}
+
+/// Amounts of the tokens in the boxes by the token ID
+fn token_amounts(boxes: &[ErgoBox]) -> HashMap<String, u64> {
+    let mut amounts: HashMap<String, u64> = HashMap::new();
+    for token in boxes
+        .iter()
+        .flat_map(|b| b.tokens.iter().flat_map(|t| t.iter()))
+    {
+        *amounts.entry(String::from(token.token_id)).or_default() += *token.amount.as_u64();
+    }
+    amounts
+}
+
+fn check_token_conservation(
+    input_boxes: &[ErgoBox],
+    outputs: &[ErgoBox],
+) -> Result<(), TxValidationError> {
+    let input_amounts = token_amounts(input_boxes);
+    let minted_token_id = input_boxes
+        .first()
+        .map(|b| String::from(TokenId::from(b.box_id())));
+    for (token_id, output_amount) in token_amounts(outputs) {
+        let input_amount = input_amounts.get(&token_id).copied().unwrap_or_default();
+        if output_amount > input_amount && Some(&token_id) != minted_token_id.as_ref() {
+            return Err(TxValidationError::TokenNotConserved(
+                token_id,
+                input_amount,
+                output_amount,
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn verify_input_scripts(
+    tx: &Transaction,
+    input_boxes: Vec<ErgoBox>,
+    data_boxes: Vec<ErgoBox>,
+    state_context: &ErgoStateContext,
+) -> Result<(), TxValidationError> {
+    let bytes_to_sign = tx
+        .bytes_to_sign()
+        .map_err(|e| TxValidationError::TxContext(e.to_string()))?;
+    let tx_context = TransactionContext::new(tx.clone(), input_boxes, data_boxes)
+        .map_err(|e| TxValidationError::TxContext(e.to_string()))?;
+    for input_index in 0..tx.inputs.len() {
+        let verification =
+            verify_tx_input_proof(&tx_context, state_context, input_index, &bytes_to_sign)
+                .map_err(|e| TxValidationError::ScriptError(input_index, e.to_string()))?;
+        if !verification.result {
+            return Err(TxValidationError::ScriptFalse(input_index));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::chain::transaction::input::prover_result::ProverResult;
+    use ergo_lib::chain::transaction::Input;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
+    use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ProofBytes;
+    use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+    use ergo_lib::ergotree_ir::ergo_tree::ErgoTree;
+    use ergo_lib::ergotree_ir::mir::constant::Constant;
+    use ergo_lib::ergotree_ir::mir::expr::Expr;
+    use pretty_assertions::assert_eq;
+    use sigma_test_util::force_any_val;
+
+    use super::*;
+    use crate::test_util::chain_with_box;
+    use crate::test_util::output;
+    use crate::test_util::spending_tx;
+    use crate::test_util::token;
+    use crate::test_util::wallet_tree;
+    use crate::Block;
+    use crate::ChainSim;
+    use crate::ChainSimError;
+
+    fn verifying_chain_with_box(ergo_tree: ErgoTree) -> (ChainSim, ErgoBox) {
+        let mut chain = ChainSim::new().with_state_context(force_any_val::<ErgoStateContext>());
+        chain.generate_unspent_box(ergo_tree, BoxValue::try_from(1_000_000u64).unwrap(), None);
+        let unspent_box = chain.unspent_boxes()[0].clone();
+        (chain, unspent_box)
+    }
+
+    fn rejection(chain: &mut ChainSim, tx: Transaction) -> TxValidationError {
+        let height = chain.height;
+        let error = match chain.add_block(Block::new(vec![tx])).unwrap_err() {
+            ChainSimError::InvalidTx(_, error) => error,
+            e @ (ChainSimError::MempoolConflict(..) | ChainSimError::RollbackAboveHeight(..)) => {
+                unreachable!("expected an invalid tx, got {}", e)
+            }
+        };
+        assert_eq!(chain.height, height);
+        error
+    }
+
+    #[test]
+    fn test_erg_conservation() {
+        let (mut chain, input) = chain_with_box(2_000_000, vec![]);
+        let tx = spending_tx(&[input.clone()], &[], vec![output(1_000_000, vec![])]);
+        assert_eq!(
+            rejection(&mut chain, tx),
+            TxValidationError::ErgNotConserved(2_000_000, 1_000_000)
+        );
+        let tx = spending_tx(
+            &[input],
+            &[],
+            vec![output(1_000_000, vec![]), output(1_000_000, vec![])],
+        );
+        chain.add_block(Block::new(vec![tx])).unwrap();
+        assert_eq!(chain.height, 1);
+        assert_eq!(chain.unspent_boxes().len(), 2);
+    }
+
+    #[test]
+    fn test_token_conservation_and_minting() {
+        let (_, other_box) = chain_with_box(1_000_000, vec![]);
+        let existing_token_id: TokenId = other_box.box_id().into();
+        let (mut chain, input) = chain_with_box(1_000_000, vec![token(existing_token_id, 10)]);
+        let tx = spending_tx(
+            &[input.clone()],
+            &[],
+            vec![output(1_000_000, vec![token(existing_token_id, 11)])],
+        );
+        assert_eq!(
+            rejection(&mut chain, tx),
+            TxValidationError::TokenNotConserved(String::from(existing_token_id), 10, 11)
+        );
+        // the token with the first input box ID is minted, the existing one partially burned
+        let minted_token_id: TokenId = input.box_id().into();
+        let tx = spending_tx(
+            &[input],
+            &[],
+            vec![output(
+                1_000_000,
+                vec![token(existing_token_id, 5), token(minted_token_id, 1000)],
+            )],
+        );
+        chain.add_block(Block::new(vec![tx])).unwrap();
+    }
+
+    #[test]
+    fn test_inputs_and_data_inputs_exist() {
+        let (mut chain, input) = chain_with_box(1_000_000, vec![]);
+        let (_, unknown_box) = chain_with_box(1_000_000, vec![]);
+        let tx = spending_tx(
+            &[input.clone()],
+            &[unknown_box.clone()],
+            vec![output(1_000_000, vec![])],
+        );
+        assert_eq!(
+            rejection(&mut chain, tx),
+            TxValidationError::DataInputNotFound(String::from(unknown_box.box_id()))
+        );
+        let tx = spending_tx(&[input.clone()], &[], vec![output(1_000_000, vec![])]);
+        chain.add_block(Block::new(vec![tx])).unwrap();
+        // already spent
+        let tx = spending_tx(&[input.clone()], &[], vec![output(1_000_000, vec![])]);
+        assert_eq!(
+            rejection(&mut chain, tx),
+            TxValidationError::InputNotFound(String::from(input.box_id()))
+        );
+    }
+
+    #[test]
+    fn test_input_scripts_are_verified() {
+        let false_expr: Expr = Constant::from(false).into();
+        let (mut chain, input) = verifying_chain_with_box(ErgoTree::try_from(false_expr).unwrap());
+        let tx = spending_tx(&[input], &[], vec![output(1_000_000, vec![])]);
+        assert_eq!(rejection(&mut chain, tx), TxValidationError::ScriptFalse(0));
+
+        let (mut chain, input) = verifying_chain_with_box(wallet_tree());
+        let wrong_proof = ProverResult {
+            proof: ProofBytes::Some(vec![1, 2, 3]),
+            extension: ContextExtension::empty(),
+        };
+        let tx = Transaction::new_from_vec(
+            vec![Input::new(input.box_id(), wrong_proof)],
+            vec![],
+            vec![output(1_000_000, vec![])],
+        )
+        .unwrap();
+        assert!(matches!(
+            rejection(&mut chain, tx),
+            TxValidationError::ScriptError(0, _)
+        ));
+    }
+
+    #[test]
+    fn test_no_state_context_to_verify_against() {
+        let mut chain = ChainSim::new();
+        chain.generate_unspent_box(
+            wallet_tree(),
+            BoxValue::try_from(1_000_000u64).unwrap(),
+            None,
+        );
+        let input = chain.unspent_boxes()[0].clone();
+        let tx = spending_tx(&[input], &[], vec![output(1_000_000, vec![])]);
+        assert_eq!(rejection(&mut chain, tx), TxValidationError::NoStateContext);
+        // opted out
+        let mut chain = chain.without_script_verification();
+        let tx = spending_tx(
+            &chain.unspent_boxes().to_vec(),
+            &[],
+            vec![output(1_000_000, vec![])],
+        );
+        chain.add_block(Block::new(vec![tx])).unwrap();
+    }
+}